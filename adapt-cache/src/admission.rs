@@ -0,0 +1,114 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const DEPTH: usize = 4;
+const MAX_COUNT: u8 = 15;
+
+/// TinyLFU frequency sketch: a count-min sketch of 4-bit counters that halves
+/// every counter after `sample_size` recorded accesses, so old popularity fades.
+///
+/// A newcomer is only admitted over a victim it has been requested more often than,
+/// which keeps one-hit wonders from flushing hot entries.
+pub(crate) struct TinyLfu {
+    counters: Vec<[u8; DEPTH]>,
+    additions: usize,
+    sample_size: usize,
+}
+
+impl TinyLfu {
+    pub(crate) fn new(width: usize) -> Self {
+        let width = width.max(16).next_power_of_two();
+        Self {
+            counters: vec![[0; DEPTH]; width],
+            additions: 0,
+            sample_size: width * 10,
+        }
+    }
+
+    pub(crate) fn record(&mut self, key: &str) {
+        let mut incremented = false;
+        for row in 0..DEPTH {
+            let index = self.index(key, row);
+            let counter = &mut self.counters[index][row];
+            if *counter < MAX_COUNT {
+                *counter += 1;
+                incremented = true;
+            }
+        }
+
+        if incremented {
+            self.additions += 1;
+            if self.additions >= self.sample_size {
+                self.reset();
+            }
+        }
+    }
+
+    pub(crate) fn estimate(&self, key: &str) -> u8 {
+        (0..DEPTH)
+            .map(|row| self.counters[self.index(key, row)][row])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn admit(&self, candidate: &str, victim: &str) -> bool {
+        self.estimate(candidate) > self.estimate(victim)
+    }
+
+    fn index(&self, key: &str, row: usize) -> usize {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        key.hash(&mut hasher);
+        hasher.finish() as usize & (self.counters.len() - 1)
+    }
+
+    fn reset(&mut self) {
+        for counters in &mut self.counters {
+            for counter in counters {
+                *counter /= 2;
+            }
+        }
+        self.additions /= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_counts_accesses() {
+        let mut sketch = TinyLfu::new(64);
+        for _ in 0..3 {
+            sketch.record("hot");
+        }
+        sketch.record("cold");
+
+        assert_eq!(sketch.estimate("hot"), 3);
+        assert_eq!(sketch.estimate("cold"), 1);
+        assert_eq!(sketch.estimate("unknown"), 0);
+    }
+
+    #[test]
+    fn test_admit_requires_higher_frequency() {
+        let mut sketch = TinyLfu::new(64);
+        sketch.record("victim");
+        sketch.record("candidate");
+        assert!(!sketch.admit("candidate", "victim"));
+
+        sketch.record("candidate");
+        assert!(sketch.admit("candidate", "victim"));
+    }
+
+    #[test]
+    fn test_counters_saturate_and_age() {
+        let mut sketch = TinyLfu::new(64);
+        for _ in 0..100 {
+            sketch.record("hot");
+        }
+        assert_eq!(sketch.estimate("hot"), MAX_COUNT);
+
+        sketch.reset();
+        assert_eq!(sketch.estimate("hot"), MAX_COUNT / 2);
+    }
+}
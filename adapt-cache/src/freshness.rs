@@ -0,0 +1,40 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long a validated entry may be served before asking the origin again.
+///
+/// The default revalidates on every hit and never serves stale data.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Freshness {
+    /// Hits validated within this window are served from memory with no network call.
+    pub fresh_for: Duration,
+    /// After `fresh_for`, hits are still served from memory for this long
+    /// while a background task revalidates them.
+    pub stale_while_revalidate: Duration,
+    /// When revalidation fails, hits validated within this window are served anyway.
+    pub stale_if_error: Duration,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Staleness {
+    Fresh,
+    Stale,
+    Expired,
+}
+
+impl Freshness {
+    pub(crate) fn staleness(&self, validated_at: Instant) -> Staleness {
+        let age = validated_at.elapsed();
+        if age < self.fresh_for {
+            Staleness::Fresh
+        } else if age < self.fresh_for + self.stale_while_revalidate {
+            Staleness::Stale
+        } else {
+            Staleness::Expired
+        }
+    }
+
+    pub(crate) fn can_serve_on_error(&self, validated_at: Instant) -> bool {
+        validated_at.elapsed() < self.stale_if_error
+    }
+}
@@ -1,16 +1,26 @@
 use super::*;
+use crate::integrity::SIGNATURE_SUFFIX;
+use crate::lru::WeightedLru;
 use async_singleflight::Group;
 use bytes::Bytes;
 use std::collections::VecDeque;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::SystemTime;
+use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
 
 pub struct FsAdaptCache<T, E> {
     base_path: PathBuf,
-    cache: Arc<Mutex<VecDeque<CacheEntry<T>>>>,
+    cache: Arc<Mutex<WeightedLru<CacheEntry<T>>>>,
+    // raw bytes from `put` or `prefetch`, not converted yet. front is new, back is old
+    prefetched: Arc<Mutex<VecDeque<PrefetchedEntry>>>,
     cache_size: usize,
+    integrity: Integrity,
+    // bumped on `put` and `invalidate` so an in-flight singleflight leader
+    // doesn't cache a value that was superseded while it was reading.
+    generation: Arc<AtomicU64>,
     singleflight: Arc<Group<String, T, Error<E>>>,
 }
 
@@ -19,7 +29,10 @@ impl<T, E> Clone for FsAdaptCache<T, E> {
         Self {
             base_path: self.base_path.clone(),
             cache: self.cache.clone(),
+            prefetched: self.prefetched.clone(),
             cache_size: self.cache_size,
+            integrity: self.integrity.clone(),
+            generation: self.generation.clone(),
             singleflight: self.singleflight.clone(),
         }
     }
@@ -29,18 +42,33 @@ impl<T: Clone + Send + Sync + 'static, E> FsAdaptCache<T, E> {
     pub fn new(base_path: PathBuf, cache_size: usize) -> Self {
         Self {
             base_path,
-            cache: Default::default(),
+            cache: Arc::new(Mutex::new(WeightedLru::new(cache_size, Default::default()))),
+            prefetched: Default::default(),
             cache_size,
+            integrity: Default::default(),
+            generation: Default::default(),
             singleflight: Default::default(),
         }
     }
 
+    pub fn with_integrity(mut self, integrity: Integrity) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    pub fn with_eviction(mut self, eviction: Eviction) -> Self {
+        self.cache = Arc::new(Mutex::new(WeightedLru::new(self.cache_size, eviction)));
+        self
+    }
+
     async fn try_hit_cache(&self, path: &str) -> Option<CacheEntry<T>> {
-        let mut cache = self.cache.lock().await;
-        let index = cache.iter().position(|entry| entry.key == path)?;
-        let entry = cache.remove(index).expect("unreachable");
-        cache.push_front(entry.clone());
-        Some(entry)
+        self.cache.lock().await.get(path)
+    }
+
+    async fn take_prefetched(&self, path: &str) -> Option<PrefetchedEntry> {
+        let mut prefetched = self.prefetched.lock().await;
+        let index = prefetched.iter().position(|entry| entry.key == path)?;
+        prefetched.remove(index)
     }
 
     async fn read_from_fs(&self, path: &str) -> anyhow::Result<(Bytes, SystemTime, u64)> {
@@ -52,22 +80,57 @@ impl<T: Clone + Send + Sync + 'static, E> FsAdaptCache<T, E> {
         Ok((Bytes::from(data), mtime, file_size))
     }
 
+    async fn verify(&self, path: &str, data: &Bytes) -> Result<(), Error<E>> {
+        let signature = if self.integrity.requires_signature() {
+            let signature_path = self.base_path.join(format!("{path}{SIGNATURE_SUFFIX}"));
+            match tokio::fs::read(&signature_path).await {
+                Ok(signature) => Some(signature),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+                Err(error) => return Err(Error::StorageError(anyhow::anyhow!(error))),
+            }
+        } else {
+            None
+        };
+
+        self.integrity
+            .verify(path, data, signature.as_deref())
+            .map_err(Error::IntegrityError)
+    }
+
     async fn fetch_and_cache(
         &self,
         path: &str,
+        generation: u64,
         convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
     ) -> Result<T, Error<E>> {
         let (data, mtime, file_size) =
             self.read_from_fs(path).await.map_err(Error::StorageError)?;
-        let (value, byte_len) = convert(data).map_err(Error::ConvertError)?;
+        self.verify(path, &data).await?;
+        self.convert_and_cache(path, data, mtime, file_size, generation, convert)
+            .await
+    }
 
-        self.put_to_cache(CacheEntry {
-            key: path.to_string(),
-            value: value.clone(),
-            byte_len,
-            mtime,
-            file_size,
-        })
+    async fn convert_and_cache(
+        &self,
+        path: &str,
+        data: Bytes,
+        mtime: SystemTime,
+        file_size: u64,
+        generation: u64,
+        convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
+    ) -> Result<T, Error<E>> {
+        let (value, weight) = convert(data).map_err(Error::ConvertError)?;
+
+        self.put_to_cache(
+            path,
+            weight,
+            CacheEntry {
+                value: value.clone(),
+                mtime,
+                file_size,
+            },
+            generation,
+        )
         .await;
 
         Ok(value)
@@ -78,6 +141,7 @@ impl<T: Clone + Send + Sync + 'static, E> FsAdaptCache<T, E> {
         path: &str,
         convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
     ) -> Result<T, Error<E>> {
+        let generation = self.generation.load(Ordering::Acquire);
         let cached = self.try_hit_cache(path).await;
 
         let full_path = self.base_path.join(path);
@@ -97,7 +161,23 @@ impl<T: Clone + Send + Sync + 'static, E> FsAdaptCache<T, E> {
                     return Ok(cache_entry.value);
                 }
 
-                self.fetch_and_cache(path, convert).await
+                if let Some(prefetched) = self.take_prefetched(path).await
+                    && prefetched.mtime == mtime
+                    && prefetched.file_size == file_size
+                {
+                    return self
+                        .convert_and_cache(
+                            path,
+                            prefetched.data,
+                            mtime,
+                            file_size,
+                            generation,
+                            convert,
+                        )
+                        .await;
+                }
+
+                self.fetch_and_cache(path, generation, convert).await
             }
             Err(error) => {
                 if error.kind() == std::io::ErrorKind::NotFound {
@@ -108,26 +188,123 @@ impl<T: Clone + Send + Sync + 'static, E> FsAdaptCache<T, E> {
         }
     }
 
-    async fn put_to_cache(&self, new_entry: CacheEntry<T>) {
+    async fn put_impl(&self, path: &str, bytes: Bytes) -> anyhow::Result<()> {
+        let full_path = self.base_path.join(path);
+        let temp_path = temp_path_for(&full_path);
+
+        if let Some(parent) = full_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let write_result = async {
+            let mut file = tokio::fs::File::create(&temp_path).await?;
+            file.write_all(&bytes).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&temp_path, &full_path).await
+        }
+        .await;
+
+        if let Err(error) = write_result {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(error.into());
+        }
+
+        let metadata = tokio::fs::metadata(&full_path).await?;
+
+        self.invalidate_impl(path).await;
+        self.put_to_prefetched(PrefetchedEntry {
+            key: path.to_string(),
+            data: bytes,
+            mtime: metadata.modified()?,
+            file_size: metadata.len(),
+        })
+        .await;
+
+        Ok(())
+    }
+
+    async fn invalidate_impl(&self, path: &str) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.cache.lock().await.remove(path);
+        self.prefetched
+            .lock()
+            .await
+            .retain(|entry| entry.key != path);
+    }
+
+    async fn prefetch_impl(&self, path: &str) -> Result<(), Error<E>> {
+        if self.cache.lock().await.contains(path) {
+            return Ok(());
+        }
+
+        let (data, mtime, file_size) = self.read_from_fs(path).await.map_err(|error| {
+            match error.downcast_ref::<std::io::Error>() {
+                Some(io_error) if io_error.kind() == std::io::ErrorKind::NotFound => {
+                    Error::NotFound
+                }
+                _ => Error::StorageError(error),
+            }
+        })?;
+        self.verify(path, &data).await?;
+
+        self.put_to_prefetched(PrefetchedEntry {
+            key: path.to_string(),
+            data,
+            mtime,
+            file_size,
+        })
+        .await;
+
+        Ok(())
+    }
+
+    async fn put_to_cache(
+        &self,
+        path: &str,
+        weight: usize,
+        new_entry: CacheEntry<T>,
+        generation: u64,
+    ) {
         let mut cache = self.cache.lock().await;
 
-        if let Some(index) = cache.iter().position(|entry| entry.key == new_entry.key) {
-            cache.remove(index).expect("unreachable");
-        };
+        if self.generation.load(Ordering::Acquire) != generation {
+            return;
+        }
+
+        cache.insert(path.to_string(), weight, new_entry);
+    }
+
+    async fn put_to_prefetched(&self, new_entry: PrefetchedEntry) {
+        let mut prefetched = self.prefetched.lock().await;
 
-        cache.push_front(new_entry);
+        prefetched.retain(|entry| entry.key != new_entry.key);
+        prefetched.push_front(new_entry);
 
-        let mut cached_bytes = 0;
-        for (index, entry) in cache.iter().enumerate() {
-            cached_bytes += entry.byte_len;
-            if cached_bytes > self.cache_size {
-                cache.drain(index..);
+        let mut prefetched_bytes = 0;
+        for (index, entry) in prefetched.iter().enumerate() {
+            prefetched_bytes += entry.data.len();
+            if prefetched_bytes > self.cache_size {
+                prefetched.drain(index..);
                 break;
             }
         }
     }
 }
 
+/// Sibling of `path` so the final `rename` stays on the same filesystem and is atomic.
+fn temp_path_for(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{file_name}.{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
 impl<T, E> AdaptCache<T, E> for FsAdaptCache<T, E>
 where
     T: Clone + Send + Sync + 'static,
@@ -148,13 +325,41 @@ where
             .await
             .map_err(|opt_err| opt_err.unwrap_or(Error::SingleflightLeaderFailed))
     }
+
+    async fn put(&self, id: &str, bytes: Bytes) -> Result<(), Error<E>> {
+        // `{id}.sig` has to be put first, then `id` is checked against it like a read
+        if !id.ends_with(SIGNATURE_SUFFIX) {
+            self.verify(id, &bytes).await?;
+        }
+        self.put_impl(id, bytes).await.map_err(Error::StorageError)
+    }
+
+    async fn invalidate(&self, id: &str) {
+        self.invalidate_impl(id).await
+    }
+
+    async fn prefetch(&self, ids: &[&str]) -> Result<(), Error<E>> {
+        let mut result = Ok(());
+        for id in ids {
+            let prefetched = self.prefetch_impl(id).await;
+            if result.is_ok() {
+                result = prefetched;
+            }
+        }
+        result
+    }
 }
 
 #[derive(Clone)]
 struct CacheEntry<T> {
-    key: String,
     value: T,
-    byte_len: usize,
+    mtime: SystemTime,
+    file_size: u64,
+}
+
+struct PrefetchedEntry {
+    key: String,
+    data: Bytes,
     mtime: SystemTime,
     file_size: u64,
 }
@@ -385,4 +590,229 @@ mod tests {
         let val = cache.get("file0.txt", string_converter).await.unwrap();
         assert_eq!(val, "content-0");
     }
+
+    #[tokio::test]
+    async fn test_put_writes_file_and_serves_it() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024);
+
+        cache
+            .put("nested/test.txt", Bytes::from("put-content"))
+            .await
+            .unwrap();
+
+        let on_disk = tokio::fs::read_to_string(temp_dir.path().join("nested/test.txt"))
+            .await
+            .unwrap();
+        assert_eq!(on_disk, "put-content");
+
+        let mut entries = tokio::fs::read_dir(temp_dir.path().join("nested"))
+            .await
+            .unwrap();
+        let mut file_names = vec![];
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            file_names.push(entry.file_name());
+        }
+        assert_eq!(file_names, vec!["test.txt"]);
+
+        let result = cache
+            .get("nested/test.txt", string_converter)
+            .await
+            .unwrap();
+        assert_eq!(result, "put-content");
+    }
+
+    #[tokio::test]
+    async fn test_put_replaces_cached_value() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_name = create_test_file(&temp_dir, "test.txt", "version-1").await;
+
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024);
+
+        let val1 = cache.get(&file_name, string_converter).await.unwrap();
+        cache
+            .put(&file_name, Bytes::from("version-2"))
+            .await
+            .unwrap();
+        let val2 = cache.get(&file_name, string_converter).await.unwrap();
+
+        assert_eq!(val1, "version-1");
+        assert_eq!(val2, "version-2");
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_forces_reconvert() {
+        use std::sync::atomic::AtomicUsize;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_name = create_test_file(&temp_dir, "test.txt", "test-content").await;
+
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024);
+
+        let convert_count = Arc::new(AtomicUsize::new(0));
+        let counting_converter = |count: Arc<AtomicUsize>| {
+            move |bytes: Bytes| {
+                count.fetch_add(1, Ordering::Relaxed);
+                string_converter(bytes)
+            }
+        };
+
+        cache
+            .get(&file_name, counting_converter(convert_count.clone()))
+            .await
+            .unwrap();
+        cache
+            .get(&file_name, counting_converter(convert_count.clone()))
+            .await
+            .unwrap();
+        assert_eq!(convert_count.load(Ordering::Relaxed), 1);
+
+        cache.invalidate(&file_name).await;
+
+        cache
+            .get(&file_name, counting_converter(convert_count.clone()))
+            .await
+            .unwrap();
+        assert_eq!(convert_count.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_then_get() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_name = create_test_file(&temp_dir, "test.txt", "prefetched-content").await;
+
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024);
+
+        cache.prefetch(&[file_name.as_str()]).await.unwrap();
+
+        let result = cache.get(&file_name, string_converter).await.unwrap();
+        assert_eq!(result, "prefetched-content");
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_stale_after_file_change() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_name = create_test_file(&temp_dir, "test.txt", "version-1").await;
+
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024);
+
+        cache.prefetch(&[file_name.as_str()]).await.unwrap();
+        update_file_content(&temp_dir, &file_name, "version-2-longer").await;
+
+        let result = cache.get(&file_name, string_converter).await.unwrap();
+        assert_eq!(result, "version-2-longer");
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_not_found() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024);
+
+        let result = cache.prefetch(&["missing.txt"]).await;
+        assert!(matches!(result, Err(Error::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_content_addressed_id_verified() {
+        use sha2::{Digest, Sha256};
+
+        let temp_dir = TempDir::new().unwrap();
+        let id = hex::encode(Sha256::digest(b"artifact"));
+        create_test_file(&temp_dir, &id, "artifact").await;
+
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024);
+
+        let result = cache.get(&id, string_converter).await.unwrap();
+        assert_eq!(result, "artifact");
+    }
+
+    #[tokio::test]
+    async fn test_content_addressed_id_corrupted() {
+        use sha2::{Digest, Sha256};
+
+        let temp_dir = TempDir::new().unwrap();
+        let id = hex::encode(Sha256::digest(b"artifact"));
+        create_test_file(&temp_dir, &id, "tampered").await;
+
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024);
+
+        let result = cache
+            .get(&id, |_bytes| -> Result<(String, usize), TestError> {
+                panic!("convert must not run on corrupted bytes")
+            })
+            .await;
+        assert!(matches!(result, Err(Error::IntegrityError(_))));
+
+        let result = cache.put(&id, Bytes::from("tampered")).await;
+        assert!(matches!(result, Err(Error::IntegrityError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_signature_required() {
+        use crate::integrity::signed_message;
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let temp_dir = TempDir::new().unwrap();
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        create_test_file(&temp_dir, "signed.txt", "signed-content").await;
+        tokio::fs::write(
+            temp_dir.path().join("signed.txt.sig"),
+            signing_key
+                .sign(&signed_message("signed.txt", b"signed-content"))
+                .to_bytes(),
+        )
+        .await
+        .unwrap();
+        create_test_file(&temp_dir, "unsigned.txt", "unsigned-content").await;
+
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024)
+                .with_integrity(Integrity::new().with_public_key(signing_key.verifying_key()));
+
+        let result = cache.get("signed.txt", string_converter).await.unwrap();
+        assert_eq!(result, "signed-content");
+
+        let result = cache.get("unsigned.txt", string_converter).await;
+        assert!(matches!(result, Err(Error::IntegrityError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_put_requires_signature() {
+        use crate::integrity::signed_message;
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let temp_dir = TempDir::new().unwrap();
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let cache: FsAdaptCache<String, TestError> =
+            FsAdaptCache::new(temp_dir.path().to_path_buf(), 1024)
+                .with_integrity(Integrity::new().with_public_key(signing_key.verifying_key()));
+
+        let result = cache.put("signed.txt", Bytes::from("signed-content")).await;
+        assert!(matches!(result, Err(Error::IntegrityError(_))));
+        assert!(!temp_dir.path().join("signed.txt").exists());
+
+        let signature = signing_key.sign(&signed_message("signed.txt", b"signed-content"));
+        cache
+            .put(
+                "signed.txt.sig",
+                Bytes::copy_from_slice(&signature.to_bytes()),
+            )
+            .await
+            .unwrap();
+        cache
+            .put("signed.txt", Bytes::from("signed-content"))
+            .await
+            .unwrap();
+        let result = cache.get("signed.txt", string_converter).await.unwrap();
+        assert_eq!(result, "signed-content");
+    }
 }
@@ -0,0 +1,418 @@
+use crate::origin::{FetchOutcome, Origin, OriginCache};
+use bytes::Bytes;
+use reqwest::header::{ETAG, HeaderMap, IF_NONE_MATCH};
+use reqwest::{Method, StatusCode, Url};
+use std::sync::Arc;
+
+/// Signs origin requests right before they are sent, e.g. OCI's HTTP signature.
+pub trait SignRequest: Send + Sync + 'static {
+    fn sign(
+        &self,
+        method: &Method,
+        url: &Url,
+        headers: &mut HeaderMap,
+        body: Option<&[u8]>,
+    ) -> anyhow::Result<()>;
+}
+
+/// Fetches `{base_url}/{id}` from any HTTP origin or CDN, revalidating with ETag/If-None-Match.
+pub type HttpAdaptCache<T, E> = OriginCache<HttpOrigin, T, E>;
+
+impl<T: Clone + Send + Sync + 'static, E> HttpAdaptCache<T, E> {
+    pub fn new(client: reqwest::Client, base_url: String, cache_size: usize) -> Self {
+        OriginCache::from_origin(
+            HttpOrigin {
+                client,
+                base_url: base_url.trim_end_matches('/').to_string(),
+                signer: None,
+            },
+            cache_size,
+        )
+    }
+
+    pub fn with_signer(self, signer: impl SignRequest) -> Self {
+        self.map_origin(|origin| HttpOrigin {
+            signer: Some(Arc::new(signer)),
+            ..origin
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpOrigin {
+    client: reqwest::Client,
+    base_url: String,
+    signer: Option<Arc<dyn SignRequest>>,
+}
+
+impl HttpOrigin {
+    fn build_url(&self, id: &str) -> anyhow::Result<Url> {
+        Ok(Url::parse(&format!("{}/{}", self.base_url, id))?)
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        id: &str,
+        mut headers: HeaderMap,
+        body: Option<Bytes>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let url = self.build_url(id)?;
+
+        if let Some(signer) = &self.signer {
+            signer.sign(&method, &url, &mut headers, body.as_deref())?;
+        }
+
+        let mut req = self.client.request(method, url).headers(headers);
+        if let Some(body) = body {
+            req = req.body(body);
+        }
+
+        Ok(req.send().await?)
+    }
+}
+
+fn etag_of(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(|etag| etag.to_string())
+}
+
+impl Origin for HttpOrigin {
+    async fn fetch(&self, id: &str, if_none_match: Option<String>) -> anyhow::Result<FetchOutcome> {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = if_none_match {
+            headers.insert(IF_NONE_MATCH, etag.parse()?);
+        }
+
+        let response = self.send(Method::GET, id, headers, None).await?;
+
+        match response.status() {
+            StatusCode::NOT_MODIFIED => return Ok(FetchOutcome::NotModified),
+            StatusCode::NOT_FOUND => return Ok(FetchOutcome::NotFound),
+            status if !status.is_success() => {
+                anyhow::bail!("origin returned {status} for {id}");
+            }
+            _ => {}
+        }
+
+        let etag = etag_of(&response);
+        let data = response.bytes().await?;
+
+        Ok(FetchOutcome::Modified { data, etag })
+    }
+
+    async fn put(&self, id: &str, bytes: Bytes) -> anyhow::Result<Option<String>> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::CONTENT_TYPE,
+            reqwest::header::HeaderValue::from_static("application/octet-stream"),
+        );
+
+        let response = self.send(Method::PUT, id, headers, Some(bytes)).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("origin returned {status} for PUT {id}");
+        }
+
+        Ok(etag_of(&response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AdaptCache, Error, Freshness};
+    use std::time::Duration;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Debug, Clone)]
+    struct TestError(String);
+
+    impl std::fmt::Display for TestError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl std::error::Error for TestError {}
+
+    fn string_converter(bytes: Bytes) -> Result<(String, usize), TestError> {
+        let len = bytes.len();
+        String::from_utf8(bytes.to_vec())
+            .map(|s| (s, len))
+            .map_err(|e| TestError(e.to_string()))
+    }
+
+    fn create_cache(server: &MockServer) -> HttpAdaptCache<String, TestError> {
+        HttpAdaptCache::new(reqwest::Client::new(), server.uri(), 1024)
+    }
+
+    #[tokio::test]
+    async fn test_cache_miss_fetch_from_origin() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/test.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"etag-123\"")
+                    .set_body_string("test-content"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "test-content");
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_with_304_response() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/test.txt"))
+            .and(header("if-none-match", "\"etag-123\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/test.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"etag-123\"")
+                    .set_body_string("cached-content"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        let val1 = cache.get("test.txt", string_converter).await.unwrap();
+        let val2 = cache.get("test.txt", string_converter).await.unwrap();
+
+        assert_eq!(val1, "cached-content");
+        assert_eq!(val2, "cached-content");
+    }
+
+    #[tokio::test]
+    async fn test_cache_update_on_etag_change() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/test.txt"))
+            .and(header("if-none-match", "\"etag-v1\""))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"etag-v2\"")
+                    .set_body_string("version-2"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/test.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"etag-v1\"")
+                    .set_body_string("version-1"),
+            )
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        let val1 = cache.get("test.txt", string_converter).await.unwrap();
+        let val2 = cache.get("test.txt", string_converter).await.unwrap();
+
+        assert_eq!(val1, "version-1");
+        assert_eq!(val2, "version-2");
+    }
+
+    #[tokio::test]
+    async fn test_not_found_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        let result = cache.get("missing.txt", string_converter).await;
+        assert!(matches!(result, Err(Error::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_service_unavailable_on_cache_hit() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header_exists("if-none-match"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"etag-123\"")
+                    .set_body_string("cached-content"),
+            )
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        cache.get("test.txt", string_converter).await.unwrap();
+
+        let result = cache.get("test.txt", string_converter).await;
+        assert!(matches!(result, Err(Error::StorageError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_service_unavailable_serves_stale() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header_exists("if-none-match"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"etag-123\"")
+                    .set_body_string("cached-content"),
+            )
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server).with_freshness(Freshness {
+            stale_if_error: Duration::from_secs(600),
+            ..Default::default()
+        });
+
+        cache.get("test.txt", string_converter).await.unwrap();
+
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "cached-content");
+    }
+
+    #[tokio::test]
+    async fn test_fresh_hit_without_network() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"etag-123\"")
+                    .set_body_string("cached-content"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server).with_freshness(Freshness {
+            fresh_for: Duration::from_secs(60),
+            ..Default::default()
+        });
+
+        cache.get("test.txt", string_converter).await.unwrap();
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "cached-content");
+    }
+
+    #[tokio::test]
+    async fn test_origin_without_etag() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("no-etag-content"))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        cache.get("test.txt", string_converter).await.unwrap();
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "no-etag-content");
+    }
+
+    #[tokio::test]
+    async fn test_put_then_get() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/test.txt"))
+            .respond_with(ResponseTemplate::new(200).insert_header("etag", "\"etag-put\""))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/test.txt"))
+            .and(header("if-none-match", "\"etag-put\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        cache
+            .put("test.txt", Bytes::from("put-content"))
+            .await
+            .unwrap();
+
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "put-content");
+    }
+
+    #[tokio::test]
+    async fn test_content_addressed_corrupted() {
+        use sha2::{Digest, Sha256};
+
+        let server = MockServer::start().await;
+        let id = hex::encode(Sha256::digest(b"artifact"));
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("tampered"))
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        let result = cache.get(&id, string_converter).await;
+        assert!(matches!(result, Err(Error::IntegrityError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_signer_applied() {
+        struct StaticSigner;
+
+        impl SignRequest for StaticSigner {
+            fn sign(
+                &self,
+                _method: &Method,
+                _url: &Url,
+                headers: &mut HeaderMap,
+                _body: Option<&[u8]>,
+            ) -> anyhow::Result<()> {
+                headers.insert("authorization", "signed".parse()?);
+                Ok(())
+            }
+        }
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("authorization", "signed"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("signed-content"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server).with_signer(StaticSigner);
+
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "signed-content");
+    }
+}
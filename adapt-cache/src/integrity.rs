@@ -0,0 +1,151 @@
+use anyhow::{anyhow, bail};
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// Suffix of the object holding the detached ed25519 signature of `{id}`, over
+/// [`signed_message`].
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// Checks fetched bytes before they reach `convert`.
+///
+/// An id whose last path segment is a 64-char hex string is content-addressed:
+/// its bytes must hash to that sha256 digest. When a public key is configured,
+/// every object must also come with a valid detached signature at `{id}.sig`, so
+/// a signature for one id can't vouch for bytes served under another.
+#[derive(Clone, Default)]
+pub struct Integrity {
+    public_key: Option<VerifyingKey>,
+}
+
+impl Integrity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_public_key(mut self, public_key: VerifyingKey) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+
+    pub(crate) fn requires_signature(&self) -> bool {
+        self.public_key.is_some()
+    }
+
+    pub(crate) fn verify(
+        &self,
+        key: &str,
+        data: &[u8],
+        signature: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        verify_digest(key, data)?;
+
+        let Some(public_key) = &self.public_key else {
+            return Ok(());
+        };
+        let Some(signature) = signature else {
+            bail!("missing signature for {key}");
+        };
+        let signature = Signature::from_slice(signature)
+            .map_err(|error| anyhow!("malformed signature for {key}: {error}"))?;
+        public_key
+            .verify_strict(&signed_message(key, data), &signature)
+            .map_err(|error| anyhow!("invalid signature for {key}: {error}"))
+    }
+}
+
+/// What `{id}.sig` signs: the utf-8 bytes of `id` followed by the 32-byte sha256 of
+/// `data`. `id` is the one passed to [`AdaptCache::get`](crate::AdaptCache::get),
+/// without any bucket prefix.
+pub fn signed_message(id: &str, data: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(id.len() + 32);
+    message.extend_from_slice(id.as_bytes());
+    message.extend_from_slice(&Sha256::digest(data));
+    message
+}
+
+/// Returns the sha256 digest `key` addresses, if it is content-addressed.
+pub fn content_digest(key: &str) -> Option<[u8; 32]> {
+    let id = key.rsplit('/').next()?;
+    if id.len() != 64 {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    hex::decode_to_slice(id, &mut digest).ok()?;
+    Some(digest)
+}
+
+/// Fails if `key` is content-addressed and `data` doesn't hash to it.
+pub fn verify_digest(key: &str, data: &[u8]) -> anyhow::Result<()> {
+    let Some(expected) = content_digest(key) else {
+        return Ok(());
+    };
+    let actual: [u8; 32] = Sha256::digest(data).into();
+    if actual != expected {
+        bail!("sha256 mismatch for {key}: got {}", hex::encode(actual));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn digest_hex(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    #[test]
+    fn test_content_digest_only_for_hex_ids() {
+        assert!(content_digest("test.txt").is_none());
+        assert!(content_digest(&"z".repeat(64)).is_none());
+        assert!(content_digest(&digest_hex(b"abc")).is_some());
+        assert!(content_digest(&format!("prefix/{}", digest_hex(b"abc"))).is_some());
+    }
+
+    #[test]
+    fn test_verify_digest() {
+        let id = digest_hex(b"content");
+        assert!(verify_digest(&id, b"content").is_ok());
+        assert!(verify_digest(&id, b"tampered").is_err());
+        assert!(verify_digest("test.txt", b"anything").is_ok());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let integrity = Integrity::new().with_public_key(signing_key.verifying_key());
+        let signature = signing_key
+            .sign(&signed_message("test.txt", b"content"))
+            .to_bytes();
+
+        assert!(
+            integrity
+                .verify("test.txt", b"content", Some(&signature))
+                .is_ok()
+        );
+        assert!(
+            integrity
+                .verify("other.txt", b"content", Some(&signature))
+                .is_err()
+        );
+        // the payload alone is not what is signed
+        let payload_signature = signing_key.sign(b"content").to_bytes();
+        assert!(
+            integrity
+                .verify("test.txt", b"content", Some(&payload_signature))
+                .is_err()
+        );
+        assert!(
+            integrity
+                .verify("test.txt", b"tampered", Some(&signature))
+                .is_err()
+        );
+        assert!(integrity.verify("test.txt", b"content", None).is_err());
+        assert!(
+            integrity
+                .verify("test.txt", b"content", Some(b"short"))
+                .is_err()
+        );
+    }
+}
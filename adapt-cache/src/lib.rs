@@ -1,7 +1,18 @@
+mod admission;
+mod freshness;
 pub mod fs;
+pub mod http;
+pub mod integrity;
+mod lru;
+pub mod oci;
+pub mod origin;
 pub mod s3;
+mod telemetry;
 
 use bytes::Bytes;
+pub use freshness::Freshness;
+pub use integrity::Integrity;
+pub use lru::{Eviction, TenantOf, WeightedLru};
 
 pub trait AdaptCache<T, E>: Clone + Send + Sync + 'static {
     fn get(
@@ -9,6 +20,19 @@ pub trait AdaptCache<T, E>: Clone + Send + Sync + 'static {
         id: &str,
         convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
     ) -> impl Future<Output = Result<T, Error<E>>> + Send;
+
+    /// Writes `bytes` to the backing storage and drops any converted value cached for `id`.
+    /// The written bytes are kept in memory so the next `get` converts them without a round-trip.
+    /// Bytes are checked like fetched ones, so with a public key configured `{id}.sig` has to be
+    /// put before `id`.
+    fn put(&self, id: &str, bytes: Bytes) -> impl Future<Output = Result<(), Error<E>>> + Send;
+
+    /// Drops everything cached for `id`. The backing storage is untouched.
+    fn invalidate(&self, id: &str) -> impl Future<Output = ()> + Send;
+
+    /// Downloads raw bytes for `ids` ahead of time without converting them,
+    /// so a later `get` only pays for `convert`.
+    fn prefetch(&self, ids: &[&str]) -> impl Future<Output = Result<(), Error<E>>> + Send;
 }
 
 #[derive(Debug)]
@@ -16,5 +40,20 @@ pub enum Error<ConvertError> {
     NotFound,
     StorageError(anyhow::Error),
     ConvertError(ConvertError),
+    /// Fetched bytes don't match their content-addressed id or signature.
+    IntegrityError(anyhow::Error),
     SingleflightLeaderFailed,
 }
+
+impl<ConvertError> Error<ConvertError> {
+    /// Names the variant, for metric labels that must stay low-cardinality.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::NotFound => "not_found",
+            Error::StorageError(_) => "storage",
+            Error::ConvertError(_) => "convert",
+            Error::IntegrityError(_) => "integrity",
+            Error::SingleflightLeaderFailed => "singleflight_leader_failed",
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use crate::admission::TinyLfu;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// How converted values are kept within the cache's memory budget.
+///
+/// Weights are whatever `convert` reports, so converters should return the value's
+/// resident size rather than the length of the raw bytes.
+#[derive(Clone, Default)]
+pub struct Eviction {
+    /// Guard the cache with a TinyLFU admission filter.
+    pub admission: bool,
+    /// Upper bound on the weight a single tenant can hold, so one deployment
+    /// cannot evict everyone else's warm entries.
+    pub per_tenant_budget: Option<usize>,
+    /// Maps an id to its tenant. Ids without a tenant are only bound by the total budget.
+    pub tenant_of: Option<TenantOf>,
+}
+
+/// Maps a cache id to its tenant, see [`Eviction::tenant_of`].
+pub type TenantOf = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Weighted LRU shared by the adapt caches, for [`AdaptCache`](crate::AdaptCache)
+/// implementations outside this crate to keep converted values the same way.
+pub struct WeightedLru<V> {
+    // front is new, back is old
+    entries: VecDeque<LruEntry<V>>,
+    budget: usize,
+    eviction: Eviction,
+    sketch: Option<TinyLfu>,
+}
+
+struct LruEntry<V> {
+    key: String,
+    tenant: Option<String>,
+    weight: usize,
+    value: V,
+}
+
+impl<V: Clone> WeightedLru<V> {
+    pub fn new(budget: usize, eviction: Eviction) -> Self {
+        let sketch = eviction.admission.then(|| TinyLfu::new(1024));
+        Self {
+            entries: Default::default(),
+            budget,
+            eviction,
+            sketch,
+        }
+    }
+
+    /// Returns the value and marks it most recently used.
+    /// Misses count too, so a repeatedly missed key earns admission.
+    pub fn get(&mut self, key: &str) -> Option<V> {
+        if let Some(sketch) = &mut self.sketch {
+            sketch.record(key);
+        }
+
+        let index = self.entries.iter().position(|entry| entry.key == key)?;
+        let entry = self.entries.remove(index).expect("unreachable");
+        let value = entry.value.clone();
+        self.entries.push_front(entry);
+        Some(value)
+    }
+
+    pub(crate) fn peek_mut(&mut self, key: &str) -> Option<&mut V> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.key == key)
+            .map(|entry| &mut entry.value)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.iter().any(|entry| entry.key == key)
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|entry| entry.key != key);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns false if the value was not admitted, in which case an entry already
+    /// cached for `key` stays.
+    pub fn insert(&mut self, key: String, weight: usize, value: V) -> bool {
+        let tenant = self
+            .eviction
+            .tenant_of
+            .as_ref()
+            .and_then(|tenant_of| tenant_of(&key));
+        let tenant_budget = tenant
+            .as_ref()
+            .and(self.eviction.per_tenant_budget)
+            .unwrap_or(usize::MAX);
+
+        if weight > self.budget || weight > tenant_budget {
+            return false;
+        }
+
+        let replaced = self.entries.iter().position(|entry| entry.key == key);
+        let victims = self.victims(tenant.as_deref(), weight, tenant_budget, replaced);
+
+        if let Some(sketch) = &self.sketch
+            && victims
+                .iter()
+                .any(|&index| !sketch.admit(&key, &self.entries[index].key))
+        {
+            return false;
+        }
+
+        let mut dropped = victims;
+        if let Some(replaced) = replaced {
+            let index = dropped.partition_point(|&index| index < replaced);
+            dropped.insert(index, replaced);
+        }
+        if !dropped.is_empty() {
+            // dropped are in ascending order, so one pass drops them all
+            let mut dropped = dropped.into_iter().peekable();
+            let mut index = 0;
+            self.entries.retain(|_| {
+                let is_dropped = dropped.next_if_eq(&index).is_some();
+                index += 1;
+                !is_dropped
+            });
+        }
+
+        self.entries.push_front(LruEntry {
+            key,
+            tenant,
+            weight,
+            value,
+        });
+        true
+    }
+
+    /// Least recently used entries to drop so `weight` fits: first within the tenant, then overall.
+    /// The `replaced` entry is not one, as it goes anyway. Returned in ascending order.
+    fn victims(
+        &self,
+        tenant: Option<&str>,
+        weight: usize,
+        tenant_budget: usize,
+        replaced: Option<usize>,
+    ) -> Vec<usize> {
+        let mut is_victim = vec![false; self.entries.len()];
+        if let Some(replaced) = replaced {
+            is_victim[replaced] = true;
+        }
+        let mut victims = vec![];
+        let mut total_weight: usize = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| Some(*index) != replaced)
+            .map(|(_, entry)| entry.weight)
+            .sum();
+
+        if let Some(tenant) = tenant {
+            let mut tenant_weight: usize = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(index, entry)| {
+                    Some(*index) != replaced && entry.tenant.as_deref() == Some(tenant)
+                })
+                .map(|(_, entry)| entry.weight)
+                .sum();
+            for (index, entry) in self.entries.iter().enumerate().rev() {
+                if tenant_weight + weight <= tenant_budget {
+                    break;
+                }
+                if !is_victim[index] && entry.tenant.as_deref() == Some(tenant) {
+                    tenant_weight -= entry.weight;
+                    total_weight -= entry.weight;
+                    is_victim[index] = true;
+                    victims.push(index);
+                }
+            }
+        }
+
+        for (index, entry) in self.entries.iter().enumerate().rev() {
+            if total_weight + weight <= self.budget {
+                break;
+            }
+            if !is_victim[index] {
+                total_weight -= entry.weight;
+                is_victim[index] = true;
+                victims.push(index);
+            }
+        }
+
+        victims.sort_unstable();
+        victims
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant_by_prefix() -> Eviction {
+        Eviction {
+            admission: false,
+            per_tenant_budget: Some(10),
+            tenant_of: Some(Arc::new(|key: &str| {
+                key.split_once('/').map(|(tenant, _)| tenant.to_string())
+            })),
+        }
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_by_weight() {
+        let mut lru = WeightedLru::new(10, Eviction::default());
+        assert!(lru.insert("a".to_string(), 4, 'a'));
+        assert!(lru.insert("b".to_string(), 4, 'b'));
+        lru.get("a");
+        assert!(lru.insert("c".to_string(), 4, 'c'));
+
+        assert!(lru.contains("a"));
+        assert!(!lru.contains("b"));
+        assert!(lru.contains("c"));
+    }
+
+    #[test]
+    fn test_evicts_several_entries_for_one_insert() {
+        let mut lru = WeightedLru::new(100, tenant_by_prefix());
+        assert!(lru.insert("noisy/a".to_string(), 4, 'a'));
+        assert!(lru.insert("other/a".to_string(), 4, 'b'));
+        assert!(lru.insert("noisy/b".to_string(), 4, 'c'));
+        assert!(lru.insert("noisy/c".to_string(), 9, 'd'));
+
+        assert!(!lru.contains("noisy/a"));
+        assert!(!lru.contains("noisy/b"));
+        assert!(lru.contains("other/a"));
+        assert!(lru.contains("noisy/c"));
+
+        let mut lru = WeightedLru::new(10, Eviction::default());
+        assert!(lru.insert("a".to_string(), 3, 'a'));
+        assert!(lru.insert("b".to_string(), 3, 'b'));
+        assert!(lru.insert("c".to_string(), 3, 'c'));
+        assert!(lru.insert("d".to_string(), 7, 'd'));
+
+        assert!(!lru.contains("a"));
+        assert!(!lru.contains("b"));
+        assert!(lru.contains("c"));
+        assert!(lru.contains("d"));
+    }
+
+    #[test]
+    fn test_rejects_value_heavier_than_budget() {
+        let mut lru = WeightedLru::new(10, Eviction::default());
+        assert!(lru.insert("a".to_string(), 4, 'a'));
+        assert!(!lru.insert("b".to_string(), 11, 'b'));
+
+        assert!(lru.contains("a"));
+        assert!(!lru.contains("b"));
+    }
+
+    #[test]
+    fn test_per_tenant_budget() {
+        let mut lru = WeightedLru::new(100, tenant_by_prefix());
+        assert!(lru.insert("other/a".to_string(), 8, 'a'));
+        assert!(lru.insert("noisy/a".to_string(), 6, 'b'));
+        assert!(lru.insert("noisy/b".to_string(), 6, 'c'));
+
+        assert!(lru.contains("other/a"));
+        assert!(!lru.contains("noisy/a"));
+        assert!(lru.contains("noisy/b"));
+    }
+
+    #[test]
+    fn test_admission_rejects_one_hit_wonder() {
+        let mut lru = WeightedLru::new(
+            10,
+            Eviction {
+                admission: true,
+                ..Default::default()
+            },
+        );
+        lru.get("hot");
+        lru.get("hot");
+        assert!(lru.insert("hot".to_string(), 10, 'h'));
+
+        lru.get("cold");
+        assert!(!lru.insert("cold".to_string(), 10, 'c'));
+        assert!(lru.contains("hot"));
+
+        lru.get("cold");
+        lru.get("cold");
+        assert!(lru.insert("cold".to_string(), 10, 'c'));
+        assert!(!lru.contains("hot"));
+    }
+
+    #[test]
+    fn test_rejected_replacement_keeps_old_entry() {
+        let mut lru = WeightedLru::new(10, tenant_by_prefix());
+        assert!(lru.insert("noisy/a".to_string(), 4, 'a'));
+        assert!(!lru.insert("noisy/a".to_string(), 11, 'b'));
+        assert_eq!(lru.get("noisy/a"), Some('a'));
+
+        let mut lru = WeightedLru::new(
+            10,
+            Eviction {
+                admission: true,
+                ..Default::default()
+            },
+        );
+        lru.get("hot");
+        lru.get("hot");
+        assert!(lru.insert("hot".to_string(), 6, 'h'));
+        assert!(!lru.insert("cold".to_string(), 6, 'c'));
+        assert_eq!(lru.get("hot"), Some('h'));
+    }
+
+    #[test]
+    fn test_replacement_frees_its_old_weight() {
+        let mut lru = WeightedLru::new(10, tenant_by_prefix());
+        assert!(lru.insert("noisy/a".to_string(), 6, 'a'));
+        assert!(lru.insert("other/a".to_string(), 4, 'b'));
+        assert!(lru.insert("noisy/a".to_string(), 6, 'c'));
+
+        assert_eq!(lru.get("noisy/a"), Some('c'));
+        assert!(lru.contains("other/a"));
+    }
+}
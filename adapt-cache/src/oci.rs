@@ -0,0 +1,185 @@
+use crate::http::{HttpAdaptCache, SignRequest};
+use oci_rust_sdk::auth::{AuthProviderRef, RequestSigner};
+use reqwest::header::HeaderMap;
+use reqwest::{Method, Url};
+
+/// Signs Object Storage requests with OCI's HTTP signature scheme.
+pub struct OciSigner {
+    signer: RequestSigner,
+}
+
+impl OciSigner {
+    pub fn new(auth_provider: AuthProviderRef) -> anyhow::Result<Self> {
+        Ok(Self {
+            signer: RequestSigner::new(auth_provider)?,
+        })
+    }
+}
+
+impl SignRequest for OciSigner {
+    fn sign(
+        &self,
+        method: &Method,
+        url: &Url,
+        headers: &mut HeaderMap,
+        body: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        Ok(self
+            .signer
+            .sign_request(method.as_str(), url, headers, body)?)
+    }
+}
+
+/// e.g. `https://objectstorage.ap-seoul-1.oraclecloud.com`
+pub fn object_storage_endpoint(region: &str) -> String {
+    format!("https://objectstorage.{region}.oraclecloud.com")
+}
+
+/// Object Storage is an ETag-speaking HTTP origin at `{endpoint}/n/{namespace}/b/{bucket}/o/{object}`,
+/// so the OCI backend is an [`HttpAdaptCache`] with an [`OciSigner`].
+pub fn object_storage_cache<T: Clone + Send + Sync + 'static, E>(
+    client: reqwest::Client,
+    endpoint: &str,
+    namespace: &str,
+    bucket: &str,
+    auth_provider: AuthProviderRef,
+    cache_size: usize,
+) -> anyhow::Result<HttpAdaptCache<T, E>> {
+    let base_url = format!(
+        "{}/n/{namespace}/b/{bucket}/o",
+        endpoint.trim_end_matches('/')
+    );
+    Ok(HttpAdaptCache::new(client, base_url, cache_size)
+        .with_signer(OciSigner::new(auth_provider)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AdaptCache, Error};
+    use bytes::Bytes;
+    use oci_rust_sdk::auth::SimpleAuthProvider;
+    use std::sync::Arc;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Debug, Clone)]
+    struct TestError(String);
+
+    impl std::fmt::Display for TestError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl std::error::Error for TestError {}
+
+    fn string_converter(bytes: Bytes) -> Result<(String, usize), TestError> {
+        let len = bytes.len();
+        String::from_utf8(bytes.to_vec())
+            .map(|s| (s, len))
+            .map_err(|e| TestError(e.to_string()))
+    }
+
+    fn create_cache(server: &MockServer) -> HttpAdaptCache<String, TestError> {
+        let auth_provider = Arc::new(SimpleAuthProvider::new(
+            "ocid1.tenancy.oc1..test",
+            "ocid1.user.oc1..test",
+            "aa:bb:cc:dd",
+            include_str!("../testdata/oci_test_key.pem"),
+        ));
+        object_storage_cache(
+            reqwest::Client::new(),
+            &server.uri(),
+            "test-namespace",
+            "test-bucket",
+            auth_provider,
+            1024,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_object_signed() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/n/test-namespace/b/test-bucket/o/test.txt"))
+            .and(header_exists("authorization"))
+            .and(header_exists("x-date"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "etag-123")
+                    .set_body_string("test-content"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "test-content");
+    }
+
+    #[tokio::test]
+    async fn test_get_object_revalidates_with_etag() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/n/test-namespace/b/test-bucket/o/test.txt"))
+            .and(header("if-none-match", "etag-123"))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/n/test-namespace/b/test-bucket/o/test.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "etag-123")
+                    .set_body_string("cached-content"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        cache.get("test.txt", string_converter).await.unwrap();
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "cached-content");
+    }
+
+    #[tokio::test]
+    async fn test_put_object_signed() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/n/test-namespace/b/test-bucket/o/test.txt"))
+            .and(header_exists("authorization"))
+            .and(header_exists("x-content-sha256"))
+            .respond_with(ResponseTemplate::new(200).insert_header("etag", "etag-put"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        cache
+            .put("test.txt", Bytes::from("put-content"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_object_not_found() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let cache = create_cache(&server);
+
+        let result = cache.get("missing.txt", string_converter).await;
+        assert!(matches!(result, Err(Error::NotFound)));
+    }
+}
@@ -0,0 +1,615 @@
+//! The revalidating cache behind [`HttpAdaptCache`](crate::http::HttpAdaptCache) and
+//! [`S3AdaptCache`](crate::s3::S3AdaptCache). Backends only differ in how they talk to
+//! their [`Origin`]; caching, freshness, integrity and prefetching live here once.
+
+use super::*;
+use crate::freshness::Staleness;
+use crate::integrity::{SIGNATURE_SUFFIX, content_digest};
+use crate::lru::WeightedLru;
+use async_singleflight::Group;
+use bytes::Bytes;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// An object store that speaks ETags.
+pub trait Origin: Clone + Send + Sync + 'static {
+    /// Gets the object at `id`, or [`FetchOutcome::NotModified`] if its ETag still
+    /// matches `if_none_match`.
+    fn fetch(
+        &self,
+        id: &str,
+        if_none_match: Option<String>,
+    ) -> impl Future<Output = anyhow::Result<FetchOutcome>> + Send;
+
+    /// Writes the object at `id` and returns its new ETag.
+    fn put(
+        &self,
+        id: &str,
+        bytes: Bytes,
+    ) -> impl Future<Output = anyhow::Result<Option<String>>> + Send;
+}
+
+pub enum FetchOutcome {
+    Modified { data: Bytes, etag: Option<String> },
+    NotModified,
+    NotFound,
+}
+
+/// Caches converted values of an [`Origin`]'s objects, revalidating them by ETag.
+pub struct OriginCache<O, T, E> {
+    origin: O,
+    cache: Arc<Mutex<WeightedLru<CacheEntry<T>>>>,
+    // raw bytes from `put`, `prefetch` or background revalidation, not converted yet.
+    // always newer than the converted entry of the same id. front is new, back is old
+    prefetched: Arc<Mutex<VecDeque<PrefetchedEntry>>>,
+    cache_size: usize,
+    integrity: Integrity,
+    freshness: Freshness,
+    // ids with a background revalidation in flight
+    pub(crate) revalidating: Arc<Mutex<HashSet<String>>>,
+    // bumped on `put` and `invalidate` so an in-flight singleflight leader
+    // doesn't cache a value that was superseded while it was fetching.
+    generation: Arc<AtomicU64>,
+    singleflight: Arc<Group<String, T, Error<E>>>,
+}
+
+impl<O: Clone, T, E> Clone for OriginCache<O, T, E> {
+    fn clone(&self) -> Self {
+        Self {
+            origin: self.origin.clone(),
+            cache: self.cache.clone(),
+            prefetched: self.prefetched.clone(),
+            cache_size: self.cache_size,
+            integrity: self.integrity.clone(),
+            freshness: self.freshness,
+            revalidating: self.revalidating.clone(),
+            generation: self.generation.clone(),
+            singleflight: self.singleflight.clone(),
+        }
+    }
+}
+
+impl<O: Origin, T: Clone + Send + Sync + 'static, E> OriginCache<O, T, E> {
+    pub fn from_origin(origin: O, cache_size: usize) -> Self {
+        Self {
+            origin,
+            cache: Arc::new(Mutex::new(WeightedLru::new(cache_size, Default::default()))),
+            prefetched: Default::default(),
+            cache_size,
+            integrity: Default::default(),
+            freshness: Default::default(),
+            revalidating: Default::default(),
+            generation: Default::default(),
+            singleflight: Default::default(),
+        }
+    }
+
+    pub fn with_integrity(mut self, integrity: Integrity) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    pub fn with_freshness(mut self, freshness: Freshness) -> Self {
+        self.freshness = freshness;
+        self
+    }
+
+    pub fn with_eviction(mut self, eviction: Eviction) -> Self {
+        self.cache = Arc::new(Mutex::new(WeightedLru::new(self.cache_size, eviction)));
+        self
+    }
+
+    pub(crate) fn map_origin(mut self, map: impl FnOnce(O) -> O) -> Self {
+        self.origin = map(self.origin);
+        self
+    }
+
+    async fn try_hit_cache(&self, id: &str) -> Option<CacheEntry<T>> {
+        self.cache.lock().await.get(id)
+    }
+
+    async fn take_prefetched(&self, id: &str) -> Option<PrefetchedEntry> {
+        let mut prefetched = self.prefetched.lock().await;
+        let index = prefetched.iter().position(|entry| entry.id == id)?;
+        prefetched.remove(index)
+    }
+
+    async fn mark_validated(&self, id: &str, validated_at: Instant, generation: u64) {
+        let mut cache = self.cache.lock().await;
+
+        if self.generation.load(Ordering::Acquire) != generation {
+            return;
+        }
+
+        if let Some(entry) = cache.peek_mut(id) {
+            entry.validated_at = validated_at;
+        }
+    }
+
+    async fn fetch_from_origin(
+        &self,
+        id: &str,
+        if_none_match: Option<String>,
+    ) -> Result<Fetched, Error<E>> {
+        match self
+            .origin
+            .fetch(id, if_none_match)
+            .await
+            .map_err(Error::StorageError)?
+        {
+            FetchOutcome::Modified { data, etag } => Ok(Fetched::Modified { data, etag }),
+            FetchOutcome::NotModified => Ok(Fetched::NotModified),
+            FetchOutcome::NotFound => Err(Error::NotFound),
+        }
+    }
+
+    async fn verify(&self, id: &str, data: &Bytes) -> Result<(), Error<E>> {
+        let signature = if self.integrity.requires_signature() {
+            match self
+                .fetch_from_origin(&format!("{id}{SIGNATURE_SUFFIX}"), None)
+                .await
+            {
+                Ok(Fetched::Modified { data, .. }) => Some(data),
+                Ok(Fetched::NotModified) | Err(Error::NotFound) => None,
+                Err(error) => return Err(error),
+            }
+        } else {
+            None
+        };
+
+        self.integrity
+            .verify(id, data, signature.as_deref())
+            .map_err(Error::IntegrityError)
+    }
+
+    async fn convert_and_cache(
+        &self,
+        id: &str,
+        raw: PrefetchedEntry,
+        generation: u64,
+        convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
+    ) -> Result<T, Error<E>> {
+        let (value, weight) = convert(raw.data).map_err(Error::ConvertError)?;
+
+        self.put_to_cache(
+            id,
+            weight,
+            CacheEntry {
+                value: value.clone(),
+                etag: raw.etag,
+                validated_at: raw.validated_at,
+            },
+            generation,
+        )
+        .await;
+
+        Ok(value)
+    }
+
+    async fn get_impl(
+        &self,
+        id: &str,
+        convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
+    ) -> Result<T, Error<E>>
+    where
+        E: std::fmt::Debug + Send + Sync + 'static,
+    {
+        let generation = self.generation.load(Ordering::Acquire);
+        let cached = self.try_hit_cache(id).await;
+        let prefetched = self.take_prefetched(id).await;
+
+        // content-addressed objects never change, so there is nothing to revalidate.
+        let is_content_addressed = content_digest(id).is_some();
+
+        if let Some(entry) = prefetched.as_ref()
+            && (is_content_addressed
+                || self.freshness.staleness(entry.validated_at) == Staleness::Fresh)
+        {
+            let entry = prefetched.expect("unreachable");
+            return self.convert_and_cache(id, entry, generation, convert).await;
+        }
+
+        if prefetched.is_none()
+            && let Some(entry) = cached.as_ref()
+        {
+            if is_content_addressed {
+                return Ok(entry.value.clone());
+            }
+            match self.freshness.staleness(entry.validated_at) {
+                Staleness::Fresh => return Ok(entry.value.clone()),
+                Staleness::Stale => {
+                    self.spawn_revalidate(id, entry.etag.clone()).await;
+                    return Ok(entry.value.clone());
+                }
+                Staleness::Expired => {}
+            }
+        }
+
+        let if_none_match_param = match (&prefetched, &cached) {
+            (Some(entry), _) => entry.etag.clone(),
+            (None, Some(entry)) => entry.etag.clone(),
+            (None, None) => None,
+        };
+
+        let validated_at = Instant::now();
+        match self.fetch_from_origin(id, if_none_match_param).await {
+            Ok(Fetched::Modified { data, etag }) => {
+                self.verify(id, &data).await?;
+                let raw = PrefetchedEntry {
+                    id: id.to_string(),
+                    data,
+                    etag,
+                    validated_at,
+                };
+                self.convert_and_cache(id, raw, generation, convert).await
+            }
+            Ok(Fetched::NotModified) => match (prefetched, cached) {
+                (Some(entry), _) => {
+                    let raw = PrefetchedEntry {
+                        validated_at,
+                        ..entry
+                    };
+                    self.convert_and_cache(id, raw, generation, convert).await
+                }
+                (None, Some(entry)) => {
+                    self.mark_validated(id, validated_at, generation).await;
+                    Ok(entry.value)
+                }
+                (None, None) => Err(Error::StorageError(anyhow::anyhow!(
+                    "origin returned 304 without If-None-Match"
+                ))),
+            },
+            Err(Error::StorageError(error)) => match (prefetched, cached) {
+                (Some(entry), _) if self.freshness.can_serve_on_error(entry.validated_at) => {
+                    telemetry::stale_served(id);
+                    self.convert_and_cache(id, entry, generation, convert).await
+                }
+                (None, Some(entry)) if self.freshness.can_serve_on_error(entry.validated_at) => {
+                    telemetry::stale_served(id);
+                    Ok(entry.value)
+                }
+                _ => Err(Error::StorageError(error)),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Revalidates `id` off the request path. A newer object is downloaded and
+    /// staged as prefetched bytes so the next `get` converts it without a round-trip.
+    /// The result is dropped if `invalidate` or `put` superseded it in the meantime.
+    async fn spawn_revalidate(&self, id: &str, etag: Option<String>)
+    where
+        E: std::fmt::Debug + Send + Sync + 'static,
+    {
+        if !self.revalidating.lock().await.insert(id.to_string()) {
+            return;
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let provider = self.clone();
+        let id = id.to_string();
+        tokio::spawn(async move {
+            let validated_at = Instant::now();
+            match provider.fetch_from_origin(&id, etag).await {
+                Ok(Fetched::NotModified) => {
+                    provider.mark_validated(&id, validated_at, generation).await;
+                    telemetry::background_revalidation(&id, "not_modified");
+                }
+                Ok(Fetched::Modified { data, etag }) => match provider.verify(&id, &data).await {
+                    Ok(()) => {
+                        provider
+                            .put_to_prefetched(
+                                PrefetchedEntry {
+                                    id: id.clone(),
+                                    data,
+                                    etag,
+                                    validated_at,
+                                },
+                                generation,
+                            )
+                            .await;
+                        telemetry::background_revalidation(&id, "modified");
+                    }
+                    Err(error) => {
+                        telemetry::revalidation_error(&id, error.kind());
+                    }
+                },
+                Err(Error::NotFound) => {
+                    if provider.generation.load(Ordering::Acquire) == generation {
+                        provider.invalidate_impl(&id).await;
+                    }
+                    telemetry::background_revalidation(&id, "not_found");
+                }
+                Err(error) => {
+                    telemetry::revalidation_error(&id, error.kind());
+                }
+            }
+            provider.revalidating.lock().await.remove(&id);
+        });
+    }
+
+    async fn put_impl(&self, id: &str, bytes: Bytes) -> Result<(), Error<E>> {
+        // `{id}.sig` has to be put first, then `id` is checked against it like a fetch
+        if !id.ends_with(SIGNATURE_SUFFIX) {
+            self.verify(id, &bytes).await?;
+        }
+
+        let etag = self
+            .origin
+            .put(id, bytes.clone())
+            .await
+            .map_err(Error::StorageError)?;
+
+        self.invalidate_impl(id).await;
+        let generation = self.generation.load(Ordering::Acquire);
+        self.put_to_prefetched(
+            PrefetchedEntry {
+                id: id.to_string(),
+                data: bytes,
+                etag,
+                validated_at: Instant::now(),
+            },
+            generation,
+        )
+        .await;
+
+        Ok(())
+    }
+
+    async fn invalidate_impl(&self, id: &str) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.cache.lock().await.remove(id);
+        self.prefetched.lock().await.retain(|entry| entry.id != id);
+    }
+
+    async fn prefetch_impl(&self, id: &str) -> Result<(), Error<E>> {
+        if self.cache.lock().await.contains(id) {
+            return Ok(());
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let validated_at = Instant::now();
+        let Fetched::Modified { data, etag } = self.fetch_from_origin(id, None).await? else {
+            return Err(Error::StorageError(anyhow::anyhow!(
+                "origin returned 304 without If-None-Match"
+            )));
+        };
+        self.verify(id, &data).await?;
+
+        self.put_to_prefetched(
+            PrefetchedEntry {
+                id: id.to_string(),
+                data,
+                etag,
+                validated_at,
+            },
+            generation,
+        )
+        .await;
+
+        Ok(())
+    }
+
+    async fn put_to_cache(
+        &self,
+        id: &str,
+        weight: usize,
+        new_entry: CacheEntry<T>,
+        generation: u64,
+    ) {
+        let mut cache = self.cache.lock().await;
+
+        if self.generation.load(Ordering::Acquire) != generation {
+            return;
+        }
+
+        cache.insert(id.to_string(), weight, new_entry);
+    }
+
+    async fn put_to_prefetched(&self, new_entry: PrefetchedEntry, generation: u64) {
+        let mut prefetched = self.prefetched.lock().await;
+
+        if self.generation.load(Ordering::Acquire) != generation {
+            return;
+        }
+
+        prefetched.retain(|entry| entry.id != new_entry.id);
+        prefetched.push_front(new_entry);
+
+        let mut prefetched_bytes = 0;
+        for (index, entry) in prefetched.iter().enumerate() {
+            prefetched_bytes += entry.data.len();
+            if prefetched_bytes > self.cache_size {
+                prefetched.drain(index..);
+                break;
+            }
+        }
+    }
+}
+
+impl<O, T, E> AdaptCache<T, E> for OriginCache<O, T, E>
+where
+    O: Origin,
+    T: Clone + Send + Sync + 'static,
+    E: std::error::Error + Send + Sync + 'static + Clone,
+{
+    async fn get(
+        &self,
+        id: &str,
+        convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
+    ) -> Result<T, Error<E>> {
+        let id = id.to_string();
+
+        let provider = self.clone();
+        self.singleflight
+            .work(
+                &id.clone(),
+                async move { provider.get_impl(&id, convert).await },
+            )
+            .await
+            .map_err(|opt_err| opt_err.unwrap_or(Error::SingleflightLeaderFailed))
+    }
+
+    async fn put(&self, id: &str, bytes: Bytes) -> Result<(), Error<E>> {
+        self.put_impl(id, bytes).await
+    }
+
+    async fn invalidate(&self, id: &str) {
+        self.invalidate_impl(id).await
+    }
+
+    async fn prefetch(&self, ids: &[&str]) -> Result<(), Error<E>> {
+        let mut join_set = tokio::task::JoinSet::new();
+        for id in ids {
+            let id = id.to_string();
+            let provider = self.clone();
+            join_set.spawn(async move { provider.prefetch_impl(&id).await });
+        }
+
+        let mut result = Ok(());
+        while let Some(joined) = join_set.join_next().await {
+            let prefetched = joined
+                .map_err(|error| Error::StorageError(error.into()))
+                .and_then(|prefetched| prefetched);
+            if result.is_ok() {
+                result = prefetched;
+            }
+        }
+        result
+    }
+}
+
+/// A [`FetchOutcome`] with `NotFound` turned into [`Error::NotFound`].
+enum Fetched {
+    Modified { data: Bytes, etag: Option<String> },
+    NotModified,
+}
+
+#[derive(Clone)]
+struct CacheEntry<T> {
+    value: T,
+    etag: Option<String>,
+    validated_at: Instant,
+}
+
+struct PrefetchedEntry {
+    id: String,
+    data: Bytes,
+    etag: Option<String>,
+    validated_at: Instant,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+
+    /// Objects in memory. Each fetch waits for a permit, so a test decides when
+    /// it completes.
+    #[derive(Clone)]
+    struct GatedOrigin {
+        objects: Arc<std::sync::Mutex<HashMap<String, (Bytes, String)>>>,
+        permits: Arc<Semaphore>,
+        fetches: Arc<AtomicU64>,
+    }
+
+    impl Origin for GatedOrigin {
+        async fn fetch(
+            &self,
+            id: &str,
+            if_none_match: Option<String>,
+        ) -> anyhow::Result<FetchOutcome> {
+            self.fetches.fetch_add(1, Ordering::AcqRel);
+            self.permits.acquire().await?.forget();
+            let object = self.objects.lock().unwrap().get(id).cloned();
+            Ok(match object {
+                None => FetchOutcome::NotFound,
+                Some((_, etag)) if if_none_match.as_ref() == Some(&etag) => {
+                    FetchOutcome::NotModified
+                }
+                Some((data, etag)) => FetchOutcome::Modified {
+                    data,
+                    etag: Some(etag),
+                },
+            })
+        }
+
+        async fn put(&self, id: &str, bytes: Bytes) -> anyhow::Result<Option<String>> {
+            let etag = format!("etag-{}", bytes.len());
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(id.to_string(), (bytes, etag.clone()));
+            Ok(Some(etag))
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestError;
+
+    impl std::fmt::Display for TestError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "test error")
+        }
+    }
+
+    impl std::error::Error for TestError {}
+
+    fn string_converter(bytes: Bytes) -> Result<(String, usize), TestError> {
+        let len = bytes.len();
+        Ok((String::from_utf8_lossy(&bytes).into_owned(), len))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_invalidate_drops_in_flight_revalidation() {
+        let origin = GatedOrigin {
+            objects: Default::default(),
+            permits: Arc::new(Semaphore::new(1)),
+            fetches: Default::default(),
+        };
+        origin.objects.lock().unwrap().insert(
+            "test.txt".to_string(),
+            (Bytes::from("version-1"), "etag-v1".to_string()),
+        );
+        let cache: OriginCache<_, String, TestError> =
+            OriginCache::from_origin(origin.clone(), 1024).with_freshness(Freshness {
+                fresh_for: Duration::from_secs(1),
+                stale_while_revalidate: Duration::from_secs(60),
+                ..Default::default()
+            });
+        cache.get("test.txt", string_converter).await.unwrap();
+
+        // a stale hit starts a revalidation, held at the origin
+        tokio::time::advance(Duration::from_secs(2)).await;
+        origin.objects.lock().unwrap().insert(
+            "test.txt".to_string(),
+            (Bytes::from("version-2"), "etag-v2".to_string()),
+        );
+        cache.get("test.txt", string_converter).await.unwrap();
+        cache.invalidate("test.txt").await;
+        origin.permits.add_permits(1);
+        while cache.revalidating.lock().await.contains("test.txt") {
+            tokio::task::yield_now().await;
+        }
+
+        // the invalidated key goes back to the origin
+        origin.permits.add_permits(1);
+        let fetches = origin.fetches.load(Ordering::Acquire);
+        assert_eq!(
+            cache.get("test.txt", string_converter).await.unwrap(),
+            "version-2"
+        );
+        assert_eq!(origin.fetches.load(Ordering::Acquire), fetches + 1);
+    }
+
+    #[test]
+    fn test_error_kind_is_bounded() {
+        let error: Error<TestError> = Error::StorageError(anyhow::anyhow!("timeout after 3s"));
+        assert_eq!(error.kind(), "storage");
+    }
+}
@@ -1,184 +1,103 @@
-use super::*;
-use async_singleflight::Group;
-use aws_sdk_s3::{Client, operation::get_object::GetObjectError};
+use crate::origin::{FetchOutcome, Origin, OriginCache};
+use aws_sdk_s3::{Client, operation::get_object::GetObjectError, primitives::ByteStream};
+use base64::Engine;
 use bytes::Bytes;
-use std::collections::VecDeque;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use sha2::{Digest, Sha256};
 
-#[derive(Clone)]
-pub struct S3AdaptCache<T, E> {
-    client: Client,
-    bucket: String,
-    prefix: Option<String>,
-    // front is new, back is old
-    cache: Arc<Mutex<VecDeque<CacheEntry<T>>>>,
-    cache_size: usize,
-    singleflight: Arc<Group<String, T, Error<E>>>,
-}
+pub type S3AdaptCache<T, E> = OriginCache<S3Origin, T, E>;
 
 impl<T: Clone + Send + Sync + 'static, E> S3AdaptCache<T, E> {
     pub fn new(client: Client, bucket: String, prefix: Option<String>, cache_size: usize) -> Self {
-        Self {
-            client,
-            bucket,
-            prefix,
-            cache: Default::default(),
+        OriginCache::from_origin(
+            S3Origin {
+                client,
+                bucket,
+                prefix,
+            },
             cache_size,
-            singleflight: Default::default(),
-        }
+        )
     }
+}
+
+#[derive(Clone)]
+pub struct S3Origin {
+    client: Client,
+    bucket: String,
+    prefix: Option<String>,
+}
 
+impl S3Origin {
     fn build_key(&self, id: &str) -> String {
         match &self.prefix {
             Some(prefix) => format!("{}/{}", prefix, id),
             None => id.to_string(),
         }
     }
+}
 
-    async fn try_hit_cache(&self, key: &str) -> Option<CacheEntry<T>> {
-        let mut cache = self.cache.lock().await;
-        let index = cache.iter().position(|entry| entry.key == key)?;
-        let entry = cache.remove(index).expect("unreachable");
-        cache.push_front(entry.clone());
-        Some(entry)
-    }
-
-    async fn fetch_from_s3(
-        &self,
-        key: &str,
-        if_none_match: Option<String>,
-    ) -> anyhow::Result<(Bytes, String)> {
-        let mut req = self.client.get_object().bucket(&self.bucket).key(key);
+impl Origin for S3Origin {
+    async fn fetch(&self, id: &str, if_none_match: Option<String>) -> anyhow::Result<FetchOutcome> {
+        let mut req = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.build_key(id));
 
         if let Some(etag) = if_none_match {
             req = req.if_none_match(etag);
         }
 
-        let output = req.send().await?;
-
-        let data = output.body.collect().await?.into_bytes();
-
-        let etag = output.e_tag.expect("S3 should return e_tag");
-
-        Ok((data, etag))
-    }
-
-    async fn fetch_and_cache(
-        &self,
-        key: &str,
-        if_none_match: Option<String>,
-        convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
-    ) -> Result<T, Error<E>> {
-        let (data, etag) = self
-            .fetch_from_s3(key, if_none_match)
-            .await
-            .map_err(Error::StorageError)?;
-        let (value, byte_len) = convert(data).map_err(Error::ConvertError)?;
-
-        self.put_to_cache(CacheEntry {
-            key: key.to_string(),
-            value: value.clone(),
-            byte_len,
-            etag,
-        })
-        .await;
-
-        Ok(value)
-    }
-
-    async fn get_impl(
-        &self,
-        key: &str,
-        convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
-    ) -> Result<T, Error<E>> {
-        let cached = self.try_hit_cache(key).await;
-
-        let if_none_match_param = cached.as_ref().map(|entry| entry.etag.clone());
-
-        let result = self
-            .fetch_and_cache(key, if_none_match_param, convert)
-            .await;
-        if let Ok(value) = result {
-            return Ok(value);
-        }
-
-        let error = result.err().unwrap();
-        match error {
-            Error::StorageError(error) => {
-                match &error.downcast_ref::<aws_sdk_s3::error::SdkError<aws_sdk_s3::operation::get_object::GetObjectError>>() {
-                Some(aws_sdk_s3::error::SdkError::ServiceError(service_err)) => {
+        let output = match req.send().await {
+            Ok(output) => output,
+            Err(error) => {
+                if let aws_sdk_s3::error::SdkError::ServiceError(service_err) = &error {
                     if service_err.raw().status().as_u16() == 304 {
-                        return Ok(cached.unwrap().value);
+                        return Ok(FetchOutcome::NotModified);
                     }
-                    match service_err.err() {
-                        GetObjectError::NoSuchKey(_) => Err(Error::NotFound),
-                        _ => Err(Error::StorageError(error)),
+                    if let GetObjectError::NoSuchKey(_) = service_err.err() {
+                        return Ok(FetchOutcome::NotFound);
                     }
                 }
-                _ => Err(Error::StorageError(error)),
+                return Err(error.into());
             }
-            }
-            _ => Err(error),
-        }
-    }
-
-    async fn put_to_cache(&self, new_entry: CacheEntry<T>) {
-        let mut cache = self.cache.lock().await;
-
-        if let Some(index) = cache.iter().position(|entry| entry.key == new_entry.key) {
-            cache.remove(index).expect("unreachable");
         };
 
-        cache.push_front(new_entry);
+        let data = output.body.collect().await?.into_bytes();
 
-        let mut cached_bytes = 0;
-        for (index, entry) in cache.iter().enumerate() {
-            cached_bytes += entry.byte_len;
-            if cached_bytes > self.cache_size {
-                cache.drain(index..);
-                break;
-            }
-        }
-    }
-}
+        let etag = output.e_tag.expect("S3 should return e_tag");
 
-impl<T, E> AdaptCache<T, E> for S3AdaptCache<T, E>
-where
-    T: Clone + Send + Sync + 'static,
-    E: std::error::Error + Send + Sync + 'static + Clone,
-{
-    async fn get(
-        &self,
-        id: &str,
-        convert: impl FnOnce(Bytes) -> std::result::Result<(T, usize), E> + Send,
-    ) -> Result<T, Error<E>> {
-        let key = self.build_key(id);
-
-        let provider = self.clone();
-        self.singleflight
-            .work(&key.clone(), async move {
-                provider.get_impl(&key, convert).await
-            })
-            .await
-            .map_err(|opt_err| opt_err.unwrap_or(Error::SingleflightLeaderFailed))
+        Ok(FetchOutcome::Modified {
+            data,
+            etag: Some(etag),
+        })
     }
-}
 
-#[derive(Clone)]
-struct CacheEntry<T> {
-    key: String,
-    value: T,
-    byte_len: usize,
-    etag: String,
+    async fn put(&self, id: &str, bytes: Bytes) -> anyhow::Result<Option<String>> {
+        let checksum = base64::engine::general_purpose::STANDARD.encode(Sha256::digest(&bytes));
+
+        let output = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.build_key(id))
+            .checksum_sha256(checksum)
+            .body(ByteStream::from(bytes))
+            .send()
+            .await?;
+
+        Ok(output.e_tag)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{AdaptCache, Error, Freshness, Integrity};
     use aws_sdk_s3::operation::get_object::{GetObjectError, GetObjectOutput};
     use aws_sdk_s3::primitives::ByteStream;
     use aws_smithy_mocks::{mock, mock_client};
+    use std::sync::Arc;
+    use std::time::Duration;
 
     #[derive(Debug, Clone)]
     struct TestError(String);
@@ -777,4 +696,388 @@ mod tests {
         let result = cache.get("test.txt", string_converter).await;
         assert!(matches!(result, Err(Error::StorageError(_))));
     }
+
+    #[tokio::test]
+    async fn test_put_then_get_without_download() {
+        use aws_sdk_s3::operation::put_object::PutObjectOutput;
+        use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+        use aws_smithy_runtime_api::http::StatusCode;
+        use aws_smithy_types::body::SdkBody;
+
+        let put_rule = mock!(Client::put_object)
+            .match_requests(|req| req.checksum_sha256().is_some())
+            .then_output(|| PutObjectOutput::builder().e_tag("etag-put").build());
+
+        let get_rule = mock!(Client::get_object)
+            .match_requests(|req| req.if_none_match() == Some("etag-put"))
+            .then_http_response(|| {
+                HttpResponse::new(StatusCode::try_from(304).unwrap(), SdkBody::empty())
+            });
+
+        let client = mock_client!(aws_sdk_s3, [&put_rule, &get_rule]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024);
+
+        cache
+            .put("test.txt", Bytes::from("put-content"))
+            .await
+            .unwrap();
+
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "put-content");
+    }
+
+    #[tokio::test]
+    async fn test_put_replaces_cached_value() {
+        use aws_sdk_s3::operation::put_object::PutObjectOutput;
+        use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+        use aws_smithy_runtime_api::http::StatusCode;
+        use aws_smithy_types::body::SdkBody;
+
+        let data = create_test_string_data("version-1");
+        let get_rule1 = mock!(Client::get_object).then_output(move || {
+            GetObjectOutput::builder()
+                .body(ByteStream::from(data.clone()))
+                .e_tag("etag-v1")
+                .build()
+        });
+
+        let put_rule = mock!(Client::put_object)
+            .then_output(|| PutObjectOutput::builder().e_tag("etag-v2").build());
+
+        let get_rule2 = mock!(Client::get_object)
+            .match_requests(|req| req.if_none_match() == Some("etag-v2"))
+            .then_http_response(|| {
+                HttpResponse::new(StatusCode::try_from(304).unwrap(), SdkBody::empty())
+            });
+
+        let client = mock_client!(aws_sdk_s3, [&get_rule1, &put_rule, &get_rule2]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024);
+
+        let val1 = cache.get("test.txt", string_converter).await.unwrap();
+        cache
+            .put("test.txt", Bytes::from("version-2"))
+            .await
+            .unwrap();
+        let val2 = cache.get("test.txt", string_converter).await.unwrap();
+
+        assert_eq!(val1, "version-1");
+        assert_eq!(val2, "version-2");
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_drops_cached_value() {
+        let data1 = create_test_string_data("version-1");
+        let rule1 = mock!(Client::get_object).then_output(move || {
+            GetObjectOutput::builder()
+                .body(ByteStream::from(data1.clone()))
+                .e_tag("etag-v1")
+                .build()
+        });
+
+        let data2 = create_test_string_data("version-2");
+        let rule2 = mock!(Client::get_object)
+            .match_requests(|req| req.if_none_match().is_none())
+            .then_output(move || {
+                GetObjectOutput::builder()
+                    .body(ByteStream::from(data2.clone()))
+                    .e_tag("etag-v2")
+                    .build()
+            });
+
+        let client = mock_client!(aws_sdk_s3, [&rule1, &rule2]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024);
+
+        let val1 = cache.get("test.txt", string_converter).await.unwrap();
+        cache.invalidate("test.txt").await;
+        let val2 = cache.get("test.txt", string_converter).await.unwrap();
+
+        assert_eq!(val1, "version-1");
+        assert_eq!(val2, "version-2");
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_then_get_without_download() {
+        use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+        use aws_smithy_runtime_api::http::StatusCode;
+        use aws_smithy_types::body::SdkBody;
+
+        let data = create_test_string_data("prefetched-content");
+        let rule1 = mock!(Client::get_object)
+            .match_requests(|req| req.if_none_match().is_none())
+            .then_output(move || {
+                GetObjectOutput::builder()
+                    .body(ByteStream::from(data.clone()))
+                    .e_tag("etag-123")
+                    .build()
+            });
+
+        let rule2 = mock!(Client::get_object)
+            .match_requests(|req| req.if_none_match() == Some("etag-123"))
+            .then_http_response(|| {
+                HttpResponse::new(StatusCode::try_from(304).unwrap(), SdkBody::empty())
+            });
+
+        let client = mock_client!(aws_sdk_s3, [&rule1, &rule2]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024);
+
+        cache.prefetch(&["test.txt"]).await.unwrap();
+
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "prefetched-content");
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_not_found() {
+        let rule = mock!(Client::get_object).then_error(|| {
+            GetObjectError::NoSuchKey(aws_sdk_s3::types::error::NoSuchKey::builder().build())
+        });
+
+        let client = mock_client!(aws_sdk_s3, [&rule]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024);
+
+        let result = cache.prefetch(&["missing.txt"]).await;
+        assert!(matches!(result, Err(Error::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_content_addressed_hit_skips_revalidation() {
+        use sha2::{Digest, Sha256};
+
+        let id = hex::encode(Sha256::digest(b"artifact"));
+        let rule = mock!(Client::get_object).then_output(|| {
+            GetObjectOutput::builder()
+                .body(ByteStream::from_static(b"artifact"))
+                .e_tag("etag-123")
+                .build()
+        });
+
+        let client = mock_client!(aws_sdk_s3, [&rule]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024);
+
+        let val1 = cache.get(&id, string_converter).await.unwrap();
+        let val2 = cache.get(&id, string_converter).await.unwrap();
+
+        assert_eq!(val1, "artifact");
+        assert_eq!(val2, "artifact");
+        assert_eq!(rule.num_calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_content_addressed_corrupted() {
+        use sha2::{Digest, Sha256};
+
+        let id = hex::encode(Sha256::digest(b"artifact"));
+        let rule = mock!(Client::get_object).then_output(|| {
+            GetObjectOutput::builder()
+                .body(ByteStream::from_static(b"tampered"))
+                .e_tag("etag-123")
+                .build()
+        });
+
+        let client = mock_client!(aws_sdk_s3, [&rule]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024);
+
+        let result = cache
+            .get(&id, |_bytes| -> Result<(String, usize), TestError> {
+                panic!("convert must not run on corrupted bytes")
+            })
+            .await;
+        assert!(matches!(result, Err(Error::IntegrityError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_signature_verified() {
+        use crate::integrity::signed_message;
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let signature = signing_key
+            .sign(&signed_message("test.txt", b"signed-content"))
+            .to_bytes()
+            .to_vec();
+
+        let object_rule = mock!(Client::get_object)
+            .match_requests(|req| req.key() == Some("test.txt"))
+            .then_output(|| {
+                GetObjectOutput::builder()
+                    .body(ByteStream::from_static(b"signed-content"))
+                    .e_tag("etag-123")
+                    .build()
+            });
+        let signature_rule = mock!(Client::get_object)
+            .match_requests(|req| req.key() == Some("test.txt.sig"))
+            .then_output(move || {
+                GetObjectOutput::builder()
+                    .body(ByteStream::from(signature.clone()))
+                    .e_tag("etag-sig")
+                    .build()
+            });
+
+        let client = mock_client!(aws_sdk_s3, [&object_rule, &signature_rule]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024)
+                .with_integrity(Integrity::new().with_public_key(signing_key.verifying_key()));
+
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "signed-content");
+    }
+
+    #[tokio::test]
+    async fn test_signature_missing() {
+        use ed25519_dalek::SigningKey;
+
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+
+        let object_rule = mock!(Client::get_object)
+            .match_requests(|req| req.key() == Some("test.txt"))
+            .then_output(|| {
+                GetObjectOutput::builder()
+                    .body(ByteStream::from_static(b"unsigned-content"))
+                    .e_tag("etag-123")
+                    .build()
+            });
+        let signature_rule = mock!(Client::get_object)
+            .match_requests(|req| req.key() == Some("test.txt.sig"))
+            .then_error(|| {
+                GetObjectError::NoSuchKey(aws_sdk_s3::types::error::NoSuchKey::builder().build())
+            });
+
+        let client = mock_client!(aws_sdk_s3, [&object_rule, &signature_rule]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024)
+                .with_integrity(Integrity::new().with_public_key(signing_key.verifying_key()));
+
+        let result = cache.get("test.txt", string_converter).await;
+        assert!(matches!(result, Err(Error::IntegrityError(_))));
+    }
+
+    fn freshness() -> Freshness {
+        Freshness {
+            fresh_for: Duration::from_secs(10),
+            stale_while_revalidate: Duration::from_secs(60),
+            stale_if_error: Duration::from_secs(600),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fresh_hit_without_network() {
+        let rule = mock!(Client::get_object).then_output(|| {
+            GetObjectOutput::builder()
+                .body(ByteStream::from_static(b"cached-content"))
+                .e_tag("etag-123")
+                .build()
+        });
+
+        let client = mock_client!(aws_sdk_s3, [&rule]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024)
+                .with_freshness(freshness());
+
+        cache.get("test.txt", string_converter).await.unwrap();
+        tokio::time::advance(Duration::from_secs(5)).await;
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+
+        assert_eq!(result, "cached-content");
+        assert_eq!(rule.num_calls(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stale_hit_revalidates_in_background() {
+        let rule1 = mock!(Client::get_object).then_output(|| {
+            GetObjectOutput::builder()
+                .body(ByteStream::from_static(b"version-1"))
+                .e_tag("etag-v1")
+                .build()
+        });
+        let rule2 = mock!(Client::get_object)
+            .match_requests(|req| req.if_none_match() == Some("etag-v1"))
+            .then_output(|| {
+                GetObjectOutput::builder()
+                    .body(ByteStream::from_static(b"version-2"))
+                    .e_tag("etag-v2")
+                    .build()
+            });
+
+        let client = mock_client!(aws_sdk_s3, [&rule1, &rule2]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024)
+                .with_freshness(freshness());
+
+        cache.get("test.txt", string_converter).await.unwrap();
+        tokio::time::advance(Duration::from_secs(20)).await;
+
+        let stale = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(stale, "version-1");
+
+        while cache.revalidating.lock().await.contains("test.txt") || rule2.num_calls() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let revalidated = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(revalidated, "version-2");
+        assert_eq!(rule1.num_calls() + rule2.num_calls(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_service_unavailable_serves_stale() {
+        use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+        use aws_smithy_runtime_api::http::StatusCode;
+        use aws_smithy_types::body::SdkBody;
+
+        let rule1 = mock!(Client::get_object).then_output(|| {
+            GetObjectOutput::builder()
+                .body(ByteStream::from_static(b"cached-content"))
+                .e_tag("etag-123")
+                .build()
+        });
+        let rule2 = mock!(Client::get_object).then_http_response(|| {
+            HttpResponse::new(StatusCode::try_from(503).unwrap(), SdkBody::empty())
+        });
+
+        let client = mock_client!(aws_sdk_s3, [&rule1, &rule2]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024)
+                .with_freshness(freshness());
+
+        cache.get("test.txt", string_converter).await.unwrap();
+        tokio::time::advance(Duration::from_secs(120)).await;
+
+        let result = cache.get("test.txt", string_converter).await.unwrap();
+        assert_eq!(result, "cached-content");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_service_unavailable_after_stale_if_error() {
+        use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+        use aws_smithy_runtime_api::http::StatusCode;
+        use aws_smithy_types::body::SdkBody;
+
+        let rule1 = mock!(Client::get_object).then_output(|| {
+            GetObjectOutput::builder()
+                .body(ByteStream::from_static(b"cached-content"))
+                .e_tag("etag-123")
+                .build()
+        });
+        let rule2 = mock!(Client::get_object).then_http_response(|| {
+            HttpResponse::new(StatusCode::try_from(503).unwrap(), SdkBody::empty())
+        });
+
+        let client = mock_client!(aws_sdk_s3, [&rule1, &rule2]);
+        let cache: S3AdaptCache<String, TestError> =
+            S3AdaptCache::new(client, "test-bucket".to_string(), None, 1024)
+                .with_freshness(freshness());
+
+        cache.get("test.txt", string_converter).await.unwrap();
+        tokio::time::advance(Duration::from_secs(1200)).await;
+
+        let result = cache.get("test.txt", string_converter).await;
+        assert!(matches!(result, Err(Error::StorageError(_))));
+    }
 }
@@ -0,0 +1,30 @@
+use opentelemetry::{KeyValue, global};
+
+// Keys are unbounded, so they go to tracing and never become metric labels.
+
+/// Stale entries are only served on storage errors, so the error itself is not a label.
+pub fn stale_served(key: &str) {
+    tracing::warn!(key, "serving stale entry after a storage error");
+    let counter = global::meter("adapt-cache")
+        .u64_counter("stale_served")
+        .build();
+    counter.add(1, &[]);
+}
+
+pub fn background_revalidation(key: &str, outcome: &'static str) {
+    tracing::debug!(key, outcome, "background revalidation finished");
+    let counter = global::meter("adapt-cache")
+        .u64_counter("background_revalidation")
+        .build();
+    counter.add(1, &[KeyValue::new("outcome", outcome)]);
+}
+
+/// `kind` is one of [`Error::kind`](crate::Error::kind), never the message, to keep
+/// the label's cardinality bounded.
+pub fn revalidation_error(key: &str, kind: &'static str) {
+    tracing::warn!(key, error = kind, "background revalidation failed");
+    let counter = global::meter("adapt-cache")
+        .u64_counter("revalidation_error")
+        .build();
+    counter.add(1, &[KeyValue::new("error", kind)]);
+}
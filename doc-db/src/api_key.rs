@@ -0,0 +1,285 @@
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::aead::rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use super::*;
+
+const API_KEY_PREFIX: &str = "fn0_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+    Deploy,
+    ReadMetrics,
+    /// Calls the tenant's codes, for keys sent by clients rather than by deploy tools.
+    Invoke,
+    /// Implies every other scope, and manages the api keys of its tenant.
+    Admin,
+}
+
+/// An issued api key. The key itself is only returned once, on creation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ApiKey {
+    pub id: u64,
+    /// `None` for keys of the platform operator, which apply to every tenant.
+    pub tenant_id: Option<u64>,
+    pub scopes: Vec<ApiScope>,
+    pub created_at_secs: u64,
+}
+
+impl ApiKey {
+    /// Whether the key may act with `scope`, on `tenant_id` or on the platform when `None`.
+    pub fn allows(&self, tenant_id: Option<u64>, scope: ApiScope) -> bool {
+        let tenant_matches = match (self.tenant_id, tenant_id) {
+            (None, _) => true,
+            (Some(own), Some(tenant_id)) => own == tenant_id,
+            (Some(_), None) => false,
+        };
+        tenant_matches
+            && self
+                .scopes
+                .iter()
+                .any(|own| *own == scope || *own == ApiScope::Admin)
+    }
+}
+
+/// Row value of an api key, keyed by its id.
+#[derive(Serialize, Deserialize)]
+struct StoredApiKey {
+    tenant_id: Option<u64>,
+    scopes: Vec<ApiScope>,
+    created_at_secs: u64,
+    /// Hex SHA-256 of the key. Keys are 256 random bits, so a plain hash is enough.
+    key_hash: String,
+}
+
+fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("{API_KEY_PREFIX}{hex}")
+}
+
+impl DocDb {
+    /// Returns the new key with its secret, which is not stored and can't be shown again.
+    pub async fn create_api_key(
+        &self,
+        tenant_id: Option<u64>,
+        scopes: Vec<ApiScope>,
+    ) -> Result<(ApiKey, String)> {
+        let key = generate_key();
+        let created_at_secs = now_secs();
+        let stored = StoredApiKey {
+            tenant_id,
+            scopes: scopes.clone(),
+            created_at_secs,
+            key_hash: hash_key(&key),
+        };
+
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "INSERT INTO docs (pk, sk, value)
+                SELECT 'api-keys', COALESCE(MAX(sk), 0) + 1, ? FROM docs WHERE pk = 'api-keys'
+                RETURNING sk",
+                libsql::params![serde_json::to_string(&stored).unwrap()],
+            )
+            .await?;
+        let row = rows
+            .next()
+            .await?
+            .ok_or(libsql::Error::QueryReturnedNoRows)?;
+
+        let api_key = ApiKey {
+            id: row.get(0)?,
+            tenant_id,
+            scopes,
+            created_at_secs,
+        };
+        Ok((api_key, key))
+    }
+
+    /// Returns whether the key existed. Allow-lists drop it on their next refresh.
+    pub async fn revoke_api_key(&self, id: u64) -> Result<bool> {
+        let conn = self.db.connect()?;
+        let deleted = conn
+            .execute(
+                "DELETE FROM docs WHERE pk = 'api-keys' AND sk = ?",
+                libsql::params![id],
+            )
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    pub async fn get_api_key(&self, id: u64) -> Result<Option<ApiKey>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT sk, value FROM docs WHERE pk = 'api-keys' AND sk = ?",
+                libsql::params![id],
+            )
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(api_key_from_row(&row)?.0)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        Ok(self
+            .api_keys_with_hash()
+            .await?
+            .into_iter()
+            .map(|(api_key, _hash)| api_key)
+            .collect())
+    }
+
+    async fn api_keys_with_hash(&self) -> Result<Vec<(ApiKey, String)>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT sk, value FROM docs WHERE pk = 'api-keys' ORDER BY sk ASC",
+                libsql::params!(),
+            )
+            .await?;
+
+        let mut api_keys = vec![];
+        while let Some(row) = rows.next().await? {
+            api_keys.push(api_key_from_row(&row)?);
+        }
+        Ok(api_keys)
+    }
+}
+
+fn api_key_from_row(row: &libsql::Row) -> Result<(ApiKey, String)> {
+    let id: u64 = row.get(0)?;
+    let json: String = row.get(1)?;
+    let stored: StoredApiKey = serde_json::from_str(&json).map_err(decode_error)?;
+    Ok((
+        ApiKey {
+            id,
+            tenant_id: stored.tenant_id,
+            scopes: stored.scopes,
+            created_at_secs: stored.created_at_secs,
+        },
+        stored.key_hash,
+    ))
+}
+
+/// In-memory copy of the issued api keys, so requests are authorized without a
+/// round-trip to the database. Clones share the same list.
+///
+/// The owner calls [`ApiKeyAllowList::refresh`] on a short interval, which bounds
+/// how long a revoked key keeps working.
+#[derive(Clone, Default)]
+pub struct ApiKeyAllowList {
+    keys: Arc<RwLock<HashMap<String, ApiKey>>>,
+}
+
+impl ApiKeyAllowList {
+    /// Replaces the list with the keys in `doc_db` and returns how many there are.
+    pub async fn refresh(&self, doc_db: &DocDb) -> Result<usize> {
+        let keys = doc_db
+            .api_keys_with_hash()
+            .await?
+            .into_iter()
+            .map(|(api_key, hash)| (hash, api_key))
+            .collect::<HashMap<_, _>>();
+        let count = keys.len();
+        *self.keys.write().unwrap() = keys;
+        Ok(count)
+    }
+
+    /// Returns the api key that `key` is, if it is issued.
+    pub fn lookup(&self, key: &str) -> Option<ApiKey> {
+        if !key.starts_with(API_KEY_PREFIX) {
+            return None;
+        }
+        self.keys.read().unwrap().get(&hash_key(key)).cloned()
+    }
+
+    /// Returns the api key that `key` is, if it is issued and allows `scope` on
+    /// `tenant_id`.
+    pub fn authorize(&self, key: &str, tenant_id: Option<u64>, scope: ApiScope) -> Option<ApiKey> {
+        self.lookup(key)
+            .filter(|api_key| api_key.allows(tenant_id, scope))
+    }
+
+    #[cfg(test)]
+    fn insert(&self, key: &str, api_key: ApiKey) {
+        self.keys.write().unwrap().insert(hash_key(key), api_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_key(tenant_id: Option<u64>, scopes: Vec<ApiScope>) -> ApiKey {
+        ApiKey {
+            id: 1,
+            tenant_id,
+            scopes,
+            created_at_secs: 0,
+        }
+    }
+
+    #[test]
+    fn test_allows() {
+        let deployer = api_key(Some(1), vec![ApiScope::Deploy]);
+        assert!(deployer.allows(Some(1), ApiScope::Deploy));
+        assert!(!deployer.allows(Some(1), ApiScope::ReadMetrics));
+        assert!(!deployer.allows(Some(2), ApiScope::Deploy));
+        assert!(!deployer.allows(None, ApiScope::Deploy));
+
+        let tenant_admin = api_key(Some(1), vec![ApiScope::Admin]);
+        assert!(tenant_admin.allows(Some(1), ApiScope::ReadMetrics));
+        assert!(tenant_admin.allows(Some(1), ApiScope::Invoke));
+        assert!(!deployer.allows(Some(1), ApiScope::Invoke));
+        assert!(!tenant_admin.allows(None, ApiScope::Admin));
+
+        let operator = api_key(None, vec![ApiScope::ReadMetrics]);
+        assert!(operator.allows(Some(7), ApiScope::ReadMetrics));
+        assert!(operator.allows(None, ApiScope::ReadMetrics));
+        assert!(!operator.allows(None, ApiScope::Admin));
+    }
+
+    #[test]
+    fn test_allow_list_matches_by_hash() {
+        let key = generate_key();
+        assert!(key.starts_with(API_KEY_PREFIX));
+        assert_eq!(key.len(), API_KEY_PREFIX.len() + 64);
+
+        let allow_list = ApiKeyAllowList::default();
+        allow_list.insert(&key, api_key(Some(1), vec![ApiScope::Deploy]));
+
+        assert!(
+            allow_list
+                .authorize(&key, Some(1), ApiScope::Deploy)
+                .is_some()
+        );
+        assert!(
+            allow_list
+                .authorize(&key, Some(1), ApiScope::Admin)
+                .is_none()
+        );
+        assert!(
+            allow_list
+                .authorize(&generate_key(), Some(1), ApiScope::Deploy)
+                .is_none()
+        );
+    }
+}
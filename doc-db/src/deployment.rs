@@ -1,4 +1,3 @@
-use bytes::Buf;
 use libsql::Row;
 
 use super::*;
@@ -9,6 +8,18 @@ pub struct Deployment {
     pub code_version: u64,
 }
 
+/// What [`DocDb::deploy`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeployOutcome {
+    Deployed {
+        deployment_id: u64,
+    },
+    /// The tenant reached [`Quota::max_deployments_per_day`].
+    QuotaExceeded,
+    /// The version was deployed before.
+    VersionExists,
+}
+
 impl DocDb {
     pub async fn all_deployments(&self) -> Result<Vec<Deployment>> {
         let mut deployments = vec![];
@@ -16,46 +27,174 @@ impl DocDb {
         let conn = self.db.connect()?;
         let mut rows = conn
             .query(
-                "SELECT value FROM docs WHERE pk = 'deployments' ORDER BY sk ASC",
+                "SELECT code_id, code_version FROM deployments ORDER BY id ASC",
                 libsql::params!(),
             )
             .await?;
 
         while let Some(row) = rows.next().await? {
-            deployments.push(row.into());
+            deployments.push(Deployment::try_from(&row)?);
         }
 
         Ok(deployments)
     }
 
-    pub async fn deployments_after(&self, sk: u64) -> Result<Vec<Deployment>> {
+    /// Appends `deployment` and returns its deployment id, the id hosts catch up to.
+    pub async fn insert_deployment(&self, deployment: Deployment) -> Result<u64> {
+        let conn = self.db.connect()?;
+        insert_deployment(&conn, deployment).await
+    }
+
+    /// Checks the quota of `tenant_id`, records the version and appends `deployment`
+    /// in one transaction, so concurrent deploys can't overrun the quota and a failed
+    /// append doesn't leave the version recorded.
+    pub async fn deploy(
+        &self,
+        tenant_id: u64,
+        deployment: Deployment,
+        max_deployments_per_day: u32,
+    ) -> Result<DeployOutcome> {
+        let conn = self.db.connect()?;
+        let tx = conn
+            .transaction_with_behavior(libsql::TransactionBehavior::Immediate)
+            .await?;
+
+        let since_secs = now_secs().saturating_sub(24 * 60 * 60);
+        if count_deployments_since(&tx, tenant_id, since_secs).await?
+            >= max_deployments_per_day as u64
+        {
+            tx.rollback().await?;
+            return Ok(DeployOutcome::QuotaExceeded);
+        }
+        if !record_code_version(&tx, deployment.code_id, deployment.code_version).await? {
+            tx.rollback().await?;
+            return Ok(DeployOutcome::VersionExists);
+        }
+        let deployment_id = insert_deployment(&tx, deployment).await?;
+        tx.commit().await?;
+
+        Ok(DeployOutcome::Deployed { deployment_id })
+    }
+
+    pub async fn deployments_after(&self, id: u64) -> Result<Vec<Deployment>> {
         let mut deployments = vec![];
 
         let conn = self.db.connect()?;
         let mut rows = conn
             .query(
-                "SELECT value FROM docs WHERE pk = 'deployments' AND sk > ? ORDER BY sk ASC",
-                libsql::params!(sk),
+                "SELECT code_id, code_version FROM deployments WHERE id > ? ORDER BY id ASC",
+                libsql::params!(id),
             )
             .await?;
 
         while let Some(row) = rows.next().await? {
-            deployments.push(row.into());
+            deployments.push(Deployment::try_from(&row)?);
         }
 
         Ok(deployments)
     }
 }
 
-impl From<Row> for Deployment {
-    fn from(row: Row) -> Self {
-        let bytes: [u8; 16] = row.get(0).unwrap();
-        assert_eq!(bytes.len(), 16);
+async fn insert_deployment(conn: &libsql::Connection, deployment: Deployment) -> Result<u64> {
+    conn.execute(
+        "INSERT INTO deployments (code_id, code_version, created_at_secs) VALUES (?, ?, ?)",
+        libsql::params![deployment.code_id, deployment.code_version, now_secs()],
+    )
+    .await?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+impl TryFrom<&Row> for Deployment {
+    type Error = libsql::Error;
+
+    fn try_from(row: &Row) -> Result<Self> {
+        Ok(Deployment {
+            code_id: row.get(0)?,
+            code_version: row.get(1)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let mut cursor = bytes.as_slice();
-        Deployment {
-            code_id: cursor.get_u64_le(),
-            code_version: cursor.get_u64_le(),
+    #[tokio::test]
+    async fn test_migration_keeps_blob_deployments() {
+        let doc_db = DocDb::temporary().await;
+        let conn = doc_db.db.connect().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE docs (
+                pk BLOB NOT NULL,
+                sk BLOB NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (pk, sk)
+            ) WITHOUT ROWID;",
+        )
+        .await
+        .unwrap();
+        for (sk, code_id, code_version) in [(1u64, 7u64, 1u64), (2, 300, 0x0102_0304_0506)] {
+            let mut value = code_id.to_le_bytes().to_vec();
+            value.extend(code_version.to_le_bytes());
+            conn.execute(
+                "INSERT INTO docs (pk, sk, value) VALUES ('deployments', ?, ?)",
+                libsql::params![sk, value],
+            )
+            .await
+            .unwrap();
         }
+
+        doc_db.migrate().await.unwrap();
+        let id = doc_db
+            .insert_deployment(Deployment {
+                code_id: 9,
+                code_version: 2,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(id, 3);
+        let deployments = doc_db
+            .all_deployments()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|deployment| (deployment.code_id, deployment.code_version))
+            .collect::<Vec<_>>();
+        assert_eq!(deployments, [(7, 1), (300, 0x0102_0304_0506), (9, 2)]);
+        assert_eq!(doc_db.deployments_after(2).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deploy_checks_quota_and_version() {
+        let doc_db = DocDb::temporary().await;
+        doc_db.migrate().await.unwrap();
+        let tenant = doc_db.create_tenant("acme").await.unwrap();
+        let project = doc_db.create_project(tenant.id, "web").await.unwrap();
+        let code = doc_db.create_code(project.id, CodeKind::Js).await.unwrap();
+        let deployment = |code_version| Deployment {
+            code_id: code.id,
+            code_version,
+        };
+
+        assert_eq!(
+            doc_db.deploy(tenant.id, deployment(1), 2).await.unwrap(),
+            DeployOutcome::Deployed { deployment_id: 1 }
+        );
+        assert_eq!(
+            doc_db.deploy(tenant.id, deployment(1), 2).await.unwrap(),
+            DeployOutcome::VersionExists
+        );
+        assert_eq!(
+            doc_db.deploy(tenant.id, deployment(2), 2).await.unwrap(),
+            DeployOutcome::Deployed { deployment_id: 2 }
+        );
+        assert_eq!(
+            doc_db.deploy(tenant.id, deployment(3), 2).await.unwrap(),
+            DeployOutcome::QuotaExceeded
+        );
+        assert_eq!(doc_db.all_deployments().await.unwrap().len(), 2);
+        // the rejected deploy recorded nothing
+        assert!(doc_db.record_code_version(code.id, 3).await.unwrap());
     }
 }
@@ -0,0 +1,218 @@
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::*;
+
+const NONCE_LEN: usize = 24;
+
+/// Per-code environment variables like API keys and config.
+///
+/// `Debug` only shows the names, so bindings can sit in structs that get logged.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvBindings {
+    pub vars: BTreeMap<String, String>,
+}
+
+impl std::fmt::Debug for EnvBindings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.vars.keys().map(|name| (name, "<redacted>")))
+            .finish()
+    }
+}
+
+/// Key that encrypts env bindings at rest. Only hosts that serve the code and the
+/// control plane that writes bindings should hold it.
+#[derive(Clone)]
+pub struct EnvKey(chacha20poly1305::Key);
+
+impl EnvKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes.into())
+    }
+
+    /// Parses the 64 hex characters that configs carry the key as.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return None;
+        }
+        let mut bytes = [0u8; 32];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+        }
+        Some(Self::new(bytes))
+    }
+
+    /// `nonce || ciphertext`, with `code_id` as associated data so a value copied
+    /// onto another code's row fails to decrypt.
+    fn encrypt(&self, code_id: u64, plaintext: &[u8]) -> Vec<u8> {
+        let cipher = XChaCha20Poly1305::new(&self.0);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = code_id.to_le_bytes();
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .expect("xchacha20poly1305 encryption is infallible for in-memory input");
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    fn decrypt(&self, code_id: u64, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let cipher = XChaCha20Poly1305::new(&self.0);
+        let aad = code_id.to_le_bytes();
+        cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .ok()
+    }
+}
+
+impl std::fmt::Debug for EnvKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EnvKey(<redacted>)")
+    }
+}
+
+#[derive(Debug)]
+pub enum EnvBindingsError {
+    Db(libsql::Error),
+    /// Wrong key, or the row was tampered with.
+    Decrypt,
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for EnvBindingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvBindingsError::Db(error) => write!(f, "db error: {error}"),
+            EnvBindingsError::Decrypt => f.write_str("failed to decrypt env bindings"),
+            EnvBindingsError::Json(error) => write!(f, "invalid env bindings: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for EnvBindingsError {}
+
+impl From<libsql::Error> for EnvBindingsError {
+    fn from(error: libsql::Error) -> Self {
+        EnvBindingsError::Db(error)
+    }
+}
+
+impl DocDb {
+    /// Readers pick up a new value on their next read, the code is not redeployed.
+    pub async fn set_env_bindings(
+        &self,
+        key: &EnvKey,
+        code_id: u64,
+        bindings: &EnvBindings,
+    ) -> Result<()> {
+        let json = serde_json::to_vec(bindings).unwrap();
+        let sealed = key.encrypt(code_id, &json);
+
+        let conn = self.db.connect()?;
+        conn.execute(
+            "REPLACE INTO docs (pk, sk, value) VALUES ('env-bindings', ?, ?)",
+            libsql::params![code_id, sealed],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_env_bindings(
+        &self,
+        key: &EnvKey,
+        code_id: u64,
+    ) -> std::result::Result<Option<EnvBindings>, EnvBindingsError> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT value FROM docs WHERE pk = 'env-bindings' AND sk = ?",
+                libsql::params!(code_id),
+            )
+            .await?;
+        let Some(row) = rows.next().await? else {
+            return Ok(None);
+        };
+
+        let sealed: Vec<u8> = row.get(0)?;
+        let json = key
+            .decrypt(code_id, &sealed)
+            .ok_or(EnvBindingsError::Decrypt)?;
+        serde_json::from_slice(&json)
+            .map(Some)
+            .map_err(EnvBindingsError::Json)
+    }
+
+    pub async fn delete_env_bindings(&self, code_id: u64) -> Result<()> {
+        let conn = self.db.connect()?;
+        conn.execute(
+            "DELETE FROM docs WHERE pk = 'env-bindings' AND sk = ?",
+            libsql::params![code_id],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let key = EnvKey::new([7; 32]);
+        let sealed = key.encrypt(1, b"secret");
+
+        assert_ne!(&sealed[NONCE_LEN..], b"secret");
+        assert_eq!(key.decrypt(1, &sealed).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_other_code_id_fails() {
+        let key = EnvKey::new([7; 32]);
+        let sealed = key.encrypt(1, b"secret");
+
+        assert_eq!(key.decrypt(2, &sealed), None);
+        assert_eq!(EnvKey::new([8; 32]).decrypt(1, &sealed), None);
+    }
+
+    #[test]
+    fn test_from_hex() {
+        let key = EnvKey::from_hex(&"07".repeat(32)).unwrap();
+        let sealed = EnvKey::new([7; 32]).encrypt(1, b"secret");
+
+        assert_eq!(key.decrypt(1, &sealed).unwrap(), b"secret");
+        assert!(EnvKey::from_hex(&"07".repeat(31)).is_none());
+        assert!(EnvKey::from_hex(&"zz".repeat(32)).is_none());
+    }
+
+    #[test]
+    fn test_debug_redacts_values() {
+        let bindings = EnvBindings {
+            vars: BTreeMap::from([("API_KEY".to_string(), "hunter2".to_string())]),
+        };
+        let debug = format!("{bindings:?}");
+
+        assert!(debug.contains("API_KEY"));
+        assert!(!debug.contains("hunter2"));
+    }
+}
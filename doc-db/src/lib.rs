@@ -1,10 +1,17 @@
+mod api_key;
 mod deployment;
+mod env_bindings;
+mod migration;
 mod scale_config;
+mod tenant;
 
+pub use api_key::*;
 pub use deployment::*;
+pub use env_bindings::*;
 use libsql::{Builder, Database, Result};
 pub use scale_config::*;
 use std::sync::Arc;
+pub use tenant::*;
 
 #[derive(Clone)]
 pub struct DocDb {
@@ -16,4 +23,39 @@ impl DocDb {
         let db = Builder::new_remote(url, token).build().await?;
         Ok(Self { db: Arc::new(db) })
     }
+
+    /// A database in a local file, e.g. for simulations of hq.
+    pub async fn new_local(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let db = Builder::new_local(path.as_ref()).build().await?;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// A fresh database in a temporary file. In-memory databases don't work here,
+    /// each connection would open a different one.
+    #[cfg(test)]
+    pub(crate) async fn temporary() -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "doc-db-test-{}-{}.sqlite3",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_file(&path);
+        Self::new_local(path).await.unwrap()
+    }
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// libsql has no error for a stored value that fails to decode, this variant at
+/// least carries the cause.
+fn decode_error(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> libsql::Error {
+    libsql::Error::ToSqlConversionFailure(error.into())
 }
@@ -0,0 +1,135 @@
+use super::*;
+
+/// Schema changes in the order they apply. Append only: an applied migration is
+/// never edited, a fix is a new migration.
+const MIGRATIONS: &[&str] = &[
+    // 1: the key-value table everything started in
+    "CREATE TABLE IF NOT EXISTS docs (
+        pk BLOB NOT NULL,
+        sk BLOB NOT NULL,
+        value BLOB NOT NULL,
+        PRIMARY KEY (pk, sk)
+    ) WITHOUT ROWID;",
+    // 2: tenants own projects, projects own codes, codes have versions
+    "CREATE TABLE tenants (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        created_at_secs INTEGER NOT NULL
+    );
+    CREATE TABLE projects (
+        id INTEGER PRIMARY KEY,
+        tenant_id INTEGER NOT NULL REFERENCES tenants (id),
+        name TEXT NOT NULL,
+        created_at_secs INTEGER NOT NULL,
+        UNIQUE (tenant_id, name)
+    );
+    CREATE TABLE codes (
+        id INTEGER PRIMARY KEY,
+        project_id INTEGER NOT NULL REFERENCES projects (id),
+        kind TEXT NOT NULL CHECK (kind IN ('wasm', 'js')),
+        created_at_secs INTEGER NOT NULL
+    );
+    CREATE TABLE code_versions (
+        code_id INTEGER NOT NULL REFERENCES codes (id),
+        version INTEGER NOT NULL,
+        created_at_secs INTEGER NOT NULL,
+        PRIMARY KEY (code_id, version)
+    );
+    CREATE TABLE quotas (
+        tenant_id INTEGER PRIMARY KEY REFERENCES tenants (id),
+        max_deployments_per_day INTEGER NOT NULL,
+        max_host_share_percent INTEGER NOT NULL,
+        max_requests_per_sec INTEGER NOT NULL
+    );",
+    // 3: region-wide request rate, split between hosts by hq
+    "ALTER TABLE quotas ADD COLUMN max_region_requests_per_sec INTEGER;",
+    // 4: deployments out of `docs`, where they were two little-endian u64s; ids keep
+    // their `sk`. The old rows are left behind but nothing writes them anymore, so
+    // every host must run a build that reads `deployments` before this is applied.
+    "CREATE TABLE deployments (
+        id INTEGER PRIMARY KEY,
+        code_id INTEGER NOT NULL,
+        code_version INTEGER NOT NULL,
+        created_at_secs INTEGER NOT NULL
+    );
+    WITH RECURSIVE byte (i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM byte WHERE i < 7)
+    INSERT INTO deployments (id, code_id, code_version, created_at_secs)
+    SELECT sk,
+        (SELECT SUM(
+            ((instr('0123456789ABCDEF', substr(hex(value), 2 * i + 1, 1)) - 1) * 16
+                + instr('0123456789ABCDEF', substr(hex(value), 2 * i + 2, 1)) - 1) << (8 * i)
+        ) FROM byte),
+        (SELECT SUM(
+            ((instr('0123456789ABCDEF', substr(hex(value), 2 * i + 17, 1)) - 1) * 16
+                + instr('0123456789ABCDEF', substr(hex(value), 2 * i + 18, 1)) - 1) << (8 * i)
+        ) FROM byte),
+        0
+    FROM docs WHERE pk = 'deployments' AND length(value) = 16;",
+];
+
+impl DocDb {
+    /// Applies the migrations this build knows and the database doesn't, each in its
+    /// own transaction. Returns the schema version.
+    ///
+    /// Only the control plane migrates. A host built before a migration keeps
+    /// reading the tables it knows.
+    pub async fn migrate(&self) -> Result<usize> {
+        let conn = self.db.connect()?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at_secs INTEGER NOT NULL
+            )",
+            libsql::params!(),
+        )
+        .await?;
+
+        let mut rows = conn
+            .query(
+                "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+                libsql::params!(),
+            )
+            .await?;
+        let row = rows
+            .next()
+            .await?
+            .ok_or(libsql::Error::QueryReturnedNoRows)?;
+        let applied = row.get::<u64>(0)? as usize;
+        drop(rows);
+
+        for (index, sql) in MIGRATIONS.iter().enumerate().skip(applied) {
+            let version = index + 1;
+            let tx = conn.transaction().await?;
+            tx.execute_batch(sql).await?;
+            tx.execute(
+                "INSERT INTO schema_migrations (version, applied_at_secs) VALUES (?, ?)",
+                libsql::params![version as u64, now_secs()],
+            )
+            .await?;
+            tx.commit().await?;
+        }
+
+        Ok(MIGRATIONS.len().max(applied))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_migrate_is_idempotent() {
+        let doc_db = DocDb::temporary().await;
+
+        assert_eq!(doc_db.migrate().await.unwrap(), MIGRATIONS.len());
+        assert_eq!(doc_db.migrate().await.unwrap(), MIGRATIONS.len());
+
+        let conn = doc_db.db.connect().unwrap();
+        let mut rows = conn
+            .query("SELECT COUNT(*) FROM schema_migrations", libsql::params!())
+            .await
+            .unwrap();
+        let count: u64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
+        assert_eq!(count as usize, MIGRATIONS.len());
+    }
+}
@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 
 use super::*;
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, schemars::JsonSchema)]
 /// max instances = min(instance_per_gb * memory, instance_per_core * cores)
 pub struct ScaleConfig {
     pub instances_per_gb: NonZeroUsize,
@@ -29,6 +29,125 @@ pub struct ScaleConfig {
 
     /// min_hosts <= max_hosts
     pub min_hosts: NonZeroUsize,
+
+    /// Override min_hosts and max_hosts while they're in effect, e.g. for known
+    /// traffic peaks. The first one in effect wins.
+    #[serde(default)]
+    pub schedules: Vec<ScaleSchedule>,
+
+    /// Scales for the instances the recent trend predicts, when more than now.
+    #[serde(default)]
+    pub predictive: Option<PredictiveScaling>,
+
+    /// Hosts launched on top of the target, so a spike doesn't wait for a launch.
+    /// They are in DNS like any other host, so the load spreads over them rather
+    /// than they idle. Still bounded by max_hosts.
+    #[serde(default, alias = "warm_pool_hosts")]
+    pub headroom_hosts: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+/// A daily UTC time window on some days of the week.
+pub struct ScaleSchedule {
+    /// 0 (Monday) ~ 6 (Sunday), empty for every day. A window past midnight
+    /// belongs to the day it starts on.
+    #[serde(default)]
+    pub weekdays: Vec<u8>,
+
+    /// 0~1439, minutes since midnight UTC
+    pub start_minute: u16,
+
+    /// 0~1439, exclusive. Less than or equal to start_minute to end the next day.
+    pub end_minute: u16,
+
+    pub min_hosts: NonZeroUsize,
+
+    /// min_hosts <= max_hosts
+    pub max_hosts: NonZeroUsize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+/// Least-squares trend of the total instances.
+pub struct PredictiveScaling {
+    /// How far back the trend looks.
+    pub window_secs: NonZeroUsize,
+
+    /// How far ahead the trend is extended, e.g. about the host launch time.
+    pub horizon_secs: NonZeroUsize,
+}
+
+impl ScaleSchedule {
+    /// `weekday` is 0 (Monday) ~ 6 (Sunday), `minute` is since midnight UTC.
+    pub fn is_in_effect(&self, weekday: u8, minute: u16) -> bool {
+        let on = |weekday: u8| self.weekdays.is_empty() || self.weekdays.contains(&weekday);
+        if self.start_minute < self.end_minute {
+            on(weekday) && (self.start_minute..self.end_minute).contains(&minute)
+        } else {
+            (on(weekday) && minute >= self.start_minute)
+                || (on((weekday + 6) % 7) && minute < self.end_minute)
+        }
+    }
+}
+
+impl ScaleConfig {
+    /// Checks the invariants the scaler relies on, documented on each field.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        for (name, percent) in [
+            (
+                "scale_out_threshold_percent",
+                self.scale_out_threshold_percent,
+            ),
+            (
+                "scale_in_threshold_percent",
+                self.scale_in_threshold_percent,
+            ),
+        ] {
+            if percent.get() > 100 {
+                return Err(format!("{name} must be 1~100, got {percent}"));
+            }
+        }
+        if self.scale_in_threshold_percent >= self.scale_out_threshold_percent {
+            return Err(format!(
+                "scale_in_threshold_percent ({}) must be less than scale_out_threshold_percent ({})",
+                self.scale_in_threshold_percent, self.scale_out_threshold_percent
+            ));
+        }
+        if self.min_hosts > self.max_hosts {
+            return Err(format!(
+                "min_hosts ({}) must not exceed max_hosts ({})",
+                self.min_hosts, self.max_hosts
+            ));
+        }
+        for schedule in &self.schedules {
+            if let Some(weekday) = schedule.weekdays.iter().find(|weekday| **weekday > 6) {
+                return Err(format!("schedule weekday must be 0~6, got {weekday}"));
+            }
+            for (name, minute) in [
+                ("start_minute", schedule.start_minute),
+                ("end_minute", schedule.end_minute),
+            ] {
+                if minute >= 24 * 60 {
+                    return Err(format!("schedule {name} must be 0~1439, got {minute}"));
+                }
+            }
+            if schedule.min_hosts > schedule.max_hosts {
+                return Err(format!(
+                    "schedule min_hosts ({}) must not exceed max_hosts ({})",
+                    schedule.min_hosts, schedule.max_hosts
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// min_hosts and max_hosts, as the schedule in effect overrides them.
+    pub fn host_bounds(&self, weekday: u8, minute: u16) -> (NonZeroUsize, NonZeroUsize) {
+        self.schedules
+            .iter()
+            .find(|schedule| schedule.is_in_effect(weekday, minute))
+            .map(|schedule| (schedule.min_hosts, schedule.max_hosts))
+            .unwrap_or((self.min_hosts, self.max_hosts))
+    }
 }
 
 impl DocDb {
@@ -40,7 +159,10 @@ impl DocDb {
                 libsql::params!(),
             )
             .await?;
-        Ok(rows.next().await?.map(|row| row.into()))
+        rows.next()
+            .await?
+            .map(|row| ScaleConfig::try_from(&row))
+            .transpose()
     }
     pub async fn set_scale_config(&self, scale_config: ScaleConfig) -> Result<()> {
         let conn = self.db.connect()?;
@@ -53,9 +175,114 @@ impl DocDb {
     }
 }
 
-impl From<Row> for ScaleConfig {
-    fn from(row: Row) -> Self {
-        let json: String = row.get(0).unwrap();
-        serde_json::from_str(&json).unwrap()
+impl TryFrom<&Row> for ScaleConfig {
+    type Error = libsql::Error;
+
+    fn try_from(row: &Row) -> Result<Self> {
+        let json: String = row.get(0)?;
+        serde_json::from_str(&json).map_err(decode_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scale_config() -> ScaleConfig {
+        let n = |n| NonZeroUsize::new(n).unwrap();
+        ScaleConfig {
+            instances_per_gb: n(4),
+            instances_per_core: n(8),
+            scale_out_threshold_percent: n(80),
+            scale_in_threshold_percent: n(40),
+            scale_out_cooldown_secs: n(60),
+            scale_in_threshold_ticks: n(3),
+            scale_in_cooldown_secs: n(300),
+            max_hosts: n(10),
+            min_hosts: n(1),
+            schedules: vec![],
+            predictive: None,
+            headroom_hosts: 0,
+        }
+    }
+
+    fn schedule(weekdays: Vec<u8>, start_minute: u16, end_minute: u16) -> ScaleSchedule {
+        ScaleSchedule {
+            weekdays,
+            start_minute,
+            end_minute,
+            min_hosts: NonZeroUsize::new(5).unwrap(),
+            max_hosts: NonZeroUsize::new(20).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(scale_config().validate(), Ok(()));
+
+        let mut config = scale_config();
+        config.scale_out_threshold_percent = NonZeroUsize::new(101).unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config = scale_config();
+        config.scale_in_threshold_percent = config.scale_out_threshold_percent;
+        assert!(config.validate().is_err());
+
+        let mut config = scale_config();
+        config.min_hosts = NonZeroUsize::new(11).unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config = scale_config();
+        config.schedules = vec![schedule(vec![7], 0, 60)];
+        assert!(config.validate().is_err());
+
+        let mut config = scale_config();
+        config.schedules = vec![schedule(vec![], 0, 1440)];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_schedule_window() {
+        // weekdays 09:00~18:00
+        let office = schedule(vec![0, 1, 2, 3, 4], 9 * 60, 18 * 60);
+        assert!(office.is_in_effect(0, 9 * 60));
+        assert!(!office.is_in_effect(0, 18 * 60));
+        assert!(!office.is_in_effect(5, 12 * 60));
+
+        // friday 22:00 ~ saturday 02:00
+        let night = schedule(vec![4], 22 * 60, 2 * 60);
+        assert!(night.is_in_effect(4, 23 * 60));
+        assert!(night.is_in_effect(5, 60));
+        assert!(!night.is_in_effect(5, 23 * 60));
+        assert!(!night.is_in_effect(4, 60));
+    }
+
+    #[test]
+    fn test_host_bounds() {
+        let mut config = scale_config();
+        config.schedules = vec![schedule(vec![], 9 * 60, 18 * 60)];
+        let n = |n| NonZeroUsize::new(n).unwrap();
+        assert_eq!(config.host_bounds(0, 12 * 60), (n(5), n(20)));
+        assert_eq!(config.host_bounds(0, 20 * 60), (n(1), n(10)));
+    }
+
+    #[test]
+    fn test_policies_default_for_stored_configs() {
+        let mut json = serde_json::to_value(scale_config()).unwrap();
+        for key in ["schedules", "predictive", "headroom_hosts"] {
+            json.as_object_mut().unwrap().remove(key);
+        }
+        let config: ScaleConfig = serde_json::from_value(json).unwrap();
+        assert!(config.schedules.is_empty());
+        assert_eq!(config.predictive, None);
+        assert_eq!(config.headroom_hosts, 0);
+
+        // stored before the field was renamed
+        json = serde_json::to_value(scale_config()).unwrap();
+        let object = json.as_object_mut().unwrap();
+        object.remove("headroom_hosts");
+        object.insert("warm_pool_hosts".to_string(), 2.into());
+        let config: ScaleConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(config.headroom_hosts, 2);
     }
 }
@@ -0,0 +1,512 @@
+use libsql::Row;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+/// A customer. Api keys, projects and quotas belong to a tenant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Tenant {
+    pub id: u64,
+    pub name: String,
+    pub created_at_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Project {
+    pub id: u64,
+    pub tenant_id: u64,
+    pub name: String,
+    pub created_at_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeKind {
+    Wasm,
+    Js,
+}
+
+impl CodeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            CodeKind::Wasm => "wasm",
+            CodeKind::Js => "js",
+        }
+    }
+}
+
+/// A deployable unit, the `code_id` of [`Deployment`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Code {
+    pub id: u64,
+    pub project_id: u64,
+    /// Owner of the project, denormalized for authorization.
+    pub tenant_id: u64,
+    pub kind: CodeKind,
+    pub created_at_secs: u64,
+}
+
+/// Limits of a tenant. Tenants without a row get [`Quota::default`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Quota {
+    /// Over all codes of the tenant, in the last 24 hours.
+    pub max_deployments_per_day: u32,
+    /// Share of a host's capacity the tenant's in-flight requests may take, 1~100.
+    pub max_host_share_percent: u8,
+    /// Over all codes of the tenant, per host.
+    pub max_requests_per_sec: u32,
+    /// Over all codes of the tenant, over all hosts of a region. hq splits it
+    /// between the hosts by their demand. `None` for no region limit.
+    #[serde(default)]
+    pub max_region_requests_per_sec: Option<u32>,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self {
+            max_deployments_per_day: 100,
+            max_host_share_percent: 25,
+            max_requests_per_sec: 1000,
+            max_region_requests_per_sec: None,
+        }
+    }
+}
+
+impl Quota {
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if !(1..=100).contains(&self.max_host_share_percent) {
+            return Err(format!(
+                "max_host_share_percent must be 1~100, got {}",
+                self.max_host_share_percent
+            ));
+        }
+        if self.max_requests_per_sec == 0 {
+            return Err("max_requests_per_sec must be positive".to_string());
+        }
+        if self.max_region_requests_per_sec == Some(0) {
+            return Err("max_region_requests_per_sec must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The quota that applies to a code, as hosts need it on every request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeQuota {
+    pub code_id: u64,
+    pub tenant_id: u64,
+    pub quota: Quota,
+}
+
+impl DocDb {
+    pub async fn create_tenant(&self, name: &str) -> Result<Tenant> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "INSERT INTO tenants (name, created_at_secs) VALUES (?, ?)
+                RETURNING id, name, created_at_secs",
+                libsql::params![name, now_secs()],
+            )
+            .await?;
+        let row = rows
+            .next()
+            .await?
+            .ok_or(libsql::Error::QueryReturnedNoRows)?;
+        Tenant::try_from(&row)
+    }
+
+    pub async fn get_tenant(&self, id: u64) -> Result<Option<Tenant>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT id, name, created_at_secs FROM tenants WHERE id = ?",
+                libsql::params![id],
+            )
+            .await?;
+        rows.next()
+            .await?
+            .map(|row| Tenant::try_from(&row))
+            .transpose()
+    }
+
+    pub async fn list_tenants(&self) -> Result<Vec<Tenant>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT id, name, created_at_secs FROM tenants ORDER BY id ASC",
+                libsql::params!(),
+            )
+            .await?;
+
+        let mut tenants = vec![];
+        while let Some(row) = rows.next().await? {
+            tenants.push(Tenant::try_from(&row)?);
+        }
+        Ok(tenants)
+    }
+
+    pub async fn create_project(&self, tenant_id: u64, name: &str) -> Result<Project> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "INSERT INTO projects (tenant_id, name, created_at_secs) VALUES (?, ?, ?)
+                RETURNING id, tenant_id, name, created_at_secs",
+                libsql::params![tenant_id, name, now_secs()],
+            )
+            .await?;
+        let row = rows
+            .next()
+            .await?
+            .ok_or(libsql::Error::QueryReturnedNoRows)?;
+        Project::try_from(&row)
+    }
+
+    pub async fn get_project(&self, id: u64) -> Result<Option<Project>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT id, tenant_id, name, created_at_secs FROM projects WHERE id = ?",
+                libsql::params![id],
+            )
+            .await?;
+        rows.next()
+            .await?
+            .map(|row| Project::try_from(&row))
+            .transpose()
+    }
+
+    pub async fn list_projects(&self, tenant_id: u64) -> Result<Vec<Project>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT id, tenant_id, name, created_at_secs FROM projects
+                WHERE tenant_id = ? ORDER BY id ASC",
+                libsql::params![tenant_id],
+            )
+            .await?;
+
+        let mut projects = vec![];
+        while let Some(row) = rows.next().await? {
+            projects.push(Project::try_from(&row)?);
+        }
+        Ok(projects)
+    }
+
+    pub async fn create_code(&self, project_id: u64, kind: CodeKind) -> Result<Code> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "INSERT INTO codes (project_id, kind, created_at_secs) VALUES (?, ?, ?)
+                RETURNING id, project_id,
+                    (SELECT tenant_id FROM projects WHERE projects.id = codes.project_id),
+                    kind, created_at_secs",
+                libsql::params![project_id, kind.as_str(), now_secs()],
+            )
+            .await?;
+        let row = rows
+            .next()
+            .await?
+            .ok_or(libsql::Error::QueryReturnedNoRows)?;
+        Code::try_from(&row)
+    }
+
+    pub async fn get_code(&self, id: u64) -> Result<Option<Code>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT codes.id, codes.project_id, projects.tenant_id, codes.kind, codes.created_at_secs
+                FROM codes JOIN projects ON projects.id = codes.project_id
+                WHERE codes.id = ?",
+                libsql::params![id],
+            )
+            .await?;
+        rows.next()
+            .await?
+            .map(|row| Code::try_from(&row))
+            .transpose()
+    }
+
+    /// Records that `version` of `code_id` was deployed, which counts against the
+    /// tenant's [`Quota::max_deployments_per_day`]. Returns false if the version was
+    /// recorded before.
+    pub async fn record_code_version(&self, code_id: u64, version: u64) -> Result<bool> {
+        let conn = self.db.connect()?;
+        record_code_version(&conn, code_id, version).await
+    }
+
+    /// Deployments of all codes of `tenant_id` since `since_secs`.
+    pub async fn count_deployments_since(&self, tenant_id: u64, since_secs: u64) -> Result<u64> {
+        let conn = self.db.connect()?;
+        count_deployments_since(&conn, tenant_id, since_secs).await
+    }
+
+    pub async fn set_quota(&self, tenant_id: u64, quota: &Quota) -> Result<()> {
+        let conn = self.db.connect()?;
+        conn.execute(
+            "REPLACE INTO quotas
+            (tenant_id, max_deployments_per_day, max_host_share_percent, max_requests_per_sec,
+                max_region_requests_per_sec)
+            VALUES (?, ?, ?, ?, ?)",
+            libsql::params![
+                tenant_id,
+                quota.max_deployments_per_day,
+                quota.max_host_share_percent as u32,
+                quota.max_requests_per_sec,
+                quota.max_region_requests_per_sec
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_quota(&self, tenant_id: u64) -> Result<Quota> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT max_deployments_per_day, max_host_share_percent, max_requests_per_sec,
+                    max_region_requests_per_sec
+                FROM quotas WHERE tenant_id = ?",
+                libsql::params![tenant_id],
+            )
+            .await?;
+        match rows.next().await? {
+            Some(row) => Quota::try_from(&row),
+            None => Ok(Quota::default()),
+        }
+    }
+
+    /// The quota of every code, for hosts to enforce without a query per request.
+    pub async fn code_quotas(&self) -> Result<Vec<CodeQuota>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT quotas.max_deployments_per_day, quotas.max_host_share_percent,
+                    quotas.max_requests_per_sec, quotas.max_region_requests_per_sec, codes.id,
+                    projects.tenant_id
+                FROM codes
+                JOIN projects ON projects.id = codes.project_id
+                LEFT JOIN quotas ON quotas.tenant_id = projects.tenant_id
+                ORDER BY codes.id ASC",
+                libsql::params!(),
+            )
+            .await?;
+
+        let mut code_quotas = vec![];
+        while let Some(row) = rows.next().await? {
+            let quota = match row.get::<Option<u32>>(0)? {
+                Some(_) => Quota::try_from(&row)?,
+                None => Quota::default(),
+            };
+            code_quotas.push(CodeQuota {
+                code_id: row.get(4)?,
+                tenant_id: row.get(5)?,
+                quota,
+            });
+        }
+        Ok(code_quotas)
+    }
+
+    /// `(tenant_id, max_region_requests_per_sec)` of the tenants with a region limit.
+    pub async fn region_rate_limits(&self) -> Result<Vec<(u64, u32)>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT tenant_id, max_region_requests_per_sec FROM quotas
+                WHERE max_region_requests_per_sec IS NOT NULL
+                ORDER BY tenant_id ASC",
+                libsql::params!(),
+            )
+            .await?;
+
+        let mut limits = vec![];
+        while let Some(row) = rows.next().await? {
+            limits.push((row.get(0)?, row.get(1)?));
+        }
+        Ok(limits)
+    }
+}
+
+pub(crate) async fn record_code_version(
+    conn: &libsql::Connection,
+    code_id: u64,
+    version: u64,
+) -> Result<bool> {
+    let inserted = conn
+        .execute(
+            "INSERT OR IGNORE INTO code_versions (code_id, version, created_at_secs)
+            VALUES (?, ?, ?)",
+            libsql::params![code_id, version, now_secs()],
+        )
+        .await?;
+    Ok(inserted > 0)
+}
+
+pub(crate) async fn count_deployments_since(
+    conn: &libsql::Connection,
+    tenant_id: u64,
+    since_secs: u64,
+) -> Result<u64> {
+    let mut rows = conn
+        .query(
+            "SELECT COUNT(*) FROM code_versions
+            JOIN codes ON codes.id = code_versions.code_id
+            JOIN projects ON projects.id = codes.project_id
+            WHERE projects.tenant_id = ? AND code_versions.created_at_secs >= ?",
+            libsql::params![tenant_id, since_secs],
+        )
+        .await?;
+    let row = rows
+        .next()
+        .await?
+        .ok_or(libsql::Error::QueryReturnedNoRows)?;
+    row.get(0)
+}
+
+impl TryFrom<&Row> for Tenant {
+    type Error = libsql::Error;
+
+    fn try_from(row: &Row) -> Result<Self> {
+        Ok(Tenant {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_at_secs: row.get(2)?,
+        })
+    }
+}
+
+impl TryFrom<&Row> for Project {
+    type Error = libsql::Error;
+
+    fn try_from(row: &Row) -> Result<Self> {
+        Ok(Project {
+            id: row.get(0)?,
+            tenant_id: row.get(1)?,
+            name: row.get(2)?,
+            created_at_secs: row.get(3)?,
+        })
+    }
+}
+
+impl TryFrom<&Row> for Code {
+    type Error = libsql::Error;
+
+    fn try_from(row: &Row) -> Result<Self> {
+        let kind = match row.get::<String>(3)?.as_str() {
+            "wasm" => CodeKind::Wasm,
+            "js" => CodeKind::Js,
+            kind => return Err(decode_error(format!("unknown code kind {kind:?}"))),
+        };
+        Ok(Code {
+            id: row.get(0)?,
+            project_id: row.get(1)?,
+            tenant_id: row.get(2)?,
+            kind,
+            created_at_secs: row.get(4)?,
+        })
+    }
+}
+
+/// From the first four columns.
+impl TryFrom<&Row> for Quota {
+    type Error = libsql::Error;
+
+    fn try_from(row: &Row) -> Result<Self> {
+        let max_host_share_percent: u32 = row.get(1)?;
+        Ok(Quota {
+            max_deployments_per_day: row.get(0)?,
+            max_host_share_percent: u8::try_from(max_host_share_percent).map_err(decode_error)?,
+            max_requests_per_sec: row.get(2)?,
+            max_region_requests_per_sec: row.get(3)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_code_belongs_to_tenant() {
+        let doc_db = DocDb::temporary().await;
+        doc_db.migrate().await.unwrap();
+
+        let tenant = doc_db.create_tenant("acme").await.unwrap();
+        let project = doc_db.create_project(tenant.id, "web").await.unwrap();
+        let code = doc_db.create_code(project.id, CodeKind::Js).await.unwrap();
+
+        assert_eq!(code.tenant_id, tenant.id);
+        assert_eq!(doc_db.get_code(code.id).await.unwrap(), Some(code.clone()));
+        assert_eq!(
+            doc_db.list_projects(tenant.id).await.unwrap(),
+            vec![project]
+        );
+        assert!(doc_db.create_tenant("acme").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_quota_and_deployment_count() {
+        let doc_db = DocDb::temporary().await;
+        doc_db.migrate().await.unwrap();
+
+        let tenant = doc_db.create_tenant("acme").await.unwrap();
+        let project = doc_db.create_project(tenant.id, "web").await.unwrap();
+        let code = doc_db
+            .create_code(project.id, CodeKind::Wasm)
+            .await
+            .unwrap();
+
+        assert_eq!(doc_db.get_quota(tenant.id).await.unwrap(), Quota::default());
+        let quota = Quota {
+            max_deployments_per_day: 2,
+            max_host_share_percent: 10,
+            max_requests_per_sec: 5,
+            max_region_requests_per_sec: Some(20),
+        };
+        doc_db.set_quota(tenant.id, &quota).await.unwrap();
+        assert_eq!(doc_db.get_quota(tenant.id).await.unwrap(), quota);
+        assert_eq!(
+            doc_db.code_quotas().await.unwrap(),
+            vec![CodeQuota {
+                code_id: code.id,
+                tenant_id: tenant.id,
+                quota,
+            }]
+        );
+
+        assert_eq!(
+            doc_db.region_rate_limits().await.unwrap(),
+            vec![(tenant.id, 20)]
+        );
+
+        assert!(doc_db.record_code_version(code.id, 1).await.unwrap());
+        assert!(doc_db.record_code_version(code.id, 2).await.unwrap());
+        assert!(!doc_db.record_code_version(code.id, 2).await.unwrap());
+        assert_eq!(
+            doc_db.count_deployments_since(tenant.id, 0).await.unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_quota_validate() {
+        assert_eq!(Quota::default().validate(), Ok(()));
+
+        let quota = Quota {
+            max_host_share_percent: 0,
+            ..Quota::default()
+        };
+        assert!(quota.validate().is_err());
+
+        let quota = Quota {
+            max_requests_per_sec: 0,
+            ..Quota::default()
+        };
+        assert!(quota.validate().is_err());
+
+        let quota = Quota {
+            max_region_requests_per_sec: Some(0),
+            ..Quota::default()
+        };
+        assert!(quota.validate().is_err());
+    }
+}
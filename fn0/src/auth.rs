@@ -0,0 +1,192 @@
+use doc_db::{ApiKeyAllowList, ApiScope, DocDb};
+use hyper::HeaderMap;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+/// Decides whether a request may invoke a code, e.g. by checking its api key
+/// against an in-memory allow-list. Runs on every request, so it should not wait
+/// on a database.
+pub trait Authorize: Send + Sync + 'static {
+    fn authorize(&self, code_id: &str, headers: &HeaderMap) -> bool;
+}
+
+/// Every request may invoke every code.
+pub struct AllowAll;
+
+impl Authorize for AllowAll {
+    fn authorize(&self, _code_id: &str, _headers: &HeaderMap) -> bool {
+        true
+    }
+}
+
+/// Lets a request invoke a code when its bearer token is an api key of doc-db with
+/// the [`ApiScope::Invoke`] scope, or [`ApiScope::Admin`], on the code's tenant.
+///
+/// Keys and code tenants are kept in memory. Run [`ApiKeyAuthorizer::run_sync`] so
+/// revoked keys and new codes are picked up. Clones share the same lists.
+#[derive(Clone)]
+pub struct ApiKeyAuthorizer {
+    doc_db: DocDb,
+    allow_list: ApiKeyAllowList,
+    code_tenants: Arc<RwLock<HashMap<String, u64>>>,
+    aliases: Vec<(String, u64)>,
+}
+
+impl ApiKeyAuthorizer {
+    /// Fetches the lists once, so requests are not rejected until the first sync.
+    pub async fn new(doc_db: DocDb) -> anyhow::Result<Self> {
+        Self::with_aliases(doc_db, []).await
+    }
+
+    /// Like [`ApiKeyAuthorizer::new`], also authorizing each `code_id` as doc-db code
+    /// `doc_db_code_id`, e.g. forte's `backend`.
+    pub async fn with_aliases(
+        doc_db: DocDb,
+        aliases: impl IntoIterator<Item = (String, u64)>,
+    ) -> anyhow::Result<Self> {
+        let authorizer = Self {
+            doc_db,
+            allow_list: Default::default(),
+            code_tenants: Default::default(),
+            aliases: aliases.into_iter().collect(),
+        };
+        authorizer.refresh().await?;
+        Ok(authorizer)
+    }
+
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        self.allow_list.refresh(&self.doc_db).await?;
+
+        let mut code_tenants = self
+            .doc_db
+            .code_quotas()
+            .await?
+            .into_iter()
+            .map(|code_quota| (code_quota.code_id.to_string(), code_quota.tenant_id))
+            .collect::<HashMap<_, _>>();
+        for (code_id, doc_db_code_id) in &self.aliases {
+            if let Some(tenant_id) = code_tenants.get(&doc_db_code_id.to_string()).copied() {
+                code_tenants.insert(code_id.clone(), tenant_id);
+            }
+        }
+        *self.code_tenants.write().unwrap() = code_tenants;
+        Ok(())
+    }
+
+    /// Refreshes every `interval`, which bounds how long a revoked key keeps working.
+    pub async fn run_sync(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.refresh().await {
+                warn!(%err, "Failed to refresh api keys");
+            }
+        }
+    }
+}
+
+impl Authorize for ApiKeyAuthorizer {
+    fn authorize(&self, code_id: &str, headers: &HeaderMap) -> bool {
+        let Some(tenant_id) = self.code_tenants.read().unwrap().get(code_id).copied() else {
+            return false;
+        };
+        bearer_token(headers).is_some_and(|token| {
+            self.allow_list
+                .authorize(token, Some(tenant_id), ApiScope::Invoke)
+                .is_some()
+        })
+    }
+}
+
+/// The token of `Authorization: Bearer <token>`.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(hyper::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(hyper::header::AUTHORIZATION, "Basic abc".parse().unwrap());
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(
+            hyper::header::AUTHORIZATION,
+            "Bearer fn0_abc".parse().unwrap(),
+        );
+        assert_eq!(bearer_token(&headers), Some("fn0_abc"));
+    }
+
+    #[tokio::test]
+    async fn test_api_key_authorizer_checks_tenant_of_code() {
+        let path =
+            std::env::temp_dir().join(format!("fn0-auth-test-{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let doc_db = DocDb::new_local(&path).await.unwrap();
+        doc_db.migrate().await.unwrap();
+        let tenant = doc_db.create_tenant("acme").await.unwrap();
+        let project = doc_db.create_project(tenant.id, "web").await.unwrap();
+        let code = doc_db
+            .create_code(project.id, doc_db::CodeKind::Js)
+            .await
+            .unwrap();
+        let other = doc_db.create_tenant("other").await.unwrap();
+        let (_, invoker) = doc_db
+            .create_api_key(Some(tenant.id), vec![ApiScope::Invoke])
+            .await
+            .unwrap();
+        let (_, admin) = doc_db
+            .create_api_key(Some(tenant.id), vec![ApiScope::Admin])
+            .await
+            .unwrap();
+        let (_, deployer) = doc_db
+            .create_api_key(Some(tenant.id), vec![ApiScope::Deploy])
+            .await
+            .unwrap();
+        let (_, outsider) = doc_db
+            .create_api_key(Some(other.id), vec![ApiScope::Invoke])
+            .await
+            .unwrap();
+        let (_, reader) = doc_db
+            .create_api_key(Some(tenant.id), vec![ApiScope::ReadMetrics])
+            .await
+            .unwrap();
+
+        let authorizer =
+            ApiKeyAuthorizer::with_aliases(doc_db.clone(), [("backend".to_string(), code.id)])
+                .await
+                .unwrap();
+        let headers = |key: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                hyper::header::AUTHORIZATION,
+                format!("Bearer {key}").parse().unwrap(),
+            );
+            headers
+        };
+
+        assert!(authorizer.authorize(&code.id.to_string(), &headers(&invoker)));
+        assert!(authorizer.authorize("backend", &headers(&invoker)));
+        assert!(authorizer.authorize(&code.id.to_string(), &headers(&admin)));
+        assert!(!authorizer.authorize(&code.id.to_string(), &headers(&deployer)));
+        assert!(!authorizer.authorize(&code.id.to_string(), &headers(&outsider)));
+        assert!(!authorizer.authorize(&code.id.to_string(), &headers(&reader)));
+        assert!(!authorizer.authorize(&code.id.to_string(), &HeaderMap::new()));
+        assert!(!authorizer.authorize("frontend", &headers(&invoker)));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Environment bindings of a code, like API keys and config.
+///
+/// Looked up per request, so a changed binding applies without redeploying the code.
+/// `Debug` only shows the names.
+#[derive(Clone, Default)]
+pub struct Env(Arc<BTreeMap<String, String>>);
+
+impl Env {
+    pub fn new(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self(Arc::new(vars.into_iter().collect()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub(crate) fn vars(&self) -> Arc<BTreeMap<String, String>> {
+        self.0.clone()
+    }
+}
+
+impl std::fmt::Debug for Env {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.0.keys().map(|name| (name, "<redacted>")))
+            .finish()
+    }
+}
+
+/// Where [`Fn0`](crate::Fn0) gets the [`Env`] of a code, e.g. doc-db.
+pub trait EnvSource: Send + Sync + 'static {
+    fn env<'a>(&'a self, code_id: &'a str) -> BoxFuture<'a, Result<Env>>;
+}
+
+/// No code has bindings.
+pub struct NoEnv;
+
+impl EnvSource for NoEnv {
+    fn env<'a>(&'a self, _code_id: &'a str) -> BoxFuture<'a, Result<Env>> {
+        Box::pin(async { Ok(Env::default()) })
+    }
+}
+
+/// Reads the bindings that hq stores encrypted in doc-db. Wrap it in a
+/// [`CachedEnvSource`], every call is a doc-db round-trip.
+///
+/// Code ids are the doc-db ids in decimal, other ids have no bindings unless they are
+/// aliased with [`DocDbEnvSource::with_alias`].
+pub struct DocDbEnvSource {
+    doc_db: doc_db::DocDb,
+    key: doc_db::EnvKey,
+    aliases: HashMap<String, u64>,
+}
+
+impl DocDbEnvSource {
+    pub fn new(doc_db: doc_db::DocDb, key: doc_db::EnvKey) -> Self {
+        Self {
+            doc_db,
+            key,
+            aliases: Default::default(),
+        }
+    }
+
+    /// Serves the bindings of doc-db code `doc_db_code_id` to `code_id`, e.g. forte's
+    /// `backend`.
+    pub fn with_alias(mut self, code_id: impl Into<String>, doc_db_code_id: u64) -> Self {
+        self.aliases.insert(code_id.into(), doc_db_code_id);
+        self
+    }
+}
+
+impl EnvSource for DocDbEnvSource {
+    fn env<'a>(&'a self, code_id: &'a str) -> BoxFuture<'a, Result<Env>> {
+        Box::pin(async move {
+            let Some(doc_db_code_id) = self
+                .aliases
+                .get(code_id)
+                .copied()
+                .or_else(|| code_id.parse().ok())
+            else {
+                return Ok(Env::default());
+            };
+            let bindings = self
+                .doc_db
+                .get_env_bindings(&self.key, doc_db_code_id)
+                .await?;
+            Ok(bindings
+                .map(|bindings| Env::new(bindings.vars))
+                .unwrap_or_default())
+        })
+    }
+}
+
+/// Keeps each code's [`Env`] for `ttl`, so the source is not hit on every request
+/// while updates still show up within `ttl`.
+pub struct CachedEnvSource<S> {
+    source: S,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Env)>>,
+}
+
+impl<S: EnvSource> CachedEnvSource<S> {
+    pub fn new(source: S, ttl: Duration) -> Self {
+        Self {
+            source,
+            ttl,
+            entries: Default::default(),
+        }
+    }
+}
+
+impl<S: EnvSource> EnvSource for CachedEnvSource<S> {
+    fn env<'a>(&'a self, code_id: &'a str) -> BoxFuture<'a, Result<Env>> {
+        Box::pin(async move {
+            if let Some((fetched_at, env)) = self.entries.lock().unwrap().get(code_id)
+                && fetched_at.elapsed() < self.ttl
+            {
+                return Ok(env.clone());
+            }
+
+            let env = self.source.env(code_id).await?;
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(code_id.to_string(), (Instant::now(), env.clone()));
+            Ok(env)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counting {
+        fetches: Arc<AtomicUsize>,
+    }
+
+    impl EnvSource for Counting {
+        fn env<'a>(&'a self, _code_id: &'a str) -> BoxFuture<'a, Result<Env>> {
+            let fetch = self.fetches.fetch_add(1, Ordering::Relaxed);
+            Box::pin(async move { Ok(Env::new([("FETCH".to_string(), fetch.to_string())])) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_env_source_refreshes_after_ttl() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let source = CachedEnvSource::new(
+            Counting {
+                fetches: fetches.clone(),
+            },
+            Duration::from_millis(50),
+        );
+
+        source.env("a").await.unwrap();
+        source.env("a").await.unwrap();
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let env = source.env("a").await.unwrap();
+        assert_eq!(fetches.load(Ordering::Relaxed), 2);
+        assert_eq!(env.iter().collect::<Vec<_>>(), vec![("FETCH", "1")]);
+    }
+
+    #[tokio::test]
+    async fn test_doc_db_env_source() {
+        let path =
+            std::env::temp_dir().join(format!("fn0-env-test-{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let doc_db = doc_db::DocDb::new_local(&path).await.unwrap();
+        doc_db.migrate().await.unwrap();
+        let key = doc_db::EnvKey::new([7; 32]);
+        let bindings = doc_db::EnvBindings {
+            vars: BTreeMap::from([("API_KEY".to_string(), "hunter2".to_string())]),
+        };
+        doc_db.set_env_bindings(&key, 3, &bindings).await.unwrap();
+        let source = DocDbEnvSource::new(doc_db, key).with_alias("backend", 3);
+
+        for code_id in ["3", "backend"] {
+            let env = source.env(code_id).await.unwrap();
+            assert_eq!(env.iter().collect::<Vec<_>>(), vec![("API_KEY", "hunter2")]);
+        }
+        assert_eq!(source.env("4").await.unwrap().iter().count(), 0);
+        assert_eq!(source.env("frontend").await.unwrap().iter().count(), 0);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_debug_redacts_values() {
+        let env = Env::new([("API_KEY".to_string(), "hunter2".to_string())]);
+        let debug = format!("{env:?}");
+
+        assert!(debug.contains("API_KEY"));
+        assert!(!debug.contains("hunter2"));
+    }
+}
@@ -1,9 +1,12 @@
-use crate::{Body, Request, Response, telemetry};
+use crate::{Body, Env, Request, Response, telemetry};
 use adapt_cache::AdaptCache;
 use anyhow::{Result, anyhow};
 use bytes::Bytes;
 use http_body_util::BodyExt;
-use measure_cpu_time::{Clock, TimeTracker, measure_cpu_time};
+use measure_cpu_time::{
+    AttributedTimeTracker, Category, Clock, SystemClock, TimeTracker, measure_attributed,
+    measure_cpu_time,
+};
 use std::{
     sync::{
         Arc,
@@ -11,12 +14,13 @@ use std::{
     },
     time::Duration,
 };
-use tokio::sync::{mpsc::Sender, oneshot};
+use tokio::sync::{Notify, mpsc::Sender, oneshot};
 use wasmtime::{
-    Config, Engine, InstanceAllocationStrategy, PoolingAllocationConfig, Store,
-    component::{Component, Linker},
+    CallHook, Config, Engine, InstanceAllocationStrategy, PoolingAllocationConfig, Store,
+    component::{Component, HasSelf, Linker},
 };
 use wasmtime_wasi::*;
+use wasmtime_wasi_config::{WasiConfig, WasiConfigVariables};
 use wasmtime_wasi_http::{
     WasiHttpCtx, WasiHttpView,
     bindings::{
@@ -25,10 +29,23 @@ use wasmtime_wasi_http::{
     },
 };
 
+/// CPU time a single request may use, for both wasm and JS code.
+pub(crate) const CPU_TIME_LIMIT: Duration = Duration::from_millis(1000);
+
+/// CPU time of the work left after the response, through `ctx.waitUntil` in JS or
+/// `fn0:runtime/background.wait-until` in wasm.
+pub(crate) const WAIT_UNTIL_CPU_TIME_LIMIT: Duration = Duration::from_millis(500);
+
+/// Wall time of the work left after the response.
+pub(crate) const WAIT_UNTIL_WALL_TIME_LIMIT: Duration = Duration::from_secs(10);
+
+wasmtime::component::bindgen!("host" in "wit");
+
 pub struct Job {
     pub req: Request,
     pub res_tx: oneshot::Sender<Response>,
     pub code_id: String,
+    pub env: Env,
 }
 
 pub struct WasmExecutor {
@@ -47,6 +64,12 @@ impl WasmExecutor {
         let mut linker = Linker::new(&engine);
         wasmtime_wasi::p2::add_to_linker_async(&mut linker).unwrap();
         wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker).unwrap();
+        fn0::runtime::background::add_to_linker::<_, HasSelf<_>>(&mut linker, |state| state)
+            .unwrap();
+        wasmtime_wasi_config::add_to_linker(&mut linker, |state: &mut ClientState<C>| {
+            WasiConfig::from(&state.config_vars)
+        })
+        .unwrap();
 
         tokio::spawn({
             let proxy_cache = proxy_cache.clone();
@@ -85,12 +108,13 @@ impl WasmExecutor {
         Self { job_tx }
     }
 
-    pub(crate) async fn run(&self, code_id: &str, request: Request) -> Result<Response> {
+    pub(crate) async fn run(&self, code_id: &str, request: Request, env: Env) -> Result<Response> {
         let (res_tx, res_rx) = oneshot::channel();
         let job = Job {
             req: request,
             res_tx,
             code_id: code_id.to_string(),
+            env,
         };
 
         self.job_tx
@@ -102,16 +126,22 @@ impl WasmExecutor {
     }
 }
 
-pub fn engine_config() -> Config {
-    const MB: usize = 1024 * 1024;
+const MAX_MEMORY_MB: usize = 128;
 
+/// How many wasm instances fit in the memory of this machine.
+pub(crate) fn max_instance_count() -> usize {
     let mut sys = sysinfo::System::new_all();
     sys.refresh_all();
 
     let total_memory_bytes = sys.total_memory() as usize;
     let total_memory_mb = total_memory_bytes / (1024 * 1024);
-    const MAX_MEMORY_MB: usize = 128;
-    let max_instance_count = total_memory_mb / MAX_MEMORY_MB;
+    total_memory_mb / MAX_MEMORY_MB
+}
+
+pub fn engine_config() -> Config {
+    const MB: usize = 1024 * 1024;
+
+    let max_instance_count = max_instance_count();
 
     let mut pooling_allocation_config = PoolingAllocationConfig::new();
     pooling_allocation_config
@@ -158,7 +188,7 @@ async fn run_job<A, C>(
         return;
     };
 
-    let response = handle_request(proxy_pre, job.req, job.code_id, clock).await;
+    let response = handle_request(proxy_pre, job.req, job.code_id, job.env, clock).await;
 
     let _ = job.res_tx.send(response);
 }
@@ -180,7 +210,7 @@ where
             let proxy_pre = ProxyPre::new(instance_pre)?;
 
             telemetry::create_instance(&code_id);
-            Ok((proxy_pre, bytes.len()))
+            Ok((proxy_pre, resident_size(&component, bytes.len())))
         })
         .await
     {
@@ -192,37 +222,73 @@ where
     }
 }
 
+/// Estimated memory a cached `ProxyPre` keeps alive. `deserialize` copies the
+/// artifact into its own mapping, and everything outside the text section can be
+/// copied once more into copy-on-write memory images on first instantiation.
+fn resident_size(component: &Component, artifact_len: usize) -> usize {
+    let text = component.image_range();
+    let text_len = text.end as usize - text.start as usize;
+    artifact_len + artifact_len.saturating_sub(text_len)
+}
+
 async fn handle_request<C>(
     pre: ProxyPre<ClientState<C>>,
     req: Request,
     code_id: String,
+    env: Env,
     clock: C,
 ) -> Response
 where
     C: Clock + Send + 'static,
 {
-    let time_tracker = TimeTracker::new(clock);
+    // `clock` drives the CPU limit, which counts guest, host-call and GC time alike,
+    // so a guest can't dodge it by spinning in a host call. Wall time in polls is
+    // only reported.
+    let wall_tracker = TimeTracker::new(SystemClock);
+    let time_tracker = AttributedTimeTracker::new(clock, Category::Guest);
     let is_timeout = Arc::new(AtomicBool::new(false));
+    let wait_until_started = Arc::new(Notify::new());
+    let response_sent = Arc::new(ResponseSent::default());
 
     let mut store = Store::new(
         pre.engine(),
         ClientState {
             table: ResourceTable::new(),
-            wasi: WasiCtx::builder().inherit_stdio().build(),
+            // the same bindings back both `wasi:cli/environment` and `wasi:config/store`
+            wasi: WasiCtx::builder()
+                .inherit_stdio()
+                .envs(&env.iter().collect::<Vec<_>>())
+                .build(),
+            config_vars: WasiConfigVariables::from_iter(env.iter()),
             http: WasiHttpCtx::new(),
             time_tracker: time_tracker.clone(),
             code_id: code_id.clone(),
             is_timeout: is_timeout.clone(),
+            wait_until: None,
+            wait_until_started: wait_until_started.clone(),
+            response_sent: response_sent.clone(),
         },
     );
     store.epoch_deadline_trap();
     store.set_epoch_deadline(1);
     store.epoch_deadline_async_yield_and_update(1);
     store.epoch_deadline_callback({
-        |context| {
-            let state = context.data();
-            let cpu_time = state.time_tracker.duration();
-            if cpu_time > Duration::from_millis(1000) {
+        |mut context| {
+            let state = context.data_mut();
+            let cpu_time = state.time_tracker.total();
+            // the request limit holds until the response is out, even after `wait-until`
+            if let Some(wait_until) = &mut state.wait_until
+                && state.response_sent.get()
+            {
+                let cpu_time_at_start = *wait_until.cpu_time_at_start.get_or_insert(cpu_time);
+                let used = cpu_time.saturating_sub(cpu_time_at_start);
+                if used > WAIT_UNTIL_CPU_TIME_LIMIT {
+                    telemetry::wait_until_timeout(&state.code_id, "cpu", used);
+                    return Ok(wasmtime::UpdateDeadline::Interrupt);
+                }
+                return Ok(wasmtime::UpdateDeadline::Continue(1));
+            }
+            if cpu_time > CPU_TIME_LIMIT {
                 telemetry::cpu_timeout(&state.code_id, cpu_time);
                 state.is_timeout.store(true, Ordering::Relaxed);
                 return Ok(wasmtime::UpdateDeadline::Interrupt);
@@ -231,6 +297,15 @@ where
         }
     });
 
+    store.call_hook(|context, hook| {
+        match hook {
+            CallHook::CallingHost => context.data().time_tracker.enter(Category::HostCall),
+            CallHook::ReturningFromHost => context.data().time_tracker.exit(),
+            CallHook::CallingWasm | CallHook::ReturningFromWasm => {}
+        }
+        Ok(())
+    });
+
     let (tx, rx) = tokio::sync::oneshot::channel();
     let req: wasmtime::component::Resource<wasmtime_wasi_http::types::HostIncomingRequest> =
         match store.data_mut().new_incoming_request(
@@ -267,22 +342,40 @@ where
 
     let task = tokio::task::spawn({
         let code_id = code_id.clone();
+        let response_sent = response_sent.clone();
         async move {
-            let result = measure_cpu_time(
-                time_tracker.clone(),
-                proxy
-                    .wasi_http_incoming_handler()
-                    .call_handle(store, req, out),
-            )
-            .await;
+            let call = measure_cpu_time(
+                wall_tracker.clone(),
+                measure_attributed(
+                    time_tracker.clone(),
+                    proxy
+                        .wasi_http_incoming_handler()
+                        .call_handle(store, req, out),
+                ),
+            );
+            // the response is already out by then, so dropping the call is all there is to do
+            let result = tokio::select! {
+                result = call => result,
+                () = wait_until_deadline(wait_until_started, response_sent) => {
+                    telemetry::wait_until_timeout(&code_id, "wall", WAIT_UNTIL_WALL_TIME_LIMIT);
+                    Ok(())
+                }
+            };
 
-            telemetry::cpu_time(&code_id, time_tracker.duration());
+            telemetry::cpu_time(&code_id, time_tracker.total());
+            for (category, cpu_time) in time_tracker.breakdown() {
+                telemetry::cpu_time_by_category(&code_id, category.as_str(), cpu_time);
+            }
+            telemetry::wall_time_in_poll(&code_id, wall_tracker.duration());
 
             result
         }
     });
 
     let result = rx.await;
+    if result.is_ok() {
+        response_sent.set();
+    }
 
     if let Err(_oneshot_recv_err) = result {
         let result = task.await;
@@ -325,6 +418,33 @@ where
     internal_error_response()
 }
 
+/// Resolves `WAIT_UNTIL_WALL_TIME_LIMIT` after the guest calls `wait-until` and the
+/// response is out, never before.
+async fn wait_until_deadline(started: Arc<Notify>, response_sent: Arc<ResponseSent>) {
+    tokio::join!(started.notified(), response_sent.notify.notified());
+    tokio::time::sleep(WAIT_UNTIL_WALL_TIME_LIMIT).await;
+}
+
+/// Set once the guest's response reaches the caller. Until then the guest runs under
+/// the request limits, whether it called `wait-until` or not.
+#[derive(Default)]
+struct ResponseSent {
+    sent: AtomicBool,
+    notify: Notify,
+}
+
+impl ResponseSent {
+    fn set(&self) {
+        self.sent.store(true, Ordering::Relaxed);
+        // `notify_one` keeps the permit if the deadline is not polled yet
+        self.notify.notify_one();
+    }
+
+    fn get(&self) -> bool {
+        self.sent.load(Ordering::Relaxed)
+    }
+}
+
 fn response(status: hyper::StatusCode, body: Bytes) -> Response {
     let body = http_body_util::Full::new(body).map_err(|never| match never {});
     let mut res = hyper::Response::new(Body::new(body));
@@ -332,13 +452,34 @@ fn response(status: hyper::StatusCode, body: Bytes) -> Response {
     res
 }
 
-fn timeout_response() -> Response {
+pub(crate) fn timeout_response() -> Response {
     response(
         hyper::StatusCode::GATEWAY_TIMEOUT,
         Bytes::from("Gateway Timeout"),
     )
 }
 
+pub(crate) fn service_unavailable_response() -> Response {
+    response(
+        hyper::StatusCode::SERVICE_UNAVAILABLE,
+        Bytes::from("Service Unavailable"),
+    )
+}
+
+pub(crate) fn too_many_requests_response(retry_after_secs: u64) -> Response {
+    let mut res = response(
+        hyper::StatusCode::TOO_MANY_REQUESTS,
+        Bytes::from("Too Many Requests"),
+    );
+    res.headers_mut()
+        .insert(hyper::header::RETRY_AFTER, retry_after_secs.into());
+    res
+}
+
+pub(crate) fn unauthorized_response() -> Response {
+    response(hyper::StatusCode::UNAUTHORIZED, Bytes::from("Unauthorized"))
+}
+
 fn internal_error_response() -> Response {
     response(
         hyper::StatusCode::INTERNAL_SERVER_ERROR,
@@ -348,11 +489,34 @@ fn internal_error_response() -> Response {
 
 pub struct ClientState<C: Clock> {
     wasi: WasiCtx,
+    config_vars: WasiConfigVariables,
     http: WasiHttpCtx,
     table: ResourceTable,
-    time_tracker: TimeTracker<C>,
+    time_tracker: AttributedTimeTracker<C>,
     code_id: String,
     is_timeout: Arc<AtomicBool>,
+    wait_until: Option<WaitUntil>,
+    wait_until_started: Arc<Notify>,
+    response_sent: Arc<ResponseSent>,
+}
+
+/// Set once the guest moves the rest of its call to the background.
+struct WaitUntil {
+    /// Taken once the response is out, as the background budget starts there.
+    cpu_time_at_start: Option<Duration>,
+}
+
+impl<C: Clock> fn0::runtime::background::Host for ClientState<C> {
+    fn wait_until(&mut self) {
+        if self.wait_until.is_some() {
+            return;
+        }
+        self.wait_until = Some(WaitUntil {
+            cpu_time_at_start: None,
+        });
+        // `notify_one` keeps the permit if the deadline is not polled yet
+        self.wait_until_started.notify_one();
+    }
 }
 
 impl<C: Clock> WasiView for ClientState<C> {
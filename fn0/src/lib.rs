@@ -1,5 +1,9 @@
+mod auth;
 mod deployment;
+mod env;
 mod execute;
+mod quota;
+mod rate_limit;
 pub mod telemetry;
 
 use adapt_cache::AdaptCache;
@@ -7,10 +11,17 @@ use anyhow::*;
 use bytes::Bytes;
 use deployment::*;
 use execute::*;
+pub use auth::{AllowAll, ApiKeyAuthorizer, Authorize, bearer_token};
 pub use deployment::{CodeKind, DeploymentMap};
+pub use env::{CachedEnvSource, DocDbEnvSource, Env, EnvSource, NoEnv};
+pub use quota::{DocDbQuotaSource, NoQuota, QuotaSource, TenantQuota, cache_eviction};
+pub use rate_limit::RateLimit;
 use http_body_util::combinators::UnsyncBoxBody;
-use measure_cpu_time::SystemClock;
+use measure_cpu_time::ThreadCpuClock;
+use quota::QuotaEnforcer;
+use rate_limit::{RateLimiter, retry_after_secs};
 use std::string::FromUtf8Error;
+use std::sync::Arc;
 use wasmtime::Engine;
 use wasmtime_wasi_http::bindings::ProxyPre;
 
@@ -25,28 +36,115 @@ where
     js_cache: J,
     deployment_map: DeploymentMap,
     wasm_executor: WasmExecutor,
+    js_pool: ski::IsolatePool,
+    env_source: Arc<dyn EnvSource>,
+    authorizer: Arc<dyn Authorize>,
+    quota_source: Arc<dyn QuotaSource>,
+    quota_enforcer: Arc<QuotaEnforcer>,
+    rate_limiter: RateLimiter,
 }
 
 impl<J> Fn0<J>
 where
     J: AdaptCache<String, FromUtf8Error>,
 {
+    /// Build `wasm_proxy_cache` and `js_cache` with [`cache_eviction`] to keep codes
+    /// and tenants from flushing each other's warm entries.
     pub fn new<W>(wasm_proxy_cache: W, js_cache: J, deployment_map: DeploymentMap) -> Self
     where
-        W: AdaptCache<ProxyPre<ClientState<SystemClock>>, wasmtime::Error>,
+        W: AdaptCache<ProxyPre<ClientState<ThreadCpuClock>>, wasmtime::Error>,
     {
         Self {
             js_cache,
             deployment_map,
-            wasm_executor: WasmExecutor::new(wasm_proxy_cache, SystemClock),
+            wasm_executor: WasmExecutor::new(wasm_proxy_cache, ThreadCpuClock),
+            js_pool: ski::IsolatePool::new(ski::PoolConfig {
+                limits: ski::Limits {
+                    cpu_time: CPU_TIME_LIMIT,
+                    wait_until_cpu_time: WAIT_UNTIL_CPU_TIME_LIMIT,
+                    wait_until_wall_time: WAIT_UNTIL_WALL_TIME_LIMIT,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with_wait_until_reporter(report_js_wait_until),
+            env_source: Arc::new(NoEnv),
+            authorizer: Arc::new(AllowAll),
+            quota_source: Arc::new(NoQuota),
+            quota_enforcer: QuotaEnforcer::new(max_instance_count()),
+            rate_limiter: Default::default(),
         }
     }
+
+    /// Codes get no env bindings unless a source is set.
+    pub fn with_env_source(mut self, env_source: impl EnvSource) -> Self {
+        self.env_source = Arc::new(env_source);
+        self
+    }
+
+    /// Requests the authorizer rejects get a 401 before the code is loaded. By default
+    /// every request is allowed.
+    pub fn with_authorizer(mut self, authorizer: impl Authorize) -> Self {
+        self.authorizer = Arc::new(authorizer);
+        self
+    }
+
+    /// Requests over the rate limit of their code or tenant, or over their tenant's
+    /// share of the host, get a 429. By default no code is limited.
+    pub fn with_quota_source(mut self, quota_source: impl QuotaSource) -> Self {
+        self.quota_source = Arc::new(quota_source);
+        self
+    }
+
+    /// Where JS codes keep their `caches` and `Deno.openKv` data. By default caches
+    /// stay in memory and KV is unavailable.
+    pub fn with_js_storage(mut self, storage: ski::StorageConfig) -> Self {
+        self.js_pool = self.js_pool.with_storage(storage);
+        self
+    }
+
     pub async fn run(&self, code_id: &str, request: Request) -> Result<Response> {
         let Some(code_kind) = self.deployment_map.code_kind(code_id) else {
             return Err(anyhow!("code_id not found"));
         };
+        if !self.authorizer.authorize(code_id, request.headers()) {
+            telemetry::unauthorized(code_id);
+            return Ok(unauthorized_response());
+        }
+        let quota = self.quota_source.quota(code_id);
+        if let Err(limited) = self.rate_limiter.check(
+            code_id,
+            self.quota_source.code_rate_limit(code_id),
+            quota.map(|quota| {
+                (
+                    quota.tenant_id,
+                    RateLimit::per_sec(quota.max_requests_per_sec),
+                )
+            }),
+        ) {
+            telemetry::rate_limited(code_id, limited.scope.as_str());
+            return Ok(too_many_requests_response(retry_after_secs(
+                limited.retry_after,
+            )));
+        }
+        // held until the response head is ready, a streamed body doesn't count
+        let _quota_permit = match quota {
+            Some(quota) => match self.quota_enforcer.acquire(quota) {
+                Some(permit) => Some(permit),
+                None => {
+                    telemetry::rate_limited(code_id, "host_share");
+                    return Ok(too_many_requests_response(1));
+                }
+            },
+            None => None,
+        };
+        let env = self
+            .env_source
+            .env(code_id)
+            .await
+            .map_err(|err| anyhow!("Failed to get env bindings: {:?}", err))?;
         match code_kind {
-            CodeKind::Wasm => Ok(self.wasm_executor.run(code_id, request).await?),
+            CodeKind::Wasm => Ok(self.wasm_executor.run(code_id, request, env).await?),
             CodeKind::Js => {
                 let js_code = self
                     .js_cache
@@ -55,13 +153,48 @@ where
                     })
                     .await
                     .map_err(|err| anyhow!("Failed to get JS code: {:?}", err))?;
-                let response = ski::run(&js_code, request).await?;
-                Ok(response)
+                let limits = self.js_pool.limits();
+                match self
+                    .js_pool
+                    .run(code_id, &js_code, request, env.vars())
+                    .await
+                {
+                    Ok(response) => Ok(response),
+                    Err(error) => match error.downcast::<ski::Termination>() {
+                        Ok(ski::Termination::CpuTimeExceeded) => {
+                            telemetry::cpu_timeout(code_id, limits.cpu_time);
+                            Ok(timeout_response())
+                        }
+                        Ok(ski::Termination::WallTimeExceeded) => {
+                            telemetry::wall_timeout(code_id, limits.wall_time);
+                            Ok(timeout_response())
+                        }
+                        Ok(ski::Termination::HeapLimitExceeded) => {
+                            telemetry::heap_limit_exceeded(code_id);
+                            Ok(service_unavailable_response())
+                        }
+                        Err(error) => Err(error),
+                    },
+                }
             }
         }
     }
 }
 
+/// Reports `ctx.waitUntil` work of a JS code that failed after its response was out.
+fn report_js_wait_until(code_id: &str, error: &Error) {
+    match error.downcast_ref::<ski::Termination>() {
+        Some(ski::Termination::CpuTimeExceeded) => {
+            telemetry::wait_until_timeout(code_id, "cpu", WAIT_UNTIL_CPU_TIME_LIMIT);
+        }
+        Some(ski::Termination::WallTimeExceeded) => {
+            telemetry::wait_until_timeout(code_id, "wall", WAIT_UNTIL_WALL_TIME_LIMIT);
+        }
+        Some(ski::Termination::HeapLimitExceeded) => telemetry::heap_limit_exceeded(code_id),
+        None => telemetry::wait_until_failed(code_id, &format!("{error:#}")),
+    }
+}
+
 pub fn compile(wasm_bytes: &[u8]) -> Result<Vec<u8>> {
     let engine = Engine::new(&engine_config())?;
 
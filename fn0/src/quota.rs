@@ -0,0 +1,294 @@
+use crate::rate_limit::RateLimit;
+use adapt_cache::Eviction;
+use doc_db::DocDb;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+/// Limits shared by all codes of a tenant on this host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TenantQuota {
+    pub tenant_id: u64,
+    /// Share of the host's instance capacity the tenant's in-flight requests may take.
+    pub max_host_share_percent: u8,
+    pub max_requests_per_sec: u32,
+}
+
+/// Where [`Fn0`](crate::Fn0) gets the limits of a code and its tenant, e.g. a table
+/// synced from doc-db. Looked up on every request, so it should not wait on a
+/// database.
+pub trait QuotaSource: Send + Sync + 'static {
+    /// `None` for codes without a tenant, which are not limited.
+    fn quota(&self, code_id: &str) -> Option<TenantQuota>;
+
+    /// Limit of the code on its own, below its tenant's.
+    fn code_rate_limit(&self, _code_id: &str) -> Option<RateLimit> {
+        None
+    }
+}
+
+impl<Q: QuotaSource + ?Sized> QuotaSource for Arc<Q> {
+    fn quota(&self, code_id: &str) -> Option<TenantQuota> {
+        (**self).quota(code_id)
+    }
+
+    fn code_rate_limit(&self, code_id: &str) -> Option<RateLimit> {
+        (**self).code_rate_limit(code_id)
+    }
+}
+
+/// Eviction for the wasm and JS caches passed to [`Fn0::new`](crate::Fn0::new):
+/// TinyLFU admission, so codes invoked once don't flush warm ones, and at most
+/// `per_tenant_budget` bytes per tenant of `quota_source`, so one tenant's
+/// deployments can't evict everyone else's.
+///
+/// Share `quota_source` with [`Fn0::with_quota_source`](crate::Fn0::with_quota_source)
+/// through an `Arc`, or pass a clone of a [`DocDbQuotaSource`], whose clones share
+/// one table.
+pub fn cache_eviction(quota_source: impl QuotaSource, per_tenant_budget: usize) -> Eviction {
+    Eviction {
+        admission: true,
+        per_tenant_budget: Some(per_tenant_budget),
+        tenant_of: Some(Arc::new(move |code_id: &str| {
+            quota_source
+                .quota(code_id)
+                .map(|quota| quota.tenant_id.to_string())
+        })),
+    }
+}
+
+/// No code is limited.
+pub struct NoQuota;
+
+impl QuotaSource for NoQuota {
+    fn quota(&self, _code_id: &str) -> Option<TenantQuota> {
+        None
+    }
+}
+
+/// The quotas hq keeps in doc-db, looked up by the code's id.
+///
+/// Kept in memory. Run [`DocDbQuotaSource::run_sync`] so new codes and changed
+/// quotas are picked up. Clones share the same table.
+#[derive(Clone)]
+pub struct DocDbQuotaSource {
+    doc_db: DocDb,
+    quotas: Arc<RwLock<HashMap<String, TenantQuota>>>,
+    aliases: Vec<(String, u64)>,
+}
+
+impl DocDbQuotaSource {
+    /// Fetches the quotas once, so codes are limited before the first sync.
+    pub async fn new(doc_db: DocDb) -> anyhow::Result<Self> {
+        Self::with_aliases(doc_db, []).await
+    }
+
+    /// Like [`DocDbQuotaSource::new`], also limiting each `code_id` as doc-db code
+    /// `doc_db_code_id`, e.g. forte's `backend`.
+    pub async fn with_aliases(
+        doc_db: DocDb,
+        aliases: impl IntoIterator<Item = (String, u64)>,
+    ) -> anyhow::Result<Self> {
+        let source = Self {
+            doc_db,
+            quotas: Default::default(),
+            aliases: aliases.into_iter().collect(),
+        };
+        source.refresh().await?;
+        Ok(source)
+    }
+
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let mut quotas = self
+            .doc_db
+            .code_quotas()
+            .await?
+            .into_iter()
+            .map(|code_quota| {
+                (
+                    code_quota.code_id.to_string(),
+                    TenantQuota {
+                        tenant_id: code_quota.tenant_id,
+                        max_host_share_percent: code_quota.quota.max_host_share_percent,
+                        max_requests_per_sec: code_quota.quota.max_requests_per_sec,
+                    },
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        for (code_id, doc_db_code_id) in &self.aliases {
+            if let Some(quota) = quotas.get(&doc_db_code_id.to_string()).copied() {
+                quotas.insert(code_id.clone(), quota);
+            }
+        }
+        *self.quotas.write().unwrap() = quotas;
+        Ok(())
+    }
+
+    /// Refreshes every `interval`, which bounds how long a changed quota takes to apply.
+    pub async fn run_sync(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.refresh().await {
+                warn!(%err, "Failed to refresh quotas");
+            }
+        }
+    }
+}
+
+impl QuotaSource for DocDbQuotaSource {
+    fn quota(&self, code_id: &str) -> Option<TenantQuota> {
+        self.quotas.read().unwrap().get(code_id).copied()
+    }
+}
+
+/// Tracks how much of this host each tenant's in-flight requests take.
+pub(crate) struct QuotaEnforcer {
+    capacity: usize,
+    in_flight: Mutex<HashMap<u64, usize>>,
+}
+
+impl QuotaEnforcer {
+    /// `capacity` is how many instances the host runs at once.
+    pub(crate) fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            capacity,
+            in_flight: Default::default(),
+        })
+    }
+
+    /// Counts a request of the tenant in until the permit drops, or `None` when the
+    /// tenant already takes its share of the host.
+    pub(crate) fn acquire(self: &Arc<Self>, quota: TenantQuota) -> Option<QuotaPermit> {
+        let max_in_flight = (self.capacity * quota.max_host_share_percent as usize / 100).max(1);
+
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let in_flight = in_flight.entry(quota.tenant_id).or_default();
+        if *in_flight >= max_in_flight {
+            return None;
+        }
+        *in_flight += 1;
+
+        Some(QuotaPermit {
+            enforcer: self.clone(),
+            tenant_id: quota.tenant_id,
+        })
+    }
+}
+
+pub(crate) struct QuotaPermit {
+    enforcer: Arc<QuotaEnforcer>,
+    tenant_id: u64,
+}
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        let mut in_flight = self.enforcer.in_flight.lock().unwrap();
+        if let Some(in_flight) = in_flight.get_mut(&self.tenant_id) {
+            *in_flight -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota(tenant_id: u64, max_host_share_percent: u8) -> TenantQuota {
+        TenantQuota {
+            tenant_id,
+            max_host_share_percent,
+            max_requests_per_sec: 100,
+        }
+    }
+
+    #[test]
+    fn test_host_share_frees_on_drop() {
+        let enforcer = QuotaEnforcer::new(10);
+        let quota = quota(1, 20);
+
+        let first = enforcer.acquire(quota).unwrap();
+        let _second = enforcer.acquire(quota).unwrap();
+        assert!(enforcer.acquire(quota).is_none());
+
+        drop(first);
+        assert!(enforcer.acquire(quota).is_some());
+    }
+
+    #[test]
+    fn test_cache_eviction_maps_codes_to_tenants() {
+        struct ByPrefix;
+
+        impl QuotaSource for ByPrefix {
+            fn quota(&self, code_id: &str) -> Option<TenantQuota> {
+                let (tenant_id, _) = code_id.split_once('-')?;
+                Some(quota(tenant_id.parse().ok()?, 100))
+            }
+        }
+
+        let eviction = cache_eviction(Arc::new(ByPrefix), 64);
+        let tenant_of = eviction.tenant_of.unwrap();
+
+        assert!(eviction.admission);
+        assert_eq!(eviction.per_tenant_budget, Some(64));
+        assert_eq!(tenant_of("7-web").as_deref(), Some("7"));
+        assert_eq!(tenant_of("backend"), None);
+    }
+
+    #[tokio::test]
+    async fn test_doc_db_quota_source_follows_quotas() {
+        let path =
+            std::env::temp_dir().join(format!("fn0-quota-test-{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let doc_db = DocDb::new_local(&path).await.unwrap();
+        doc_db.migrate().await.unwrap();
+        let tenant = doc_db.create_tenant("acme").await.unwrap();
+        let project = doc_db.create_project(tenant.id, "web").await.unwrap();
+        let code = doc_db
+            .create_code(project.id, doc_db::CodeKind::Js)
+            .await
+            .unwrap();
+
+        let source =
+            DocDbQuotaSource::with_aliases(doc_db.clone(), [("backend".to_string(), code.id)])
+                .await
+                .unwrap();
+        let default = doc_db::Quota::default();
+        assert_eq!(
+            source.quota(&code.id.to_string()),
+            Some(TenantQuota {
+                tenant_id: tenant.id,
+                max_host_share_percent: default.max_host_share_percent,
+                max_requests_per_sec: default.max_requests_per_sec,
+            })
+        );
+        assert_eq!(source.quota("backend"), source.quota(&code.id.to_string()));
+        assert_eq!(source.quota("frontend"), None);
+
+        doc_db
+            .set_quota(
+                tenant.id,
+                &doc_db::Quota {
+                    max_requests_per_sec: 7,
+                    ..default
+                },
+            )
+            .await
+            .unwrap();
+        source.refresh().await.unwrap();
+        assert_eq!(source.quota("backend").unwrap().max_requests_per_sec, 7);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_tenants_are_apart() {
+        let enforcer = QuotaEnforcer::new(1);
+        let _permit = enforcer.acquire(quota(1, 1)).unwrap();
+
+        assert!(enforcer.acquire(quota(2, 1)).is_some());
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A token bucket: `requests_per_sec` on average, up to `burst` at once.
+///
+/// A `burst` of 0 disables the limit, as no request could ever pass it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests_per_sec: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// Allows a second's worth of requests at once.
+    pub fn per_sec(requests_per_sec: u32) -> Self {
+        Self {
+            requests_per_sec,
+            burst: requests_per_sec,
+        }
+    }
+
+    fn is_disabled(&self) -> bool {
+        self.burst == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RateLimitScope {
+    Code,
+    Tenant,
+}
+
+impl RateLimitScope {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            RateLimitScope::Code => "code",
+            RateLimitScope::Tenant => "tenant",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RateLimited {
+    pub(crate) scope: RateLimitScope,
+    /// When the bucket that rejected has a token again.
+    pub(crate) retry_after: Duration,
+}
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    /// Keeps the tokens across a limit change, up to the new burst.
+    fn set_limit(&mut self, limit: RateLimit, now: Instant) {
+        self.refill(now);
+        self.limit = limit;
+        self.tokens = self.tokens.min(limit.burst as f64);
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.requests_per_sec as f64)
+            .min(self.limit.burst as f64);
+        self.updated_at = now;
+    }
+
+    /// How long until a token is there, zero if one is.
+    fn wait(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            return Duration::ZERO;
+        }
+        if self.limit.requests_per_sec == 0 {
+            return Duration::MAX;
+        }
+        Duration::from_secs_f64((1.0 - self.tokens) / self.limit.requests_per_sec as f64)
+    }
+}
+
+/// Token buckets of this host, per code_id and per tenant.
+#[derive(Default)]
+pub(crate) struct RateLimiter {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    codes: HashMap<String, TokenBucket>,
+    tenants: HashMap<u64, TokenBucket>,
+}
+
+impl RateLimiter {
+    /// Takes a token from every bucket the request falls in, or none if any is empty.
+    pub(crate) fn check(
+        &self,
+        code_id: &str,
+        code_limit: Option<RateLimit>,
+        tenant: Option<(u64, RateLimit)>,
+    ) -> Result<(), RateLimited> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let State { codes, tenants } = &mut *state;
+
+        let code_limit = code_limit.filter(|limit| !limit.is_disabled());
+        let tenant_limit = tenant.filter(|(_, limit)| !limit.is_disabled());
+
+        let mut buckets: Vec<(RateLimitScope, &mut TokenBucket)> = Vec::with_capacity(2);
+        match code_limit {
+            Some(limit) => {
+                let bucket = codes
+                    .entry(code_id.to_string())
+                    .or_insert_with(|| TokenBucket::new(limit, now));
+                if bucket.limit != limit {
+                    bucket.set_limit(limit, now);
+                }
+                buckets.push((RateLimitScope::Code, bucket));
+            }
+            None => {
+                codes.remove(code_id);
+            }
+        }
+        if let Some((tenant_id, limit)) = tenant_limit {
+            let bucket = tenants
+                .entry(tenant_id)
+                .or_insert_with(|| TokenBucket::new(limit, now));
+            if bucket.limit != limit {
+                bucket.set_limit(limit, now);
+            }
+            buckets.push((RateLimitScope::Tenant, bucket));
+        }
+
+        for (scope, bucket) in &mut buckets {
+            let retry_after = bucket.wait(now);
+            if !retry_after.is_zero() {
+                return Err(RateLimited {
+                    scope: *scope,
+                    retry_after,
+                });
+            }
+        }
+        for (_, bucket) in buckets {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+/// Whole seconds, at least 1, as `Retry-After` takes.
+pub(crate) fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().clamp(1.0, u32::MAX as f64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_refills_at_rate() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(
+            RateLimit {
+                requests_per_sec: 10,
+                burst: 2,
+            },
+            now,
+        );
+
+        assert_eq!(bucket.wait(now), Duration::ZERO);
+        bucket.tokens -= 1.0;
+        bucket.tokens -= 1.0;
+        let wait = bucket.wait(now);
+        assert!(wait > Duration::from_millis(99) && wait <= Duration::from_millis(101));
+
+        assert_eq!(
+            bucket.wait(now + Duration::from_millis(150)),
+            Duration::ZERO
+        );
+        // capped at burst
+        assert_eq!(bucket.wait(now + Duration::from_secs(10)), Duration::ZERO);
+        assert_eq!(bucket.tokens, 2.0);
+    }
+
+    #[test]
+    fn test_rejected_request_takes_no_token() {
+        let limiter = RateLimiter::default();
+        let code = RateLimit::per_sec(2);
+        let tenant = (1, RateLimit::per_sec(1));
+
+        assert_eq!(limiter.check("a", Some(code), Some(tenant)), Ok(()));
+        let rejected = limiter.check("a", Some(code), Some(tenant)).unwrap_err();
+        assert_eq!(rejected.scope, RateLimitScope::Tenant);
+        assert!(rejected.retry_after > Duration::ZERO);
+
+        // the code bucket still has its second token for a code of another tenant
+        assert_eq!(
+            limiter.check("a", Some(code), Some((2, RateLimit::per_sec(1)))),
+            Ok(())
+        );
+        assert_eq!(
+            limiter.check("a", Some(code), None).unwrap_err().scope,
+            RateLimitScope::Code
+        );
+    }
+
+    #[test]
+    fn test_zero_burst_disables_limit() {
+        let limiter = RateLimiter::default();
+        let tenant = (1, RateLimit::per_sec(0));
+
+        for _ in 0..3 {
+            assert_eq!(
+                limiter.check("a", Some(RateLimit::per_sec(0)), Some(tenant)),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn test_retry_after_secs() {
+        assert_eq!(retry_after_secs(Duration::from_millis(1)), 1);
+        assert_eq!(retry_after_secs(Duration::from_millis(1500)), 2);
+        assert_eq!(retry_after_secs(Duration::MAX), u32::MAX as u64);
+    }
+}
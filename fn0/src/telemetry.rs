@@ -124,6 +124,29 @@ pub fn cpu_time(code_id: &str, cpu_time: Duration) {
     );
 }
 
+pub fn cpu_time_by_category(code_id: &str, category: &'static str, cpu_time: Duration) {
+    let histogram = global::meter("fn0")
+        .f64_histogram("cpu_time_by_category_seconds")
+        .build();
+    histogram.record(
+        cpu_time.as_secs_f64(),
+        &[
+            KeyValue::new("code_id", code_id.to_string()),
+            KeyValue::new("category", category),
+        ],
+    );
+}
+
+pub fn wall_time_in_poll(code_id: &str, wall_time: Duration) {
+    let histogram = global::meter("fn0")
+        .f64_histogram("wall_time_in_poll_seconds")
+        .build();
+    histogram.record(
+        wall_time.as_secs_f64(),
+        &[KeyValue::new("code_id", code_id.to_string())],
+    );
+}
+
 pub fn cpu_timeout(code_id: &str, cpu_time: Duration) {
     let counter = global::meter("fn0").u64_counter("cpu_timeout").build();
     counter.add(1, &[KeyValue::new("code_id", code_id.to_string())]);
@@ -138,6 +161,72 @@ pub fn cpu_timeout(code_id: &str, cpu_time: Duration) {
     );
 }
 
+pub fn wall_timeout(code_id: &str, wall_time: Duration) {
+    let counter = global::meter("fn0").u64_counter("wall_timeout").build();
+    counter.add(1, &[KeyValue::new("code_id", code_id.to_string())]);
+
+    let histogram = global::meter("fn0")
+        .f64_histogram("wall_timeout_seconds")
+        .build();
+    histogram.record(
+        wall_time.as_secs_f64(),
+        &[KeyValue::new("code_id", code_id.to_string())],
+    );
+}
+
+pub fn wait_until_timeout(code_id: &str, limit: &'static str, elapsed: Duration) {
+    let attributes = [
+        KeyValue::new("code_id", code_id.to_string()),
+        KeyValue::new("limit", limit),
+    ];
+    let counter = global::meter("fn0")
+        .u64_counter("wait_until_timeout")
+        .build();
+    counter.add(1, &attributes);
+
+    let histogram = global::meter("fn0")
+        .f64_histogram("wait_until_timeout_seconds")
+        .build();
+    histogram.record(elapsed.as_secs_f64(), &attributes);
+}
+
+pub fn wait_until_failed(code_id: &str, error: &str) {
+    let counter = global::meter("fn0")
+        .u64_counter("wait_until_failed")
+        .build();
+    counter.add(
+        1,
+        &[
+            KeyValue::new("code_id", code_id.to_string()),
+            KeyValue::new("error", error.to_string()),
+        ],
+    );
+}
+
+pub fn heap_limit_exceeded(code_id: &str) {
+    let counter = global::meter("fn0")
+        .u64_counter("heap_limit_exceeded")
+        .build();
+    counter.add(1, &[KeyValue::new("code_id", code_id.to_string())]);
+}
+
+pub fn unauthorized(code_id: &str) {
+    let counter = global::meter("fn0").u64_counter("unauthorized").build();
+    counter.add(1, &[KeyValue::new("code_id", code_id.to_string())]);
+}
+
+/// `limit` is the one that rejected: code, tenant or host_share.
+pub fn rate_limited(code_id: &str, limit: &'static str) {
+    let counter = global::meter("fn0").u64_counter("rate_limited").build();
+    counter.add(
+        1,
+        &[
+            KeyValue::new("code_id", code_id.to_string()),
+            KeyValue::new("limit", limit),
+        ],
+    );
+}
+
 pub fn trapped(code_id: &str, trap: &str) {
     let counter = global::meter("fn0").u64_counter("trapped").build();
     counter.add(
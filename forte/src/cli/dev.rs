@@ -1,5 +1,7 @@
 use crate::server::{self, ServerConfig, ServerHandle};
 use anyhow::{Context, Result};
+use doc_db::{DocDb, EnvKey};
+use fn0::{ApiKeyAuthorizer, DocDbEnvSource, DocDbQuotaSource};
 use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
 use std::fs;
 use std::net::{SocketAddr, TcpListener};
@@ -24,6 +26,71 @@ impl Default for DevOptions {
     }
 }
 
+/// What the server takes from the doc-db of hq.
+#[derive(Default)]
+struct DocDbHooks {
+    env_source: Option<DocDbEnvSource>,
+    authorizer: Option<ApiKeyAuthorizer>,
+    quota_source: Option<DocDbQuotaSource>,
+}
+
+/// Hooks the server to the doc-db of hq when `FORTE_DOC_DB_URL` is set.
+///
+/// Codes get the env bindings hq stores there, decrypted with `FORTE_ENV_KEY`, the
+/// hex key hq encrypts them with, and are limited by their tenant's quota. With
+/// `FORTE_REQUIRE_API_KEY` set, requests also need an api key allowed to invoke the
+/// code. `FORTE_BACKEND_CODE_ID` and `FORTE_FRONTEND_CODE_ID` are the doc-db codes
+/// the local codes stand for.
+async fn connect_doc_db() -> Result<DocDbHooks> {
+    let Ok(url) = std::env::var("FORTE_DOC_DB_URL") else {
+        return Ok(DocDbHooks::default());
+    };
+    let token = std::env::var("FORTE_DOC_DB_TOKEN").unwrap_or_default();
+    let key = std::env::var("FORTE_ENV_KEY")
+        .ok()
+        .and_then(|hex| EnvKey::from_hex(&hex))
+        .context("FORTE_ENV_KEY must be 64 hex characters")?;
+
+    let mut aliases = vec![];
+    for (code_id, var) in [
+        ("backend", "FORTE_BACKEND_CODE_ID"),
+        ("frontend", "FORTE_FRONTEND_CODE_ID"),
+    ] {
+        if let Ok(doc_db_code_id) = std::env::var(var) {
+            let doc_db_code_id: u64 = doc_db_code_id
+                .parse()
+                .with_context(|| format!("{var} must be a code id"))?;
+            aliases.push((code_id.to_string(), doc_db_code_id));
+        }
+    }
+
+    let doc_db = DocDb::new(url, token)
+        .await
+        .context("Failed to connect to doc-db")?;
+    let env_source = aliases.iter().fold(
+        DocDbEnvSource::new(doc_db.clone(), key),
+        |env_source, (code_id, doc_db_code_id)| {
+            env_source.with_alias(code_id.clone(), *doc_db_code_id)
+        },
+    );
+    let quota_source = DocDbQuotaSource::with_aliases(doc_db.clone(), aliases.clone())
+        .await
+        .context("Failed to fetch quotas")?;
+    let authorizer = match std::env::var("FORTE_REQUIRE_API_KEY") {
+        Ok(_) => Some(
+            ApiKeyAuthorizer::with_aliases(doc_db, aliases)
+                .await
+                .context("Failed to fetch api keys")?,
+        ),
+        Err(_) => None,
+    };
+    Ok(DocDbHooks {
+        env_source: Some(env_source),
+        authorizer,
+        quota_source: Some(quota_source),
+    })
+}
+
 fn is_port_available(port: u16) -> bool {
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     TcpListener::bind(addr).is_ok()
@@ -264,6 +331,7 @@ pub async fn run(options: DevOptions) -> Result<()> {
     let public_dir = project_dir.join("fe/public");
     let fe_dir = project_dir.join("fe");
 
+    let doc_db_hooks = connect_doc_db().await?;
     let config = ServerConfig {
         port,
         backend_path,
@@ -271,6 +339,9 @@ pub async fn run(options: DevOptions) -> Result<()> {
         public_dir,
         fe_dir,
         dev_mode: true,
+        env_source: doc_db_hooks.env_source,
+        authorizer: doc_db_hooks.authorizer,
+        quota_source: doc_db_hooks.quota_source,
     };
 
     let handle = server::run(config).await?;
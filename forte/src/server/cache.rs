@@ -1,13 +1,20 @@
-use adapt_cache::AdaptCache;
+use adapt_cache::{AdaptCache, Eviction, WeightedLru};
 use anyhow::Result;
 use bytes::Bytes;
+use std::any::Any;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// Memory budget of the converted values, in what `convert` reports.
+const CONVERTED_CACHE_SIZE: usize = 256 * 1024 * 1024;
+
+type Converted = Arc<dyn Any + Send + Sync>;
+
 #[derive(Clone)]
 pub struct SimpleCache {
     memory: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    converted: Arc<Mutex<WeightedLru<Converted>>>,
     backend_path: String,
     frontend_path: String,
 }
@@ -16,25 +23,74 @@ impl SimpleCache {
     pub fn new(backend_path: String, frontend_path: String) -> Self {
         Self {
             memory: Arc::new(Mutex::new(HashMap::new())),
+            converted: Arc::new(Mutex::new(WeightedLru::new(
+                CONVERTED_CACHE_SIZE,
+                Eviction::default(),
+            ))),
             backend_path,
             frontend_path,
         }
     }
 
+    /// How converted values are evicted, e.g. [`fn0::cache_eviction`]. By default
+    /// only the total size is bounded.
+    pub fn with_eviction(mut self, eviction: Eviction) -> Self {
+        self.converted = Arc::new(Mutex::new(WeightedLru::new(CONVERTED_CACHE_SIZE, eviction)));
+        self
+    }
+
     pub async fn invalidate(&self, id: &str) {
         let mut cache = self.memory.lock().await;
         cache.remove(id);
+        self.converted.lock().await.remove(id);
     }
 
     #[allow(dead_code)]
     pub async fn invalidate_all(&self) {
         let mut cache = self.memory.lock().await;
         cache.clear();
+        self.converted.lock().await.clear();
+    }
+
+    fn path_of(&self, id: &str) -> Option<&str> {
+        match id {
+            "backend" => Some(&self.backend_path),
+            "frontend" => Some(&self.frontend_path),
+            _ => None,
+        }
     }
 
     async fn load_file(&self, path: &str) -> Result<Vec<u8>> {
         tokio::fs::read(path).await.map_err(|e| anyhow::anyhow!(e))
     }
+
+    async fn load(&self, id: &str, path: &str) -> Result<Vec<u8>> {
+        let data = self.load_file(path).await?;
+        self.prepare(id, data)
+    }
+
+    /// Turns the file bytes of `id` into what `memory` keeps, compiling the backend.
+    fn prepare(&self, id: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        if id != "backend" {
+            return Ok(data);
+        }
+
+        eprintln!("Compiling backend WASM ({} bytes) to CWASM...", data.len());
+        match fn0::compile(&data) {
+            Ok(cwasm) => {
+                eprintln!(
+                    "Compilation successful: {} bytes -> {} bytes",
+                    data.len(),
+                    cwasm.len()
+                );
+                Ok(cwasm)
+            }
+            Err(e) => {
+                eprintln!("Compilation failed: {:?}", e);
+                Err(e)
+            }
+        }
+    }
 }
 
 impl<T: Clone + Send + Sync + 'static, E: Send + 'static> AdaptCache<T, E> for SimpleCache {
@@ -45,43 +101,76 @@ impl<T: Clone + Send + Sync + 'static, E: Send + 'static> AdaptCache<T, E> for S
     ) -> std::result::Result<T, adapt_cache::Error<E>> {
         let mut cache = self.memory.lock().await;
 
+        // the wasm and JS caches are this same cache, so a value of another type is a miss
+        if let Some(converted) = self.converted.lock().await.get(id)
+            && let Some(converted) = converted.downcast_ref::<T>()
+        {
+            return Ok(converted.clone());
+        }
+
         let bytes = if let Some(data) = cache.get(id) {
             Bytes::copy_from_slice(data)
         } else {
-            let path = match id {
-                "backend" => &self.backend_path,
-                "frontend" => &self.frontend_path,
-                _ => return Err(adapt_cache::Error::NotFound),
+            let Some(path) = self.path_of(id) else {
+                return Err(adapt_cache::Error::NotFound);
             };
 
-            let mut data = self
-                .load_file(path)
+            let data = self
+                .load(id, path)
                 .await
-                .map_err(|e| adapt_cache::Error::StorageError(anyhow::anyhow!(e)))?;
-
-            if id == "backend" {
-                eprintln!("Compiling backend WASM ({} bytes) to CWASM...", data.len());
-                match fn0::compile(&data) {
-                    Ok(cwasm) => {
-                        eprintln!(
-                            "Compilation successful: {} bytes -> {} bytes",
-                            data.len(),
-                            cwasm.len()
-                        );
-                        data = cwasm;
-                    }
-                    Err(e) => {
-                        eprintln!("Compilation failed: {:?}", e);
-                        return Err(adapt_cache::Error::StorageError(e));
-                    }
-                }
-            }
+                .map_err(adapt_cache::Error::StorageError)?;
 
             cache.insert(id.to_string(), data.clone());
             Bytes::from(data)
         };
 
-        let (converted, _) = convert(bytes).map_err(adapt_cache::Error::ConvertError)?;
+        let (converted, weight) = convert(bytes).map_err(adapt_cache::Error::ConvertError)?;
+        self.converted
+            .lock()
+            .await
+            .insert(id.to_string(), weight, Arc::new(converted.clone()));
         Ok(converted)
     }
+
+    async fn put(&self, id: &str, bytes: Bytes) -> std::result::Result<(), adapt_cache::Error<E>> {
+        let Some(path) = self.path_of(id) else {
+            return Err(adapt_cache::Error::NotFound);
+        };
+
+        // compile first, so bytes the backend can't run never reach the file
+        let data = self
+            .prepare(id, bytes.to_vec())
+            .map_err(adapt_cache::Error::StorageError)?;
+        tokio::fs::write(path, &bytes)
+            .await
+            .map_err(|e| adapt_cache::Error::StorageError(anyhow::anyhow!(e)))?;
+
+        self.memory.lock().await.insert(id.to_string(), data);
+        self.converted.lock().await.remove(id);
+        Ok(())
+    }
+
+    async fn invalidate(&self, id: &str) {
+        SimpleCache::invalidate(self, id).await
+    }
+
+    async fn prefetch(&self, ids: &[&str]) -> std::result::Result<(), adapt_cache::Error<E>> {
+        for id in ids {
+            if self.memory.lock().await.contains_key(*id) {
+                continue;
+            }
+
+            let Some(path) = self.path_of(id) else {
+                return Err(adapt_cache::Error::NotFound);
+            };
+
+            let data = self
+                .load(id, path)
+                .await
+                .map_err(adapt_cache::Error::StorageError)?;
+
+            self.memory.lock().await.insert(id.to_string(), data);
+        }
+        Ok(())
+    }
 }
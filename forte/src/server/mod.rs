@@ -3,7 +3,10 @@ mod hmr;
 
 use anyhow::{Context, Result};
 pub use cache::SimpleCache;
-use fn0::{CodeKind, DeploymentMap, Fn0};
+use fn0::{
+    ApiKeyAuthorizer, CachedEnvSource, CodeKind, DeploymentMap, DocDbEnvSource, DocDbQuotaSource,
+    Fn0,
+};
 use futures_util::{SinkExt, StreamExt};
 pub use hmr::HmrBroadcaster;
 use http_body_util::{BodyExt, Full, combinators::UnsyncBoxBody};
@@ -14,6 +17,7 @@ use hyper_util::rt::TokioIo;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
@@ -25,8 +29,26 @@ pub struct ServerConfig {
     pub public_dir: PathBuf,
     pub fe_dir: PathBuf,
     pub dev_mode: bool,
+    /// Env bindings of the codes, kept in doc-db by hq. Without it codes get none.
+    pub env_source: Option<DocDbEnvSource>,
+    /// Without it every request may invoke the codes.
+    pub authorizer: Option<ApiKeyAuthorizer>,
+    /// Rate and host share limits of the codes' tenants. Without it codes aren't limited.
+    pub quota_source: Option<DocDbQuotaSource>,
 }
 
+/// How long a changed env binding takes to reach the codes.
+const ENV_TTL: Duration = Duration::from_secs(5);
+
+/// How long a revoked api key keeps working.
+const API_KEY_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a changed quota takes to apply.
+const QUOTA_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Converted code a tenant may keep in the cache, so its codes can't evict others'.
+const PER_TENANT_CACHE_BUDGET: usize = 128 * 1024 * 1024;
+
 pub struct ServerHandle {
     pub cache: SimpleCache,
     pub hmr: HmrBroadcaster,
@@ -39,7 +61,13 @@ pub async fn run(config: ServerConfig) -> Result<ServerHandle> {
     deployment_map.register_code("backend", CodeKind::Wasm);
     deployment_map.register_code("frontend", CodeKind::Js);
 
-    let cache = SimpleCache::new(config.backend_path.clone(), config.frontend_path.clone());
+    let mut cache = SimpleCache::new(config.backend_path.clone(), config.frontend_path.clone());
+    if let Some(quota_source) = &config.quota_source {
+        cache = cache.with_eviction(fn0::cache_eviction(
+            quota_source.clone(),
+            PER_TENANT_CACHE_BUDGET,
+        ));
+    }
     let hmr = HmrBroadcaster::new();
 
     let (vite_port, vite_child) = if config.dev_mode {
@@ -66,7 +94,21 @@ pub async fn run(config: ServerConfig) -> Result<ServerHandle> {
         _ssr_adapter_child: ssr_adapter_child,
     };
 
-    let fn0 = Arc::new(Fn0::new(cache.clone(), cache, deployment_map));
+    let mut fn0 = Fn0::new(cache.clone(), cache, deployment_map);
+    if let Some(env_source) = config.env_source {
+        fn0 = fn0.with_env_source(CachedEnvSource::new(env_source, ENV_TTL));
+    }
+    if let Some(authorizer) = config.authorizer {
+        let syncing = authorizer.clone();
+        tokio::spawn(async move { syncing.run_sync(API_KEY_SYNC_INTERVAL).await });
+        fn0 = fn0.with_authorizer(authorizer);
+    }
+    if let Some(quota_source) = config.quota_source {
+        let syncing = quota_source.clone();
+        tokio::spawn(async move { syncing.run_sync(QUOTA_SYNC_INTERVAL).await });
+        fn0 = fn0.with_quota_source(quota_source);
+    }
+    let fn0 = Arc::new(fn0);
     let public_dir = Arc::new(config.public_dir);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], config.port));
@@ -13,6 +13,14 @@ pub enum HqToHostReliable {
         code_id_and_versions: Vec<(u64, u64)>,
     },
     GracefulShutdown,
+    /// The host's share of each tenant's region limit, in requests per second.
+    /// Tenants left out are not limited by region.
+    ///
+    /// Only hq's half ships: no host in this repository enforces the shares yet, and
+    /// hosts must be able to decode this variant before hq is deployed with it.
+    RegionRateLimits {
+        limits: Vec<(u64, u32)>,
+    },
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -22,6 +30,12 @@ pub enum HostToHq {
         deployment_id: u64,
         instances: u64,
     },
+    /// Requests admitted per tenant over the last `interval_ms`. Nothing sends this
+    /// yet; without reports hq splits region limits evenly.
+    ReportTenantRequests {
+        interval_ms: u64,
+        requests: Vec<(u64, u64)>,
+    },
 }
 
 impl HqToHostDatagram {
@@ -0,0 +1,728 @@
+//! REST API to operate hq, served under `/api/` of the web server.
+//!
+//! Every request needs `Authorization: Bearer <token>`, where the token is either the
+//! admin token of hq, which acts as a platform admin, or an api key with the scope
+//! the route needs.
+
+use crate::{
+    api_key_cache::ApiKeyCache,
+    site::{HostInfo, SiteHandle},
+};
+use doc_db::{
+    ApiKey, ApiScope, CodeKind, DeployOutcome, Deployment, DocDb, EnvBindings, EnvKey, Quota,
+    ScaleConfig,
+};
+use http_body_util::{BodyExt, Full};
+use hyper::{Method, Request, Response, StatusCode, body::Bytes, header};
+use std::collections::BTreeMap;
+use tracing::*;
+
+pub struct AdminApi {
+    sites: Vec<SiteHandle>,
+    doc_db: DocDb,
+    admin_token: String,
+    env_key: EnvKey,
+    api_key_cache: ApiKeyCache,
+}
+
+/// Who sent a request.
+enum Caller {
+    AdminToken,
+    ApiKey(ApiKey),
+}
+
+impl Caller {
+    fn allows(&self, tenant_id: Option<u64>, scope: ApiScope) -> bool {
+        match self {
+            Caller::AdminToken => true,
+            Caller::ApiKey(api_key) => api_key.allows(tenant_id, scope),
+        }
+    }
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct SiteSummary {
+    pub index: usize,
+    pub hosts: Vec<HostInfo>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateDeploymentRequest {
+    pub code_id: u64,
+    pub code_version: u64,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct CreateDeploymentResponse {
+    pub deployment_id: u64,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateTenantRequest {
+    pub name: String,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateProjectRequest {
+    pub name: String,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateCodeRequest {
+    pub kind: CodeKind,
+}
+
+/// Replaces every env binding of a code.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SetEnvBindingsRequest {
+    pub vars: BTreeMap<String, String>,
+}
+
+/// Values are not echoed back, they are secrets.
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct EnvBindingsResponse {
+    pub code_id: u64,
+    pub names: Vec<String>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateApiKeyRequest {
+    /// Omitted for a platform key, which only the admin token or platform admins
+    /// can create.
+    #[serde(default)]
+    pub tenant_id: Option<u64>,
+    pub scopes: Vec<ApiScope>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct CreateApiKeyResponse {
+    pub api_key: ApiKey,
+    /// Shown only once, doc-db keeps a hash of it.
+    pub key: String,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+impl AdminApi {
+    pub fn new(
+        sites: Vec<SiteHandle>,
+        doc_db: DocDb,
+        admin_token: String,
+        env_key: EnvKey,
+        api_key_cache: ApiKeyCache,
+    ) -> Self {
+        Self {
+            sites,
+            doc_db,
+            admin_token,
+            env_key,
+            api_key_cache,
+        }
+    }
+
+    pub async fn handle(&self, req: Request<hyper::body::Incoming>) -> Response<Full<Bytes>> {
+        let Some(caller) = self.caller(&req) else {
+            return error(StatusCode::UNAUTHORIZED, "unauthorized");
+        };
+
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        let query_tenant_id = req.uri().query().and_then(tenant_id_from_query);
+        let segments = path
+            .trim_start_matches("/api/")
+            .trim_end_matches('/')
+            .split('/')
+            .collect::<Vec<_>>();
+
+        let body = match req.into_body().collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(err) => return error(StatusCode::BAD_REQUEST, err),
+        };
+
+        if let Some(scope) = platform_scope(&method, &segments)
+            && !caller.allows(None, scope)
+        {
+            return forbidden();
+        }
+
+        match (&method, segments.as_slice()) {
+            (&Method::GET, ["sites"]) => json(
+                StatusCode::OK,
+                &self
+                    .sites
+                    .iter()
+                    .enumerate()
+                    .map(|(index, site)| site_summary(index, site))
+                    .collect::<Vec<_>>(),
+            ),
+            (&Method::GET, ["sites", index]) => match self.site(index) {
+                Some((index, site)) => json(StatusCode::OK, &site_summary(index, site)),
+                None => not_found(),
+            },
+            (&Method::GET, ["sites", index, "hosts", host_id]) => {
+                match self.site(index).and_then(|(_, site)| site.host(host_id)) {
+                    Some(host) => json(StatusCode::OK, &host),
+                    None => not_found(),
+                }
+            }
+            (&Method::POST, ["sites", index, "hosts", host_id, "drain"]) => {
+                match self.site(index).and_then(|(_, site)| site.drain(host_id)) {
+                    Some(host) => {
+                        info!(%host_id, "Host drained by admin api");
+                        json(StatusCode::OK, &host)
+                    }
+                    None => not_found(),
+                }
+            }
+            (&Method::POST, ["sites", index, "hosts", host_id, "terminate"]) => {
+                let Some((_, site)) = self.site(index) else {
+                    return not_found();
+                };
+                match site.terminate(host_id).await {
+                    Some(Ok(host)) => {
+                        info!(%host_id, "Host terminated by admin api");
+                        json(StatusCode::OK, &host)
+                    }
+                    Some(Err(err)) => error(StatusCode::BAD_GATEWAY, err),
+                    None => not_found(),
+                }
+            }
+            (&Method::GET, ["scale-config"]) => match self.doc_db.get_scale_config().await {
+                Ok(Some(scale_config)) => json(StatusCode::OK, &scale_config),
+                Ok(None) => not_found(),
+                Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, err),
+            },
+            (&Method::PUT, ["scale-config"]) => {
+                let scale_config = match serde_json::from_slice::<ScaleConfig>(&body) {
+                    Ok(scale_config) => scale_config,
+                    Err(err) => return error(StatusCode::BAD_REQUEST, err),
+                };
+                if let Err(err) = scale_config.validate() {
+                    return error(StatusCode::UNPROCESSABLE_ENTITY, err);
+                }
+                match self.doc_db.set_scale_config(scale_config.clone()).await {
+                    Ok(()) => {
+                        info!(?scale_config, "Scale config set by admin api");
+                        json(StatusCode::OK, &scale_config)
+                    }
+                    Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, err),
+                }
+            }
+            (&Method::POST, ["deployments"]) => {
+                let request = match serde_json::from_slice::<CreateDeploymentRequest>(&body) {
+                    Ok(request) => request,
+                    Err(err) => return error(StatusCode::BAD_REQUEST, err),
+                };
+                self.create_deployment(&caller, request)
+                    .await
+                    .unwrap_or_else(|response| response)
+            }
+            (_, ["codes", code_id, "env"]) => self
+                .handle_env_bindings(&caller, &method, code_id, &body)
+                .await
+                .unwrap_or_else(|response| response),
+            (_, ["tenants", ..] | ["projects", ..]) => self
+                .handle_tenancy(&caller, &method, &segments, &body)
+                .await
+                .unwrap_or_else(|response| response),
+            (&Method::GET, ["api-keys"]) => {
+                if !caller.allows(query_tenant_id, ApiScope::Admin) {
+                    return forbidden();
+                }
+                match self.doc_db.list_api_keys().await {
+                    Ok(api_keys) => json(
+                        StatusCode::OK,
+                        &api_keys
+                            .into_iter()
+                            .filter(|api_key| {
+                                query_tenant_id.is_none() || api_key.tenant_id == query_tenant_id
+                            })
+                            .collect::<Vec<_>>(),
+                    ),
+                    Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, err),
+                }
+            }
+            (&Method::POST, ["api-keys"]) => {
+                let request = match serde_json::from_slice::<CreateApiKeyRequest>(&body) {
+                    Ok(request) => request,
+                    Err(err) => return error(StatusCode::BAD_REQUEST, err),
+                };
+                if !caller.allows(request.tenant_id, ApiScope::Admin) {
+                    return forbidden();
+                }
+                if request.scopes.is_empty() {
+                    return error(StatusCode::UNPROCESSABLE_ENTITY, "scopes must not be empty");
+                }
+                match self
+                    .doc_db
+                    .create_api_key(request.tenant_id, request.scopes)
+                    .await
+                {
+                    Ok((api_key, key)) => {
+                        info!(id = api_key.id, tenant_id = ?api_key.tenant_id, "Api key created");
+                        self.api_key_cache.refresh().await;
+                        json(StatusCode::CREATED, &CreateApiKeyResponse { api_key, key })
+                    }
+                    Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, err),
+                }
+            }
+            (&Method::DELETE, ["api-keys", id]) => {
+                let Ok(id) = id.parse() else {
+                    return not_found();
+                };
+                let api_key = match self.doc_db.get_api_key(id).await {
+                    Ok(Some(api_key)) => api_key,
+                    Ok(None) => return not_found(),
+                    Err(err) => return error(StatusCode::INTERNAL_SERVER_ERROR, err),
+                };
+                if !caller.allows(api_key.tenant_id, ApiScope::Admin) {
+                    return forbidden();
+                }
+                match self.doc_db.revoke_api_key(id).await {
+                    Ok(_) => {
+                        info!(id, tenant_id = ?api_key.tenant_id, "Api key revoked");
+                        self.api_key_cache.refresh().await;
+                        json(StatusCode::OK, &api_key)
+                    }
+                    Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, err),
+                }
+            }
+            (&Method::GET, ["schemas"]) => json(StatusCode::OK, &schemas()),
+            _ => not_found(),
+        }
+    }
+
+    fn caller(&self, req: &Request<hyper::body::Incoming>) -> Option<Caller> {
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))?;
+
+        if !self.admin_token.is_empty()
+            && constant_time_eq(token.as_bytes(), self.admin_token.as_bytes())
+        {
+            return Some(Caller::AdminToken);
+        }
+        self.api_key_cache
+            .allow_list()
+            .lookup(token)
+            .map(Caller::ApiKey)
+    }
+
+    /// Deploys a version of a code the caller's tenant owns, within its quota.
+    async fn create_deployment(
+        &self,
+        caller: &Caller,
+        request: CreateDeploymentRequest,
+    ) -> Result<Response<Full<Bytes>>, Response<Full<Bytes>>> {
+        let code = self
+            .doc_db
+            .get_code(request.code_id)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(not_found)?;
+        if !caller.allows(Some(code.tenant_id), ApiScope::Deploy) {
+            return Err(forbidden());
+        }
+
+        let quota = self
+            .doc_db
+            .get_quota(code.tenant_id)
+            .await
+            .map_err(internal_error)?;
+        let outcome = self
+            .doc_db
+            .deploy(
+                code.tenant_id,
+                Deployment {
+                    code_id: request.code_id,
+                    code_version: request.code_version,
+                },
+                quota.max_deployments_per_day,
+            )
+            .await
+            .map_err(internal_error)?;
+        let deployment_id = match outcome {
+            DeployOutcome::Deployed { deployment_id } => deployment_id,
+            DeployOutcome::QuotaExceeded => {
+                return Err(error(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    format!(
+                        "tenant {} reached its quota of {} deployments per day",
+                        code.tenant_id, quota.max_deployments_per_day
+                    ),
+                ));
+            }
+            DeployOutcome::VersionExists => {
+                return Err(error(
+                    StatusCode::CONFLICT,
+                    format!(
+                        "version {} of code {} is already deployed",
+                        request.code_version, code.id
+                    ),
+                ));
+            }
+        };
+        info!(
+            code_id = code.id,
+            code_version = request.code_version,
+            tenant_id = code.tenant_id,
+            deployment_id,
+            "Deployment created"
+        );
+        Ok(json(
+            StatusCode::CREATED,
+            &CreateDeploymentResponse { deployment_id },
+        ))
+    }
+
+    /// Sets or deletes the env bindings of a code the caller's tenant owns. Hosts pick
+    /// them up on their next read, the code is not redeployed.
+    async fn handle_env_bindings(
+        &self,
+        caller: &Caller,
+        method: &Method,
+        code_id: &str,
+        body: &[u8],
+    ) -> Result<Response<Full<Bytes>>, Response<Full<Bytes>>> {
+        let code_id = parse_id(code_id)?;
+        let code = self
+            .doc_db
+            .get_code(code_id)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(not_found)?;
+        if !caller.allows(Some(code.tenant_id), ApiScope::Deploy) {
+            return Err(forbidden());
+        }
+
+        match *method {
+            Method::PUT => {
+                let request: SetEnvBindingsRequest = parse_body(body)?;
+                let bindings = EnvBindings { vars: request.vars };
+                self.doc_db
+                    .set_env_bindings(&self.env_key, code.id, &bindings)
+                    .await
+                    .map_err(internal_error)?;
+                info!(code_id = code.id, ?bindings, "Env bindings set");
+                Ok(json(
+                    StatusCode::OK,
+                    &EnvBindingsResponse {
+                        code_id: code.id,
+                        names: bindings.vars.into_keys().collect(),
+                    },
+                ))
+            }
+            Method::DELETE => {
+                self.doc_db
+                    .delete_env_bindings(code.id)
+                    .await
+                    .map_err(internal_error)?;
+                info!(code_id = code.id, "Env bindings deleted");
+                Ok(json(
+                    StatusCode::OK,
+                    &EnvBindingsResponse {
+                        code_id: code.id,
+                        names: vec![],
+                    },
+                ))
+            }
+            _ => Err(not_found()),
+        }
+    }
+
+    /// Routes under `/api/tenants/` and `/api/projects/`.
+    async fn handle_tenancy(
+        &self,
+        caller: &Caller,
+        method: &Method,
+        segments: &[&str],
+        body: &[u8],
+    ) -> Result<Response<Full<Bytes>>, Response<Full<Bytes>>> {
+        let require = |tenant_id: Option<u64>, scope: ApiScope| {
+            if caller.allows(tenant_id, scope) {
+                Ok(())
+            } else {
+                Err(forbidden())
+            }
+        };
+
+        match (method, segments) {
+            (&Method::GET, ["tenants"]) => {
+                require(None, ApiScope::Admin)?;
+                let tenants = self.doc_db.list_tenants().await.map_err(internal_error)?;
+                Ok(json(StatusCode::OK, &tenants))
+            }
+            (&Method::POST, ["tenants"]) => {
+                require(None, ApiScope::Admin)?;
+                let request: CreateTenantRequest = parse_body(body)?;
+                let tenant = self
+                    .doc_db
+                    .create_tenant(&request.name)
+                    .await
+                    .map_err(|err| error(StatusCode::CONFLICT, err))?;
+                info!(tenant_id = tenant.id, name = %tenant.name, "Tenant created");
+                Ok(json(StatusCode::CREATED, &tenant))
+            }
+            (&Method::GET, ["tenants", tenant_id]) => {
+                let tenant_id = parse_id(tenant_id)?;
+                require(Some(tenant_id), ApiScope::Admin)?;
+                let tenant = self
+                    .doc_db
+                    .get_tenant(tenant_id)
+                    .await
+                    .map_err(internal_error)?
+                    .ok_or_else(not_found)?;
+                Ok(json(StatusCode::OK, &tenant))
+            }
+            (&Method::GET, ["tenants", tenant_id, "quota"]) => {
+                let tenant_id = parse_id(tenant_id)?;
+                require(Some(tenant_id), ApiScope::ReadMetrics)?;
+                let quota = self
+                    .doc_db
+                    .get_quota(tenant_id)
+                    .await
+                    .map_err(internal_error)?;
+                Ok(json(StatusCode::OK, &quota))
+            }
+            // tenants can't raise their own limits
+            (&Method::PUT, ["tenants", tenant_id, "quota"]) => {
+                let tenant_id = parse_id(tenant_id)?;
+                require(None, ApiScope::Admin)?;
+                let quota: Quota = parse_body(body)?;
+                quota
+                    .validate()
+                    .map_err(|err| error(StatusCode::UNPROCESSABLE_ENTITY, err))?;
+                self.doc_db
+                    .get_tenant(tenant_id)
+                    .await
+                    .map_err(internal_error)?
+                    .ok_or_else(not_found)?;
+                self.doc_db
+                    .set_quota(tenant_id, &quota)
+                    .await
+                    .map_err(internal_error)?;
+                info!(tenant_id, ?quota, "Quota set");
+                Ok(json(StatusCode::OK, &quota))
+            }
+            (&Method::GET, ["tenants", tenant_id, "projects"]) => {
+                let tenant_id = parse_id(tenant_id)?;
+                require(Some(tenant_id), ApiScope::Deploy)?;
+                let projects = self
+                    .doc_db
+                    .list_projects(tenant_id)
+                    .await
+                    .map_err(internal_error)?;
+                Ok(json(StatusCode::OK, &projects))
+            }
+            (&Method::POST, ["tenants", tenant_id, "projects"]) => {
+                let tenant_id = parse_id(tenant_id)?;
+                require(Some(tenant_id), ApiScope::Admin)?;
+                let request: CreateProjectRequest = parse_body(body)?;
+                self.doc_db
+                    .get_tenant(tenant_id)
+                    .await
+                    .map_err(internal_error)?
+                    .ok_or_else(not_found)?;
+                let project = self
+                    .doc_db
+                    .create_project(tenant_id, &request.name)
+                    .await
+                    .map_err(|err| error(StatusCode::CONFLICT, err))?;
+                Ok(json(StatusCode::CREATED, &project))
+            }
+            (&Method::POST, ["projects", project_id, "codes"]) => {
+                let project_id = parse_id(project_id)?;
+                let request: CreateCodeRequest = parse_body(body)?;
+                let project = self
+                    .doc_db
+                    .get_project(project_id)
+                    .await
+                    .map_err(internal_error)?
+                    .ok_or_else(not_found)?;
+                require(Some(project.tenant_id), ApiScope::Deploy)?;
+                let code = self
+                    .doc_db
+                    .create_code(project.id, request.kind)
+                    .await
+                    .map_err(internal_error)?;
+                Ok(json(StatusCode::CREATED, &code))
+            }
+            _ => Err(not_found()),
+        }
+    }
+
+    fn site(&self, index: &str) -> Option<(usize, &SiteHandle)> {
+        let index = index.parse().ok()?;
+        self.sites.get(index).map(|site| (index, site))
+    }
+}
+
+/// Scope of the routes that act on the platform rather than on a tenant.
+fn platform_scope(method: &Method, segments: &[&str]) -> Option<ApiScope> {
+    match (method, segments) {
+        (&Method::GET, ["sites", ..] | ["scale-config"] | ["schemas"]) => {
+            Some(ApiScope::ReadMetrics)
+        }
+        (&Method::POST, ["sites", ..]) | (&Method::PUT, ["scale-config"]) => Some(ApiScope::Admin),
+        _ => None,
+    }
+}
+
+fn tenant_id_from_query(query: &str) -> Option<u64> {
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("tenant_id="))
+        .and_then(|tenant_id| tenant_id.parse().ok())
+}
+
+fn site_summary(index: usize, site: &SiteHandle) -> SiteSummary {
+    SiteSummary {
+        index,
+        hosts: site.hosts(),
+    }
+}
+
+/// JSON schemas of the request and response bodies, by type name.
+fn schemas() -> serde_json::Value {
+    serde_json::json!({
+        "SiteSummary": schemars::schema_for!(SiteSummary),
+        "HostInfo": schemars::schema_for!(HostInfo),
+        "ScaleConfig": schemars::schema_for!(ScaleConfig),
+        "CreateDeploymentRequest": schemars::schema_for!(CreateDeploymentRequest),
+        "CreateDeploymentResponse": schemars::schema_for!(CreateDeploymentResponse),
+        "CreateApiKeyRequest": schemars::schema_for!(CreateApiKeyRequest),
+        "CreateApiKeyResponse": schemars::schema_for!(CreateApiKeyResponse),
+        "ApiKey": schemars::schema_for!(ApiKey),
+        "CreateTenantRequest": schemars::schema_for!(CreateTenantRequest),
+        "CreateProjectRequest": schemars::schema_for!(CreateProjectRequest),
+        "CreateCodeRequest": schemars::schema_for!(CreateCodeRequest),
+        "Tenant": schemars::schema_for!(doc_db::Tenant),
+        "Project": schemars::schema_for!(doc_db::Project),
+        "Code": schemars::schema_for!(doc_db::Code),
+        "Quota": schemars::schema_for!(Quota),
+        "SetEnvBindingsRequest": schemars::schema_for!(SetEnvBindingsRequest),
+        "EnvBindingsResponse": schemars::schema_for!(EnvBindingsResponse),
+        "ErrorResponse": schemars::schema_for!(ErrorResponse),
+    })
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn json(status: StatusCode, body: &impl serde::Serialize) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(serde_json::to_vec(body).unwrap())))
+        .unwrap()
+}
+
+fn error(status: StatusCode, error: impl ToString) -> Response<Full<Bytes>> {
+    json(
+        status,
+        &ErrorResponse {
+            error: error.to_string(),
+        },
+    )
+}
+
+fn not_found() -> Response<Full<Bytes>> {
+    error(StatusCode::NOT_FOUND, "not found")
+}
+
+fn forbidden() -> Response<Full<Bytes>> {
+    error(StatusCode::FORBIDDEN, "forbidden")
+}
+
+fn internal_error(err: impl ToString) -> Response<Full<Bytes>> {
+    error(StatusCode::INTERNAL_SERVER_ERROR, err)
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, Response<Full<Bytes>>> {
+    serde_json::from_slice(body).map_err(|err| error(StatusCode::BAD_REQUEST, err))
+}
+
+fn parse_id(id: &str) -> Result<u64, Response<Full<Bytes>>> {
+    id.parse().map_err(|_| not_found())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token2"));
+        assert!(!constant_time_eq(b"", b"token"));
+    }
+
+    #[test]
+    fn test_platform_scope() {
+        assert_eq!(
+            platform_scope(&Method::GET, &["sites", "0", "hosts", "a"]),
+            Some(ApiScope::ReadMetrics)
+        );
+        assert_eq!(
+            platform_scope(&Method::POST, &["sites", "0", "hosts", "a", "drain"]),
+            Some(ApiScope::Admin)
+        );
+        assert_eq!(
+            platform_scope(&Method::PUT, &["scale-config"]),
+            Some(ApiScope::Admin)
+        );
+        // checked against the tenant that owns the resource
+        assert_eq!(platform_scope(&Method::POST, &["deployments"]), None);
+        assert_eq!(platform_scope(&Method::POST, &["api-keys"]), None);
+        assert_eq!(platform_scope(&Method::POST, &["tenants"]), None);
+    }
+
+    #[test]
+    fn test_tenant_id_from_query() {
+        assert_eq!(tenant_id_from_query("tenant_id=7"), Some(7));
+        assert_eq!(tenant_id_from_query("a=b&tenant_id=7"), Some(7));
+        assert_eq!(tenant_id_from_query("tenant_id=x"), None);
+        assert_eq!(tenant_id_from_query(""), None);
+    }
+
+    #[test]
+    fn test_schemas_cover_every_body() {
+        let schemas = schemas();
+        for name in [
+            "SiteSummary",
+            "HostInfo",
+            "ScaleConfig",
+            "CreateDeploymentRequest",
+            "CreateDeploymentResponse",
+            "CreateApiKeyRequest",
+            "CreateApiKeyResponse",
+            "ApiKey",
+            "CreateTenantRequest",
+            "CreateProjectRequest",
+            "CreateCodeRequest",
+            "Tenant",
+            "Project",
+            "Code",
+            "Quota",
+            "ErrorResponse",
+        ] {
+            assert!(schemas.get(name).is_some(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::telemetry;
+use color_eyre::eyre::Result;
+use doc_db::{ApiKeyAllowList, DocDb};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+/// Keeps the api key allow-list of hq in sync with doc-db.
+#[derive(Clone)]
+pub struct ApiKeyCache {
+    allow_list: ApiKeyAllowList,
+    doc_db: DocDb,
+}
+
+impl ApiKeyCache {
+    pub async fn new(doc_db: DocDb) -> Result<Self> {
+        let allow_list = ApiKeyAllowList::default();
+        let count = allow_list.refresh(&doc_db).await?;
+        telemetry::api_key_cache_cached_count(count);
+        Ok(Self { allow_list, doc_db })
+    }
+
+    pub async fn run_sync(&self) {
+        let mut interval = tokio::time::interval(api_key_sync_interval_ms());
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+            self.refresh().await;
+        }
+    }
+
+    /// Also called right after a key is created or revoked here, so this hq doesn't
+    /// wait for the next tick.
+    pub async fn refresh(&self) {
+        match self.allow_list.refresh(&self.doc_db).await {
+            Ok(count) => {
+                telemetry::api_key_cache_fetch_status(true);
+                telemetry::api_key_cache_cached_count(count);
+            }
+            Err(err) => {
+                warn!(%err, "Failed to fetch api keys");
+                telemetry::api_key_cache_fetch_status(false);
+            }
+        }
+    }
+
+    pub fn allow_list(&self) -> &ApiKeyAllowList {
+        &self.allow_list
+    }
+}
+
+/// Upper bound of how long a revoked key keeps working.
+fn api_key_sync_interval_ms() -> Duration {
+    match std::env::var("API_KEY_SYNC_INTERVAL_MS") {
+        Ok(s) => match s.parse() {
+            Ok(v) => return Duration::from_millis(v),
+            Err(err) => warn!(%err, "API_KEY_SYNC_INTERVAL_MS is not a valid number"),
+        },
+        Err(err) => warn!(%err, "Fail to get API_KEY_SYNC_INTERVAL_MS from env"),
+    }
+    Duration::from_secs(5)
+}
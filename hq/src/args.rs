@@ -8,6 +8,11 @@ pub struct HqArgs {
     pub sites: Vec<SiteArgs>,
     pub doc_db: DocDbArgs,
     pub cert: String,
+    /// Bearer token of the admin API under `/api/`.
+    pub admin_token: String,
+    /// 64 hex characters. Encrypts the env bindings of codes in doc-db, hosts need
+    /// the same key to read them.
+    pub env_key: String,
 }
 
 #[derive(serde::Deserialize, schemars::JsonSchema)]
@@ -21,6 +26,9 @@ pub struct SiteArgs {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub enum HostProviderArg {
     OciContainerInstance(OciContainerInstanceHostProviderArgs),
+    LocalProcess(LocalProcessHostProviderArgs),
+    Docker(DockerHostProviderArgs),
+    AwsEc2(AwsEc2HostProviderArgs),
 }
 
 #[derive(serde::Deserialize, schemars::JsonSchema)]
@@ -42,10 +50,59 @@ pub struct OciContainerInstanceHostProviderArgs {
     pub envs: BTreeMap<String, String>,
 }
 
+/// Runs hosts as child processes of hq, for development and integration tests.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct LocalProcessHostProviderArgs {
+    /// Path of the host binary.
+    pub command: String,
+    pub args: Vec<String>,
+    /// Each host also gets `FN0_HOST_IP`, the loopback address it must bind.
+    pub envs: BTreeMap<String, String>,
+    pub physics_cpu_cores: NonZeroUsize,
+    pub memory_in_gbs: NonZeroUsize,
+}
+
+/// Runs hosts as containers of a Docker engine.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DockerHostProviderArgs {
+    /// `unix:///var/run/docker.sock` or `http://host:port`.
+    pub engine_url: String,
+    /// Hosts are attached to it and listed by it, so sites sharing an engine
+    /// need their own.
+    pub network: String,
+    pub image: String,
+    pub physics_cpu_cores: NonZeroUsize,
+    pub memory_in_gbs: NonZeroUsize,
+    pub envs: BTreeMap<String, String>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AwsEc2HostProviderArgs {
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Instances are tagged `fn0-site=<site_tag>` and listed by it.
+    pub site_tag: String,
+    pub image_id: String,
+    pub instance_type: String,
+    pub subnet_id: String,
+    pub security_group_ids: Vec<String>,
+    /// Starts the host on boot, e.g. a cloud-init script running its image.
+    pub user_data: String,
+    pub physics_cpu_cores: NonZeroUsize,
+    pub memory_in_gbs: NonZeroUsize,
+}
+
 #[derive(serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub enum DnsProviderArg {
     Cloudflare(CloudflareDnsProviderArgs),
+    Route53(Route53DnsProviderArgs),
+    OciDns(OciDnsProviderArgs),
+    Rfc2136(Rfc2136DnsProviderArgs),
 }
 
 #[derive(serde::Deserialize, schemars::JsonSchema)]
@@ -54,6 +111,58 @@ pub struct CloudflareDnsProviderArgs {
     pub zone_id: String,
     pub asterisk_domain: String,
     pub api_token: String,
+    /// With `load_balancer_pool_id`, hosts are published as weighted origins of
+    /// the site's pool rather than as A and AAAA records.
+    #[serde(default)]
+    pub account_id: String,
+    #[serde(default)]
+    pub load_balancer_pool_id: String,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Route53DnsProviderArgs {
+    pub hosted_zone_id: String,
+    pub asterisk_domain: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    #[serde(default)]
+    pub routing: Route53Routing,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub enum Route53Routing {
+    /// One set of every host, without a set identifier.
+    #[default]
+    Simple,
+    /// A set per host, weighted by its load.
+    Weighted,
+    /// One set of every host per site, identified and latency-routed by `region`, an
+    /// AWS region like `ap-northeast-2`, so clients reach the nearest site.
+    Latency { region: String },
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct OciDnsProviderArgs {
+    pub private_key_base64: String,
+    pub user_id: String,
+    pub fingerprint: String,
+    pub tenancy_id: String,
+    pub region: String,
+    pub zone_id: String,
+    pub asterisk_domain: String,
+}
+
+/// Dynamic DNS updates to a server that allows them from hq without TSIG.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Rfc2136DnsProviderArgs {
+    /// `host:port` of the zone's primary, reached over TCP.
+    pub server: String,
+    pub zone: String,
+    pub asterisk_domain: String,
 }
 
 #[derive(serde::Deserialize, schemars::JsonSchema)]
@@ -1,17 +1,27 @@
 use color_eyre::eyre::{Result, eyre};
-use doc_db::DocDb;
+use doc_db::{DocDb, EnvKey};
 
 use crate::{
+    admin_api::AdminApi,
+    api_key_cache::ApiKeyCache,
     args::*,
     deployment_cache::DeploymentCache,
-    dns::{DnsProvider, cloudflare::CloudflareDnsProvider},
-    host_provider::{HostProvider, oci_container::OciContainerInstanceHostProvider},
+    dns::{
+        DnsProvider, cloudflare::CloudflareDnsProvider, oci_dns::OciDnsProvider,
+        rfc2136::Rfc2136DnsProvider, route53::Route53DnsProvider,
+    },
+    host_provider::{
+        HostProvider, aws_ec2::AwsEc2HostProvider, docker::DockerHostProvider,
+        local_process::LocalProcessHostProvider, oci_container::OciContainerInstanceHostProvider,
+    },
     site::Site,
 };
 
 pub struct HqArgsParsed {
     pub sites: Vec<Site>,
     pub deployment_cache: DeploymentCache,
+    pub api_key_cache: ApiKeyCache,
+    pub admin_api: AdminApi,
 }
 
 impl HqArgs {
@@ -24,8 +34,14 @@ impl HqArgs {
         let args: HqArgs = serde_json::from_str(&content)
             .map_err(|e| eyre!("Failed to parse config file: {}", e))?;
 
+        let env_key = EnvKey::from_hex(&args.env_key)
+            .ok_or_else(|| eyre!("envKey must be 64 hex characters"))?;
+
         let doc_db = DocDb::new(args.doc_db.url, args.doc_db.token).await?;
+        let schema_version = doc_db.migrate().await?;
+        tracing::info!(schema_version, "doc-db migrated");
         let deployment_cache = DeploymentCache::new(doc_db.clone()).await?;
+        let api_key_cache = ApiKeyCache::new(doc_db.clone()).await?;
 
         let sites = args
             .sites
@@ -40,14 +56,38 @@ impl HqArgs {
                                 OciContainerInstanceHostProvider::new(args),
                             ),
                         ),
+                        HostProviderArg::LocalProcess(args) => (
+                            args.physics_cpu_cores,
+                            args.memory_in_gbs,
+                            HostProvider::LocalProcess(LocalProcessHostProvider::new(args)),
+                        ),
+                        HostProviderArg::Docker(args) => (
+                            args.physics_cpu_cores,
+                            args.memory_in_gbs,
+                            HostProvider::Docker(DockerHostProvider::new(args)?),
+                        ),
+                        HostProviderArg::AwsEc2(args) => (
+                            args.physics_cpu_cores,
+                            args.memory_in_gbs,
+                            HostProvider::AwsEc2(AwsEc2HostProvider::new(args, None)),
+                        ),
                     };
                 let dns_provider = match site_args.dns_provider {
                     DnsProviderArg::Cloudflare(args) => {
                         DnsProvider::Cloudflare(CloudflareDnsProvider::new(args, None))
                     }
+                    DnsProviderArg::Route53(args) => {
+                        DnsProvider::Route53(Route53DnsProvider::new(args, None)?)
+                    }
+                    DnsProviderArg::OciDns(args) => {
+                        DnsProvider::OciDns(OciDnsProvider::new(args, None))
+                    }
+                    DnsProviderArg::Rfc2136(args) => {
+                        DnsProvider::Rfc2136(Rfc2136DnsProvider::new(args))
+                    }
                 };
 
-                Site::new(
+                Ok(Site::new(
                     host_provider,
                     dns_provider,
                     args.cert.clone(),
@@ -55,13 +95,23 @@ impl HqArgs {
                     host_cpu_cores,
                     host_memory_in_gb,
                     doc_db.clone(),
-                )
+                ))
             })
-            .collect();
+            .collect::<Result<Vec<_>>>()?;
+
+        let admin_api = AdminApi::new(
+            sites.iter().map(Site::handle).collect(),
+            doc_db,
+            args.admin_token,
+            env_key,
+            api_key_cache.clone(),
+        );
 
         Ok(HqArgsParsed {
             sites,
             deployment_cache,
+            api_key_cache,
+            admin_api,
         })
     }
 }
@@ -25,9 +25,9 @@ impl DeploymentCache {
         loop {
             interval.tick().await;
 
-            let sk = self.cache.count() as u64;
+            let latest_id = self.cache.count() as u64;
 
-            let deployments = match self.doc_db.deployments_after(sk).await {
+            let deployments = match self.doc_db.deployments_after(latest_id).await {
                 Ok(stream) => {
                     telemetry::deployment_cache_fetch_status(true);
                     stream
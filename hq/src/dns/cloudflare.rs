@@ -1,6 +1,9 @@
 use super::*;
 use crate::args::CloudflareDnsProviderArgs;
-use std::{collections::BTreeSet, net::IpAddr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::IpAddr,
+};
 
 const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
 
@@ -10,6 +13,9 @@ pub struct CloudflareDnsProvider {
     asterisk_domain: String,
     api_token: String,
     api_url: String,
+    /// Empty to publish A and AAAA records instead of pool origins.
+    account_id: String,
+    load_balancer_pool_id: String,
 }
 
 impl CloudflareDnsProvider {
@@ -23,9 +29,125 @@ impl CloudflareDnsProvider {
             asterisk_domain: args.asterisk_domain,
             api_token: args.api_token,
             api_url: api_url.unwrap_or_else(|| "https://api.cloudflare.com/client/v4".to_string()),
+            account_id: args.account_id,
+            load_balancer_pool_id: args.load_balancer_pool_id,
         }
     }
-    async fn list_records(&self) -> color_eyre::Result<Vec<Record>> {
+
+    /// Replaces the origins of the site's load balancer pool when an ip or weight
+    /// changed. Steering between sites, e.g. by geography, is the load balancer's.
+    async fn sync_pool(&self, weights: BTreeMap<IpAddr, u8>) -> color_eyre::Result<()> {
+        if weights.is_empty() {
+            // a pool can't be emptied, its health checks take the hosts out
+            return Ok(());
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        struct PoolResponse {
+            success: bool,
+            result: Option<Pool>,
+            #[allow(dead_code)]
+            errors: serde_json::Value,
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        struct Pool {
+            origins: Vec<Origin>,
+        }
+
+        #[derive(Debug, serde::Serialize, serde::Deserialize)]
+        struct Origin {
+            name: String,
+            address: String,
+            weight: f64,
+            enabled: bool,
+        }
+
+        let url = format!(
+            "{}/accounts/{}/load_balancers/pools/{}",
+            self.api_url, self.account_id, self.load_balancer_pool_id
+        );
+
+        let text = self
+            .client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.api_token))
+            .timeout(DEFAULT_TIMEOUT)
+            .send()
+            .await?
+            .text()
+            .await?;
+        let response: PoolResponse = serde_json::from_str(&text)?;
+        let Some(pool) = response.result.filter(|_| response.success) else {
+            return Err(color_eyre::eyre::eyre!("Failed to get pool: {text}"));
+        };
+
+        let current = pool
+            .origins
+            .iter()
+            .filter(|origin| origin.enabled)
+            .map(|origin| {
+                (
+                    origin.address.clone(),
+                    (origin.weight * 100.0).round() as u8,
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        let desired = weights
+            .iter()
+            .map(|(ip, weight)| (ip.to_string(), *weight))
+            .collect::<BTreeMap<_, _>>();
+        if current == desired {
+            return Ok(());
+        }
+
+        let origins = desired
+            .into_iter()
+            .map(|(address, weight)| Origin {
+                name: address.replace([':', '.'], "-"),
+                address,
+                weight: weight as f64 / 100.0,
+                enabled: true,
+            })
+            .collect::<Vec<_>>();
+
+        let text = self
+            .client
+            .patch(&url)
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", self.api_token))
+            .body(serde_json::json!({ "origins": origins }).to_string())
+            .timeout(DEFAULT_TIMEOUT)
+            .send()
+            .await?
+            .text()
+            .await?;
+        tracing::debug!(response = %text, "Patched load balancer pool");
+
+        let response: PoolResponse = serde_json::from_str(&text)?;
+        if !response.success {
+            return Err(color_eyre::eyre::eyre!("Failed to patch pool: {text}"));
+        }
+
+        Ok(())
+    }
+}
+
+impl DnsProvide for CloudflareDnsProvider {
+    async fn sync_ips(&self, ips: BTreeSet<IpAddr>) -> color_eyre::Result<()> {
+        sync_records(self, ips).await
+    }
+
+    async fn sync_weighted_ips(&self, weights: BTreeMap<IpAddr, u8>) -> color_eyre::Result<()> {
+        if self.load_balancer_pool_id.is_empty() {
+            return self.sync_ips(weights.into_keys().collect()).await;
+        }
+        self.sync_pool(weights).await
+    }
+}
+
+impl RecordStore for CloudflareDnsProvider {
+    async fn list_records(&self) -> color_eyre::Result<Vec<DnsRecord>> {
         let url = format!("{}/zones/{}/dns_records", self.api_url, self.zone_id);
         let params = [
             ("per_page", "5000000"),
@@ -45,6 +167,7 @@ impl CloudflareDnsProvider {
             r#type: String,
             content: String,
             id: String,
+            ttl: u32,
         }
 
         let text = self
@@ -70,32 +193,15 @@ impl CloudflareDnsProvider {
             .unwrap_or_default()
             .into_iter()
             .filter(|record| record.r#type == "A" || record.r#type == "AAAA")
-            .map(|record| Record {
+            .map(|record| DnsRecord {
                 ip: record.content.parse().unwrap(),
+                ttl: record.ttl,
                 id: record.id,
             })
             .collect())
     }
-}
-
-impl DnsProvide for CloudflareDnsProvider {
-    async fn sync_ips(&self, ips: BTreeSet<IpAddr>) -> color_eyre::Result<()> {
-        let old_records = self.list_records().await?;
-
-        let new_ips = ips
-            .iter()
-            .filter(|ip| old_records.iter().all(|record| record.ip != **ip))
-            .collect::<BTreeSet<_>>();
-
-        let deleted_ips = old_records
-            .iter()
-            .filter(|record| ips.iter().all(|ip| record.ip != *ip))
-            .collect::<BTreeSet<_>>();
-
-        if new_ips.is_empty() && deleted_ips.is_empty() {
-            return Ok(());
-        }
 
+    async fn apply(&self, diff: RecordDiff, _ips: &BTreeSet<IpAddr>) -> color_eyre::Result<()> {
         #[derive(serde::Serialize)]
         struct Body<'a> {
             deletes: Vec<Delete<'a>>,
@@ -110,7 +216,7 @@ impl DnsProvide for CloudflareDnsProvider {
         #[derive(serde::Serialize)]
         struct Post<'a> {
             name: &'a str,
-            ttl: usize,
+            ttl: u32,
             r#type: &'static str,
             content: String,
             proxied: bool,
@@ -125,21 +231,20 @@ impl DnsProvide for CloudflareDnsProvider {
             .header("Content-Type", "application/json")
             .header("Authorization", format!("Bearer {}", self.api_token))
             .body(serde_json::to_string(&Body {
-                deletes: deleted_ips
-                    .into_iter()
+                deletes: diff
+                    .deletes
+                    .iter()
                     .map(|record| Delete {
                         id: record.id.as_str(),
                     })
                     .collect(),
-                posts: new_ips
-                    .into_iter()
+                posts: diff
+                    .adds
+                    .iter()
                     .map(|ip| Post {
                         name: &self.asterisk_domain,
-                        ttl: 60,
-                        r#type: match ip {
-                            IpAddr::V4(_) => "A",
-                            IpAddr::V6(_) => "AAAA",
-                        },
+                        ttl: RECORD_TTL_SECS,
+                        r#type: record_type(ip),
                         content: ip.to_string(),
                         proxied: false,
                     })
@@ -157,8 +262,112 @@ impl DnsProvide for CloudflareDnsProvider {
     }
 }
 
-#[derive(Ord, PartialOrd, Eq, PartialEq)]
-struct Record {
-    ip: IpAddr,
-    id: String,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider(server: &MockServer) -> CloudflareDnsProvider {
+        let provider = CloudflareDnsProvider::new(
+            CloudflareDnsProviderArgs {
+                zone_id: "zone".to_string(),
+                asterisk_domain: "*.fn0.dev".to_string(),
+                api_token: "token".to_string(),
+                account_id: "account".to_string(),
+                load_balancer_pool_id: "pool".to_string(),
+            },
+            Some(server.uri()),
+        );
+        // the mock listens on IPv4 only
+        CloudflareDnsProvider {
+            client: reqwest::Client::new(),
+            ..provider
+        }
+    }
+
+    async fn mock_pool(server: &MockServer) {
+        Mock::given(method("GET"))
+            .and(path("/accounts/account/load_balancers/pools/pool"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true,
+                "errors": [],
+                "result": {
+                    "origins": [
+                        { "name": "10-0-0-1", "address": "10.0.0.1", "weight": 0.75, "enabled": true },
+                    ]
+                }
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_pool_origins_follow_weights() {
+        let server = MockServer::start().await;
+        mock_pool(&server).await;
+        Mock::given(method("PATCH"))
+            .and(path("/accounts/account/load_balancers/pools/pool"))
+            .and(body_partial_json(serde_json::json!({
+                "origins": [
+                    { "address": "10.0.0.1", "weight": 0.5, "enabled": true },
+                    { "address": "10.0.0.2", "weight": 1.0, "enabled": true },
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true,
+                "errors": [],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        provider(&server)
+            .sync_weighted_ips(
+                [
+                    ("10.0.0.1".parse().unwrap(), 50),
+                    ("10.0.0.2".parse().unwrap(), 100),
+                ]
+                .into(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejected_pool_patch_is_an_error() {
+        let server = MockServer::start().await;
+        mock_pool(&server).await;
+        Mock::given(method("PATCH"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": false,
+                "errors": [{ "code": 1002, "message": "Invalid origin" }],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert!(
+            provider(&server)
+                .sync_weighted_ips([("10.0.0.2".parse().unwrap(), 100)].into())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_pool_is_not_patched() {
+        let server = MockServer::start().await;
+        mock_pool(&server).await;
+        Mock::given(method("PATCH"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        provider(&server)
+            .sync_weighted_ips([("10.0.0.1".parse().unwrap(), 75)].into())
+            .await
+            .unwrap();
+    }
 }
@@ -0,0 +1,30 @@
+use super::*;
+use std::sync::{Arc, Mutex};
+
+/// Keeps the records in memory, for asserting on what a simulated site publishes.
+#[derive(Clone, Default)]
+pub struct FakeDnsProvider {
+    weights: Arc<Mutex<BTreeMap<IpAddr, u8>>>,
+}
+
+impl FakeDnsProvider {
+    pub fn weights(&self) -> BTreeMap<IpAddr, u8> {
+        self.weights.lock().unwrap().clone()
+    }
+
+    pub fn ips(&self) -> BTreeSet<IpAddr> {
+        self.weights().into_keys().collect()
+    }
+}
+
+impl DnsProvide for FakeDnsProvider {
+    async fn sync_ips(&self, ips: BTreeSet<IpAddr>) -> color_eyre::Result<()> {
+        self.sync_weighted_ips(ips.into_iter().map(|ip| (ip, 100)).collect())
+            .await
+    }
+
+    async fn sync_weighted_ips(&self, weights: BTreeMap<IpAddr, u8>) -> color_eyre::Result<()> {
+        *self.weights.lock().unwrap() = weights;
+        Ok(())
+    }
+}
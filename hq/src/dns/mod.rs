@@ -1,19 +1,170 @@
 pub mod cloudflare;
+#[cfg(test)]
+pub mod fake;
+pub mod oci_dns;
+pub mod rfc2136;
+pub mod route53;
 
-use std::{collections::BTreeSet, net::IpAddr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::IpAddr,
+};
+
+/// TTL of the records hq publishes. Records with another TTL are replaced.
+const RECORD_TTL_SECS: u32 = 60;
 
 pub trait DnsProvide: Send + Sync {
     async fn sync_ips(&self, ips: BTreeSet<IpAddr>) -> color_eyre::Result<()>;
+
+    /// `weights` are 1~100, higher for hosts that should get more traffic. Providers
+    /// without weighted records publish every ip alike.
+    async fn sync_weighted_ips(&self, weights: BTreeMap<IpAddr, u8>) -> color_eyre::Result<()> {
+        self.sync_ips(weights.into_keys().collect()).await
+    }
 }
 
 pub enum DnsProvider {
     Cloudflare(cloudflare::CloudflareDnsProvider),
+    Route53(route53::Route53DnsProvider),
+    OciDns(oci_dns::OciDnsProvider),
+    Rfc2136(rfc2136::Rfc2136DnsProvider),
+    #[cfg(test)]
+    Fake(fake::FakeDnsProvider),
 }
 
 impl DnsProvide for DnsProvider {
     async fn sync_ips(&self, ips: BTreeSet<IpAddr>) -> color_eyre::Result<()> {
         match self {
             DnsProvider::Cloudflare(cloudflare) => cloudflare.sync_ips(ips).await,
+            DnsProvider::Route53(route53) => route53.sync_ips(ips).await,
+            DnsProvider::OciDns(oci_dns) => oci_dns.sync_ips(ips).await,
+            DnsProvider::Rfc2136(rfc2136) => rfc2136.sync_ips(ips).await,
+            #[cfg(test)]
+            DnsProvider::Fake(fake) => fake.sync_ips(ips).await,
+        }
+    }
+
+    async fn sync_weighted_ips(&self, weights: BTreeMap<IpAddr, u8>) -> color_eyre::Result<()> {
+        match self {
+            DnsProvider::Cloudflare(cloudflare) => cloudflare.sync_weighted_ips(weights).await,
+            DnsProvider::Route53(route53) => route53.sync_weighted_ips(weights).await,
+            DnsProvider::OciDns(oci_dns) => oci_dns.sync_weighted_ips(weights).await,
+            DnsProvider::Rfc2136(rfc2136) => rfc2136.sync_weighted_ips(weights).await,
+            #[cfg(test)]
+            DnsProvider::Fake(fake) => fake.sync_weighted_ips(weights).await,
         }
     }
 }
+
+/// An A or AAAA record of the asterisk domain, as the provider has it.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
+struct DnsRecord {
+    ip: IpAddr,
+    ttl: u32,
+    /// Empty where the provider has no id per record.
+    id: String,
+}
+
+/// The records a sync adds and deletes.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct RecordDiff {
+    adds: BTreeSet<IpAddr>,
+    deletes: Vec<DnsRecord>,
+}
+
+impl RecordDiff {
+    fn new(records: Vec<DnsRecord>, ips: &BTreeSet<IpAddr>) -> Self {
+        let mut diff = RecordDiff::default();
+        let mut kept = BTreeSet::new();
+        for record in records {
+            if ips.contains(&record.ip) && record.ttl == RECORD_TTL_SECS && kept.insert(record.ip) {
+                continue;
+            }
+            diff.deletes.push(record);
+        }
+        diff.adds = ips.difference(&kept).copied().collect();
+        diff
+    }
+
+    fn is_empty(&self) -> bool {
+        self.adds.is_empty() && self.deletes.is_empty()
+    }
+}
+
+/// What a provider implements to share [`sync_records`].
+trait RecordStore {
+    async fn list_records(&self) -> color_eyre::Result<Vec<DnsRecord>>;
+    /// `ips` is the whole set after the diff, for providers that replace record sets.
+    async fn apply(&self, diff: RecordDiff, ips: &BTreeSet<IpAddr>) -> color_eyre::Result<()>;
+}
+
+/// Adds the missing records, deletes the stale and duplicated ones, and replaces
+/// those with another TTL. Doesn't write when nothing changed.
+async fn sync_records(store: &impl RecordStore, ips: BTreeSet<IpAddr>) -> color_eyre::Result<()> {
+    let diff = RecordDiff::new(store.list_records().await?, &ips);
+    if diff.is_empty() {
+        return Ok(());
+    }
+    store.apply(diff, &ips).await
+}
+
+fn record_type(ip: &IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "A",
+        IpAddr::V6(_) => "AAAA",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ip: &str, ttl: u32, id: &str) -> DnsRecord {
+        DnsRecord {
+            ip: ip.parse().unwrap(),
+            ttl,
+            id: id.to_string(),
+        }
+    }
+
+    fn ips(ips: &[&str]) -> BTreeSet<IpAddr> {
+        ips.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_diff_adds_and_deletes() {
+        let diff = RecordDiff::new(
+            vec![record("10.0.0.1", 60, "a"), record("10.0.0.2", 60, "b")],
+            &ips(&["10.0.0.2", "::1"]),
+        );
+        assert_eq!(
+            diff,
+            RecordDiff {
+                adds: ips(&["::1"]),
+                deletes: vec![record("10.0.0.1", 60, "a")],
+            }
+        );
+    }
+
+    #[test]
+    fn test_diff_replaces_other_ttl_and_duplicates() {
+        let diff = RecordDiff::new(
+            vec![
+                record("10.0.0.1", 300, "a"),
+                record("10.0.0.2", 60, "b"),
+                record("10.0.0.2", 60, "c"),
+            ],
+            &ips(&["10.0.0.1", "10.0.0.2"]),
+        );
+        assert_eq!(
+            diff,
+            RecordDiff {
+                adds: ips(&["10.0.0.1"]),
+                deletes: vec![record("10.0.0.1", 300, "a"), record("10.0.0.2", 60, "c")],
+            }
+        );
+
+        let diff = RecordDiff::new(vec![record("10.0.0.1", 60, "a")], &ips(&["10.0.0.1"]));
+        assert!(diff.is_empty());
+    }
+}
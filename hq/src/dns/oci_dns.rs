@@ -0,0 +1,327 @@
+use super::*;
+use crate::args::OciDnsProviderArgs;
+use base64::Engine;
+use rsa::RsaPrivateKey;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::sha2::{Digest, Sha256};
+use rsa::signature::{SignatureEncoding, Signer};
+
+const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+pub struct OciDnsProvider {
+    client: reqwest::Client,
+    signing_key: SigningKey<Sha256>,
+    key_id: String,
+    zone_id: String,
+    asterisk_domain: String,
+    api_url: String,
+}
+
+impl OciDnsProvider {
+    pub fn new(args: OciDnsProviderArgs, api_url: Option<String>) -> Self {
+        let pem = String::from_utf8(
+            base64::engine::general_purpose::STANDARD
+                .decode(args.private_key_base64)
+                .unwrap(),
+        )
+        .unwrap();
+        let private_key = RsaPrivateKey::from_pkcs8_pem(&pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&pem))
+            .expect("Invalid OCI private key");
+
+        Self {
+            client: reqwest::Client::new(),
+            signing_key: SigningKey::new(private_key),
+            key_id: format!("{}/{}/{}", args.tenancy_id, args.user_id, args.fingerprint),
+            zone_id: args.zone_id,
+            asterisk_domain: args.asterisk_domain,
+            api_url: api_url
+                .unwrap_or_else(|| format!("https://dns.{}.oraclecloud.com/20180115", args.region)),
+        }
+    }
+
+    fn records_url(&self) -> String {
+        format!(
+            "{}/zones/{}/records/{}",
+            self.api_url, self.zone_id, self.asterisk_domain
+        )
+    }
+
+    /// Signs with OCI's HTTP signature scheme, which covers the body of a request
+    /// that has one.
+    fn sign(&self, mut request: reqwest::Request) -> color_eyre::Result<reqwest::Request> {
+        let date = chrono::Utc::now()
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let url = request.url();
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let target = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+
+        let mut headers = vec![
+            ("date", date),
+            (
+                "(request-target)",
+                format!("{} {target}", request.method().as_str().to_lowercase()),
+            ),
+            ("host", host),
+        ];
+        if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+            let content_sha256 =
+                base64::engine::general_purpose::STANDARD.encode(Sha256::digest(body));
+            headers.extend([
+                ("content-length", body.len().to_string()),
+                ("content-type", "application/json".to_string()),
+                ("x-content-sha256", content_sha256),
+            ]);
+        }
+
+        let signing_string = headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let signature = base64::engine::general_purpose::STANDARD
+            .encode(self.signing_key.sign(signing_string.as_bytes()).to_bytes());
+        let authorization = format!(
+            r#"Signature version="1",keyId="{}",algorithm="rsa-sha256",headers="{}",signature="{signature}""#,
+            self.key_id,
+            headers
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+
+        for (name, value) in headers {
+            if name == "(request-target)" || name == "host" {
+                continue;
+            }
+            request.headers_mut().insert(name, value.parse()?);
+        }
+        request
+            .headers_mut()
+            .insert("authorization", authorization.parse()?);
+        Ok(request)
+    }
+}
+
+impl DnsProvide for OciDnsProvider {
+    async fn sync_ips(&self, ips: BTreeSet<IpAddr>) -> color_eyre::Result<()> {
+        sync_records(self, ips).await
+    }
+}
+
+impl RecordStore for OciDnsProvider {
+    async fn list_records(&self) -> color_eyre::Result<Vec<DnsRecord>> {
+        #[derive(Debug, serde::Deserialize)]
+        struct RecordCollection {
+            items: Vec<RecordResponse>,
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct RecordResponse {
+            rtype: String,
+            rdata: String,
+            record_hash: String,
+            ttl: u32,
+        }
+
+        let mut page: Option<String> = None;
+        let mut records = Vec::new();
+        loop {
+            let mut query = vec![("limit", "100".to_string())];
+            if let Some(page) = page.take() {
+                query.push(("page", page));
+            }
+            let request = self
+                .client
+                .get(self.records_url())
+                .query(&query)
+                .timeout(DEFAULT_TIMEOUT)
+                .build()?;
+            let response = self
+                .client
+                .execute(self.sign(request)?)
+                .await?
+                .error_for_status()?;
+
+            page = response
+                .headers()
+                .get("opc-next-page")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let collection: RecordCollection = serde_json::from_str(&response.text().await?)?;
+
+            records.extend(
+                collection
+                    .items
+                    .into_iter()
+                    .filter(|record| record.rtype == "A" || record.rtype == "AAAA")
+                    .map(|record| -> color_eyre::Result<DnsRecord> {
+                        Ok(DnsRecord {
+                            ip: record.rdata.parse()?,
+                            ttl: record.ttl,
+                            id: record.record_hash,
+                        })
+                    })
+                    .collect::<color_eyre::Result<Vec<_>>>()?,
+            );
+
+            if page.is_none() {
+                break;
+            }
+        }
+        Ok(records)
+    }
+
+    async fn apply(&self, diff: RecordDiff, _ips: &BTreeSet<IpAddr>) -> color_eyre::Result<()> {
+        let items = diff
+            .deletes
+            .iter()
+            .map(|record| {
+                serde_json::json!({
+                    "recordHash": record.id,
+                    "operation": "REMOVE",
+                })
+            })
+            .chain(diff.adds.iter().map(|ip| {
+                serde_json::json!({
+                    "domain": self.asterisk_domain,
+                    "rtype": record_type(ip),
+                    "rdata": ip.to_string(),
+                    "ttl": RECORD_TTL_SECS,
+                    "operation": "ADD",
+                })
+            }))
+            .collect::<Vec<_>>();
+
+        let request = self
+            .client
+            .patch(self.records_url())
+            .header("Content-Type", "application/json")
+            .body(serde_json::json!({ "items": items }).to_string())
+            .timeout(DEFAULT_TIMEOUT)
+            .build()?;
+        self.client
+            .execute(self.sign(request)?)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::pkcs1v15::{Signature, VerifyingKey};
+    use rsa::pkcs8::{EncodePrivateKey, LineEnding};
+    use rsa::signature::Verifier;
+    use wiremock::matchers::{body_partial_json, header_exists, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider(server: &MockServer) -> (OciDnsProvider, RsaPrivateKey) {
+        let private_key = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 1024).unwrap();
+        let pem = private_key.to_pkcs8_pem(LineEnding::LF).unwrap();
+        let provider = OciDnsProvider::new(
+            OciDnsProviderArgs {
+                private_key_base64: base64::engine::general_purpose::STANDARD.encode(pem),
+                user_id: "ocid1.user".to_string(),
+                fingerprint: "aa:bb".to_string(),
+                tenancy_id: "ocid1.tenancy".to_string(),
+                region: "ap-chuncheon-1".to_string(),
+                zone_id: "ocid1.dns-zone".to_string(),
+                asterisk_domain: "*.fn0.dev".to_string(),
+            },
+            Some(format!("{}/20180115", server.uri())),
+        );
+        (provider, private_key)
+    }
+
+    #[tokio::test]
+    async fn test_signature_covers_body() {
+        let server = MockServer::start().await;
+        let (provider, private_key) = provider(&server);
+
+        let request = provider
+            .client
+            .patch(provider.records_url())
+            .body("{}")
+            .build()
+            .unwrap();
+        let request = provider.sign(request).unwrap();
+        let headers = request.headers();
+        let authorization = headers["authorization"].to_str().unwrap();
+        assert!(authorization.contains(r#"keyId="ocid1.tenancy/ocid1.user/aa:bb""#));
+        assert!(authorization.contains(
+            r#"headers="date (request-target) host content-length content-type x-content-sha256""#
+        ));
+
+        let signature = authorization
+            .split("signature=\"")
+            .nth(1)
+            .unwrap()
+            .trim_end_matches('"');
+        let signing_string = format!(
+            "date: {}\n(request-target): patch /20180115/zones/ocid1.dns-zone/records/*.fn0.dev\nhost: {}\ncontent-length: 2\ncontent-type: application/json\nx-content-sha256: {}",
+            headers["date"].to_str().unwrap(),
+            server.address(),
+            headers["x-content-sha256"].to_str().unwrap(),
+        );
+        let signature = Signature::try_from(
+            base64::engine::general_purpose::STANDARD
+                .decode(signature)
+                .unwrap()
+                .as_slice(),
+        )
+        .unwrap();
+        VerifyingKey::<Sha256>::new(private_key.to_public_key())
+            .verify(signing_string.as_bytes(), &signature)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sync_patches_diff() {
+        let server = MockServer::start().await;
+        let (provider, _) = provider(&server);
+
+        Mock::given(method("GET"))
+            .and(path("/20180115/zones/ocid1.dns-zone/records/*.fn0.dev"))
+            .and(query_param("limit", "100"))
+            .and(header_exists("authorization"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "items": [
+                    { "domain": "*.fn0.dev", "rtype": "A", "rdata": "10.0.0.1", "recordHash": "h1", "ttl": 60 },
+                    { "domain": "*.fn0.dev", "rtype": "A", "rdata": "10.0.0.2", "recordHash": "h2", "ttl": 60 },
+                ]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/20180115/zones/ocid1.dns-zone/records/*.fn0.dev"))
+            .and(header_exists("x-content-sha256"))
+            .and(body_partial_json(serde_json::json!({
+                "items": [
+                    { "recordHash": "h1", "operation": "REMOVE" },
+                    { "rtype": "A", "rdata": "10.0.0.3", "ttl": 60, "operation": "ADD" },
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        provider
+            .sync_ips(["10.0.0.2".parse().unwrap(), "10.0.0.3".parse().unwrap()].into())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,362 @@
+use super::*;
+use crate::args::Rfc2136DnsProviderArgs;
+use color_eyre::eyre::{bail, eyre};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+const TYPE_A: u16 = 1;
+const TYPE_SOA: u16 = 6;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+/// In an update, deletes the record with the same rdata.
+const CLASS_NONE: u16 = 254;
+const OPCODE_UPDATE: u16 = 5;
+
+/// Dynamic updates (RFC 2136) over TCP to the primary of the zone, e.g. a local
+/// BIND or Knot. Updates are not signed, so the server has to allow them from hq's
+/// address.
+pub struct Rfc2136DnsProvider {
+    server: String,
+    zone: String,
+    asterisk_domain: String,
+}
+
+impl Rfc2136DnsProvider {
+    pub fn new(args: Rfc2136DnsProviderArgs) -> Self {
+        Self {
+            server: args.server,
+            zone: args.zone,
+            asterisk_domain: args.asterisk_domain,
+        }
+    }
+
+    async fn exchange(&self, message: Vec<u8>) -> color_eyre::Result<Vec<u8>> {
+        tokio::time::timeout(DEFAULT_TIMEOUT, async {
+            let mut stream = TcpStream::connect(&self.server).await?;
+            stream.write_u16(message.len() as u16).await?;
+            stream.write_all(&message).await?;
+
+            let len = stream.read_u16().await?;
+            let mut response = vec![0; len as usize];
+            stream.read_exact(&mut response).await?;
+            Ok(response)
+        })
+        .await?
+    }
+}
+
+impl DnsProvide for Rfc2136DnsProvider {
+    async fn sync_ips(&self, ips: BTreeSet<IpAddr>) -> color_eyre::Result<()> {
+        sync_records(self, ips).await
+    }
+}
+
+impl RecordStore for Rfc2136DnsProvider {
+    async fn list_records(&self) -> color_eyre::Result<Vec<DnsRecord>> {
+        let mut records = Vec::new();
+        for rtype in [TYPE_A, TYPE_AAAA] {
+            let id = rand::random();
+            let response = self
+                .exchange(query_message(id, &self.asterisk_domain, rtype)?)
+                .await?;
+            records.extend(parse_answers(&response, id)?);
+        }
+        Ok(records)
+    }
+
+    async fn apply(&self, diff: RecordDiff, _ips: &BTreeSet<IpAddr>) -> color_eyre::Result<()> {
+        let id = rand::random();
+        let response = self
+            .exchange(update_message(
+                id,
+                &self.zone,
+                &self.asterisk_domain,
+                &diff,
+            )?)
+            .await?;
+        check_response(&response, id)?;
+        Ok(())
+    }
+}
+
+fn header(buf: &mut Vec<u8>, id: u16, flags: u16, counts: [u16; 4]) {
+    buf.extend(id.to_be_bytes());
+    buf.extend(flags.to_be_bytes());
+    for count in counts {
+        buf.extend(count.to_be_bytes());
+    }
+}
+
+fn encode_name(buf: &mut Vec<u8>, name: &str) -> color_eyre::Result<()> {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("Invalid DNS name: {name}");
+        }
+        buf.push(label.len() as u8);
+        buf.extend(label.as_bytes());
+    }
+    buf.push(0);
+    Ok(())
+}
+
+fn query_message(id: u16, name: &str, rtype: u16) -> color_eyre::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    header(&mut buf, id, 0, [1, 0, 0, 0]);
+    encode_name(&mut buf, name)?;
+    buf.extend(rtype.to_be_bytes());
+    buf.extend(CLASS_IN.to_be_bytes());
+    Ok(buf)
+}
+
+/// Deletes before adds, so a record re-added with a new TTL ends up present.
+fn update_message(
+    id: u16,
+    zone: &str,
+    name: &str,
+    diff: &RecordDiff,
+) -> color_eyre::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let updates = (diff.deletes.len() + diff.adds.len()) as u16;
+    header(&mut buf, id, OPCODE_UPDATE << 11, [1, 0, updates, 0]);
+
+    encode_name(&mut buf, zone)?;
+    buf.extend(TYPE_SOA.to_be_bytes());
+    buf.extend(CLASS_IN.to_be_bytes());
+
+    let records = diff
+        .deletes
+        .iter()
+        .map(|record| (&record.ip, CLASS_NONE, 0))
+        .chain(diff.adds.iter().map(|ip| (ip, CLASS_IN, RECORD_TTL_SECS)));
+    for (ip, class, ttl) in records {
+        encode_name(&mut buf, name)?;
+        let (rtype, rdata) = match ip {
+            IpAddr::V4(ip) => (TYPE_A, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (TYPE_AAAA, ip.octets().to_vec()),
+        };
+        buf.extend(rtype.to_be_bytes());
+        buf.extend(class.to_be_bytes());
+        buf.extend(ttl.to_be_bytes());
+        buf.extend((rdata.len() as u16).to_be_bytes());
+        buf.extend(rdata);
+    }
+    Ok(buf)
+}
+
+struct Reader<'a> {
+    message: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> color_eyre::Result<&[u8]> {
+        let bytes = self
+            .message
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| eyre!("DNS message is truncated"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> color_eyre::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> color_eyre::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Names are skipped, the answers are to the single question asked.
+    fn skip_name(&mut self) -> color_eyre::Result<()> {
+        loop {
+            let len = self.take(1)?[0];
+            match len {
+                0 => return Ok(()),
+                // a compression pointer ends the name
+                len if len & 0xC0 == 0xC0 => {
+                    self.take(1)?;
+                    return Ok(());
+                }
+                len => {
+                    self.take(len as usize)?;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the header's question and answer counts.
+fn check_response(message: &[u8], id: u16) -> color_eyre::Result<(u16, u16)> {
+    let mut reader = Reader { message, pos: 0 };
+    if reader.u16()? != id {
+        bail!("DNS response id mismatch");
+    }
+    let flags = reader.u16()?;
+    let rcode = flags & 0x000F;
+    if rcode != 0 {
+        bail!("DNS server returned rcode {rcode}");
+    }
+    Ok((reader.u16()?, reader.u16()?))
+}
+
+fn parse_answers(message: &[u8], id: u16) -> color_eyre::Result<Vec<DnsRecord>> {
+    let (questions, answers) = check_response(message, id)?;
+    let mut reader = Reader { message, pos: 12 };
+    for _ in 0..questions {
+        reader.skip_name()?;
+        reader.take(4)?;
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        reader.skip_name()?;
+        let rtype = reader.u16()?;
+        let _class = reader.u16()?;
+        let ttl = reader.u32()?;
+        let len = reader.u16()? as usize;
+        let rdata = reader.take(len)?;
+        let ip = match (rtype, rdata.len()) {
+            (TYPE_A, 4) => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(rdata)?)),
+            (TYPE_AAAA, 16) => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(rdata)?)),
+            _ => continue,
+        };
+        records.push(DnsRecord {
+            ip,
+            ttl,
+            id: String::new(),
+        });
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_query_message() {
+        assert_eq!(
+            query_message(0x1234, "*.fn0.dev", TYPE_A).unwrap(),
+            [
+                &[0x12, 0x34, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0][..],
+                b"\x01*\x03fn0\x03dev\x00",
+                &[0, 1, 0, 1],
+            ]
+            .concat()
+        );
+        assert!(query_message(0, "a..b", TYPE_A).is_err());
+    }
+
+    #[test]
+    fn test_update_message_deletes_then_adds() {
+        let diff = RecordDiff {
+            adds: ["10.0.0.2".parse().unwrap()].into(),
+            deletes: vec![DnsRecord {
+                ip: "10.0.0.1".parse().unwrap(),
+                ttl: 300,
+                id: String::new(),
+            }],
+        };
+        let name = b"\x01*\x03fn0\x03dev\x00";
+        assert_eq!(
+            update_message(7, "fn0.dev", "*.fn0.dev", &diff).unwrap(),
+            [
+                &[0, 7, 0x28, 0, 0, 1, 0, 0, 0, 2, 0, 0][..],
+                b"\x03fn0\x03dev\x00",
+                &[0, 6, 0, 1],
+                name,
+                &[0, 1, 0, 254, 0, 0, 0, 0, 0, 4, 10, 0, 0, 1],
+                name,
+                &[0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 0, 0, 2],
+            ]
+            .concat()
+        );
+    }
+
+    fn response(id: u16, rcode: u8, answers: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        header(
+            &mut buf,
+            id,
+            0x8400 | rcode as u16,
+            [1, answers.len() as u16, 0, 0],
+        );
+        encode_name(&mut buf, "*.fn0.dev").unwrap();
+        buf.extend([0, 1, 0, 1]);
+        for (rtype, rdata) in answers {
+            // pointer to the question's name
+            buf.extend([0xC0, 12]);
+            buf.extend(rtype.to_be_bytes());
+            buf.extend([0, 1, 0, 0, 0, 60]);
+            buf.extend((rdata.len() as u16).to_be_bytes());
+            buf.extend(*rdata);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_parse_answers() {
+        let message = response(9, 0, &[(TYPE_A, &[10, 0, 0, 1]), (TYPE_SOA, &[1, 2])]);
+        assert_eq!(
+            parse_answers(&message, 9).unwrap(),
+            vec![DnsRecord {
+                ip: "10.0.0.1".parse().unwrap(),
+                ttl: 60,
+                id: String::new(),
+            }]
+        );
+        assert!(parse_answers(&message, 8).is_err());
+        assert!(parse_answers(&response(9, 5, &[]), 9).is_err());
+        assert!(parse_answers(&message[..message.len() - 1], 9).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_sends_update_to_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let provider = Rfc2136DnsProvider::new(Rfc2136DnsProviderArgs {
+            server: listener.local_addr().unwrap().to_string(),
+            zone: "fn0.dev".to_string(),
+            asterisk_domain: "*.fn0.dev".to_string(),
+        });
+
+        // answers the A query with one record and the AAAA query with none, then
+        // returns the update
+        let server = tokio::spawn(async move {
+            let mut update = Vec::new();
+            for answer in [Some([10, 0, 0, 1]), None, None] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let len = stream.read_u16().await.unwrap();
+                let mut request = vec![0; len as usize];
+                stream.read_exact(&mut request).await.unwrap();
+                let id = u16::from_be_bytes([request[0], request[1]]);
+
+                let reply = match answer {
+                    Some(ip) => response(id, 0, &[(TYPE_A, &ip)]),
+                    None => response(id, 0, &[]),
+                };
+                stream.write_u16(reply.len() as u16).await.unwrap();
+                stream.write_all(&reply).await.unwrap();
+                update = request;
+            }
+            update
+        });
+
+        provider
+            .sync_ips(["10.0.0.2".parse().unwrap()].into())
+            .await
+            .unwrap();
+
+        let update = server.await.unwrap();
+        let flags = u16::from_be_bytes([update[2], update[3]]);
+        assert_eq!(flags >> 11, OPCODE_UPDATE);
+        // one delete of 10.0.0.1 and one add of 10.0.0.2
+        assert_eq!(&update[8..10], &[0, 2]);
+        assert!(update.ends_with(&[0, 0, 0, 60, 0, 4, 10, 0, 0, 2]));
+    }
+}
@@ -0,0 +1,527 @@
+use super::*;
+use crate::args::{Route53DnsProviderArgs, Route53Routing};
+use aws_sdk_route53::config::{BehaviorVersion, Credentials, Region};
+use aws_sdk_route53::types::{
+    Change, ChangeAction, ChangeBatch, ResourceRecord, ResourceRecordSet, ResourceRecordSetRegion,
+    RrType,
+};
+
+/// Prefix of the set identifiers of weighted sets, one per host.
+const WEIGHTED_SET_PREFIX: &str = "fn0-";
+
+pub struct Route53DnsProvider {
+    client: aws_sdk_route53::Client,
+    hosted_zone_id: String,
+    asterisk_domain: String,
+    routing: Route53Routing,
+}
+
+impl Route53Routing {
+    fn is_own_set(&self, set_identifier: Option<&str>) -> bool {
+        match self {
+            Route53Routing::Simple => set_identifier.is_none(),
+            Route53Routing::Weighted => {
+                set_identifier.is_some_and(|id| id.starts_with(WEIGHTED_SET_PREFIX))
+            }
+            Route53Routing::Latency { region } => set_identifier == Some(region.as_str()),
+        }
+    }
+}
+
+impl Route53DnsProvider {
+    /// `endpoint_url` overrides the global Route 53 endpoint, e.g. with a mock.
+    pub fn new(
+        args: Route53DnsProviderArgs,
+        endpoint_url: Option<String>,
+    ) -> color_eyre::Result<Self> {
+        if let Route53Routing::Latency { region } = &args.routing
+            && !ResourceRecordSetRegion::values().contains(&region.as_str())
+        {
+            return Err(color_eyre::eyre::eyre!(
+                "{region} is not a Route 53 latency region"
+            ));
+        }
+
+        let config = aws_sdk_route53::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new(
+                args.access_key_id,
+                args.secret_access_key,
+                None,
+                None,
+                "hq-args",
+            ))
+            .set_endpoint_url(endpoint_url)
+            .build();
+
+        Ok(Self {
+            client: aws_sdk_route53::Client::from_conf(config),
+            hosted_zone_id: args.hosted_zone_id,
+            asterisk_domain: args.asterisk_domain,
+            routing: args.routing,
+        })
+    }
+
+    /// A and AAAA sets of the asterisk domain that this routing owns. Sets of other
+    /// routings, e.g. other sites' latency sets, are left alone.
+    async fn list_record_sets(&self) -> color_eyre::Result<Vec<ResourceRecordSet>> {
+        let mut record_sets = Vec::new();
+        let mut start = (self.asterisk_domain.clone(), RrType::A, None);
+        loop {
+            let (name, rr_type, identifier) = start;
+            let response = self
+                .client
+                .list_resource_record_sets()
+                .hosted_zone_id(&self.hosted_zone_id)
+                .start_record_name(name)
+                .start_record_type(rr_type)
+                .set_start_record_identifier(identifier)
+                .max_items(100)
+                .send()
+                .await?;
+
+            let mut past_domain = false;
+            for record_set in response.resource_record_sets() {
+                if !self.is_asterisk_domain(record_set.name()) {
+                    past_domain = true;
+                    continue;
+                }
+                if matches!(record_set.r#type(), RrType::A | RrType::Aaaa)
+                    && self.routing.is_own_set(record_set.set_identifier())
+                {
+                    record_sets.push(record_set.clone());
+                }
+            }
+
+            // sets are listed in name order, so the rest are of other names
+            if past_domain || !response.is_truncated() {
+                break;
+            }
+            let (Some(name), Some(rr_type)) =
+                (response.next_record_name(), response.next_record_type())
+            else {
+                break;
+            };
+            start = (
+                name.to_string(),
+                rr_type.clone(),
+                response.next_record_identifier().map(str::to_string),
+            );
+        }
+        Ok(record_sets)
+    }
+
+    /// Upserts a set per host whose weight or ip changed and deletes the sets of
+    /// hosts that are gone.
+    async fn sync_weighted_sets(&self, weights: BTreeMap<IpAddr, u8>) -> color_eyre::Result<()> {
+        let current = self.list_record_sets().await?;
+        let mut changes = Vec::new();
+
+        for (ip, weight) in &weights {
+            let set_identifier = format!("{WEIGHTED_SET_PREFIX}{ip}");
+            let up_to_date = current.iter().any(|record_set| {
+                record_set.set_identifier() == Some(set_identifier.as_str())
+                    && record_set.weight() == Some(*weight as i64)
+                    && record_set.ttl() == Some(RECORD_TTL_SECS as i64)
+                    && record_set.r#type().as_str() == record_type(ip)
+                    && record_set
+                        .resource_records()
+                        .iter()
+                        .map(|record| record.value())
+                        .eq([ip.to_string().as_str()])
+            });
+            if up_to_date {
+                continue;
+            }
+            let record_set = ResourceRecordSet::builder()
+                .name(&self.asterisk_domain)
+                .r#type(RrType::from(record_type(ip)))
+                .ttl(RECORD_TTL_SECS as i64)
+                .set_identifier(set_identifier)
+                .weight(*weight as i64)
+                .resource_records(ResourceRecord::builder().value(ip.to_string()).build()?)
+                .build()?;
+            changes.push(
+                Change::builder()
+                    .action(ChangeAction::Upsert)
+                    .resource_record_set(record_set)
+                    .build()?,
+            );
+        }
+
+        for record_set in current {
+            let is_stale = record_set
+                .set_identifier()
+                .and_then(|id| id.strip_prefix(WEIGHTED_SET_PREFIX))
+                .and_then(|ip| ip.parse::<IpAddr>().ok())
+                .is_none_or(|ip| {
+                    !weights.contains_key(&ip) || record_set.r#type().as_str() != record_type(&ip)
+                });
+            if is_stale {
+                // a delete has to match the current set exactly
+                changes.push(
+                    Change::builder()
+                        .action(ChangeAction::Delete)
+                        .resource_record_set(record_set)
+                        .build()?,
+                );
+            }
+        }
+
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.client
+            .change_resource_record_sets()
+            .hosted_zone_id(&self.hosted_zone_id)
+            .change_batch(ChangeBatch::builder().set_changes(Some(changes)).build()?)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    fn is_asterisk_domain(&self, name: &str) -> bool {
+        // Route 53 returns names fully qualified, with `*` escaped
+        name.replace("\\052", "*").trim_end_matches('.')
+            == self.asterisk_domain.trim_end_matches('.')
+    }
+}
+
+impl DnsProvide for Route53DnsProvider {
+    async fn sync_ips(&self, ips: BTreeSet<IpAddr>) -> color_eyre::Result<()> {
+        sync_records(self, ips).await
+    }
+
+    async fn sync_weighted_ips(&self, weights: BTreeMap<IpAddr, u8>) -> color_eyre::Result<()> {
+        match self.routing {
+            Route53Routing::Weighted => self.sync_weighted_sets(weights).await,
+            Route53Routing::Simple | Route53Routing::Latency { .. } => {
+                self.sync_ips(weights.into_keys().collect()).await
+            }
+        }
+    }
+}
+
+impl RecordStore for Route53DnsProvider {
+    async fn list_records(&self) -> color_eyre::Result<Vec<DnsRecord>> {
+        let mut records = Vec::new();
+        for record_set in self.list_record_sets().await? {
+            let ttl = record_set.ttl().unwrap_or_default() as u32;
+            for record in record_set.resource_records() {
+                records.push(DnsRecord {
+                    ip: record.value().parse()?,
+                    ttl,
+                    id: String::new(),
+                });
+            }
+        }
+        Ok(records)
+    }
+
+    /// Route 53 keeps a set per name and type, so a changed set is replaced whole.
+    async fn apply(&self, diff: RecordDiff, ips: &BTreeSet<IpAddr>) -> color_eyre::Result<()> {
+        let mut changes = Vec::new();
+
+        for rr_type in [RrType::A, RrType::Aaaa] {
+            let of_type = |ip: &IpAddr| record_type(ip) == rr_type.as_str();
+            let deletes = diff
+                .deletes
+                .iter()
+                .filter(|record| of_type(&record.ip))
+                .collect::<Vec<_>>();
+            if !diff.adds.iter().any(of_type) && deletes.is_empty() {
+                continue;
+            }
+
+            let values = ips.iter().filter(|ip| of_type(ip)).collect::<Vec<_>>();
+            let change = if values.is_empty() {
+                // a delete has to match the current set exactly
+                record_set_change(
+                    ChangeAction::Delete,
+                    &self.asterisk_domain,
+                    &self.routing,
+                    rr_type,
+                    deletes[0].ttl,
+                    deletes.iter().map(|record| &record.ip),
+                )?
+            } else {
+                record_set_change(
+                    ChangeAction::Upsert,
+                    &self.asterisk_domain,
+                    &self.routing,
+                    rr_type,
+                    RECORD_TTL_SECS,
+                    values.into_iter(),
+                )?
+            };
+            changes.push(change);
+        }
+
+        self.client
+            .change_resource_record_sets()
+            .hosted_zone_id(&self.hosted_zone_id)
+            .change_batch(ChangeBatch::builder().set_changes(Some(changes)).build()?)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+fn record_set_change<'a>(
+    action: ChangeAction,
+    name: &str,
+    routing: &Route53Routing,
+    rr_type: RrType,
+    ttl: u32,
+    ips: impl Iterator<Item = &'a IpAddr>,
+) -> color_eyre::Result<Change> {
+    let resource_records = ips
+        .map(|ip| ResourceRecord::builder().value(ip.to_string()).build())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut record_set = ResourceRecordSet::builder()
+        .name(name)
+        .r#type(rr_type)
+        .ttl(ttl as i64)
+        .set_resource_records(Some(resource_records));
+    if let Route53Routing::Latency { region } = routing {
+        record_set = record_set
+            .set_identifier(region)
+            .region(ResourceRecordSetRegion::from(region.as_str()));
+    }
+    let record_set = record_set.build()?;
+    Ok(Change::builder()
+        .action(action)
+        .resource_record_set(record_set)
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, method, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider(server: &MockServer) -> Route53DnsProvider {
+        Route53DnsProvider::new(
+            Route53DnsProviderArgs {
+                hosted_zone_id: "Z1".to_string(),
+                asterisk_domain: "*.fn0.dev".to_string(),
+                access_key_id: "AKIDEXAMPLE".to_string(),
+                secret_access_key: "secret".to_string(),
+                routing: Route53Routing::Simple,
+            },
+            Some(server.uri()),
+        )
+        .unwrap()
+    }
+
+    fn xml(body: &str) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_raw(body.to_string(), "text/xml")
+    }
+
+    async fn mock_list(server: &MockServer) {
+        Mock::given(method("GET"))
+            .and(path_regex("/hostedzone/Z1/rrset$"))
+            .respond_with(xml(
+                r#"<ListResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+                    <ResourceRecordSets>
+                        <ResourceRecordSet>
+                            <Name>\052.fn0.dev.</Name>
+                            <Type>A</Type>
+                            <TTL>60</TTL>
+                            <ResourceRecords>
+                                <ResourceRecord><Value>10.0.0.1</Value></ResourceRecord>
+                            </ResourceRecords>
+                        </ResourceRecordSet>
+                        <ResourceRecordSet>
+                            <Name>\052.fn0.dev.</Name>
+                            <Type>AAAA</Type>
+                            <TTL>60</TTL>
+                            <ResourceRecords>
+                                <ResourceRecord><Value>::1</Value></ResourceRecord>
+                            </ResourceRecords>
+                        </ResourceRecordSet>
+                        <ResourceRecordSet>
+                            <Name>www.fn0.dev.</Name>
+                            <Type>A</Type>
+                            <TTL>300</TTL>
+                            <ResourceRecords>
+                                <ResourceRecord><Value>10.9.9.9</Value></ResourceRecord>
+                            </ResourceRecords>
+                        </ResourceRecordSet>
+                    </ResourceRecordSets>
+                    <IsTruncated>false</IsTruncated>
+                    <MaxItems>100</MaxItems>
+                </ListResourceRecordSetsResponse>"#,
+            ))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_list_records_of_asterisk_domain() {
+        let server = MockServer::start().await;
+        mock_list(&server).await;
+
+        let mut records = provider(&server).list_records().await.unwrap();
+        records.sort();
+        assert_eq!(
+            records
+                .into_iter()
+                .map(|record| (record.ip.to_string(), record.ttl))
+                .collect::<Vec<_>>(),
+            vec![("10.0.0.1".to_string(), 60), ("::1".to_string(), 60)]
+        );
+    }
+
+    #[test]
+    fn test_routing_owns_its_sets() {
+        assert!(Route53Routing::Simple.is_own_set(None));
+        assert!(!Route53Routing::Simple.is_own_set(Some("ap-northeast-2")));
+        assert!(Route53Routing::Weighted.is_own_set(Some("fn0-10.0.0.1")));
+        assert!(!Route53Routing::Weighted.is_own_set(None));
+        let latency = Route53Routing::Latency {
+            region: "ap-northeast-2".to_string(),
+        };
+        assert!(latency.is_own_set(Some("ap-northeast-2")));
+        assert!(!latency.is_own_set(Some("us-east-1")));
+    }
+
+    #[test]
+    fn test_routing_parses_from_args() {
+        let routing = |json: &str| serde_json::from_str::<Route53Routing>(json);
+        assert_eq!(routing(r#""simple""#).unwrap(), Route53Routing::Simple);
+        assert_eq!(routing(r#""weighted""#).unwrap(), Route53Routing::Weighted);
+        assert_eq!(
+            routing(r#"{"latency":{"region":"ap-northeast-2"}}"#).unwrap(),
+            Route53Routing::Latency {
+                region: "ap-northeast-2".to_string()
+            }
+        );
+        assert!(routing(r#""weigthed""#).is_err());
+    }
+
+    #[test]
+    fn test_unknown_latency_region_is_rejected() {
+        let args = Route53DnsProviderArgs {
+            hosted_zone_id: "Z1".to_string(),
+            asterisk_domain: "*.fn0.dev".to_string(),
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            routing: Route53Routing::Latency {
+                region: "ap-northeast".to_string(),
+            },
+        };
+        assert!(Route53DnsProvider::new(args, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_upserts_changed_set_and_deletes_emptied_set() {
+        let server = MockServer::start().await;
+        mock_list(&server).await;
+        Mock::given(method("POST"))
+            .and(path_regex("/hostedzone/Z1/rrset/?$"))
+            .and(body_string_contains("<Action>UPSERT</Action>"))
+            .and(body_string_contains("<Value>10.0.0.2</Value>"))
+            .and(body_string_contains("<Action>DELETE</Action>"))
+            .and(body_string_contains("<Value>::1</Value>"))
+            .respond_with(xml(
+                r#"<ChangeResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+                    <ChangeInfo>
+                        <Id>/change/C1</Id>
+                        <Status>PENDING</Status>
+                        <SubmittedAt>2025-01-01T00:00:00Z</SubmittedAt>
+                    </ChangeInfo>
+                </ChangeResourceRecordSetsResponse>"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        provider(&server)
+            .sync_ips(["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()].into())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_weighted_sync_upserts_changed_and_deletes_gone_hosts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path_regex("/hostedzone/Z1/rrset$"))
+            .respond_with(xml(
+                r#"<ListResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+                    <ResourceRecordSets>
+                        <ResourceRecordSet>
+                            <Name>\052.fn0.dev.</Name>
+                            <Type>A</Type>
+                            <SetIdentifier>fn0-10.0.0.1</SetIdentifier>
+                            <Weight>50</Weight>
+                            <TTL>60</TTL>
+                            <ResourceRecords>
+                                <ResourceRecord><Value>10.0.0.1</Value></ResourceRecord>
+                            </ResourceRecords>
+                        </ResourceRecordSet>
+                        <ResourceRecordSet>
+                            <Name>\052.fn0.dev.</Name>
+                            <Type>A</Type>
+                            <SetIdentifier>fn0-10.0.0.2</SetIdentifier>
+                            <Weight>80</Weight>
+                            <TTL>60</TTL>
+                            <ResourceRecords>
+                                <ResourceRecord><Value>10.0.0.2</Value></ResourceRecord>
+                            </ResourceRecords>
+                        </ResourceRecordSet>
+                        <ResourceRecordSet>
+                            <Name>\052.fn0.dev.</Name>
+                            <Type>A</Type>
+                            <SetIdentifier>fn0-10.0.0.3</SetIdentifier>
+                            <Weight>10</Weight>
+                            <TTL>60</TTL>
+                            <ResourceRecords>
+                                <ResourceRecord><Value>10.0.0.3</Value></ResourceRecord>
+                            </ResourceRecords>
+                        </ResourceRecordSet>
+                    </ResourceRecordSets>
+                    <IsTruncated>false</IsTruncated>
+                    <MaxItems>100</MaxItems>
+                </ListResourceRecordSetsResponse>"#,
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path_regex("/hostedzone/Z1/rrset/?$"))
+            .and(body_string_contains("<SetIdentifier>fn0-10.0.0.2</SetIdentifier>"))
+            .and(body_string_contains("<Weight>30</Weight>"))
+            .and(body_string_contains("<SetIdentifier>fn0-10.0.0.3</SetIdentifier>"))
+            .and(body_string_contains("<Action>DELETE</Action>"))
+            .respond_with(xml(
+                r#"<ChangeResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+                    <ChangeInfo>
+                        <Id>/change/C1</Id>
+                        <Status>PENDING</Status>
+                        <SubmittedAt>2025-01-01T00:00:00Z</SubmittedAt>
+                    </ChangeInfo>
+                </ChangeResourceRecordSetsResponse>"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = Route53DnsProvider {
+            routing: Route53Routing::Weighted,
+            ..provider(&server)
+        };
+        provider
+            .sync_weighted_ips(
+                [
+                    ("10.0.0.1".parse().unwrap(), 50),
+                    ("10.0.0.2".parse().unwrap(), 30),
+                ]
+                .into(),
+            )
+            .await
+            .unwrap();
+    }
+}
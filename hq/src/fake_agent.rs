@@ -0,0 +1,160 @@
+use crate::*;
+use host_hq_protocol::{HostToHq, HqToHostDatagram, HqToHostReliable};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// In-memory agents of the hosts of a simulated site, by ip. Each answers hq's
+/// pings with its status the way fn0's agent does, as a test scripts it.
+#[derive(Clone, Default)]
+pub struct FakeAgents {
+    agents: Arc<Mutex<BTreeMap<IpAddr, FakeAgent>>>,
+}
+
+#[derive(Clone)]
+pub struct FakeAgent {
+    /// Connections are refused until then.
+    pub booted_at: Instant,
+    /// Stops answering and refuses connections, as a crashed or terminated host.
+    pub down: bool,
+    /// Keeps answering after a graceful shutdown, as a host still finishing its
+    /// in-flight work.
+    pub drains_slowly: bool,
+    /// How long a ping waits for its status.
+    pub pong_delay: Duration,
+    pub instances: u64,
+    pub deployment_id: u64,
+    /// Messages hq sent reliably, oldest first.
+    pub received: Vec<HqToHostReliable>,
+    /// Status timestamps, increasing like the host's clock.
+    timestamp: u64,
+}
+
+impl FakeAgents {
+    pub fn boot(&self, ip: IpAddr, boot_delay: Duration) {
+        self.agents.lock().unwrap().insert(
+            ip,
+            FakeAgent {
+                booted_at: Instant::now() + boot_delay,
+                down: false,
+                drains_slowly: false,
+                pong_delay: Duration::ZERO,
+                instances: 0,
+                deployment_id: 0,
+                received: Vec::new(),
+                timestamp: 0,
+            },
+        );
+    }
+
+    pub fn get(&self, ip: IpAddr) -> Option<FakeAgent> {
+        self.agents.lock().unwrap().get(&ip).cloned()
+    }
+
+    /// Scripts the agent at `ip`, e.g. to crash it or to slow its pongs.
+    pub fn update(&self, ip: IpAddr, update: impl FnOnce(&mut FakeAgent)) {
+        if let Some(agent) = self.agents.lock().unwrap().get_mut(&ip) {
+            update(agent);
+        }
+    }
+
+    pub fn connect(&self, ip: IpAddr) -> Result<FakeAgentLink> {
+        let agents = self.agents.lock().unwrap();
+        match agents.get(&ip) {
+            Some(agent) if !agent.down && agent.booted_at <= Instant::now() => {}
+            _ => return Err(eyre!("Connection refused by fake agent {ip}")),
+        }
+
+        let (pong_tx, pong_rx) = mpsc::unbounded_channel();
+        Ok(FakeAgentLink {
+            ip,
+            agents: self.clone(),
+            pong_tx,
+            pong_rx: Arc::new(tokio::sync::Mutex::new(pong_rx)),
+            closed: CancellationToken::new(),
+        })
+    }
+}
+
+/// hq's side of a connection to a [`FakeAgent`].
+#[derive(Clone)]
+pub struct FakeAgentLink {
+    ip: IpAddr,
+    agents: FakeAgents,
+    pong_tx: mpsc::UnboundedSender<Bytes>,
+    pong_rx: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<Bytes>>>,
+    closed: CancellationToken,
+}
+
+impl FakeAgentLink {
+    /// Like a datagram, a ping to an agent that is down is lost without an error.
+    pub fn send_datagram(&self, bytes: Bytes) {
+        let Ok(HqToHostDatagram::AdvertiseLatestDeploymentId { .. }) =
+            HqToHostDatagram::from_bytes(bytes)
+        else {
+            return;
+        };
+        let Some(agent) = self.agents.get(self.ip) else {
+            return;
+        };
+
+        let link = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(agent.pong_delay).await;
+
+            let mut status = None;
+            link.agents.update(link.ip, |agent| {
+                if agent.down || link.closed.is_cancelled() {
+                    return;
+                }
+                agent.timestamp += 1;
+                status = Some(HostToHq::NotifyHostStatus {
+                    timestamp: agent.timestamp,
+                    deployment_id: agent.deployment_id,
+                    instances: agent.instances,
+                });
+            });
+            if let Some(Ok(bytes)) = status.map(|status| status.to_bytes()) {
+                let _ = link.pong_tx.send(bytes);
+            }
+        });
+    }
+
+    pub fn send_reliable(&self, bytes: Bytes) -> Result<()> {
+        if self.closed.is_cancelled() {
+            return Err(eyre!("Connection to fake agent {} is closed", self.ip));
+        }
+        let message = HqToHostReliable::from_bytes(bytes)?;
+        self.agents.update(self.ip, |agent| {
+            match &message {
+                HqToHostReliable::DeploymentUpdates {
+                    deployment_id,
+                    code_id_and_versions,
+                } => {
+                    agent.deployment_id = deployment_id + code_id_and_versions.len() as u64;
+                }
+                // the host finishes its in-flight work and exits
+                HqToHostReliable::GracefulShutdown => agent.down = !agent.drains_slowly,
+                HqToHostReliable::RegionRateLimits { .. } => {}
+            }
+            agent.received.push(message);
+        });
+        Ok(())
+    }
+
+    pub async fn read_datagram(&self) -> Result<Bytes> {
+        let mut pong_rx = self.pong_rx.lock().await;
+        tokio::select! {
+            _ = self.closed.cancelled() => Err(eyre!("Connection to fake agent {} is closed", self.ip)),
+            bytes = pong_rx.recv() => bytes.ok_or_else(|| eyre!("Fake agent {} is gone", self.ip)),
+        }
+    }
+
+    pub fn close(&self) {
+        self.closed.cancel();
+    }
+}
@@ -1,19 +1,52 @@
+use bytes::Bytes;
 use color_eyre::eyre::{Result, eyre};
 use host_hq_protocol::{HqToHostDatagram, HqToHostReliable};
-use quinn::{ClientConfig, Connection, Endpoint, ReadDatagram};
+use quinn::{ClientConfig, Connection, Endpoint};
 use rustls::pki_types::CertificateDer;
 use std::{
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     sync::Arc,
 };
+use tokio::time::Instant;
+
+/// How hq reaches the agents on its hosts.
+#[derive(Clone)]
+pub enum HostConnector {
+    Quic {
+        cert: String,
+    },
+    #[cfg(test)]
+    Fake(crate::fake_agent::FakeAgents),
+}
+
+impl HostConnector {
+    pub async fn connect(&self, addr: SocketAddr) -> Result<HostConnection> {
+        match self {
+            HostConnector::Quic { cert } => HostConnection::connect(addr, cert).await,
+            #[cfg(test)]
+            HostConnector::Fake(agents) => Ok(HostConnection {
+                link: Link::Fake(agents.connect(addr.ip())?),
+                connected_at: Instant::now(),
+            }),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct HostConnection {
-    connection: Connection,
+    link: Link,
+    connected_at: Instant,
+}
+
+#[derive(Clone)]
+enum Link {
+    Quic(Connection),
+    #[cfg(test)]
+    Fake(crate::fake_agent::FakeAgentLink),
 }
 
 impl HostConnection {
-    pub async fn connect(addr: SocketAddr, cert: &str) -> Result<Self> {
+    async fn connect(addr: SocketAddr, cert: &str) -> Result<Self> {
         let local = if addr.is_ipv4() {
             LOCAL_IPV4
         } else {
@@ -25,28 +58,52 @@ impl HostConnection {
             .connect_with(client_config, addr, "host.fn0")?
             .await?;
 
-        Ok(Self { connection })
+        Ok(Self {
+            link: Link::Quic(connection),
+            connected_at: Instant::now(),
+        })
+    }
+    pub fn connected_at(&self) -> Instant {
+        self.connected_at
     }
     pub fn send_datagram(&self, datagram: HqToHostDatagram) -> Result<()> {
         let bytes = datagram.to_bytes()?;
         if bytes.len() > 1200 {
             return Err(eyre!("Datagram is too large"));
         }
-        self.connection.send_datagram(bytes)?;
+        match &self.link {
+            Link::Quic(connection) => connection.send_datagram(bytes)?,
+            #[cfg(test)]
+            Link::Fake(link) => link.send_datagram(bytes),
+        }
         Ok(())
     }
     pub async fn send_reliable(&self, message: HqToHostReliable) -> Result<()> {
         let bytes = message.to_bytes()?;
-        let mut send = self.connection.open_uni().await?;
-        send.write_all(&bytes).await?;
-        send.finish()?;
+        match &self.link {
+            Link::Quic(connection) => {
+                let mut send = connection.open_uni().await?;
+                send.write_all(&bytes).await?;
+                send.finish()?;
+            }
+            #[cfg(test)]
+            Link::Fake(link) => link.send_reliable(bytes)?,
+        }
         Ok(())
     }
-    pub fn read_unreliable_small_message(&self) -> ReadDatagram<'_> {
-        self.connection.read_datagram()
+    pub async fn read_unreliable_small_message(&self) -> Result<Bytes> {
+        match &self.link {
+            Link::Quic(connection) => Ok(connection.read_datagram().await?),
+            #[cfg(test)]
+            Link::Fake(link) => link.read_datagram().await,
+        }
     }
     pub fn close(&self) {
-        self.connection.close(0_u8.into(), &[]);
+        match &self.link {
+            Link::Quic(connection) => connection.close(0_u8.into(), &[]),
+            #[cfg(test)]
+            Link::Fake(link) => link.close(),
+        }
     }
 }
 
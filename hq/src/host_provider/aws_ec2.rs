@@ -0,0 +1,245 @@
+use super::*;
+use crate::args::AwsEc2HostProviderArgs;
+use aws_sdk_ec2::config::{BehaviorVersion, Credentials, Region};
+use aws_sdk_ec2::types::{
+    Filter, InstanceNetworkInterfaceSpecification, InstanceType, ResourceType, Tag,
+    TagSpecification,
+};
+use base64::Engine;
+
+const SITE_TAG_KEY: &str = "fn0-site";
+
+#[derive(Clone)]
+pub struct AwsEc2HostProvider {
+    client: aws_sdk_ec2::Client,
+    site_tag: String,
+    image_id: String,
+    instance_type: String,
+    subnet_id: String,
+    security_group_ids: Vec<String>,
+    user_data: String,
+}
+
+impl AwsEc2HostProvider {
+    /// `endpoint_url` overrides the regional EC2 endpoint, e.g. with a mock.
+    pub fn new(args: AwsEc2HostProviderArgs, endpoint_url: Option<String>) -> Self {
+        let config = aws_sdk_ec2::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(args.region))
+            .credentials_provider(Credentials::new(
+                args.access_key_id,
+                args.secret_access_key,
+                None,
+                None,
+                "hq-args",
+            ))
+            .set_endpoint_url(endpoint_url)
+            .build();
+
+        Self {
+            client: aws_sdk_ec2::Client::from_conf(config),
+            site_tag: args.site_tag,
+            image_id: args.image_id,
+            instance_type: args.instance_type,
+            subnet_id: args.subnet_id,
+            security_group_ids: args.security_group_ids,
+            user_data: base64::engine::general_purpose::STANDARD.encode(args.user_data),
+        }
+    }
+}
+
+impl HostProvide for AwsEc2HostProvider {
+    async fn list_hosts(&self) -> color_eyre::Result<Vec<Host>> {
+        // pending instances have no public ip yet, they are listed once running
+        let mut pages = self
+            .client
+            .describe_instances()
+            .filters(
+                Filter::builder()
+                    .name(format!("tag:{SITE_TAG_KEY}"))
+                    .values(&self.site_tag)
+                    .build(),
+            )
+            .filters(
+                Filter::builder()
+                    .name("instance-state-name")
+                    .values("running")
+                    .build(),
+            )
+            .into_paginator()
+            .send();
+
+        let mut hosts = Vec::new();
+        while let Some(page) = pages.next().await {
+            for instance in page?
+                .reservations()
+                .iter()
+                .flat_map(|reservation| reservation.instances())
+            {
+                let Some(id) = instance.instance_id() else {
+                    continue;
+                };
+                let Some(ip) = instance
+                    .public_ip_address()
+                    .and_then(|ip| ip.parse::<IpAddr>().ok())
+                else {
+                    error!("Failed to get public ip, id: {id}");
+                    continue;
+                };
+
+                hosts.push(Host {
+                    id: HostId::new(id.to_string()),
+                    ip,
+                });
+            }
+        }
+
+        Ok(hosts)
+    }
+
+    async fn terminate(&self, host_id: &HostId) -> color_eyre::Result<()> {
+        self.client
+            .terminate_instances()
+            .instance_ids(host_id.as_str())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn launch_instance(&self) -> color_eyre::Result<()> {
+        self.client
+            .run_instances()
+            .image_id(&self.image_id)
+            .instance_type(InstanceType::from(self.instance_type.as_str()))
+            .min_count(1)
+            .max_count(1)
+            .user_data(&self.user_data)
+            .network_interfaces(
+                InstanceNetworkInterfaceSpecification::builder()
+                    .device_index(0)
+                    .subnet_id(&self.subnet_id)
+                    .associate_public_ip_address(true)
+                    .set_groups(Some(self.security_group_ids.clone()))
+                    .build(),
+            )
+            .tag_specifications(
+                TagSpecification::builder()
+                    .resource_type(ResourceType::Instance)
+                    .tags(
+                        Tag::builder()
+                            .key(SITE_TAG_KEY)
+                            .value(&self.site_tag)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroUsize;
+    use wiremock::matchers::{body_string_contains, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider(server: &MockServer) -> AwsEc2HostProvider {
+        AwsEc2HostProvider::new(
+            AwsEc2HostProviderArgs {
+                region: "us-east-1".to_string(),
+                access_key_id: "AKIDEXAMPLE".to_string(),
+                secret_access_key: "secret".to_string(),
+                site_tag: "site-a".to_string(),
+                image_id: "ami-1".to_string(),
+                instance_type: "c7g.large".to_string(),
+                subnet_id: "subnet-1".to_string(),
+                security_group_ids: vec!["sg-1".to_string()],
+                user_data: "#!/bin/sh".to_string(),
+                physics_cpu_cores: NonZeroUsize::new(2).unwrap(),
+                memory_in_gbs: NonZeroUsize::new(4).unwrap(),
+            },
+            Some(server.uri()),
+        )
+    }
+
+    fn xml(body: &str) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_raw(body.to_string(), "text/xml")
+    }
+
+    #[tokio::test]
+    async fn test_list_hosts_with_public_ip() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("Action=DescribeInstances"))
+            .and(body_string_contains("site-a"))
+            .respond_with(xml(
+                r#"<DescribeInstancesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+                    <requestId>r</requestId>
+                    <reservationSet>
+                        <item>
+                            <reservationId>r-1</reservationId>
+                            <instancesSet>
+                                <item>
+                                    <instanceId>i-1</instanceId>
+                                    <ipAddress>203.0.113.7</ipAddress>
+                                </item>
+                                <item>
+                                    <instanceId>i-2</instanceId>
+                                </item>
+                            </instancesSet>
+                        </item>
+                    </reservationSet>
+                </DescribeInstancesResponse>"#,
+            ))
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            provider(&server).list_hosts().await.unwrap(),
+            vec![Host {
+                id: HostId::new("i-1".to_string()),
+                ip: "203.0.113.7".parse().unwrap(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_launch_and_terminate() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("Action=RunInstances"))
+            .and(body_string_contains("ImageId=ami-1"))
+            .respond_with(xml(
+                r#"<RunInstancesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+                    <requestId>r</requestId>
+                    <reservationId>r-1</reservationId>
+                    <instancesSet><item><instanceId>i-1</instanceId></item></instancesSet>
+                </RunInstancesResponse>"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("Action=TerminateInstances"))
+            .and(body_string_contains("InstanceId.1=i-1"))
+            .respond_with(xml(
+                r#"<TerminateInstancesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+                    <requestId>r</requestId>
+                    <instancesSet/>
+                </TerminateInstancesResponse>"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = provider(&server);
+        provider.launch_instance().await.unwrap();
+        provider
+            .terminate(&HostId::new("i-1".to_string()))
+            .await
+            .unwrap();
+    }
+}
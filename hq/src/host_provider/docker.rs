@@ -0,0 +1,330 @@
+use super::*;
+use crate::args::DockerHostProviderArgs;
+use http_body_util::BodyExt;
+use hyper::{Method, StatusCode};
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, UnixStream};
+
+const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+const NETWORK_LABEL: &str = "fn0.network";
+
+/// Runs hosts as containers through the Docker engine API.
+#[derive(Clone)]
+pub struct DockerHostProvider {
+    engine: Engine,
+    network: String,
+    image: String,
+    physics_cpu_cores: NonZeroUsize,
+    memory_in_gbs: NonZeroUsize,
+    envs: BTreeMap<String, String>,
+}
+
+#[derive(Clone)]
+enum Engine {
+    Unix(PathBuf),
+    /// `host:port`
+    Tcp(String),
+}
+
+impl DockerHostProvider {
+    /// Fails if `engine_url` is neither `unix://` nor `http://`.
+    pub fn new(args: DockerHostProviderArgs) -> color_eyre::Result<Self> {
+        let engine = if let Some(path) = args.engine_url.strip_prefix("unix://")
+            && !path.is_empty()
+        {
+            Engine::Unix(PathBuf::from(path))
+        } else if let Some(addr) = args.engine_url.strip_prefix("http://")
+            && !addr.trim_end_matches('/').is_empty()
+        {
+            Engine::Tcp(addr.trim_end_matches('/').to_string())
+        } else {
+            return Err(eyre!(
+                "Invalid docker engine url {:?}, expected unix:///path or http://host:port",
+                args.engine_url
+            ));
+        };
+
+        Ok(Self {
+            engine,
+            network: args.network,
+            image: args.image,
+            physics_cpu_cores: args.physics_cpu_cores,
+            memory_in_gbs: args.memory_in_gbs,
+            envs: args.envs,
+        })
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> color_eyre::Result<(StatusCode, Bytes)> {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(hyper::header::HOST, "docker")
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Full::new(match body {
+                Some(body) => Bytes::from(body.to_string()),
+                None => Bytes::new(),
+            }))?;
+
+        match &self.engine {
+            Engine::Unix(path) => send(UnixStream::connect(path).await?, request).await,
+            Engine::Tcp(addr) => send(TcpStream::connect(addr).await?, request).await,
+        }
+    }
+}
+
+async fn send(
+    stream: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    request: Request<Full<Bytes>>,
+) -> color_eyre::Result<(StatusCode, Bytes)> {
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            warn!(%err, "Docker engine connection failed");
+        }
+    });
+
+    let response = tokio::time::timeout(DEFAULT_TIMEOUT, sender.send_request(request)).await??;
+    let status = response.status();
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok((status, body))
+}
+
+fn check_status(status: StatusCode, body: &Bytes, action: &str) -> color_eyre::Result<()> {
+    if status.is_success() {
+        return Ok(());
+    }
+    Err(eyre!(
+        "Failed to {action}, status: {status}, body: {}",
+        String::from_utf8_lossy(body)
+    ))
+}
+
+impl HostProvide for DockerHostProvider {
+    async fn list_hosts(&self) -> color_eyre::Result<Vec<Host>> {
+        #[derive(Debug, serde::Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Container {
+            id: String,
+            #[serde(default)]
+            labels: BTreeMap<String, String>,
+            network_settings: NetworkSettings,
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct NetworkSettings {
+            #[serde(default)]
+            networks: BTreeMap<String, Network>,
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        struct Network {
+            #[serde(rename = "IPAddress")]
+            ip_address: String,
+        }
+
+        // running containers only, a stopped host is reaped once it misses pings
+        let (status, body) = self.request(Method::GET, "/containers/json", None).await?;
+        check_status(status, &body, "list containers")?;
+        let containers: Vec<Container> = serde_json::from_slice(&body)?;
+
+        Ok(containers
+            .into_iter()
+            .filter(|container| container.labels.get(NETWORK_LABEL) == Some(&self.network))
+            .filter_map(|container| {
+                let network = container.network_settings.networks.get(&self.network)?;
+                let Ok(ip) = network.ip_address.parse() else {
+                    error!("Failed to get container ip, id: {}", container.id);
+                    return None;
+                };
+                Some(Host {
+                    id: HostId::new(container.id),
+                    ip,
+                })
+            })
+            .collect())
+    }
+
+    async fn terminate(&self, host_id: &HostId) -> color_eyre::Result<()> {
+        let (status, body) = self
+            .request(
+                Method::DELETE,
+                &format!("/containers/{host_id}?force=true"),
+                None,
+            )
+            .await?;
+        check_status(status, &body, "remove container")
+    }
+
+    async fn launch_instance(&self) -> color_eyre::Result<()> {
+        #[derive(Debug, serde::Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct CreateResponse {
+            id: String,
+        }
+
+        let body = serde_json::json!({
+            "Image": self.image,
+            "Env": self
+                .envs
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect::<Vec<_>>(),
+            "Labels": { NETWORK_LABEL: self.network },
+            "HostConfig": {
+                "NetworkMode": self.network,
+                "NanoCpus": self.physics_cpu_cores.get() as u64 * 1_000_000_000,
+                "Memory": self.memory_in_gbs.get() as u64 * 1024 * 1024 * 1024,
+                "RestartPolicy": { "Name": "always" },
+            },
+        });
+        let (status, body) = self
+            .request(Method::POST, "/containers/create", Some(body))
+            .await?;
+        check_status(status, &body, "create container")?;
+        let created: CreateResponse = serde_json::from_slice(&body)?;
+
+        let (status, body) = self
+            .request(
+                Method::POST,
+                &format!("/containers/{}/start", created.id),
+                None,
+            )
+            .await?;
+        check_status(status, &body, "start container")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn args(engine_url: &str) -> DockerHostProviderArgs {
+        DockerHostProviderArgs {
+            engine_url: engine_url.to_string(),
+            network: "fn0-dev".to_string(),
+            image: "fn0-host:dev".to_string(),
+            physics_cpu_cores: NonZeroUsize::new(2).unwrap(),
+            memory_in_gbs: NonZeroUsize::new(1).unwrap(),
+            envs: [("RUST_LOG".to_string(), "info".to_string())].into(),
+        }
+    }
+
+    fn provider(engine_url: String) -> DockerHostProvider {
+        DockerHostProvider::new(args(&engine_url)).unwrap()
+    }
+
+    #[test]
+    fn test_rejects_malformed_engine_url() {
+        for engine_url in [
+            "",
+            "tcp://localhost:2375",
+            "localhost:2375",
+            "unix://",
+            "http://",
+        ] {
+            assert!(
+                DockerHostProvider::new(args(engine_url)).is_err(),
+                "{engine_url}"
+            );
+        }
+        assert!(DockerHostProvider::new(args("unix:///var/run/docker.sock")).is_ok());
+        assert!(DockerHostProvider::new(args("http://localhost:2375/")).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_list_hosts_of_own_network() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/containers/json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {
+                    "Id": "a1",
+                    "Labels": { "fn0.network": "fn0-dev" },
+                    "NetworkSettings": { "Networks": { "fn0-dev": { "IPAddress": "172.18.0.2" } } }
+                },
+                {
+                    "Id": "b2",
+                    "Labels": { "fn0.network": "other" },
+                    "NetworkSettings": { "Networks": { "other": { "IPAddress": "172.19.0.2" } } }
+                },
+                {
+                    "Id": "c3",
+                    "Labels": {},
+                    "NetworkSettings": { "Networks": { "fn0-dev": { "IPAddress": "172.18.0.3" } } }
+                }
+            ])))
+            .mount(&server)
+            .await;
+
+        let hosts = provider(server.uri()).list_hosts().await.unwrap();
+        assert_eq!(
+            hosts,
+            vec![Host {
+                id: HostId::new("a1".to_string()),
+                ip: "172.18.0.2".parse().unwrap(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_launch_creates_and_starts() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/containers/create"))
+            .and(body_partial_json(serde_json::json!({
+                "Image": "fn0-host:dev",
+                "Env": ["RUST_LOG=info"],
+                "Labels": { "fn0.network": "fn0-dev" },
+                "HostConfig": { "NetworkMode": "fn0-dev", "NanoCpus": 2_000_000_000u64 },
+            })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!({ "Id": "a1" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/containers/a1/start"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        provider(server.uri()).launch_instance().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_terminate_forces_removal() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/containers/a1"))
+            .and(query_param("force", "true"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+
+        let provider = provider(server.uri());
+        provider
+            .terminate(&HostId::new("a1".to_string()))
+            .await
+            .unwrap();
+        assert!(
+            provider
+                .terminate(&HostId::new("missing".to_string()))
+                .await
+                .is_err()
+        );
+    }
+}
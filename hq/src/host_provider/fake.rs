@@ -0,0 +1,74 @@
+use super::*;
+use crate::fake_agent::FakeAgents;
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Hosts that exist only in memory, for exercising the site's loops in tests.
+/// A launch adds a host right away.
+#[derive(Clone, Default)]
+pub struct FakeHostProvider {
+    state: Arc<Mutex<FakeHosts>>,
+    /// Agents booted on launch, taking the delay to accept connections.
+    agents: Option<(FakeAgents, Duration)>,
+}
+
+#[derive(Default)]
+struct FakeHosts {
+    hosts: Vec<Host>,
+    launched: u64,
+    terminated: Vec<HostId>,
+}
+
+impl FakeHostProvider {
+    pub fn with_agents(mut self, agents: FakeAgents, boot_delay: Duration) -> Self {
+        self.agents = Some((agents, boot_delay));
+        self
+    }
+
+    pub fn hosts(&self) -> Vec<Host> {
+        self.state.lock().unwrap().hosts.clone()
+    }
+
+    pub fn launched(&self) -> u64 {
+        self.state.lock().unwrap().launched
+    }
+
+    pub fn terminated(&self) -> Vec<HostId> {
+        self.state.lock().unwrap().terminated.clone()
+    }
+}
+
+impl HostProvide for FakeHostProvider {
+    async fn list_hosts(&self) -> color_eyre::Result<Vec<Host>> {
+        Ok(self.hosts())
+    }
+
+    async fn terminate(&self, host_id: &HostId) -> color_eyre::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let Some(index) = state.hosts.iter().position(|host| &host.id == host_id) else {
+            return Err(eyre!("Fake host {host_id} not found"));
+        };
+        let host = state.hosts.remove(index);
+        state.terminated.push(host.id);
+        if let Some((agents, _)) = &self.agents {
+            agents.update(host.ip, |agent| agent.down = true);
+        }
+        Ok(())
+    }
+
+    async fn launch_instance(&self) -> color_eyre::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.launched += 1;
+        let n = state.launched;
+        let host = Host {
+            id: HostId::new(format!("fake-{n}")),
+            ip: Ipv4Addr::new(10, (n >> 16) as u8, (n >> 8) as u8, n as u8).into(),
+        };
+        if let Some((agents, boot_delay)) = &self.agents {
+            agents.boot(host.ip, *boot_delay);
+        }
+        state.hosts.push(host);
+        Ok(())
+    }
+}
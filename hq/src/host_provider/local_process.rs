@@ -0,0 +1,168 @@
+use super::*;
+use crate::args::LocalProcessHostProviderArgs;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use tokio::process::{Child, Command};
+
+/// Spawns hosts as child processes, each on its own loopback address, since hq
+/// reaches every host on the same port. Hosts die with hq.
+#[derive(Clone)]
+pub struct LocalProcessHostProvider {
+    command: String,
+    args: Vec<String>,
+    envs: BTreeMap<String, String>,
+    state: Arc<Mutex<LocalProcesses>>,
+}
+
+#[derive(Default)]
+struct LocalProcesses {
+    children: BTreeMap<HostId, (Ipv4Addr, Child)>,
+    /// Ids are never reused, as hq remembers dead hosts by id.
+    launched: u64,
+}
+
+impl LocalProcessHostProvider {
+    pub fn new(args: LocalProcessHostProviderArgs) -> Self {
+        Self {
+            command: args.command,
+            args: args.args,
+            envs: args.envs,
+            state: Default::default(),
+        }
+    }
+}
+
+impl HostProvide for LocalProcessHostProvider {
+    async fn list_hosts(&self) -> color_eyre::Result<Vec<Host>> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .children
+            .retain(|id, (_, child)| match child.try_wait() {
+                Ok(None) => true,
+                Ok(Some(status)) => {
+                    warn!(%id, %status, "Local host exited");
+                    false
+                }
+                Err(err) => {
+                    warn!(%id, %err, "Failed to check local host");
+                    false
+                }
+            });
+
+        Ok(state
+            .children
+            .iter()
+            .map(|(id, (ip, _))| Host {
+                id: id.clone(),
+                ip: IpAddr::V4(*ip),
+            })
+            .collect())
+    }
+
+    async fn terminate(&self, host_id: &HostId) -> color_eyre::Result<()> {
+        let Some((_, mut child)) = self.state.lock().unwrap().children.remove(host_id) else {
+            return Err(color_eyre::eyre::eyre!("Local host {host_id} not found"));
+        };
+        child.kill().await?;
+        Ok(())
+    }
+
+    async fn launch_instance(&self) -> color_eyre::Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        let used = state
+            .children
+            .values()
+            .map(|(ip, _)| *ip)
+            .collect::<BTreeSet<_>>();
+        let Some(ip) = (2..=254)
+            .map(|last| Ipv4Addr::new(127, 0, 0, last))
+            .find(|ip| !used.contains(ip))
+        else {
+            return Err(color_eyre::eyre::eyre!("No loopback address left"));
+        };
+
+        let child = Command::new(&self.command)
+            .args(&self.args)
+            .envs(&self.envs)
+            .env("FN0_HOST_IP", ip.to_string())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        state.launched += 1;
+        let id = HostId::new(format!("local-{}", state.launched));
+        state.children.insert(id, (ip, child));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroUsize;
+
+    fn provider(command: &str, args: &[&str]) -> LocalProcessHostProvider {
+        LocalProcessHostProvider::new(LocalProcessHostProviderArgs {
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            envs: Default::default(),
+            physics_cpu_cores: NonZeroUsize::new(1).unwrap(),
+            memory_in_gbs: NonZeroUsize::new(1).unwrap(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_launch_list_terminate() {
+        let provider = provider("sleep", &["30"]);
+        provider.launch_instance().await.unwrap();
+        provider.launch_instance().await.unwrap();
+
+        let hosts = provider.list_hosts().await.unwrap();
+        assert_eq!(
+            hosts
+                .iter()
+                .map(|host| (host.id.to_string(), host.ip.to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("local-1".to_string(), "127.0.0.2".to_string()),
+                ("local-2".to_string(), "127.0.0.3".to_string()),
+            ]
+        );
+
+        provider.terminate(&hosts[0].id).await.unwrap();
+        assert!(provider.terminate(&hosts[0].id).await.is_err());
+
+        // the freed address is reused, the id isn't
+        provider.launch_instance().await.unwrap();
+        let hosts = provider.list_hosts().await.unwrap();
+        assert_eq!(
+            hosts
+                .iter()
+                .map(|host| (host.id.to_string(), host.ip.to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("local-2".to_string(), "127.0.0.3".to_string()),
+                ("local-3".to_string(), "127.0.0.2".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exited_host_is_not_listed() {
+        let provider = provider("true", &[]);
+        provider.launch_instance().await.unwrap();
+
+        // waited outside the lock, then put back for `list_hosts` to find it exited
+        let (id, (ip, mut child)) = provider.state.lock().unwrap().children.pop_first().unwrap();
+        child.wait().await.unwrap();
+        provider
+            .state
+            .lock()
+            .unwrap()
+            .children
+            .insert(id, (ip, child));
+
+        assert!(provider.list_hosts().await.unwrap().is_empty());
+    }
+}
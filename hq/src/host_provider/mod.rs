@@ -1,3 +1,8 @@
+pub mod aws_ec2;
+pub mod docker;
+#[cfg(test)]
+pub mod fake;
+pub mod local_process;
 pub mod oci_container;
 
 use crate::*;
@@ -19,24 +24,44 @@ pub trait HostProvide: Send + Sync {
 #[derive(Clone)]
 pub enum HostProvider {
     OciContainerInstance(oci_container::OciContainerInstanceHostProvider),
+    LocalProcess(local_process::LocalProcessHostProvider),
+    Docker(docker::DockerHostProvider),
+    AwsEc2(aws_ec2::AwsEc2HostProvider),
+    #[cfg(test)]
+    Fake(fake::FakeHostProvider),
 }
 
 impl HostProvide for HostProvider {
     async fn list_hosts(&self) -> color_eyre::Result<Vec<Host>> {
         match self {
             HostProvider::OciContainerInstance(provider) => provider.list_hosts().await,
+            HostProvider::LocalProcess(provider) => provider.list_hosts().await,
+            HostProvider::Docker(provider) => provider.list_hosts().await,
+            HostProvider::AwsEc2(provider) => provider.list_hosts().await,
+            #[cfg(test)]
+            HostProvider::Fake(provider) => provider.list_hosts().await,
         }
     }
 
     async fn terminate(&self, host_id: &HostId) -> color_eyre::Result<()> {
         match self {
             HostProvider::OciContainerInstance(provider) => provider.terminate(host_id).await,
+            HostProvider::LocalProcess(provider) => provider.terminate(host_id).await,
+            HostProvider::Docker(provider) => provider.terminate(host_id).await,
+            HostProvider::AwsEc2(provider) => provider.terminate(host_id).await,
+            #[cfg(test)]
+            HostProvider::Fake(provider) => provider.terminate(host_id).await,
         }
     }
 
     async fn launch_instance(&self) -> color_eyre::Result<()> {
         match self {
             HostProvider::OciContainerInstance(provider) => provider.launch_instance().await,
+            HostProvider::LocalProcess(provider) => provider.launch_instance().await,
+            HostProvider::Docker(provider) => provider.launch_instance().await,
+            HostProvider::AwsEc2(provider) => provider.launch_instance().await,
+            #[cfg(test)]
+            HostProvider::Fake(provider) => provider.launch_instance().await,
         }
     }
 }
@@ -1,14 +1,20 @@
+mod admin_api;
+mod api_key_cache;
 mod args;
 mod args_parse;
 mod deployment_cache;
 mod dns;
+#[cfg(test)]
+mod fake_agent;
 mod host_connection;
 mod host_id;
 mod host_provider;
 mod random_sleep;
 mod site;
 mod telemetry;
+mod wall_clock;
 
+use admin_api::AdminApi;
 use args::HqArgs;
 use color_eyre::eyre::{Result, eyre};
 use host_id::*;
@@ -30,6 +36,8 @@ fn main() -> Result<()> {
         let HqArgsParsed {
             sites,
             deployment_cache,
+            api_key_cache,
+            admin_api,
         } = HqArgs::parse().await?;
 
         let mut set = JoinSet::new();
@@ -38,6 +46,10 @@ fn main() -> Result<()> {
             deployment_cache.run_sync().await;
             Ok(())
         });
+        set.spawn(async move {
+            api_key_cache.run_sync().await;
+            Ok(())
+        });
         for mut site in sites {
             set.spawn(async move {
                 site.run().await;
@@ -48,7 +60,7 @@ fn main() -> Result<()> {
             tokio::signal::ctrl_c().await?;
             Ok(())
         });
-        set.spawn(web_server());
+        set.spawn(web_server(Arc::new(admin_api)));
 
         let result = set.join_next().await.unwrap().map_err(|err| eyre!(err));
 
@@ -58,17 +70,18 @@ fn main() -> Result<()> {
     })?
 }
 
-async fn web_server() -> Result<()> {
+async fn web_server(admin_api: Arc<AdminApi>) -> Result<()> {
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
     let listener = TcpListener::bind(addr).await?;
 
     loop {
         let (stream, _) = listener.accept().await?;
         let io = TokioIo::new(stream);
+        let admin_api = admin_api.clone();
 
         tokio::task::spawn(async move {
             if let Err(err) = http1::Builder::new()
-                .serve_connection(io, service_fn(route))
+                .serve_connection(io, service_fn(|req| route(req, admin_api.clone())))
                 .await
             {
                 eprintln!("Error serving connection: {:?}", err);
@@ -77,8 +90,12 @@ async fn web_server() -> Result<()> {
     }
 }
 
-async fn route(req: Request<hyper::body::Incoming>) -> Result<Response<Full<Bytes>>> {
+async fn route(
+    req: Request<hyper::body::Incoming>,
+    admin_api: Arc<AdminApi>,
+) -> Result<Response<Full<Bytes>>> {
     match req.uri().path() {
+        path if path.starts_with("/api/") => Ok(admin_api.handle(req).await),
         "/health" => {
             info!("health check");
             Ok(Response::new(Full::new(Bytes::from("ok"))))
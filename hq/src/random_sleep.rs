@@ -1,5 +1,20 @@
 use std::time::Duration;
 
-pub async fn random_sleep(ms: u64) {
-    tokio::time::sleep(Duration::from_millis(rand::random::<u64>() % ms)).await;
+/// How long [`random_sleep`] waits below its bound.
+#[derive(Debug, Clone, Copy)]
+pub enum Jitter {
+    Random,
+    /// Always half the bound, so simulations replay the same.
+    #[cfg(test)]
+    Half,
+}
+
+pub async fn random_sleep(jitter: Jitter, ms: u64) {
+    let jitter = match jitter {
+        Jitter::Random => rand::random::<u64>() % ms,
+        #[cfg(test)]
+        Jitter::Half => ms / 2,
+    };
+
+    tokio::time::sleep(Duration::from_millis(jitter)).await;
 }
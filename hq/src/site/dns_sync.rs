@@ -1,26 +1,46 @@
 use super::*;
 use crate::{dns::DnsProvide, telemetry, *};
-use std::{collections::BTreeSet, time::Duration};
+use std::{collections::BTreeMap, net::IpAddr, time::Duration};
 use tokio::time::MissedTickBehavior;
 
 impl Site {
     #[tracing::instrument(skip_all)]
     pub async fn run_dns_sync(&self) {
-        let mut interval = tokio::time::interval(dns_sync_interval_ms());
+        let mut interval = tokio::time::interval(self.timings.dns_sync_interval);
         interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let min_ready = self.timings.dns_min_ready;
 
         loop {
             interval.tick().await;
 
-            let ips = self
+            // list_host connects to draining and dead hosts again, skip them here
+            let candidates = self
                 .host_connections
                 .iter()
-                .map(|conn| conn.key().ip)
-                .collect::<BTreeSet<_>>();
+                .filter(|conn| {
+                    !self.graceful_shutdown_hosts.contains_key(conn.key())
+                        && !self.dead_hosts.contains_key(conn.key())
+                })
+                .map(|conn| DnsCandidate {
+                    ip: conn.key().ip,
+                    connected_at: conn.value().connected_at(),
+                    instances: self
+                        .hosts_status
+                        .get(conn.key())
+                        .map(|status| status.instances),
+                })
+                .collect::<Vec<_>>();
 
-            telemetry::dns_healthy_ips(ips.len());
+            let weights = dns_weights(
+                candidates,
+                self.max_instances_per_host.load(Ordering::Relaxed),
+                min_ready,
+                Instant::now(),
+            );
 
-            match self.dns_provider.sync_ips(ips).await {
+            telemetry::dns_healthy_ips(weights.len());
+
+            match self.dns_provider.sync_weighted_ips(weights).await {
                 Ok(_) => {
                     telemetry::dns_sync_status(true);
                 }
@@ -33,7 +53,62 @@ impl Site {
     }
 }
 
-fn dns_sync_interval_ms() -> Duration {
+struct DnsCandidate {
+    ip: IpAddr,
+    connected_at: Instant,
+    /// `None` until the host reports its status.
+    instances: Option<u64>,
+}
+
+/// Weights 10~100 of the hosts DNS should send traffic to, higher for less loaded
+/// ones, in steps of 10 so load moving by a few instances doesn't re-publish DNS
+/// every sync and run into the provider's rate limits. Prefers hosts that have been connected for `min_ready` and aren't full,
+/// then any that have been connected for `min_ready`, then every host, so DNS is
+/// never emptied while hosts are up, e.g. right after hq restarts.
+///
+/// `max_instances_per_host` of 0 is unknown, all hosts weigh the same then.
+fn dns_weights(
+    candidates: Vec<DnsCandidate>,
+    max_instances_per_host: usize,
+    min_ready: Duration,
+    now: Instant,
+) -> BTreeMap<IpAddr, u8> {
+    let load_percent = |candidate: &DnsCandidate| -> Option<u64> {
+        if max_instances_per_host == 0 {
+            return Some(0);
+        }
+        candidate
+            .instances
+            .map(|instances| instances.saturating_mul(100) / max_instances_per_host as u64)
+    };
+    let is_ready = |candidate: &DnsCandidate| {
+        candidate.instances.is_some()
+            && now.saturating_duration_since(candidate.connected_at) >= min_ready
+    };
+
+    let tiers: [&dyn Fn(&DnsCandidate) -> bool; 3] = [
+        &|candidate| is_ready(candidate) && load_percent(candidate).is_some_and(|load| load < 100),
+        &|candidate| is_ready(candidate),
+        &|_| true,
+    ];
+    let Some(chosen) = tiers
+        .iter()
+        .map(|tier| candidates.iter().filter(|c| tier(c)).collect::<Vec<_>>())
+        .find(|chosen| !chosen.is_empty())
+    else {
+        return BTreeMap::new();
+    };
+
+    chosen
+        .into_iter()
+        .map(|candidate| {
+            let weight = 100u64.saturating_sub(load_percent(candidate).unwrap_or(0));
+            (candidate.ip, ((weight + 5) / 10 * 10).clamp(10, 100) as u8)
+        })
+        .collect()
+}
+
+pub(super) fn dns_sync_interval_ms() -> Duration {
     match std::env::var("DNS_SYNC_INTERVAL_MS") {
         Ok(s) => match s.parse() {
             Ok(v) => return Duration::from_millis(v),
@@ -43,3 +118,105 @@ fn dns_sync_interval_ms() -> Duration {
     }
     Duration::from_secs(1)
 }
+
+/// How long a new host has to stay connected before it gets DNS traffic, so it can
+/// warm up first.
+pub(super) fn dns_min_ready_ms() -> Duration {
+    match std::env::var("DNS_MIN_READY_MS") {
+        Ok(s) => match s.parse() {
+            Ok(v) => return Duration::from_millis(v),
+            Err(err) => warn!(%err, "DNS_MIN_READY_MS is not a valid number"),
+        },
+        Err(err) => warn!(%err, "Fail to get DNS_MIN_READY_MS from env"),
+    }
+    Duration::from_secs(30)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(ip: &str, connected_secs_ago: u64, instances: Option<u64>) -> DnsCandidate {
+        DnsCandidate {
+            ip: ip.parse().unwrap(),
+            connected_at: Instant::now() - Duration::from_secs(connected_secs_ago),
+            instances,
+        }
+    }
+
+    fn weights(weights: &[(&str, u8)]) -> BTreeMap<IpAddr, u8> {
+        weights
+            .iter()
+            .map(|(ip, weight)| (ip.parse().unwrap(), *weight))
+            .collect()
+    }
+
+    #[test]
+    fn test_weights_skip_unready_and_full_hosts() {
+        let candidates = vec![
+            candidate("10.0.0.1", 60, Some(25)),
+            candidate("10.0.0.2", 60, Some(90)),
+            // full
+            candidate("10.0.0.3", 60, Some(100)),
+            // just connected
+            candidate("10.0.0.4", 1, Some(0)),
+            // no status yet
+            candidate("10.0.0.5", 60, None),
+        ];
+        assert_eq!(
+            dns_weights(candidates, 100, Duration::from_secs(30), Instant::now()),
+            weights(&[("10.0.0.1", 80), ("10.0.0.2", 10)])
+        );
+    }
+
+    #[test]
+    fn test_weights_fall_back_rather_than_empty() {
+        let full = vec![
+            candidate("10.0.0.1", 60, Some(150)),
+            candidate("10.0.0.2", 1, Some(0)),
+        ];
+        assert_eq!(
+            dns_weights(full, 100, Duration::from_secs(30), Instant::now()),
+            weights(&[("10.0.0.1", 10)])
+        );
+
+        let unready = vec![
+            candidate("10.0.0.1", 1, None),
+            candidate("10.0.0.2", 1, Some(50)),
+        ];
+        assert_eq!(
+            dns_weights(unready, 100, Duration::from_secs(30), Instant::now()),
+            weights(&[("10.0.0.1", 100), ("10.0.0.2", 50)])
+        );
+
+        assert!(dns_weights(vec![], 100, Duration::from_secs(30), Instant::now()).is_empty());
+    }
+
+    #[test]
+    fn test_weights_equal_without_capacity() {
+        let candidates = vec![
+            candidate("10.0.0.1", 60, Some(25)),
+            candidate("10.0.0.2", 60, Some(90)),
+        ];
+        assert_eq!(
+            dns_weights(candidates, 0, Duration::from_secs(30), Instant::now()),
+            weights(&[("10.0.0.1", 100), ("10.0.0.2", 100)])
+        );
+    }
+
+    #[test]
+    fn test_weights_ignore_small_load_changes() {
+        let at = |instances| {
+            dns_weights(
+                vec![candidate("10.0.0.1", 60, Some(instances))],
+                100,
+                Duration::from_secs(30),
+                Instant::now(),
+            )
+        };
+        assert_eq!(at(36), at(44));
+        assert_eq!(at(36), weights(&[("10.0.0.1", 60)]));
+        assert_eq!(at(46), weights(&[("10.0.0.1", 50)]));
+        assert_eq!(at(99), weights(&[("10.0.0.1", 10)]));
+    }
+}
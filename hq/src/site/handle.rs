@@ -0,0 +1,122 @@
+use super::*;
+use std::collections::BTreeSet;
+
+/// Shares the state of a running [`Site`] with the admin API.
+#[derive(Clone)]
+pub struct SiteHandle {
+    host_provider: HostProvider,
+    host_connections: Arc<DashMap<Host, HostConnection>>,
+    hosts_status: Arc<DashMap<Host, HostStatus>>,
+    known_hosts: Arc<DashSet<Host>>,
+    dead_hosts: Arc<DashMap<Host, Instant>>,
+    graceful_shutdown_hosts: Arc<DashMap<Host, Instant>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HostState {
+    /// Listed by the host provider, hq is not connected yet.
+    Connecting,
+    Running,
+    /// Shutting down gracefully, no longer served by DNS.
+    Draining,
+    /// Unresponsive or terminated, the host provider is asked to terminate it.
+    Dead,
+}
+
+#[derive(Debug, Clone, serde::Serialize, schemars::JsonSchema)]
+pub struct HostInfo {
+    pub id: String,
+    pub ip: String,
+    pub state: HostState,
+    pub connected: bool,
+    /// From the latest status the host reported.
+    pub instances: Option<u64>,
+    pub last_status_ms_ago: Option<u64>,
+}
+
+impl Site {
+    pub fn handle(&self) -> SiteHandle {
+        SiteHandle {
+            host_provider: self.host_provider.clone(),
+            host_connections: self.host_connections.clone(),
+            hosts_status: self.hosts_status.clone(),
+            known_hosts: self.known_hosts.clone(),
+            dead_hosts: self.dead_hosts.clone(),
+            graceful_shutdown_hosts: self.graceful_shutdown_hosts.clone(),
+        }
+    }
+}
+
+impl SiteHandle {
+    pub fn hosts(&self) -> Vec<HostInfo> {
+        let hosts = self
+            .known_hosts
+            .iter()
+            .map(|host| host.clone())
+            .chain(self.hosts_status.iter().map(|status| status.key().clone()))
+            .collect::<BTreeSet<_>>();
+
+        hosts.iter().map(|host| self.host_info(host)).collect()
+    }
+
+    pub fn host(&self, host_id: &str) -> Option<HostInfo> {
+        self.find(host_id).map(|host| self.host_info(&host))
+    }
+
+    /// Same as a scale-in of `host`, except that the scaler may launch a replacement.
+    pub fn drain(&self, host_id: &str) -> Option<HostInfo> {
+        let host = self.find(host_id)?;
+        start_graceful_shutdown(&host, &self.graceful_shutdown_hosts, &self.host_connections);
+        Some(self.host_info(&host))
+    }
+
+    /// Terminates `host` right away, dropping its in-flight work.
+    pub async fn terminate(&self, host_id: &str) -> Option<Result<HostInfo>> {
+        let host = self.find(host_id)?;
+
+        self.dead_hosts.insert(host.clone(), Instant::now());
+        self.hosts_status.remove(&host);
+        if let Some((_host, connection)) = self.host_connections.remove(&host) {
+            connection.close();
+        }
+
+        Some(
+            self.host_provider
+                .terminate(&host.id)
+                .await
+                .map(|_| self.host_info(&host)),
+        )
+    }
+
+    fn find(&self, host_id: &str) -> Option<Host> {
+        self.known_hosts
+            .iter()
+            .map(|host| host.clone())
+            .chain(self.hosts_status.iter().map(|status| status.key().clone()))
+            .find(|host| host.id.as_str() == host_id)
+    }
+
+    fn host_info(&self, host: &Host) -> HostInfo {
+        let status = self.hosts_status.get(host).map(|status| *status);
+        let state = if self.dead_hosts.contains_key(host) {
+            HostState::Dead
+        } else if self.graceful_shutdown_hosts.contains_key(host) {
+            HostState::Draining
+        } else if status.is_some() {
+            HostState::Running
+        } else {
+            HostState::Connecting
+        };
+
+        HostInfo {
+            id: host.id.to_string(),
+            ip: host.ip.to_string(),
+            state,
+            connected: self.host_connections.contains_key(host),
+            instances: status.map(|status| status.instances),
+            last_status_ms_ago: status
+                .map(|status| status.received_at.elapsed().as_millis() as u64),
+        }
+    }
+}
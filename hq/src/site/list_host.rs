@@ -1,12 +1,12 @@
 use super::*;
-use crate::{host_connection::HostConnection, random_sleep::random_sleep, telemetry, *};
-use std::time::{Duration, Instant};
-use tokio::time::{MissedTickBehavior, timeout};
+use crate::{random_sleep::random_sleep, telemetry, *};
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior, timeout};
 
 impl Site {
     #[tracing::instrument(skip_all)]
     pub async fn run_list_host_loop(&self, new_host_tx: mpsc::UnboundedSender<Host>) {
-        let mut interval = tokio::time::interval(list_host_info_interval_ms());
+        let mut interval = tokio::time::interval(self.timings.list_host_interval);
         interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
         loop {
@@ -38,8 +38,9 @@ impl Site {
     #[tracing::instrument(skip(self), fields(host_id = %host.id, host_ip = %host.ip))]
     fn on_dead_host_in_list(&self, host: Host) {
         let host_provider = self.host_provider.clone();
+        let jitter = self.timings.jitter;
         tokio::spawn(async move {
-            random_sleep(1000).await;
+            random_sleep(jitter, 1000).await;
             if let Err(err) = host_provider.terminate(&host.id).await {
                 warn!(%err, "Failed to terminate host");
             }
@@ -49,10 +50,11 @@ impl Site {
     fn on_new_host_in_list(&self, host: Host, new_host_tx: mpsc::UnboundedSender<Host>) {
         self.known_hosts.insert(host.clone());
 
-        let cert = self.cert.clone();
+        let host_connector = self.host_connector.clone();
         let addr = SocketAddr::new(host.ip, 10000);
         let dead_hosts = self.dead_hosts.clone();
         let host_connections = self.host_connections.clone();
+        let jitter = self.timings.jitter;
 
         tokio::spawn(async move {
             let start_time = Instant::now();
@@ -60,7 +62,7 @@ impl Site {
             let connect_timeout = Duration::from_secs(2);
 
             loop {
-                random_sleep(1000).await;
+                random_sleep(jitter, 1000).await;
 
                 if deadline < start_time {
                     dead_hosts.insert(host.clone(), Instant::now());
@@ -71,8 +73,7 @@ impl Site {
                 telemetry::host_connect_attempt(&host.id);
 
                 let connect_start = Instant::now();
-                let connect_result =
-                    timeout(connect_timeout, HostConnection::connect(addr, &cert)).await;
+                let connect_result = timeout(connect_timeout, host_connector.connect(addr)).await;
 
                 match connect_result {
                     Ok(Ok(connection)) => {
@@ -97,7 +98,7 @@ impl Site {
     }
 }
 
-fn list_host_info_interval_ms() -> Duration {
+pub(super) fn list_host_info_interval_ms() -> Duration {
     match std::env::var("LIST_HOST_INFO_INTERVAL_MS") {
         Ok(s) => match s.parse() {
             Ok(v) => return Duration::from_millis(v),
@@ -1,27 +1,42 @@
 mod dns_sync;
+mod handle;
 mod list_host;
+mod rate_limit;
 mod reaper;
 mod recv_pong;
 mod scaler;
 mod send_ping;
+#[cfg(test)]
+mod simulation;
 
 use crate::{
-    deployment_cache::DeploymentCache, dns::DnsProvider, host_connection::HostConnection,
-    telemetry, *,
+    deployment_cache::DeploymentCache,
+    dns::DnsProvider,
+    host_connection::{HostConnection, HostConnector},
+    random_sleep::Jitter,
+    telemetry,
+    wall_clock::WallClock,
+    *,
 };
 use dashmap::{DashMap, DashSet};
 use doc_db::DocDb;
+pub use handle::*;
+use host_hq_protocol::HqToHostReliable;
 use std::num::NonZeroUsize;
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use tokio::sync::mpsc;
-use tokio::time::MissedTickBehavior;
+use tokio::time::{Instant, MissedTickBehavior};
 
 pub struct Site {
     host_provider: HostProvider,
     dns_provider: DnsProvider,
     host_connections: Arc<DashMap<Host, HostConnection>>,
     hosts_status: Arc<DashMap<Host, HostStatus>>,
-    cert: String,
+    /// Requests per second of each tenant at each host, as last reported.
+    tenant_requests: Arc<DashMap<Host, Vec<(u64, u64)>>>,
+    host_connector: HostConnector,
+    timings: SiteTimings,
     pub deployment_cache: DeploymentCache,
     // Below fields won't be cleared so may occur out-of-memory.
     // But the size is expected to be too small to cause out-of-memory.
@@ -30,6 +45,8 @@ pub struct Site {
     graceful_shutdown_hosts: Arc<DashMap<Host, Instant>>,
     host_cpu_cores: NonZeroUsize,
     host_memory_in_gb: NonZeroUsize,
+    /// As the scaler last computed it, 0 until then.
+    max_instances_per_host: AtomicUsize,
     doc_db: DocDb,
 }
 
@@ -48,16 +65,32 @@ impl Site {
             dns_provider,
             host_connections: Default::default(),
             hosts_status: Default::default(),
+            tenant_requests: Default::default(),
             known_hosts: Default::default(),
             dead_hosts: Default::default(),
             graceful_shutdown_hosts: Default::default(),
-            cert,
+            host_connector: HostConnector::Quic { cert },
+            timings: SiteTimings::from_env(),
             deployment_cache,
             host_cpu_cores,
             host_memory_in_gb,
+            max_instances_per_host: AtomicUsize::new(0),
             doc_db,
         }
     }
+
+    #[cfg(test)]
+    pub fn with_host_connector(mut self, host_connector: HostConnector) -> Self {
+        self.host_connector = host_connector;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_timings(mut self, timings: SiteTimings) -> Self {
+        self.timings = timings;
+        self
+    }
+
     #[tracing::instrument(skip_all)]
     pub async fn run(&mut self) {
         let (new_host_tx, new_host_rx) = mpsc::unbounded_channel();
@@ -68,7 +101,8 @@ impl Site {
             self.run_reaper(),
             self.run_dns_sync(),
             self.run_metrics_reporter(),
-            self.run_scaler()
+            self.run_scaler(),
+            self.run_region_rate_limit_loop()
         );
     }
 
@@ -86,6 +120,65 @@ impl Site {
     }
 }
 
+/// How often the loops of a [`Site`] run and how long they wait for hosts. Loops
+/// run on tokio's clock, so a paused runtime drives them in simulated time.
+#[derive(Debug, Clone)]
+pub struct SiteTimings {
+    pub list_host_interval: Duration,
+    pub send_ping_interval: Duration,
+    pub reaper_interval: Duration,
+    /// A host that hasn't reported its status for this long is dead.
+    pub host_connection_timeout: Duration,
+    pub dns_sync_interval: Duration,
+    pub dns_min_ready: Duration,
+    pub scale_interval: Duration,
+    pub region_rate_limit_interval: Duration,
+    /// Spreads out the work the loops start on many hosts at once.
+    pub jitter: Jitter,
+    /// The time of day scale schedules are matched against.
+    pub wall_clock: WallClock,
+}
+
+impl SiteTimings {
+    pub fn from_env() -> Self {
+        Self {
+            list_host_interval: list_host::list_host_info_interval_ms(),
+            send_ping_interval: send_ping::send_ping_interval_ms(),
+            reaper_interval: reaper::reaper_interval_ms(),
+            host_connection_timeout: reaper::host_connection_timeout_ms(),
+            dns_sync_interval: dns_sync::dns_sync_interval_ms(),
+            dns_min_ready: dns_sync::dns_min_ready_ms(),
+            scale_interval: scaler::scale_interval_ms(),
+            region_rate_limit_interval: rate_limit::region_rate_limit_interval_ms(),
+            jitter: Jitter::Random,
+            wall_clock: WallClock::System,
+        }
+    }
+}
+
+/// Stops routing to `host` and asks it to shut down once its in-flight work is done.
+fn start_graceful_shutdown(
+    host: &Host,
+    graceful_shutdown_hosts: &DashMap<Host, Instant>,
+    host_connections: &DashMap<Host, HostConnection>,
+) {
+    graceful_shutdown_hosts.insert(host.clone(), Instant::now());
+
+    if let Some((_host, connection)) = host_connections.remove(host) {
+        tokio::spawn(async move {
+            let result = connection
+                .send_reliable(HqToHostReliable::GracefulShutdown)
+                .await;
+
+            telemetry::scaler_shutdown_command_status(result.is_ok());
+
+            if let Err(err) = result {
+                warn!(%err, "Fail to send graceful shutdown");
+            };
+        });
+    }
+}
+
 #[derive(Clone, Copy)]
 struct HostStatus {
     received_at: Instant,
@@ -0,0 +1,171 @@
+use super::*;
+use crate::{telemetry, *};
+use host_hq_protocol::HqToHostReliable;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+impl Site {
+    /// Splits each tenant's region limit between the connected hosts of this site by
+    /// the demand they reported, and sends every host its shares.
+    ///
+    /// Hosts don't act on the shares or report demand yet; this is hq's half of the
+    /// protocol, for the host that owns `Fn0` to pick up.
+    #[tracing::instrument(skip_all)]
+    pub async fn run_region_rate_limit_loop(&self) {
+        let mut interval = tokio::time::interval(self.timings.region_rate_limit_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            let region_limits = match self.doc_db.region_rate_limits().await {
+                Ok(region_limits) => {
+                    telemetry::region_rate_limit_fetch_status(true);
+                    region_limits
+                }
+                Err(err) => {
+                    telemetry::region_rate_limit_fetch_status(false);
+                    warn!(%err, "Fail to get region rate limits");
+                    continue;
+                }
+            };
+            telemetry::region_rate_limited_tenants(region_limits.len());
+
+            self.tenant_requests
+                .retain(|host, _| self.host_connections.contains_key(host));
+
+            let hosts = self
+                .host_connections
+                .iter()
+                .map(|entry| (entry.key().clone(), entry.value().clone()))
+                .collect::<Vec<_>>();
+            if hosts.is_empty() {
+                continue;
+            }
+
+            let mut host_limits = vec![Vec::with_capacity(region_limits.len()); hosts.len()];
+            // a limit of 0 is disabled, the tenant gets no share and isn't limited
+            for &(tenant_id, limit) in region_limits.iter().filter(|(_, limit)| *limit > 0) {
+                let demands = hosts
+                    .iter()
+                    .map(|(host, _)| {
+                        self.tenant_requests
+                            .get(host)
+                            .and_then(|requests| {
+                                requests
+                                    .iter()
+                                    .find(|(id, _)| *id == tenant_id)
+                                    .map(|(_, requests_per_sec)| *requests_per_sec)
+                            })
+                            .unwrap_or_default()
+                    })
+                    .collect::<Vec<_>>();
+                for (limits, share) in host_limits
+                    .iter_mut()
+                    .zip(split_region_limit(limit, &demands))
+                {
+                    limits.push((tenant_id, share));
+                }
+            }
+
+            for ((_host, connection), limits) in hosts.into_iter().zip(host_limits) {
+                tokio::spawn(async move {
+                    let result = connection
+                        .send_reliable(HqToHostReliable::RegionRateLimits { limits })
+                        .await;
+
+                    telemetry::region_rate_limit_send_status(result.is_ok());
+
+                    if let Err(err) = result {
+                        warn!(%err, "Fail to send region rate limits");
+                    };
+                });
+            }
+        }
+    }
+}
+
+/// Splits `limit` between hosts with the given demands. Every host keeps a floor of
+/// half an even split, at least 1, so a host that just got traffic isn't starved
+/// until its next report; the rest goes by demand, or evenly if there is none.
+///
+/// With fewer requests per second than hosts, the floors add up to more than
+/// `limit`.
+fn split_region_limit(limit: u32, demands: &[u64]) -> Vec<u32> {
+    if demands.is_empty() {
+        return vec![];
+    }
+    let hosts = demands.len() as u64;
+    let limit = limit as u64;
+    let floor = (limit / (2 * hosts)).max(1);
+    let rest = limit.saturating_sub(floor * hosts);
+    let total_demand: u128 = demands.iter().map(|&demand| demand as u128).sum();
+
+    demands
+        .iter()
+        .map(|&demand| {
+            let share = if total_demand == 0 {
+                rest / hosts
+            } else {
+                (rest as u128 * demand as u128 / total_demand) as u64
+            };
+            (floor + share) as u32
+        })
+        .collect()
+}
+
+/// How fast a region limit follows a shift of demand between hosts.
+pub(super) fn region_rate_limit_interval_ms() -> Duration {
+    match std::env::var("REGION_RATE_LIMIT_INTERVAL_MS") {
+        Ok(s) => match s.parse() {
+            Ok(v) => return Duration::from_millis(v),
+            Err(err) => warn!(%err, "REGION_RATE_LIMIT_INTERVAL_MS is not a valid number"),
+        },
+        Err(err) => warn!(%err, "Fail to get REGION_RATE_LIMIT_INTERVAL_MS from env"),
+    }
+    Duration::from_secs(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_region_limit_by_demand() {
+        assert_eq!(split_region_limit(100, &[]), Vec::<u32>::new());
+        assert_eq!(split_region_limit(100, &[0, 0]), vec![50, 50]);
+        // floor of 25 each, the other 50 by demand
+        assert_eq!(split_region_limit(100, &[30, 10]), vec![62, 37]);
+        assert_eq!(split_region_limit(100, &[10, 0]), vec![75, 25]);
+    }
+
+    #[test]
+    fn test_split_region_limit_never_exceeds_limit() {
+        for limit in [0, 1, 7, 1000, u32::MAX] {
+            for demands in [vec![1, 2, 3], vec![u64::MAX, u64::MAX], vec![0, 5, 0, 5]] {
+                let total: u64 = split_region_limit(limit, &demands)
+                    .into_iter()
+                    .map(u64::from)
+                    .sum();
+                // but the floor of 1 per host
+                assert!(total <= (limit as u64).max(demands.len() as u64));
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_region_limit_starves_no_host() {
+        assert_eq!(split_region_limit(1, &[0, 0]), vec![1, 1]);
+        assert_eq!(split_region_limit(3, &[9, 0, 0, 0]), vec![1, 1, 1, 1]);
+        assert_eq!(split_region_limit(5, &[9, 0]), vec![4, 1]);
+        for limit in [1, 2, 7, 1000] {
+            for demands in [vec![1, 2, 3], vec![u64::MAX, 0], vec![0, 5, 0, 5]] {
+                assert!(
+                    split_region_limit(limit, &demands)
+                        .iter()
+                        .all(|&share| share >= 1)
+                );
+            }
+        }
+    }
+}
@@ -1,18 +1,18 @@
 use super::*;
 use crate::{telemetry, *};
-use std::time::{Duration, Instant};
-use tokio::time::MissedTickBehavior;
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
 
 impl Site {
     #[tracing::instrument(skip_all)]
     pub async fn run_reaper(&self) {
-        let mut interval = tokio::time::interval(reaper_interval_ms());
+        let mut interval = tokio::time::interval(self.timings.reaper_interval);
         interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
         loop {
             interval.tick().await;
 
-            let timeout_threshold = host_connection_timeout_ms();
+            let timeout_threshold = self.timings.host_connection_timeout;
             let terminate_candidates = self
                 .hosts_status
                 .iter()
@@ -45,7 +45,7 @@ impl Site {
     }
 }
 
-fn reaper_interval_ms() -> Duration {
+pub(super) fn reaper_interval_ms() -> Duration {
     match std::env::var("REAPER_INTERVAL_MS") {
         Ok(s) => match s.parse() {
             Ok(v) => return Duration::from_millis(v),
@@ -56,7 +56,7 @@ fn reaper_interval_ms() -> Duration {
     Duration::from_secs(1)
 }
 
-fn host_connection_timeout_ms() -> Duration {
+pub(super) fn host_connection_timeout_ms() -> Duration {
     match std::env::var("HOST_CONNECTION_TIMEOUT_MS") {
         Ok(s) => match s.parse() {
             Ok(v) => return Duration::from_millis(v),
@@ -14,6 +14,7 @@ impl Site {
 
             let connection = connection.clone();
             let hosts_status = self.hosts_status.clone();
+            let tenant_requests = self.tenant_requests.clone();
             let deployment_cache = self.deployment_cache.clone();
 
             tokio::spawn(async move {
@@ -52,6 +53,19 @@ impl Site {
                                 deployment_cache.slice_updates(DeploymentId(deployment_id));
                             send_updates(&connection, &new_host.id, updates, deployment_id);
                         }
+                        HostToHq::ReportTenantRequests {
+                            interval_ms,
+                            requests,
+                        } => {
+                            let interval_ms = interval_ms.max(1);
+                            let requests_per_sec = requests
+                                .into_iter()
+                                .map(|(tenant_id, count)| {
+                                    (tenant_id, count.saturating_mul(1000) / interval_ms)
+                                })
+                                .collect();
+                            tenant_requests.insert(new_host.clone(), requests_per_sec);
+                        }
                     }
                 }
             });
@@ -1,18 +1,18 @@
 use super::*;
 use crate::{random_sleep::random_sleep, telemetry, *};
-use host_hq_protocol::HqToHostReliable;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use doc_db::{PredictiveScaling, ScaleConfig};
+use std::collections::VecDeque;
 use std::time::Duration;
 use tokio::time::MissedTickBehavior;
 
 impl Site {
     #[tracing::instrument(skip_all)]
     pub async fn run_scaler(&self) {
-        let mut interval = tokio::time::interval(scale_interval_ms());
+        let mut interval = tokio::time::interval(self.timings.scale_interval);
         interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
-        let mut scale_in_tick_count = 0;
-        let mut last_scale_out_at: Option<Instant> = None;
-        let mut last_scale_in_at: Option<Instant> = None;
+        let mut scaler = Scaler::default();
 
         loop {
             interval.tick().await;
@@ -59,98 +59,209 @@ impl Site {
             );
 
             telemetry::scaler_max_instances_per_host(max_instances_per_host.get() as u64);
+            self.max_instances_per_host
+                .store(max_instances_per_host.get(), Ordering::Relaxed);
+
+            match scaler.decide(
+                &scale_config,
+                Instant::now(),
+                self.timings.wall_clock.now(),
+                hosts,
+                instances,
+                max_instances_per_host,
+            ) {
+                ScaleAction::Hold => {}
+                ScaleAction::ScaleIn(count) => {
+                    telemetry::scaler_action_triggered("scale_in", count);
+
+                    running_hosts.sort_by_key(|h| h.instances);
+
+                    for host in running_hosts.into_iter().take(count) {
+                        start_graceful_shutdown(
+                            host.key(),
+                            &self.graceful_shutdown_hosts,
+                            &self.host_connections,
+                        );
+                    }
+                }
+                ScaleAction::ScaleOut(count) => {
+                    telemetry::scaler_action_triggered("scale_out", count);
 
-            let calculate_target = |threshold_percent: NonZeroUsize| -> usize {
-                ((instances as f32 / max_instances_per_host.get() as f32 * 100.0
-                    / threshold_percent.get() as f32)
-                    .ceil() as usize)
-                    .min(scale_config.max_hosts.get())
-                    .max(scale_config.min_hosts.get())
-            };
-
-            let scale_out_target = calculate_target(scale_config.scale_out_threshold_percent);
-            let scale_in_target = calculate_target(scale_config.scale_in_threshold_percent);
-
-            telemetry::scaler_targets(scale_out_target, scale_in_target);
-
-            if scale_in_target < hosts {
-                if let Some(last_scale_in_at) = last_scale_in_at
-                    && last_scale_in_at.elapsed().as_secs()
-                        < scale_config.scale_in_cooldown_secs.get() as _
-                {
-                    continue;
+                    launch_hosts(&self.host_provider, self.timings.jitter, count);
                 }
+            }
+        }
+    }
+}
 
-                scale_in_tick_count += 1;
+fn launch_hosts(host_provider: &HostProvider, jitter: Jitter, count: usize) {
+    for _ in 0..count {
+        let host_provider = host_provider.clone();
+        tokio::spawn(async move {
+            random_sleep(jitter, 1000).await;
+            let result = host_provider.launch_instance().await;
+            telemetry::scaler_launch_attempt_status(result.is_ok());
 
-                if scale_in_tick_count < scale_config.scale_in_threshold_ticks.get() {
-                    continue;
-                }
+            if let Err(err) = result {
+                warn!(%err, "Fail to scale out");
+            };
+        });
+    }
+}
 
-                last_scale_in_at = Some(Instant::now());
+#[derive(Debug, PartialEq, Eq)]
+enum ScaleAction {
+    Hold,
+    ScaleOut(usize),
+    ScaleIn(usize),
+}
 
-                let count = hosts - scale_in_target;
+/// What the scaler remembers between ticks. Time is passed in, so tests can drive
+/// it with a fake clock.
+#[derive(Default)]
+struct Scaler {
+    scale_in_tick_count: usize,
+    last_scale_out_at: Option<Instant>,
+    last_scale_in_at: Option<Instant>,
+    /// Total instances of the predictive window, oldest first.
+    samples: VecDeque<(Instant, u64)>,
+}
 
-                telemetry::scaler_action_triggered("scale_in", count);
+impl Scaler {
+    fn decide(
+        &mut self,
+        scale_config: &ScaleConfig,
+        now: Instant,
+        utc_now: DateTime<Utc>,
+        hosts: usize,
+        instances: u64,
+        max_instances_per_host: NonZeroUsize,
+    ) -> ScaleAction {
+        // scaling in on a rising trend would only scale out again
+        let demand = instances.max(self.predict(scale_config.predictive, now, instances));
+
+        let (min_hosts, max_hosts) = scale_config.host_bounds(
+            utc_now.weekday().num_days_from_monday() as u8,
+            (utc_now.hour() * 60 + utc_now.minute()) as u16,
+        );
+
+        let calculate_target = |threshold_percent: NonZeroUsize| -> usize {
+            ((demand as f32 / max_instances_per_host.get() as f32 * 100.0
+                / threshold_percent.get() as f32)
+                .ceil() as usize)
+                .saturating_add(scale_config.headroom_hosts)
+                .min(max_hosts.get())
+                .max(min_hosts.get())
+        };
+
+        let scale_out_target = calculate_target(scale_config.scale_out_threshold_percent);
+        let scale_in_target = calculate_target(scale_config.scale_in_threshold_percent);
+
+        telemetry::scaler_targets(scale_out_target, scale_in_target);
+
+        if scale_in_target < hosts {
+            if let Some(last_scale_in_at) = self.last_scale_in_at
+                && now.saturating_duration_since(last_scale_in_at).as_secs()
+                    < scale_config.scale_in_cooldown_secs.get() as _
+            {
+                return ScaleAction::Hold;
+            }
 
-                running_hosts.sort_by_key(|h| h.instances);
+            self.scale_in_tick_count += 1;
 
-                for host in running_hosts.into_iter().take(count) {
-                    self.graceful_shutdown_hosts
-                        .insert(host.key().clone(), Instant::now());
+            if self.scale_in_tick_count < scale_config.scale_in_threshold_ticks.get() {
+                return ScaleAction::Hold;
+            }
 
-                    if let Some((_host, connection)) = self.host_connections.remove(host.key()) {
-                        tokio::spawn(async move {
-                            let result = connection
-                                .send_reliable(HqToHostReliable::GracefulShutdown)
-                                .await;
+            self.last_scale_in_at = Some(now);
 
-                            telemetry::scaler_shutdown_command_status(result.is_ok());
+            return ScaleAction::ScaleIn(hosts - scale_in_target);
+        }
 
-                            if let Err(err) = result {
-                                warn!(%err, "Fail to send graceful shutdown");
-                            };
-                        });
-                    }
-                }
+        self.scale_in_tick_count = 0;
 
-                continue;
+        if hosts < scale_out_target {
+            if let Some(last_scale_out_at) = self.last_scale_out_at
+                && now.saturating_duration_since(last_scale_out_at).as_secs()
+                    < scale_config.scale_out_cooldown_secs.get() as _
+            {
+                return ScaleAction::Hold;
             }
 
-            scale_in_tick_count = 0;
+            self.last_scale_out_at = Some(now);
 
-            if hosts < scale_out_target {
-                if let Some(last_scale_out_at) = last_scale_out_at
-                    && last_scale_out_at.elapsed().as_secs()
-                        < scale_config.scale_out_cooldown_secs.get() as _
-                {
-                    continue;
-                }
+            return ScaleAction::ScaleOut(scale_out_target - hosts);
+        }
+
+        ScaleAction::Hold
+    }
 
-                last_scale_out_at = Some(Instant::now());
+    /// Total instances the trend of the window predicts at the horizon, or
+    /// `instances` without predictive scaling.
+    fn predict(
+        &mut self,
+        predictive: Option<PredictiveScaling>,
+        now: Instant,
+        instances: u64,
+    ) -> u64 {
+        let Some(predictive) = predictive else {
+            self.samples.clear();
+            return instances;
+        };
+
+        let window = Duration::from_secs(predictive.window_secs.get() as u64);
+        self.samples.push_back((now, instances));
+        while let Some((sampled_at, _)) = self.samples.front()
+            && now.saturating_duration_since(*sampled_at) > window
+        {
+            self.samples.pop_front();
+        }
 
-                let count = scale_out_target - hosts;
+        let horizon = Duration::from_secs(predictive.horizon_secs.get() as u64);
+        let predicted = trend_at(&self.samples, now + horizon)
+            .map(|predicted| predicted.max(0.0).ceil() as u64)
+            .unwrap_or(instances);
 
-                telemetry::scaler_action_triggered("scale_out", count);
+        telemetry::scaler_predicted_instances(predicted);
 
-                for _ in 0..count {
-                    let host_provider = self.host_provider.clone();
-                    tokio::spawn(async move {
-                        random_sleep(1000).await;
-                        let result = host_provider.launch_instance().await;
-                        telemetry::scaler_launch_attempt_status(result.is_ok());
+        predicted
+    }
+}
 
-                        if let Err(err) = result {
-                            warn!(%err, "Fail to scale out");
-                        };
-                    });
-                }
-            }
-        }
+/// The least-squares line through `samples`, at `at`. `None` without at least two
+/// samples apart in time.
+fn trend_at(samples: &VecDeque<(Instant, u64)>, at: Instant) -> Option<f64> {
+    let (origin, _) = *samples.front()?;
+    let points = samples
+        .iter()
+        .map(|(sampled_at, instances)| {
+            (
+                sampled_at.saturating_duration_since(origin).as_secs_f64(),
+                *instances as f64,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx = points
+        .iter()
+        .map(|(x, _)| (x - mean_x).powi(2))
+        .sum::<f64>();
+    if sxx == 0.0 {
+        return None;
     }
+    let sxy = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>();
+
+    let x = at.saturating_duration_since(origin).as_secs_f64();
+    Some(mean_y + sxy / sxx * (x - mean_x))
 }
 
-fn scale_interval_ms() -> Duration {
+pub(super) fn scale_interval_ms() -> Duration {
     match std::env::var("SCALE_INTERVAL_MS") {
         Ok(s) => match s.parse() {
             Ok(v) => return Duration::from_millis(v),
@@ -160,3 +271,271 @@ fn scale_interval_ms() -> Duration {
     }
     Duration::from_secs(5)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_provider::fake::FakeHostProvider;
+    use doc_db::ScaleSchedule;
+
+    fn n(n: usize) -> NonZeroUsize {
+        NonZeroUsize::new(n).unwrap()
+    }
+
+    fn scale_config() -> ScaleConfig {
+        ScaleConfig {
+            instances_per_gb: n(4),
+            instances_per_core: n(8),
+            scale_out_threshold_percent: n(80),
+            scale_in_threshold_percent: n(40),
+            scale_out_cooldown_secs: n(60),
+            scale_in_threshold_ticks: n(3),
+            scale_in_cooldown_secs: n(300),
+            max_hosts: n(10),
+            min_hosts: n(1),
+            schedules: vec![],
+            predictive: None,
+            headroom_hosts: 0,
+        }
+    }
+
+    struct FakeClock {
+        instant: Instant,
+        utc: DateTime<Utc>,
+    }
+
+    impl FakeClock {
+        fn new(utc: &str) -> Self {
+            Self {
+                instant: Instant::now(),
+                utc: utc.parse().unwrap(),
+            }
+        }
+
+        fn advance(&mut self, secs: u64) {
+            self.instant += Duration::from_secs(secs);
+            self.utc += chrono::TimeDelta::seconds(secs as i64);
+        }
+    }
+
+    /// With 100 instances per host.
+    fn decide(
+        scaler: &mut Scaler,
+        scale_config: &ScaleConfig,
+        clock: &FakeClock,
+        hosts: usize,
+        instances: u64,
+    ) -> ScaleAction {
+        scaler.decide(
+            scale_config,
+            clock.instant,
+            clock.utc,
+            hosts,
+            instances,
+            n(100),
+        )
+    }
+
+    #[test]
+    fn test_reactive_ticks_and_cooldowns() {
+        let config = scale_config();
+        let mut clock = FakeClock::new("2025-01-06T00:00:00Z");
+        let mut scaler = Scaler::default();
+
+        // 250 instances at 80% take 4 hosts
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 1, 250),
+            ScaleAction::ScaleOut(3)
+        );
+        clock.advance(10);
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 1, 250),
+            ScaleAction::Hold
+        );
+        clock.advance(60);
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 1, 250),
+            ScaleAction::ScaleOut(3)
+        );
+
+        // 50 instances at 40% take 2 hosts, after 3 ticks
+        for _ in 0..2 {
+            clock.advance(5);
+            assert_eq!(
+                decide(&mut scaler, &config, &clock, 4, 50),
+                ScaleAction::Hold
+            );
+        }
+        clock.advance(5);
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 4, 50),
+            ScaleAction::ScaleIn(2)
+        );
+        clock.advance(5);
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 2, 0),
+            ScaleAction::Hold
+        );
+        clock.advance(300);
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 2, 0),
+            ScaleAction::ScaleIn(1)
+        );
+    }
+
+    #[test]
+    fn test_schedule_overrides_host_bounds() {
+        let mut config = scale_config();
+        // weekdays 09:00~18:00
+        config.schedules = vec![ScaleSchedule {
+            weekdays: vec![0, 1, 2, 3, 4],
+            start_minute: 9 * 60,
+            end_minute: 18 * 60,
+            min_hosts: n(5),
+            max_hosts: n(20),
+        }];
+        let mut scaler = Scaler::default();
+
+        // monday
+        let mut clock = FakeClock::new("2025-01-06T08:59:00Z");
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 1, 0),
+            ScaleAction::Hold
+        );
+        clock.advance(60);
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 1, 0),
+            ScaleAction::ScaleOut(4)
+        );
+        // beyond the usual max_hosts
+        clock.advance(60);
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 5, 1500),
+            ScaleAction::ScaleOut(14)
+        );
+
+        clock.advance(9 * 60 * 60);
+        for _ in 0..2 {
+            assert_eq!(
+                decide(&mut scaler, &config, &clock, 5, 0),
+                ScaleAction::Hold
+            );
+        }
+        assert_eq!(
+            decide(&mut scaler, &config, &clock, 5, 0),
+            ScaleAction::ScaleIn(4)
+        );
+    }
+
+    #[test]
+    fn test_trend_scales_out_ahead_of_load() {
+        let config = scale_config();
+        let mut clock = FakeClock::new("2025-01-06T00:00:00Z");
+        let mut reactive = Scaler::default();
+        let mut predictive = Scaler::default();
+        let predictive_config = {
+            let mut config = config.clone();
+            config.predictive = Some(PredictiveScaling {
+                window_secs: n(60),
+                horizon_secs: n(120),
+            });
+            config
+        };
+
+        assert_eq!(
+            decide(&mut reactive, &config, &clock, 3, 100),
+            ScaleAction::Hold
+        );
+        assert_eq!(
+            decide(&mut predictive, &predictive_config, &clock, 3, 100),
+            ScaleAction::Hold
+        );
+
+        // 2 more instances a second reach 360 in 2 minutes, 5 hosts at 80%
+        clock.advance(10);
+        assert_eq!(
+            decide(&mut reactive, &config, &clock, 3, 120),
+            ScaleAction::Hold
+        );
+        assert_eq!(
+            decide(&mut predictive, &predictive_config, &clock, 3, 120),
+            ScaleAction::ScaleOut(2)
+        );
+
+        // a falling trend doesn't scale in below the current load
+        let mut predictive = Scaler::default();
+        let predictive_config = ScaleConfig {
+            scale_in_threshold_ticks: n(1),
+            ..predictive_config
+        };
+        assert_eq!(
+            decide(&mut predictive, &predictive_config, &clock, 4, 200),
+            ScaleAction::Hold
+        );
+        clock.advance(10);
+        assert_eq!(
+            decide(&mut predictive, &predictive_config, &clock, 4, 160),
+            ScaleAction::Hold
+        );
+    }
+
+    #[test]
+    fn test_trend_window_drops_old_samples() {
+        let samples = VecDeque::new();
+        assert_eq!(trend_at(&samples, Instant::now()), None);
+
+        let mut scaler = Scaler::default();
+        let predictive = Some(PredictiveScaling {
+            window_secs: n(30),
+            horizon_secs: n(10),
+        });
+        let mut clock = FakeClock::new("2025-01-06T00:00:00Z");
+        assert_eq!(scaler.predict(predictive, clock.instant, 100), 100);
+        for instances in [0, 10, 20, 30] {
+            clock.advance(10);
+            scaler.predict(predictive, clock.instant, instances);
+        }
+        assert_eq!(scaler.samples.len(), 4);
+        clock.advance(10);
+        // 10 more every 10 seconds, once the first 100 fell out of the window
+        assert_eq!(scaler.predict(predictive, clock.instant, 40), 50);
+    }
+
+    #[test]
+    fn test_headroom_hosts_on_top_of_target() {
+        let mut config = scale_config();
+        config.headroom_hosts = 2;
+        let clock = FakeClock::new("2025-01-06T00:00:00Z");
+
+        assert_eq!(
+            decide(&mut Scaler::default(), &config, &clock, 1, 0),
+            ScaleAction::ScaleOut(1)
+        );
+        // 250 instances take 4 hosts, plus 2 of headroom
+        assert_eq!(
+            decide(&mut Scaler::default(), &config, &clock, 4, 250),
+            ScaleAction::ScaleOut(2)
+        );
+
+        config.headroom_hosts = 20;
+        assert_eq!(
+            decide(&mut Scaler::default(), &config, &clock, 1, 0),
+            ScaleAction::ScaleOut(9)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scale_out_launches_hosts() {
+        let fake = FakeHostProvider::default();
+        launch_hosts(&HostProvider::Fake(fake.clone()), Jitter::Half, 3);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while fake.hosts().len() < 3 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(fake.launched(), 3);
+    }
+}
@@ -7,9 +7,11 @@ use tokio::time::MissedTickBehavior;
 impl Site {
     #[tracing::instrument(skip_all)]
     pub async fn run_send_ping_loop(&self) {
-        let mut interval = tokio::time::interval(send_ping_interval_ms());
+        let mut interval = tokio::time::interval(self.timings.send_ping_interval);
         interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
+        let jitter = self.timings.jitter;
+
         loop {
             interval.tick().await;
 
@@ -20,7 +22,7 @@ impl Site {
                 let connection = connection.value().clone();
                 let datagram = datagram.clone();
                 tokio::spawn(async move {
-                    random_sleep(250).await;
+                    random_sleep(jitter, 250).await;
                     match connection.send_datagram(datagram) {
                         Ok(_) => {
                             telemetry::ping_sent_status(true);
@@ -36,7 +38,7 @@ impl Site {
     }
 }
 
-fn send_ping_interval_ms() -> Duration {
+pub(super) fn send_ping_interval_ms() -> Duration {
     match std::env::var("SEND_PING_INTERVAL_MS") {
         Ok(s) => match s.parse() {
             Ok(v) => return Duration::from_millis(v),
@@ -0,0 +1,316 @@
+//! Runs every loop of a [`Site`] against in-memory hosts, agents and DNS on tokio's
+//! paused clock, so scenarios spanning minutes replay the same in milliseconds.
+//!
+//! A module rather than a test crate: hq is a binary without a library target, so
+//! `tests/` couldn't reach `Site` or the fakes, which also stay out of the release
+//! build behind `#[cfg(test)]`.
+
+use super::*;
+use crate::dns::fake::FakeDnsProvider;
+use crate::fake_agent::FakeAgents;
+use crate::host_provider::fake::FakeHostProvider;
+use crate::wall_clock::WallClock;
+use doc_db::{ScaleConfig, ScaleSchedule};
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+const BOOT_DELAY: Duration = Duration::from_secs(10);
+
+/// A Monday, a minute before the window of [`ten_oclock_schedule`].
+const START: &str = "2024-01-01T09:59:00Z";
+
+fn n(n: usize) -> NonZeroUsize {
+    NonZeroUsize::new(n).unwrap()
+}
+
+/// 32 instances per host, as the simulated hosts have 4 cores and 16 GB.
+fn scale_config(min_hosts: usize) -> ScaleConfig {
+    ScaleConfig {
+        instances_per_gb: n(4),
+        instances_per_core: n(8),
+        scale_out_threshold_percent: n(80),
+        scale_in_threshold_percent: n(40),
+        scale_out_cooldown_secs: n(60),
+        scale_in_threshold_ticks: n(3),
+        scale_in_cooldown_secs: n(300),
+        max_hosts: n(10),
+        min_hosts: n(min_hosts),
+        schedules: vec![],
+        predictive: None,
+        headroom_hosts: 0,
+    }
+}
+
+fn timings() -> SiteTimings {
+    SiteTimings {
+        list_host_interval: Duration::from_secs(1),
+        send_ping_interval: Duration::from_secs(1),
+        reaper_interval: Duration::from_secs(1),
+        host_connection_timeout: Duration::from_secs(6),
+        dns_sync_interval: Duration::from_secs(1),
+        dns_min_ready: Duration::from_secs(5),
+        scale_interval: Duration::from_secs(5),
+        region_rate_limit_interval: Duration::from_secs(1),
+        jitter: Jitter::Half,
+        wall_clock: WallClock::simulated(START.parse().unwrap()),
+    }
+}
+
+/// 3 hosts from 10:00 to 10:03 UTC on Mondays.
+fn ten_oclock_schedule() -> ScaleSchedule {
+    ScaleSchedule {
+        weekdays: vec![0],
+        start_minute: 600,
+        end_minute: 603,
+        min_hosts: n(3),
+        max_hosts: n(10),
+    }
+}
+
+struct Simulation {
+    hosts: FakeHostProvider,
+    agents: FakeAgents,
+    dns: FakeDnsProvider,
+    doc_db: DocDb,
+    site: SiteHandle,
+    run: tokio::task::JoinHandle<()>,
+    /// Of the doc-db, removed on drop.
+    path: PathBuf,
+}
+
+impl Simulation {
+    async fn start(scale_config: ScaleConfig) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "hq-simulation-{}-{}.sqlite3",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let doc_db = DocDb::new_local(&path).await.unwrap();
+        doc_db.migrate().await.unwrap();
+        doc_db.set_scale_config(scale_config).await.unwrap();
+
+        let agents = FakeAgents::default();
+        let hosts = FakeHostProvider::default().with_agents(agents.clone(), BOOT_DELAY);
+        let dns = FakeDnsProvider::default();
+
+        let mut site = Site::new(
+            HostProvider::Fake(hosts.clone()),
+            DnsProvider::Fake(dns.clone()),
+            String::new(),
+            DeploymentCache::new(doc_db.clone()).await.unwrap(),
+            n(4),
+            n(16),
+            doc_db.clone(),
+        )
+        .with_host_connector(HostConnector::Fake(agents.clone()))
+        .with_timings(timings());
+        let handle = site.handle();
+        let run = tokio::spawn(async move { site.run().await });
+
+        Self {
+            hosts,
+            agents,
+            dns,
+            doc_db,
+            site: handle,
+            run,
+            path,
+        }
+    }
+
+    async fn run_for(&self, secs: u64) {
+        tokio::time::sleep(Duration::from_secs(secs)).await;
+    }
+
+    fn host_ips(&self) -> BTreeSet<IpAddr> {
+        self.hosts.hosts().iter().map(|host| host.ip).collect()
+    }
+
+    fn ip_of(&self, host_id: &str) -> IpAddr {
+        self.site.host(host_id).unwrap().ip.parse().unwrap()
+    }
+
+    fn states(&self) -> Vec<(String, HostState)> {
+        self.site
+            .hosts()
+            .into_iter()
+            .map(|host| (host.id, host.state))
+            .collect()
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.run.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_hosts_boot_to_min_hosts_and_reach_dns() {
+    let simulation = Simulation::start(scale_config(2)).await;
+
+    // launched, not booted yet
+    simulation.run_for(5).await;
+    assert_eq!(simulation.hosts.hosts().len(), 2);
+    assert!(simulation.dns.ips().is_empty());
+
+    simulation.run_for(55).await;
+    assert_eq!(simulation.hosts.launched(), 2);
+    assert_eq!(
+        simulation.states(),
+        vec![
+            ("fake-1".to_string(), HostState::Running),
+            ("fake-2".to_string(), HostState::Running),
+        ]
+    );
+    assert_eq!(simulation.dns.ips(), simulation.host_ips());
+    assert!(
+        simulation
+            .dns
+            .weights()
+            .values()
+            .all(|weight| *weight == 100)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_crashed_host_is_terminated_and_replaced() {
+    let simulation = Simulation::start(scale_config(2)).await;
+    simulation.run_for(60).await;
+
+    let crashed = simulation.ip_of("fake-1");
+    simulation.agents.update(crashed, |agent| agent.down = true);
+    simulation.run_for(90).await;
+
+    assert_eq!(
+        simulation.hosts.terminated(),
+        vec![HostId::new("fake-1".to_string())]
+    );
+    assert_eq!(simulation.hosts.launched(), 3);
+    assert!(!simulation.host_ips().contains(&crashed));
+    assert_eq!(simulation.dns.ips(), simulation.host_ips());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_slow_pongs_and_short_stalls_survive() {
+    let simulation = Simulation::start(scale_config(2)).await;
+    simulation.run_for(60).await;
+
+    // late, but within the timeout
+    let slow = simulation.ip_of("fake-1");
+    simulation
+        .agents
+        .update(slow, |agent| agent.pong_delay = Duration::from_secs(4));
+    // silent, but shorter than the timeout
+    let stalled = simulation.ip_of("fake-2");
+    simulation.agents.update(stalled, |agent| agent.down = true);
+    simulation.run_for(4).await;
+    simulation
+        .agents
+        .update(stalled, |agent| agent.down = false);
+    simulation.run_for(56).await;
+
+    assert!(simulation.hosts.terminated().is_empty());
+    assert_eq!(simulation.hosts.launched(), 2);
+    assert_eq!(
+        simulation.states(),
+        vec![
+            ("fake-1".to_string(), HostState::Running),
+            ("fake-2".to_string(), HostState::Running),
+        ]
+    );
+    assert_eq!(simulation.dns.ips(), [slow, stalled].into());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_lower_min_hosts_drains_then_terminates() {
+    let simulation = Simulation::start(scale_config(3)).await;
+    simulation.run_for(60).await;
+    assert_eq!(simulation.dns.ips().len(), 3);
+    let ips = simulation.host_ips();
+
+    simulation
+        .doc_db
+        .set_scale_config(scale_config(1))
+        .await
+        .unwrap();
+    simulation.run_for(60).await;
+
+    assert_eq!(simulation.hosts.terminated().len(), 2);
+    assert_eq!(simulation.hosts.hosts().len(), 1);
+    assert_eq!(simulation.dns.ips(), simulation.host_ips());
+    for ip in ips.difference(&simulation.host_ips()) {
+        let agent = simulation.agents.get(*ip).unwrap();
+        assert!(
+            agent
+                .received
+                .iter()
+                .any(|message| matches!(message, HqToHostReliable::GracefulShutdown))
+        );
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_draining_hosts_stay_out_of_dns() {
+    let simulation = Simulation::start(scale_config(3)).await;
+    simulation.run_for(60).await;
+    for ip in simulation.host_ips() {
+        simulation
+            .agents
+            .update(ip, |agent| agent.drains_slowly = true);
+    }
+
+    simulation
+        .doc_db
+        .set_scale_config(scale_config(1))
+        .await
+        .unwrap();
+    simulation.run_for(60).await;
+
+    // still answering, so connected again, but not routed to
+    assert!(simulation.hosts.terminated().is_empty());
+    let running = simulation
+        .site
+        .hosts()
+        .into_iter()
+        .filter(|host| host.state == HostState::Running)
+        .map(|host| host.ip.parse::<IpAddr>().unwrap())
+        .collect::<BTreeSet<_>>();
+    assert_eq!(running.len(), 1);
+    assert!(
+        simulation
+            .site
+            .hosts()
+            .iter()
+            .any(|host| host.state == HostState::Draining && host.connected)
+    );
+    assert_eq!(simulation.dns.ips(), running);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_schedule_window_scales_out_then_in() {
+    let simulation = Simulation::start(ScaleConfig {
+        schedules: vec![ten_oclock_schedule()],
+        ..scale_config(1)
+    })
+    .await;
+
+    // 09:59:55
+    simulation.run_for(55).await;
+    assert_eq!(simulation.hosts.launched(), 1);
+    assert_eq!(simulation.dns.ips().len(), 1);
+
+    // 10:01:30, the window's hosts booted and warmed up
+    simulation.run_for(95).await;
+    assert_eq!(simulation.hosts.launched(), 3);
+    assert_eq!(simulation.dns.ips(), simulation.host_ips());
+    assert_eq!(simulation.dns.ips().len(), 3);
+
+    // 10:05:00, drained after the window
+    simulation.run_for(210).await;
+    assert_eq!(simulation.hosts.terminated().len(), 2);
+    assert_eq!(simulation.hosts.hosts().len(), 1);
+    assert_eq!(simulation.dns.ips(), simulation.host_ips());
+}
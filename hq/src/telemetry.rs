@@ -241,6 +241,13 @@ pub fn scaler_max_instances_per_host(count: u64) {
     gauge.record(count as f64, &[]);
 }
 
+pub fn scaler_predicted_instances(count: u64) {
+    let gauge = global::meter("hq")
+        .f64_gauge("scaler_predicted_instances")
+        .build();
+    gauge.record(count as f64, &[]);
+}
+
 pub fn scaler_targets(scale_out: usize, scale_in: usize) {
     let gauge = global::meter("hq").f64_gauge("scaler_targets").build();
     gauge.record(scale_out as f64, &[KeyValue::new("type", "scale_out")]);
@@ -292,3 +299,56 @@ pub fn scaler_config_fetch_status(success: bool) {
         )],
     );
 }
+
+pub fn api_key_cache_cached_count(count: usize) {
+    let gauge = global::meter("hq")
+        .f64_gauge("api_key_cache_cached_count")
+        .build();
+    gauge.record(count as f64, &[]);
+}
+
+pub fn api_key_cache_fetch_status(success: bool) {
+    let counter = global::meter("hq")
+        .u64_counter("api_key_cache_fetch_status")
+        .build();
+    counter.add(
+        1,
+        &[KeyValue::new(
+            "result",
+            if success { "success" } else { "failure" },
+        )],
+    );
+}
+
+pub fn region_rate_limit_fetch_status(success: bool) {
+    let counter = global::meter("hq")
+        .u64_counter("region_rate_limit_fetch_status")
+        .build();
+    counter.add(
+        1,
+        &[KeyValue::new(
+            "result",
+            if success { "success" } else { "failure" },
+        )],
+    );
+}
+
+pub fn region_rate_limit_send_status(success: bool) {
+    let counter = global::meter("hq")
+        .u64_counter("region_rate_limit_send_status")
+        .build();
+    counter.add(
+        1,
+        &[KeyValue::new(
+            "result",
+            if success { "success" } else { "failure" },
+        )],
+    );
+}
+
+pub fn region_rate_limited_tenants(count: usize) {
+    let gauge = global::meter("hq")
+        .f64_gauge("region_rate_limited_tenants")
+        .build();
+    gauge.record(count as f64, &[]);
+}
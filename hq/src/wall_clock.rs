@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+
+/// Where the scaler reads the time of day its schedules are in.
+#[derive(Debug, Clone, Copy)]
+pub enum WallClock {
+    System,
+    /// Starts at `start` and advances with tokio's clock, so simulations on a
+    /// paused clock cross schedule windows.
+    #[cfg(test)]
+    Simulated {
+        start: DateTime<Utc>,
+        started_at: tokio::time::Instant,
+    },
+}
+
+impl WallClock {
+    #[cfg(test)]
+    pub fn simulated(start: DateTime<Utc>) -> Self {
+        Self::Simulated {
+            start,
+            started_at: tokio::time::Instant::now(),
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        match self {
+            WallClock::System => Utc::now(),
+            #[cfg(test)]
+            WallClock::Simulated { start, started_at } => *start + started_at.elapsed(),
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use crate::Clock;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Category {
+    Guest,
+    HostCall,
+}
+
+impl Category {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Guest => "guest",
+            Category::HostCall => "host_call",
+        }
+    }
+}
+
+/// Splits poll time between nested scopes. Time is always charged to the innermost
+/// open scope, so a child's time is never billed to its parent as well.
+///
+/// Only time inside a [`measure_attributed`] poll is charged. A scope may stay open
+/// across polls (e.g. an async host call), and the time in between is not counted.
+pub struct AttributedTimeTracker<C: Clock> {
+    state: Arc<Mutex<AttributedState<C::Instant>>>,
+    clock: Arc<C>,
+}
+
+impl<C: Clock> Clone for AttributedTimeTracker<C> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+struct AttributedState<I> {
+    root: Category,
+    scopes: Vec<Category>,
+    polls: usize,
+    segment_start: Option<I>,
+    acc: HashMap<Category, Duration>,
+}
+
+impl<I: Copy + std::ops::Sub<Output = Duration>> AttributedState<I> {
+    fn current(&self) -> Category {
+        self.scopes.last().copied().unwrap_or(self.root)
+    }
+
+    fn charge(&mut self, now: I) {
+        if let Some(start) = self.segment_start {
+            let current = self.current();
+            *self.acc.entry(current).or_default() += now - start;
+            self.segment_start = Some(now);
+        }
+    }
+}
+
+impl<C: Clock> AttributedTimeTracker<C> {
+    /// Time outside any scope is charged to `root`.
+    pub fn new(clock: C, root: Category) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(AttributedState {
+                root,
+                scopes: vec![],
+                polls: 0,
+                segment_start: None,
+                acc: HashMap::new(),
+            })),
+            clock: Arc::new(clock),
+        }
+    }
+
+    /// Opens a child scope. Prefer [`scope`](Self::scope) unless the matching
+    /// [`exit`](Self::exit) happens in a different callback.
+    pub fn enter(&self, category: Category) {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        state.charge(now);
+        state.scopes.push(category);
+    }
+
+    /// Closes the innermost scope opened with [`enter`](Self::enter).
+    pub fn exit(&self) {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        state.charge(now);
+        state.scopes.pop();
+    }
+
+    pub fn scope(&self, category: Category) -> AttributedScope<C> {
+        self.enter(category);
+        AttributedScope {
+            tracker: self.clone(),
+        }
+    }
+
+    /// Time charged to `category` alone, excluding its children.
+    pub fn duration(&self, category: Category) -> Duration {
+        let state = self.state.lock().unwrap();
+        let charged = state.acc.get(&category).copied().unwrap_or_default();
+        match state.segment_start {
+            Some(start) if state.current() == category => charged + (self.clock.now() - start),
+            _ => charged,
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        let charged: Duration = state.acc.values().sum();
+        match state.segment_start {
+            Some(start) => charged + (self.clock.now() - start),
+            None => charged,
+        }
+    }
+
+    pub fn breakdown(&self) -> Vec<(Category, Duration)> {
+        let mut categories = {
+            let state = self.state.lock().unwrap();
+            let mut categories = state.acc.keys().copied().collect::<Vec<_>>();
+            if state.segment_start.is_some() && !state.acc.contains_key(&state.current()) {
+                categories.push(state.current());
+            }
+            categories
+        };
+        categories.sort();
+        categories
+            .into_iter()
+            .map(|category| (category, self.duration(category)))
+            .collect()
+    }
+
+    fn begin_poll(&self) {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        state.polls += 1;
+        if state.polls == 1 {
+            state.segment_start = Some(now);
+        }
+    }
+
+    fn end_poll(&self) {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        state.polls -= 1;
+        if state.polls == 0 {
+            state.charge(now);
+            state.segment_start = None;
+        }
+    }
+}
+
+/// Closes its scope on drop.
+pub struct AttributedScope<C: Clock> {
+    tracker: AttributedTimeTracker<C>,
+}
+
+impl<C: Clock> Drop for AttributedScope<C> {
+    fn drop(&mut self) {
+        self.tracker.exit();
+    }
+}
+
+pub struct MeasureAttributed<F, C: Clock> {
+    future: F,
+    tracker: AttributedTimeTracker<C>,
+    category: Option<Category>,
+}
+
+/// Charges the time spent polling `future` to whichever scope is open at the time.
+pub fn measure_attributed<F, C: Clock>(
+    tracker: AttributedTimeTracker<C>,
+    future: F,
+) -> MeasureAttributed<F, C> {
+    MeasureAttributed {
+        future,
+        tracker,
+        category: None,
+    }
+}
+
+/// Charges the time spent polling `future` to `category`, e.g. host work awaited
+/// inside a guest poll.
+pub fn attribute<F, C: Clock>(
+    tracker: AttributedTimeTracker<C>,
+    category: Category,
+    future: F,
+) -> MeasureAttributed<F, C> {
+    MeasureAttributed {
+        future,
+        tracker,
+        category: Some(category),
+    }
+}
+
+impl<F: Future, C: Clock> Future for MeasureAttributed<F, C> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        this.tracker.begin_poll();
+        let scope = this.category.map(|category| this.tracker.scope(category));
+
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let result = future.poll(cx);
+
+        drop(scope);
+        this.tracker.end_poll();
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[derive(Clone)]
+    struct MockClock {
+        now: Arc<Mutex<Instant>>,
+    }
+
+    impl MockClock {
+        fn new() -> Self {
+            Self {
+                now: Arc::new(Mutex::new(Instant::now())),
+            }
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        type Instant = Instant;
+
+        fn now(&self) -> Self::Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_child_scope_is_subtracted_from_parent() {
+        let clock = MockClock::new();
+        let tracker = AttributedTimeTracker::new(clock.clone(), Category::Guest);
+
+        let future = {
+            let clock = clock.clone();
+            let tracker = tracker.clone();
+            async move {
+                clock.advance(Duration::from_millis(100));
+                {
+                    let _host_call = tracker.scope(Category::HostCall);
+                    clock.advance(Duration::from_millis(30));
+                    {
+                        // the host calls back into the guest
+                        let _callback = tracker.scope(Category::Guest);
+                        clock.advance(Duration::from_millis(5));
+                    }
+                }
+                clock.advance(Duration::from_millis(10));
+            }
+        };
+        measure_attributed(tracker.clone(), future).await;
+
+        assert_eq!(
+            tracker.duration(Category::Guest),
+            Duration::from_millis(115)
+        );
+        assert_eq!(
+            tracker.duration(Category::HostCall),
+            Duration::from_millis(30)
+        );
+        assert_eq!(tracker.total(), Duration::from_millis(145));
+        assert_eq!(
+            tracker.breakdown(),
+            vec![
+                (Category::Guest, Duration::from_millis(115)),
+                (Category::HostCall, Duration::from_millis(30)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_attribute_charges_inner_future_to_category() {
+        let clock = MockClock::new();
+        let tracker = AttributedTimeTracker::new(clock.clone(), Category::Guest);
+
+        let future = {
+            let clock = clock.clone();
+            let tracker = tracker.clone();
+            async move {
+                clock.advance(Duration::from_millis(20));
+                attribute(tracker, Category::HostCall, {
+                    let clock = clock.clone();
+                    async move {
+                        clock.advance(Duration::from_millis(50));
+                        tokio::task::yield_now().await;
+                        clock.advance(Duration::from_millis(50));
+                    }
+                })
+                .await;
+            }
+        };
+        measure_attributed(tracker.clone(), future).await;
+
+        assert_eq!(tracker.duration(Category::Guest), Duration::from_millis(20));
+        assert_eq!(
+            tracker.duration(Category::HostCall),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_time_between_polls_is_not_charged() {
+        let clock = MockClock::new();
+        let tracker = AttributedTimeTracker::new(clock.clone(), Category::Guest);
+
+        // an async host call that stays open while the guest is suspended
+        tracker.enter(Category::HostCall);
+        clock.advance(Duration::from_millis(500));
+        measure_attributed(tracker.clone(), {
+            let clock = clock.clone();
+            async move { clock.advance(Duration::from_millis(10)) }
+        })
+        .await;
+        clock.advance(Duration::from_millis(500));
+        tracker.exit();
+
+        assert_eq!(
+            tracker.duration(Category::HostCall),
+            Duration::from_millis(10)
+        );
+        assert_eq!(tracker.duration(Category::Guest), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_duration_includes_in_flight_poll() {
+        let clock = MockClock::new();
+        let tracker = AttributedTimeTracker::new(clock.clone(), Category::Guest);
+
+        let future = {
+            let clock = clock.clone();
+            let tracker = tracker.clone();
+            async move {
+                clock.advance(Duration::from_millis(40));
+                tracker.duration(Category::Guest)
+            }
+        };
+        let in_flight = measure_attributed(tracker.clone(), future).await;
+
+        assert_eq!(in_flight, Duration::from_millis(40));
+    }
+}
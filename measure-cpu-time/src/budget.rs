@@ -0,0 +1,292 @@
+use crate::{AttributedTimeTracker, Clock, TimeTracker};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Anything that reports the time accumulated so far.
+pub trait ElapsedTime: Clone + Send + Sync + 'static {
+    fn elapsed(&self) -> Duration;
+}
+
+impl<C: Clock> ElapsedTime for TimeTracker<C> {
+    fn elapsed(&self) -> Duration {
+        self.duration()
+    }
+}
+
+impl<C: Clock> ElapsedTime for AttributedTimeTracker<C> {
+    fn elapsed(&self) -> Duration {
+        self.total()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    pub budget: Duration,
+    pub used: Duration,
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cpu budget of {:?} exceeded, used {:?}",
+            self.budget, self.used
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+type SoftLimitCallback = Box<dyn FnOnce(Duration) + Send>;
+
+pub struct CpuBudget<F, T> {
+    future: Option<F>,
+    tracker: T,
+    budget: Duration,
+    soft_limit: Option<(Duration, SoftLimitCallback)>,
+}
+
+/// Resolves to `Err(BudgetExceeded)` once `tracker` crosses `budget`, dropping `future`.
+///
+/// The check runs between polls, so `tracker` must be fed by a measuring wrapper
+/// around `future` (e.g. [`measure_cpu_time`](crate::measure_cpu_time)). A single poll
+/// that never returns still needs its own guard, like wasmtime's epoch interruption.
+pub fn with_cpu_budget<F, T: ElapsedTime>(
+    tracker: T,
+    budget: Duration,
+    future: F,
+) -> CpuBudget<F, T> {
+    CpuBudget {
+        future: Some(future),
+        tracker,
+        budget,
+        soft_limit: None,
+    }
+}
+
+impl<F, T: ElapsedTime> CpuBudget<F, T> {
+    /// Calls `on_soft_limit` once with the time used when `tracker` crosses `soft_limit`.
+    pub fn soft_limit(
+        mut self,
+        soft_limit: Duration,
+        on_soft_limit: impl FnOnce(Duration) + Send + 'static,
+    ) -> Self {
+        self.soft_limit = Some((soft_limit, Box::new(on_soft_limit)));
+        self
+    }
+
+    fn check(&mut self) -> Result<(), BudgetExceeded> {
+        let used = self.tracker.elapsed();
+
+        if let Some((soft_limit, _)) = &self.soft_limit
+            && used > *soft_limit
+        {
+            let (_, on_soft_limit) = self.soft_limit.take().expect("unreachable");
+            on_soft_limit(used);
+        }
+
+        if used > self.budget {
+            return Err(BudgetExceeded {
+                budget: self.budget,
+                used,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<F: Future, T: ElapsedTime> Future for CpuBudget<F, T> {
+    type Output = Result<F::Output, BudgetExceeded>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        assert!(this.future.is_some(), "CpuBudget polled after completion");
+
+        // the tracker may be shared with work that already used up the budget
+        if let Err(error) = this.check() {
+            // dropping in place keeps the pinning guarantee
+            this.future = None;
+            return Poll::Ready(Err(error));
+        }
+
+        let future = unsafe { Pin::new_unchecked(this.future.as_mut().expect("unreachable")) };
+        let result = future.poll(cx);
+
+        match (result, this.check()) {
+            (Poll::Ready(value), _) => {
+                this.future = None;
+                Poll::Ready(Ok(value))
+            }
+            (Poll::Pending, Err(error)) => {
+                this.future = None;
+                Poll::Ready(Err(error))
+            }
+            (Poll::Pending, Ok(())) => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure_cpu_time;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    #[derive(Clone)]
+    struct MockClock {
+        now: Arc<Mutex<Instant>>,
+    }
+
+    impl MockClock {
+        fn new() -> Self {
+            Self {
+                now: Arc::new(Mutex::new(Instant::now())),
+            }
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        type Instant = Instant;
+
+        fn now(&self) -> Self::Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    // Advances the clock by `step` on every poll, forever.
+    struct Spinning {
+        clock: MockClock,
+        step: Duration,
+        polls: Arc<Mutex<u32>>,
+        dropped: Arc<AtomicBool>,
+    }
+
+    impl Future for Spinning {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            self.clock.advance(self.step);
+            *self.polls.lock().unwrap() += 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    impl Drop for Spinning {
+        fn drop(&mut self) {
+            self.dropped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn spinning(clock: &MockClock) -> (Spinning, Arc<Mutex<u32>>, Arc<AtomicBool>) {
+        let polls = Arc::new(Mutex::new(0));
+        let dropped = Arc::new(AtomicBool::new(false));
+        let future = Spinning {
+            clock: clock.clone(),
+            step: Duration::from_millis(100),
+            polls: polls.clone(),
+            dropped: dropped.clone(),
+        };
+        (future, polls, dropped)
+    }
+
+    #[tokio::test]
+    async fn test_within_budget_resolves_ok() {
+        let clock = MockClock::new();
+        let tracker = TimeTracker::new(clock.clone());
+
+        let future = {
+            let clock = clock.clone();
+            async move {
+                clock.advance(Duration::from_millis(100));
+                42
+            }
+        };
+        let result = with_cpu_budget(
+            tracker.clone(),
+            Duration::from_secs(1),
+            measure_cpu_time(tracker, future),
+        )
+        .await;
+
+        assert_eq!(result, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn test_exceeding_budget_cancels_future() {
+        let clock = MockClock::new();
+        let tracker = TimeTracker::new(clock.clone());
+        let (future, polls, dropped) = spinning(&clock);
+
+        let result = with_cpu_budget(
+            tracker.clone(),
+            Duration::from_millis(350),
+            measure_cpu_time(tracker, future),
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            Err(BudgetExceeded {
+                budget: Duration::from_millis(350),
+                used: Duration::from_millis(400),
+            })
+        );
+        assert_eq!(*polls.lock().unwrap(), 4);
+        assert!(dropped.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_soft_limit_called_once() {
+        let clock = MockClock::new();
+        let tracker = TimeTracker::new(clock.clone());
+        let (future, _polls, _dropped) = spinning(&clock);
+        let warnings = Arc::new(Mutex::new(vec![]));
+
+        let result = with_cpu_budget(
+            tracker.clone(),
+            Duration::from_millis(550),
+            measure_cpu_time(tracker, future),
+        )
+        .soft_limit(Duration::from_millis(250), {
+            let warnings = warnings.clone();
+            move |used| warnings.lock().unwrap().push(used)
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(*warnings.lock().unwrap(), vec![Duration::from_millis(300)]);
+    }
+
+    #[tokio::test]
+    async fn test_already_exhausted_tracker_skips_poll() {
+        let clock = MockClock::new();
+        let tracker = TimeTracker::new(clock.clone());
+        let (future, _polls, _dropped) = spinning(&clock);
+        let _ = with_cpu_budget(
+            tracker.clone(),
+            Duration::from_millis(50),
+            measure_cpu_time(tracker.clone(), future),
+        )
+        .await;
+
+        let (future, polls, _dropped) = spinning(&clock);
+        let result = with_cpu_budget(
+            tracker.clone(),
+            Duration::from_millis(50),
+            measure_cpu_time(tracker, future),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(*polls.lock().unwrap(), 0);
+    }
+}
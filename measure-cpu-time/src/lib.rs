@@ -6,6 +6,17 @@ use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 
+mod attributed;
+mod budget;
+mod thread_cpu_clock;
+
+pub use attributed::{
+    AttributedScope, AttributedTimeTracker, Category, MeasureAttributed, attribute,
+    measure_attributed,
+};
+pub use budget::{BudgetExceeded, CpuBudget, ElapsedTime, with_cpu_budget};
+pub use thread_cpu_clock::{ThreadCpuClock, ThreadCpuHandle, ThreadCpuInstant};
+
 pub trait Clock: Clone + Send + Sync + 'static {
     type Instant: Sub<Output = Duration> + Copy + Send + Sync + 'static;
     fn now(&self) -> Self::Instant;
@@ -82,6 +93,7 @@ impl<C: Clock> TimeTracker<C> {
             clock: Arc::new(clock),
         }
     }
+
     pub fn duration(&self) -> Duration {
         Duration::from_nanos(self.acc.load(Ordering::Relaxed) as u64)
             + self
@@ -0,0 +1,165 @@
+use crate::Clock;
+use std::ops::Sub;
+use std::time::Duration;
+
+/// CPU time consumed by the calling OS thread, from `clock_gettime(CLOCK_THREAD_CPUTIME_ID)`.
+///
+/// Unlike [`SystemClock`](crate::SystemClock), time the thread spends preempted or
+/// blocked is not counted. Readings are only comparable on the same thread, which
+/// holds within a single poll, so read the tracker's `duration()` from the polling
+/// thread (e.g. wasmtime's epoch callback) while a poll is in flight.
+#[derive(Clone, Copy, Default)]
+pub struct ThreadCpuClock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadCpuInstant(Duration);
+
+impl Sub for ThreadCpuInstant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        // saturate instead of panicking when the two readings come from different threads
+        self.0.saturating_sub(rhs.0)
+    }
+}
+
+impl Clock for ThreadCpuClock {
+    type Instant = ThreadCpuInstant;
+
+    fn now(&self) -> Self::Instant {
+        let mut timespec = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let result = unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut timespec) };
+        assert_eq!(result, 0, "clock_gettime(CLOCK_THREAD_CPUTIME_ID) failed");
+        ThreadCpuInstant(Duration::new(
+            timespec.tv_sec as u64,
+            timespec.tv_nsec as u32,
+        ))
+    }
+}
+
+/// Reads the CPU time of the thread that created it from any other thread,
+/// e.g. a watchdog that has to stop a poll which never returns.
+///
+/// Only valid while that thread is alive. Outside Linux it falls back to the wall
+/// time since creation.
+#[derive(Clone, Copy)]
+pub struct ThreadCpuHandle {
+    #[cfg(target_os = "linux")]
+    clock_id: libc::clockid_t,
+    #[cfg(not(target_os = "linux"))]
+    created_at: std::time::Instant,
+}
+
+impl ThreadCpuHandle {
+    #[cfg(target_os = "linux")]
+    pub fn current() -> Self {
+        let mut clock_id = 0;
+        let result = unsafe { libc::pthread_getcpuclockid(libc::pthread_self(), &mut clock_id) };
+        assert_eq!(result, 0, "pthread_getcpuclockid failed");
+        Self { clock_id }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn current() -> Self {
+        Self {
+            created_at: std::time::Instant::now(),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn elapsed(&self) -> Duration {
+        let mut timespec = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let result = unsafe { libc::clock_gettime(self.clock_id, &mut timespec) };
+        assert_eq!(result, 0, "clock_gettime on a thread cpu clock failed");
+        Duration::new(timespec.tv_sec as u64, timespec.tv_nsec as u32)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn elapsed(&self) -> Duration {
+        self.created_at.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TimeTracker, measure_cpu_time};
+    use tokio::time::sleep;
+
+    fn spin(duration: Duration) {
+        let start = std::time::Instant::now();
+        while start.elapsed() < duration {
+            std::hint::black_box(());
+        }
+    }
+
+    #[test]
+    fn test_thread_cpu_clock_is_monotonic() {
+        let clock = ThreadCpuClock;
+        let start = clock.now();
+        spin(Duration::from_millis(5));
+        let end = clock.now();
+
+        assert!(end > start);
+        assert_eq!(start - end, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_thread_cpu_clock_ignores_blocking() {
+        let clock = ThreadCpuClock;
+        let start = clock.now();
+        std::thread::sleep(Duration::from_millis(50));
+        let elapsed = clock.now() - start;
+
+        assert!(elapsed < Duration::from_millis(25), "got {elapsed:?}");
+    }
+
+    #[test]
+    fn test_thread_cpu_handle_reads_other_thread() {
+        let (handle_tx, handle_rx) = std::sync::mpsc::channel();
+        let (spun_tx, spun_rx) = std::sync::mpsc::channel::<()>();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let worker = std::thread::spawn(move || {
+            handle_tx.send(ThreadCpuHandle::current()).unwrap();
+            // spin on the thread's own clock, so preemption can't cut the cpu time short
+            let clock = ThreadCpuClock;
+            let start = clock.now();
+            while clock.now() - start < Duration::from_millis(20) {
+                std::hint::black_box(());
+            }
+            spun_tx.send(()).unwrap();
+            done_rx.recv().unwrap();
+        });
+
+        let handle = handle_rx.recv().unwrap();
+        spun_rx.recv().unwrap();
+        let elapsed = handle.elapsed();
+        done_tx.send(()).unwrap();
+        worker.join().unwrap();
+
+        assert!(elapsed >= Duration::from_millis(20), "got {elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn test_measure_with_thread_cpu_clock() {
+        let future = async {
+            spin(Duration::from_millis(20));
+            sleep(Duration::from_millis(50)).await;
+            42
+        };
+
+        let tracker = TimeTracker::new(ThreadCpuClock);
+        let result = measure_cpu_time(tracker.clone(), future).await;
+        let elapsed = tracker.duration();
+
+        assert_eq!(result, 42);
+        assert!(elapsed >= Duration::from_millis(15), "got {elapsed:?}");
+        assert!(elapsed < Duration::from_millis(60), "got {elapsed:?}");
+    }
+}
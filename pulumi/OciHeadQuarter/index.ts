@@ -19,6 +19,8 @@ export interface OciHeadQuarterArgs {
   docDbToken: pulumi.Input<string>;
   sites: pulumi.Input<SiteArgs[]>;
   certificate: pulumi.Input<string>;
+  adminToken: pulumi.Input<string>;
+  envKey: pulumi.Input<string>;
 }
 
 export class OciHeadQuarter extends pulumi.ComponentResource {
@@ -39,6 +41,8 @@ export class OciHeadQuarter extends pulumi.ComponentResource {
       docDbToken,
       sites,
       certificate,
+      adminToken,
+      envKey,
     } = args;
 
     const { regionalSubnet } = createNetworking(this, {
@@ -95,6 +99,8 @@ export class OciHeadQuarter extends pulumi.ComponentResource {
           token: docDbToken,
         },
         cert: certificate,
+        adminToken,
+        envKey,
       },
     });
   }
@@ -1,23 +1,61 @@
 import * as pulumi from '@pulumi/pulumi';
 export interface HqArgs {
+  adminToken: pulumi.Input<string>;
   cert: pulumi.Input<string>;
   docDb: pulumi.Input<DocDbArgs>;
+  envKey: pulumi.Input<string>;
   sites: pulumi.Input<Array<SiteArgs>>;
 }
+export interface AwsEc2HostProviderArgs {
+  accessKeyId: pulumi.Input<string>;
+  imageId: pulumi.Input<string>;
+  instanceType: pulumi.Input<string>;
+  memoryInGbs: pulumi.Input<number>;
+  physicsCpuCores: pulumi.Input<number>;
+  region: pulumi.Input<string>;
+  secretAccessKey: pulumi.Input<string>;
+  securityGroupIds: pulumi.Input<Array<string>>;
+  siteTag: pulumi.Input<string>;
+  subnetId: pulumi.Input<string>;
+  userData: pulumi.Input<string>;
+}
 export interface CloudflareDnsProviderArgs {
+  accountId: pulumi.Input<string>;
   apiToken: pulumi.Input<string>;
   asteriskDomain: pulumi.Input<string>;
+  loadBalancerPoolId: pulumi.Input<string>;
   zoneId: pulumi.Input<string>;
 }
 export interface DnsProviderArg {
   cloudflare?: pulumi.Input<CloudflareDnsProviderArgs>;
+  route53?: pulumi.Input<Route53DnsProviderArgs>;
+  ociDns?: pulumi.Input<OciDnsProviderArgs>;
+  rfc2136?: pulumi.Input<Rfc2136DnsProviderArgs>;
 }
 export interface DocDbArgs {
   token: pulumi.Input<string>;
   url: pulumi.Input<string>;
 }
+export interface DockerHostProviderArgs {
+  engineUrl: pulumi.Input<string>;
+  envs: pulumi.Input<Record<string, string>>;
+  image: pulumi.Input<string>;
+  memoryInGbs: pulumi.Input<number>;
+  network: pulumi.Input<string>;
+  physicsCpuCores: pulumi.Input<number>;
+}
 export interface HostProviderArg {
   ociContainerInstance?: pulumi.Input<OciContainerInstanceHostProviderArgs>;
+  localProcess?: pulumi.Input<LocalProcessHostProviderArgs>;
+  docker?: pulumi.Input<DockerHostProviderArgs>;
+  awsEc2?: pulumi.Input<AwsEc2HostProviderArgs>;
+}
+export interface LocalProcessHostProviderArgs {
+  args: pulumi.Input<Array<string>>;
+  command: pulumi.Input<string>;
+  envs: pulumi.Input<Record<string, string>>;
+  memoryInGbs: pulumi.Input<number>;
+  physicsCpuCores: pulumi.Input<number>;
 }
 export interface OciContainerInstanceHostProviderArgs {
   availabilityDomain: pulumi.Input<string>;
@@ -35,6 +73,27 @@ export interface OciContainerInstanceHostProviderArgs {
   tenancyId: pulumi.Input<string>;
   userId: pulumi.Input<string>;
 }
+export interface OciDnsProviderArgs {
+  asteriskDomain: pulumi.Input<string>;
+  fingerprint: pulumi.Input<string>;
+  privateKeyBase64: pulumi.Input<string>;
+  region: pulumi.Input<string>;
+  tenancyId: pulumi.Input<string>;
+  userId: pulumi.Input<string>;
+  zoneId: pulumi.Input<string>;
+}
+export interface Rfc2136DnsProviderArgs {
+  asteriskDomain: pulumi.Input<string>;
+  server: pulumi.Input<string>;
+  zone: pulumi.Input<string>;
+}
+export interface Route53DnsProviderArgs {
+  accessKeyId: pulumi.Input<string>;
+  asteriskDomain: pulumi.Input<string>;
+  hostedZoneId: pulumi.Input<string>;
+  routing: pulumi.Input<string>;
+  secretAccessKey: pulumi.Input<string>;
+}
 export interface SiteArgs {
   dnsProvider: pulumi.Input<DnsProviderArg>;
   hostProvider: pulumi.Input<HostProviderArg>;
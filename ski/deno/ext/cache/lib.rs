@@ -25,10 +25,13 @@ use tokio::io::AsyncReadExt;
 
 mod lsc_shard;
 mod lscache;
+mod memory;
 mod sqlite;
 
 pub use lsc_shard::CacheShard;
 pub use lscache::LscBackend;
+pub use memory::CacheBudget;
+pub use memory::MemoryCache;
 pub use sqlite::SqliteBackedCache;
 use tokio_util::io::StreamReader;
 
@@ -193,6 +196,7 @@ pub trait Cache: Clone + 'static {
 pub enum CacheImpl {
   Sqlite(SqliteBackedCache),
   Lsc(LscBackend),
+  Memory(MemoryCache),
 }
 
 #[async_trait(?Send)]
@@ -203,6 +207,7 @@ impl Cache for CacheImpl {
     match self {
       Self::Sqlite(cache) => cache.storage_open(cache_name).await,
       Self::Lsc(cache) => cache.storage_open(cache_name).await,
+      Self::Memory(cache) => cache.storage_open(cache_name).await,
     }
   }
 
@@ -210,6 +215,7 @@ impl Cache for CacheImpl {
     match self {
       Self::Sqlite(cache) => cache.storage_has(cache_name).await,
       Self::Lsc(cache) => cache.storage_has(cache_name).await,
+      Self::Memory(cache) => cache.storage_has(cache_name).await,
     }
   }
 
@@ -220,6 +226,7 @@ impl Cache for CacheImpl {
     match self {
       Self::Sqlite(cache) => cache.storage_delete(cache_name).await,
       Self::Lsc(cache) => cache.storage_delete(cache_name).await,
+      Self::Memory(cache) => cache.storage_delete(cache_name).await,
     }
   }
 
@@ -231,6 +238,7 @@ impl Cache for CacheImpl {
     match self {
       Self::Sqlite(cache) => cache.put(request_response, resource).await,
       Self::Lsc(cache) => cache.put(request_response, resource).await,
+      Self::Memory(cache) => cache.put(request_response, resource).await,
     }
   }
 
@@ -244,6 +252,7 @@ impl Cache for CacheImpl {
     match self {
       Self::Sqlite(cache) => cache.r#match(request).await,
       Self::Lsc(cache) => cache.r#match(request).await,
+      Self::Memory(cache) => cache.r#match(request).await,
     }
   }
 
@@ -254,6 +263,7 @@ impl Cache for CacheImpl {
     match self {
       Self::Sqlite(cache) => cache.delete(request).await,
       Self::Lsc(cache) => cache.delete(request).await,
+      Self::Memory(cache) => cache.delete(request).await,
     }
   }
 }
@@ -0,0 +1,552 @@
+// Copyright 2018-2025 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+use deno_core::BufMutView;
+use deno_core::ByteString;
+use deno_core::Resource;
+use deno_core::parking_lot::Mutex;
+
+use crate::CacheDeleteRequest;
+use crate::CacheError;
+use crate::CacheMatchRequest;
+use crate::CacheMatchResponseMeta;
+use crate::CachePutRequest;
+use crate::CacheResponseResource;
+use crate::get_header;
+use crate::vary_header_matches;
+
+/// A cache storage kept in memory, bounded to `max_bytes`.
+///
+/// Clones share the same storage, so it can back the isolates of one
+/// deployment on different threads. Once the cached responses outgrow
+/// `max_bytes`, the least recently used ones are evicted. A response larger
+/// than `max_bytes` on its own is not stored.
+#[derive(Clone)]
+pub struct MemoryCache {
+  inner: Arc<Mutex<Inner>>,
+}
+
+/// A byte budget shared by several [`MemoryCache`]s, e.g. of all deployments
+/// on a host.
+///
+/// Once the caches together outgrow it, the least recently used responses
+/// among all of them are evicted. A dropped cache gives its bytes back.
+#[derive(Clone)]
+pub struct CacheBudget {
+  shared: Arc<BudgetShared>,
+}
+
+struct BudgetShared {
+  max_bytes: usize,
+  used: AtomicUsize,
+  /// Orders the entries of all caches of the budget by their last use.
+  clock: AtomicU64,
+  /// The cache of every entry under the budget, by `Entry::last_used`.
+  lru: Mutex<BTreeMap<u64, Weak<Mutex<Inner>>>>,
+}
+
+type Key = (i64, String);
+
+struct Inner {
+  max_bytes: usize,
+  size: usize,
+  clock: u64,
+  budget: Option<CacheBudget>,
+  /// What the budget's index points back to.
+  this: Weak<Mutex<Inner>>,
+  next_cache_id: i64,
+  cache_ids: HashMap<String, i64>,
+  entries: HashMap<Key, Entry>,
+  /// Keys by `Entry::last_used`, least recently used first.
+  lru: BTreeMap<u64, Key>,
+}
+
+struct Entry {
+  request_headers: Vec<(ByteString, ByteString)>,
+  response_headers: Vec<(ByteString, ByteString)>,
+  response_status: u16,
+  response_status_text: String,
+  body: Option<Bytes>,
+  size: usize,
+  last_used: u64,
+}
+
+impl CacheBudget {
+  pub fn new(max_bytes: usize) -> Self {
+    Self {
+      shared: Arc::new(BudgetShared {
+        max_bytes,
+        used: AtomicUsize::new(0),
+        clock: AtomicU64::new(0),
+        lru: Mutex::new(BTreeMap::new()),
+      }),
+    }
+  }
+
+  /// Total size of the responses cached under the budget, in bytes.
+  pub fn used(&self) -> usize {
+    self.shared.used.load(Ordering::Relaxed)
+  }
+
+  fn is_exceeded(&self) -> bool {
+    self.used() > self.shared.max_bytes
+  }
+
+  /// Evicts the least recently used response of all caches until the budget
+  /// fits. Takes one cache lock at a time, so callers must not hold one.
+  fn evict(&self) {
+    while self.is_exceeded() {
+      let Some((last_used, cache)) = self.shared.lru.lock().pop_first() else {
+        return;
+      };
+      if let Some(cache) = cache.upgrade() {
+        cache.lock().evict_entry(last_used);
+      }
+    }
+  }
+}
+
+impl MemoryCache {
+  pub fn new(max_bytes: usize) -> Self {
+    Self::build(max_bytes, None)
+  }
+
+  /// Also bounded, together with the other caches of `budget`, to it.
+  pub fn with_budget(max_bytes: usize, budget: CacheBudget) -> Self {
+    Self::build(max_bytes.min(budget.shared.max_bytes), Some(budget))
+  }
+
+  fn build(max_bytes: usize, budget: Option<CacheBudget>) -> Self {
+    Self {
+      inner: Arc::new_cyclic(|this| {
+        Mutex::new(Inner {
+          max_bytes,
+          size: 0,
+          clock: 0,
+          budget,
+          this: this.clone(),
+          next_cache_id: 1,
+          cache_ids: HashMap::new(),
+          entries: HashMap::new(),
+          lru: BTreeMap::new(),
+        })
+      }),
+    }
+  }
+
+  /// Total size of the cached responses, in bytes.
+  pub fn size(&self) -> usize {
+    self.inner.lock().size
+  }
+
+  pub async fn storage_open(
+    &self,
+    cache_name: String,
+  ) -> Result<i64, CacheError> {
+    let mut inner = self.inner.lock();
+    if let Some(cache_id) = inner.cache_ids.get(&cache_name) {
+      return Ok(*cache_id);
+    }
+    let cache_id = inner.next_cache_id;
+    inner.next_cache_id += 1;
+    inner.cache_ids.insert(cache_name, cache_id);
+    Ok(cache_id)
+  }
+
+  pub async fn storage_has(
+    &self,
+    cache_name: String,
+  ) -> Result<bool, CacheError> {
+    Ok(self.inner.lock().cache_ids.contains_key(&cache_name))
+  }
+
+  pub async fn storage_delete(
+    &self,
+    cache_name: String,
+  ) -> Result<bool, CacheError> {
+    let mut inner = self.inner.lock();
+    let Some(cache_id) = inner.cache_ids.remove(&cache_name) else {
+      return Ok(false);
+    };
+    let keys = inner
+      .entries
+      .keys()
+      .filter(|(id, _)| *id == cache_id)
+      .cloned()
+      .collect::<Vec<_>>();
+    for key in keys {
+      inner.remove(&key);
+    }
+    Ok(true)
+  }
+
+  pub async fn put(
+    &self,
+    request_response: CachePutRequest,
+    resource: Option<Rc<dyn Resource>>,
+  ) -> Result<(), CacheError> {
+    let max_bytes = self.inner.lock().max_bytes;
+    let mut size = request_response.request_url.len()
+      + headers_size(&request_response.request_headers)
+      + headers_size(&request_response.response_headers)
+      + request_response.response_status_text.len();
+
+    let body = match resource {
+      Some(resource) => {
+        let mut body = Vec::new();
+        let mut buf = BufMutView::new(64 * 1024);
+        loop {
+          let (read, buf2) = resource
+            .clone()
+            .read_byob(buf)
+            .await
+            .map_err(CacheError::Other)?;
+          if read == 0 {
+            break;
+          }
+          buf = buf2;
+          size += read;
+          if size > max_bytes {
+            return Ok(());
+          }
+          body.extend_from_slice(&buf[..read]);
+        }
+        Some(Bytes::from(body))
+      }
+      None => None,
+    };
+    if size > max_bytes {
+      return Ok(());
+    }
+
+    let mut inner = self.inner.lock();
+    let key = (request_response.cache_id, request_response.request_url);
+    inner.remove(&key);
+    let entry = Entry {
+      request_headers: request_response.request_headers,
+      response_headers: request_response.response_headers,
+      response_status: request_response.response_status,
+      response_status_text: request_response.response_status_text,
+      body,
+      size,
+      last_used: inner.tick(),
+    };
+    inner.insert(key, entry);
+    inner.evict();
+    let budget = inner.budget.clone();
+    drop(inner);
+
+    if let Some(budget) = budget {
+      budget.evict();
+    }
+    Ok(())
+  }
+
+  pub async fn r#match(
+    &self,
+    request: CacheMatchRequest,
+  ) -> Result<
+    Option<(CacheMatchResponseMeta, Option<CacheResponseResource>)>,
+    CacheError,
+  > {
+    let mut inner = self.inner.lock();
+    let clock = inner.tick();
+    let key = (request.cache_id, request.request_url);
+    let Some(entry) = inner.entries.get_mut(&key) else {
+      return Ok(None);
+    };
+
+    if let Some(vary_header) = get_header("vary", &entry.response_headers)
+      && !vary_header_matches(
+        &vary_header,
+        &request.request_headers,
+        &entry.request_headers,
+      )
+    {
+      return Ok(None);
+    }
+    let last_used = std::mem::replace(&mut entry.last_used, clock);
+
+    let meta = CacheMatchResponseMeta {
+      response_status: entry.response_status,
+      response_status_text: entry.response_status_text.clone(),
+      request_headers: entry.request_headers.clone(),
+      response_headers: entry.response_headers.clone(),
+    };
+    let resource = entry.body.clone().map(|body| {
+      CacheResponseResource::lsc(futures::stream::once(async move { Ok(body) }))
+    });
+    inner.unlink(last_used);
+    inner.link(clock, key);
+    Ok(Some((meta, resource)))
+  }
+
+  pub async fn delete(
+    &self,
+    request: CacheDeleteRequest,
+  ) -> Result<bool, CacheError> {
+    Ok(
+      self
+        .inner
+        .lock()
+        .remove(&(request.cache_id, request.request_url)),
+    )
+  }
+}
+
+impl Inner {
+  /// The next `last_used`, ordered across the caches of the budget if any.
+  fn tick(&mut self) -> u64 {
+    match &self.budget {
+      Some(budget) => budget.shared.clock.fetch_add(1, Ordering::Relaxed) + 1,
+      None => {
+        self.clock += 1;
+        self.clock
+      }
+    }
+  }
+
+  fn insert(&mut self, key: Key, entry: Entry) {
+    self.size += entry.size;
+    if let Some(budget) = &self.budget {
+      budget.shared.used.fetch_add(entry.size, Ordering::Relaxed);
+    }
+    self.link(entry.last_used, key.clone());
+    self.entries.insert(key, entry);
+  }
+
+  fn remove(&mut self, key: &Key) -> bool {
+    match self.entries.remove(key) {
+      Some(entry) => {
+        self.unlink(entry.last_used);
+        self.release(entry.size);
+        true
+      }
+      None => false,
+    }
+  }
+
+  fn evict(&mut self) {
+    while self.size > self.max_bytes && self.evict_one() {}
+  }
+
+  /// Evicts the least recently used entry, if there is one.
+  fn evict_one(&mut self) -> bool {
+    let Some((&last_used, _)) = self.lru.first_key_value() else {
+      return false;
+    };
+    self.evict_entry(last_used);
+    true
+  }
+
+  /// Evicts the entry used at `last_used`, unless it was used again since.
+  fn evict_entry(&mut self, last_used: u64) {
+    if let Some(key) = self.unlink(last_used)
+      && let Some(entry) = self.entries.remove(&key)
+    {
+      self.release(entry.size);
+    }
+  }
+
+  /// Orders `key` by `last_used`, in the budget's index too.
+  fn link(&mut self, last_used: u64, key: Key) {
+    self.lru.insert(last_used, key);
+    if let Some(budget) = &self.budget {
+      budget
+        .shared
+        .lru
+        .lock()
+        .insert(last_used, self.this.clone());
+    }
+  }
+
+  fn unlink(&mut self, last_used: u64) -> Option<Key> {
+    if let Some(budget) = &self.budget {
+      budget.shared.lru.lock().remove(&last_used);
+    }
+    self.lru.remove(&last_used)
+  }
+
+  fn release(&mut self, size: usize) {
+    self.size -= size;
+    if let Some(budget) = &self.budget {
+      budget.shared.used.fetch_sub(size, Ordering::Relaxed);
+    }
+  }
+}
+
+impl Drop for Inner {
+  fn drop(&mut self) {
+    if let Some(budget) = &self.budget {
+      budget.shared.used.fetch_sub(self.size, Ordering::Relaxed);
+      let mut lru = budget.shared.lru.lock();
+      for last_used in self.lru.keys() {
+        lru.remove(last_used);
+      }
+    }
+  }
+}
+
+fn headers_size(headers: &[(ByteString, ByteString)]) -> usize {
+  headers
+    .iter()
+    .map(|(name, value)| name.len() + value.len())
+    .sum()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn put_request(cache_id: i64, url: &str) -> CachePutRequest {
+    CachePutRequest {
+      cache_id,
+      request_url: url.to_string(),
+      request_headers: vec![],
+      response_headers: vec![],
+      response_status: 200,
+      response_status_text: String::new(),
+      response_rid: None,
+    }
+  }
+
+  fn match_request(cache_id: i64, url: &str) -> CacheMatchRequest {
+    CacheMatchRequest {
+      cache_id,
+      request_url: url.to_string(),
+      request_headers: vec![],
+    }
+  }
+
+  #[tokio::test]
+  async fn test_least_recently_used_is_evicted() {
+    let cache = MemoryCache::new(60);
+    let id = cache.storage_open("default".to_string()).await.unwrap();
+
+    for url in ["https://a.test/1", "https://a.test/2", "https://a.test/3"] {
+      cache.put(put_request(id, url), None).await.unwrap();
+    }
+    assert_eq!(cache.size(), 48);
+
+    // touch the first entry, so the second one is evicted next
+    assert!(
+      cache
+        .r#match(match_request(id, "https://a.test/1"))
+        .await
+        .unwrap()
+        .is_some()
+    );
+    cache
+      .put(put_request(id, "https://a.test/4"), None)
+      .await
+      .unwrap();
+
+    assert_eq!(cache.size(), 48);
+    for (url, cached) in [
+      ("https://a.test/1", true),
+      ("https://a.test/2", false),
+      ("https://a.test/3", true),
+      ("https://a.test/4", true),
+    ] {
+      let matched = cache.r#match(match_request(id, url)).await.unwrap();
+      assert_eq!(matched.is_some(), cached, "{url}");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_budget_evicts_least_recently_used_of_all_caches() {
+    let budget = CacheBudget::new(40);
+    let a = MemoryCache::with_budget(1024, budget.clone());
+    let b = MemoryCache::with_budget(1024, budget.clone());
+    let a_id = a.storage_open("default".to_string()).await.unwrap();
+    let b_id = b.storage_open("default".to_string()).await.unwrap();
+
+    a.put(put_request(a_id, "https://a.test/1"), None)
+      .await
+      .unwrap();
+    b.put(put_request(b_id, "https://b.test/1"), None)
+      .await
+      .unwrap();
+    a.put(put_request(a_id, "https://a.test/2"), None)
+      .await
+      .unwrap();
+
+    assert_eq!(budget.used(), 32);
+    for (cache, id, url, cached) in [
+      (&a, a_id, "https://a.test/1", false),
+      (&a, a_id, "https://a.test/2", true),
+      (&b, b_id, "https://b.test/1", true),
+    ] {
+      let matched = cache.r#match(match_request(id, url)).await.unwrap();
+      assert_eq!(matched.is_some(), cached, "{url}");
+    }
+
+    drop(b);
+    assert_eq!(budget.used(), 16);
+    assert_eq!(budget.shared.lru.lock().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_budget_follows_matches() {
+    let budget = CacheBudget::new(40);
+    let a = MemoryCache::with_budget(1024, budget.clone());
+    let b = MemoryCache::with_budget(1024, budget.clone());
+    let a_id = a.storage_open("default".to_string()).await.unwrap();
+    let b_id = b.storage_open("default".to_string()).await.unwrap();
+
+    a.put(put_request(a_id, "https://a.test/1"), None)
+      .await
+      .unwrap();
+    b.put(put_request(b_id, "https://b.test/1"), None)
+      .await
+      .unwrap();
+    // touch the first entry of `a`, so the one of `b` is evicted next
+    assert!(
+      a.r#match(match_request(a_id, "https://a.test/1"))
+        .await
+        .unwrap()
+        .is_some()
+    );
+    a.put(put_request(a_id, "https://a.test/2"), None)
+      .await
+      .unwrap();
+
+    assert_eq!(budget.used(), 32);
+    assert!(
+      b.r#match(match_request(b_id, "https://b.test/1"))
+        .await
+        .unwrap()
+        .is_none()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_storage_delete_frees_its_entries() {
+    let cache = MemoryCache::new(1024);
+    let id = cache.storage_open("a".to_string()).await.unwrap();
+    let other = cache.storage_open("b".to_string()).await.unwrap();
+    assert_ne!(id, other);
+
+    cache
+      .put(put_request(id, "https://a.test/"), None)
+      .await
+      .unwrap();
+    cache
+      .put(put_request(other, "https://b.test/"), None)
+      .await
+      .unwrap();
+
+    assert!(cache.storage_delete("a".to_string()).await.unwrap());
+    assert!(!cache.storage_has("a".to_string()).await.unwrap());
+    assert_eq!(cache.size(), "https://b.test/".len());
+  }
+}
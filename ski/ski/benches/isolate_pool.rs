@@ -0,0 +1,62 @@
+//! Mean latency of a trivial handler through `ski::run`, which builds an isolate per
+//! request, against `IsolatePool`, which serves from pre-warmed isolates.
+//!
+//! cargo bench --bench isolate_pool
+
+use http_body_util::BodyExt;
+use http_body_util::combinators::UnsyncBoxBody;
+use ski::{IsolatePool, Limits, PoolConfig};
+use std::time::Instant;
+
+const REQUESTS: u32 = 200;
+
+const CODE: &str = r#"
+    const routes = new Map();
+    for (let i = 0; i < 1000; i++) {
+        routes.set(`/route/${i}`, () => new Response(`route ${i}`));
+    }
+    export default () => new Response("hello");
+"#;
+
+fn request() -> hyper::Request<UnsyncBoxBody<bytes::Bytes, deno_core::anyhow::Error>> {
+    hyper::Request::new(UnsyncBoxBody::new(
+        http_body_util::Empty::new().map_err(|never| match never {}),
+    ))
+}
+
+async fn bench<F, Fut>(name: &str, mut run: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    // first request of each design pays for thread start-up and the first compile
+    run().await;
+
+    let start = Instant::now();
+    for _ in 0..REQUESTS {
+        run().await;
+    }
+    let mean = start.elapsed() / REQUESTS;
+    println!("{name:>16}: {:>10.3?} per request", mean);
+}
+
+#[tokio::main]
+async fn main() {
+    bench("ski::run", || async {
+        let response = ski::run(CODE, request(), Default::default(), Limits::default())
+            .await
+            .unwrap();
+        response.into_body().collect().await.unwrap();
+    })
+    .await;
+
+    let pool = IsolatePool::new(PoolConfig::default());
+    bench("IsolatePool::run", || async {
+        let response = pool
+            .run("bench", CODE, request(), Default::default())
+            .await
+            .unwrap();
+        response.into_body().collect().await.unwrap();
+    })
+    .await;
+}
@@ -3,7 +3,7 @@ import { core } from "ext:core/mod.js";
 import * as webidl from "ext:deno_webidl/00_webidl.js";
 
 // Import run.js to ensure it's evaluated during snapshot creation
-import { runHandler } from "ext:bootstrap/run.js";
+import { runHandler, runWaitUntil } from "ext:bootstrap/run.js";
 
 // Expose runHandler to globalThis for runtime execution
 Object.defineProperty(globalThis, "__ski_runHandler", {
@@ -13,6 +13,13 @@ Object.defineProperty(globalThis, "__ski_runHandler", {
   writable: false,
 });
 
+Object.defineProperty(globalThis, "__ski_runWaitUntil", {
+  value: runWaitUntil,
+  enumerable: false,
+  configurable: false,
+  writable: false,
+});
+
 import "ext:deno_web/00_infra.js";
 import * as url from "ext:deno_web/00_url.js";
 import * as console from "ext:deno_web/01_console.js";
@@ -38,6 +45,11 @@ import * as request from "ext:deno_fetch/23_request.js";
 import * as response from "ext:deno_fetch/23_response.js";
 import * as fetch from "ext:deno_fetch/26_fetch.js";
 
+import * as crypto from "ext:deno_crypto/00_crypto.js";
+import * as webSocket from "ext:deno_websocket/01_websocket.js";
+import * as cache from "ext:deno_cache/01_cache.js";
+import * as kv from "ext:deno_kv/01_db.ts";
+
 Object.defineProperty(globalThis, "fetch", {
   value: fetch.fetch,
   enumerable: true,
@@ -441,3 +453,130 @@ Object.defineProperty(globalThis, "performance", {
   configurable: true,
   writable: true,
 });
+
+// Web Crypto APIs
+Object.defineProperty(globalThis, "crypto", {
+  value: crypto.crypto,
+  enumerable: true,
+  configurable: true,
+  writable: false,
+});
+
+Object.defineProperty(globalThis, "Crypto", {
+  value: crypto.Crypto,
+  enumerable: false,
+  configurable: true,
+  writable: true,
+});
+
+Object.defineProperty(globalThis, "CryptoKey", {
+  value: crypto.CryptoKey,
+  enumerable: false,
+  configurable: true,
+  writable: true,
+});
+
+Object.defineProperty(globalThis, "SubtleCrypto", {
+  value: crypto.SubtleCrypto,
+  enumerable: false,
+  configurable: true,
+  writable: true,
+});
+
+// WebSocket
+Object.defineProperty(globalThis, "WebSocket", {
+  value: webSocket.WebSocket,
+  enumerable: false,
+  configurable: true,
+  writable: true,
+});
+
+// Navigator
+class Navigator {
+  constructor() {
+    webidl.illegalConstructor();
+  }
+
+  get userAgent() {
+    webidl.assertBranded(this, NavigatorPrototype);
+    return "ski";
+  }
+}
+
+const NavigatorPrototype = Navigator.prototype;
+
+Object.defineProperty(globalThis, "Navigator", {
+  value: Navigator,
+  enumerable: false,
+  configurable: true,
+  writable: true,
+});
+
+Object.defineProperty(globalThis, "navigator", {
+  value: webidl.createBranded(Navigator),
+  enumerable: true,
+  configurable: true,
+  writable: false,
+});
+
+// Cache APIs
+const cacheStorage = cache.cacheStorage();
+let defaultCache;
+
+function openDefaultCache() {
+  defaultCache ??= cacheStorage.open("default");
+  return defaultCache;
+}
+
+// `caches.default` as in Cloudflare Workers, a shorthand for `caches.open("default")`
+Object.defineProperty(cacheStorage, "default", {
+  value: Object.freeze({
+    async match(request, options) {
+      return (await openDefaultCache()).match(request, options);
+    },
+    async put(request, response) {
+      return (await openDefaultCache()).put(request, response);
+    },
+    async delete(request, options) {
+      return (await openDefaultCache()).delete(request, options);
+    },
+  }),
+  enumerable: true,
+  configurable: false,
+  writable: false,
+});
+
+Object.defineProperty(globalThis, "caches", {
+  value: cacheStorage,
+  enumerable: true,
+  configurable: true,
+  writable: false,
+});
+
+Object.defineProperty(globalThis, "CacheStorage", {
+  value: cache.CacheStorage,
+  enumerable: false,
+  configurable: true,
+  writable: true,
+});
+
+Object.defineProperty(globalThis, "Cache", {
+  value: cache.Cache,
+  enumerable: false,
+  configurable: true,
+  writable: true,
+});
+
+// KV APIs, under `Deno` so code written for Deno KV runs unchanged
+Object.defineProperty(globalThis, "Deno", {
+  value: Object.freeze({
+    openKv: kv.openKv,
+    AtomicOperation: kv.AtomicOperation,
+    Kv: kv.Kv,
+    KvListIterator: kv.KvListIterator,
+    KvU64: kv.KvU64,
+  }),
+  enumerable: false,
+  configurable: true,
+  writable: false,
+});
@@ -1,13 +1,19 @@
 #[allow(dead_code)]
+#[path = "src/net_policy.rs"]
+mod net_policy;
+#[allow(dead_code)]
 #[path = "src/runtime_options.rs"]
 mod runtime_options;
+#[allow(dead_code)]
+#[path = "src/storage.rs"]
+mod storage;
 
 use deno_core::JsRuntimeForSnapshot;
 use runtime_options::runtime_options;
 use std::{env, fs, path::PathBuf};
 
 fn main() {
-    let runtime = JsRuntimeForSnapshot::new(runtime_options());
+    let runtime = JsRuntimeForSnapshot::new(runtime_options(false));
     let snapshot = runtime.snapshot();
     let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
     let file_path = out_dir.join("RUNJS_SNAPSHOT.bin");
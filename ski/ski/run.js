@@ -1,7 +1,73 @@
 import { core } from "ext:core/mod.js";
 import { readableStreamForRid, resourceForReadableStream } from "ext:deno_web/06_streams.js";
 
-export async function runHandler() {
+const EXPORT_HINT =
+  "Export a handler with `export default async (request) => new Response(...)` " +
+  "or `export default { async fetch(request, env, ctx) { ... } }`.";
+
+function resolveFetchHandler(module) {
+  if (!("default" in module)) {
+    // code written before module support assigns a global handler instead
+    if (typeof globalThis.handler === "function") {
+      return globalThis.handler;
+    }
+    throw new TypeError(`User code has no default export. ${EXPORT_HINT}`);
+  }
+
+  const entrypoint = module.default;
+  if (typeof entrypoint === "function") {
+    return entrypoint;
+  }
+  if (typeof entrypoint === "object" && entrypoint !== null) {
+    if (typeof entrypoint.fetch !== "function") {
+      throw new TypeError(
+        `The default export has no \`fetch\` method. ${EXPORT_HINT}`,
+      );
+    }
+    return (request, env, ctx) => entrypoint.fetch(request, env, ctx);
+  }
+  throw new TypeError(
+    `The default export is ${entrypoint === null ? "null" : typeof entrypoint}, ` +
+      `not a function or an object with a \`fetch\` method. ${EXPORT_HINT}`,
+  );
+}
+
+// promises passed to `ctx.waitUntil`, settled by `runWaitUntil` after the response is out
+const waitUntilPromises = [];
+
+function createContext() {
+  return {
+    waitUntil(promise) {
+      waitUntilPromises.push(Promise.resolve(promise));
+    },
+    passThroughOnException() {},
+  };
+}
+
+// rejects once all work settled, so the host can report the failures
+export async function runWaitUntil() {
+  const errors = [];
+  // settled work may register more work, e.g. a retry
+  while (waitUntilPromises.length > 0) {
+    const results = await Promise.allSettled(waitUntilPromises.splice(0));
+    for (const result of results) {
+      if (result.status === "rejected") {
+        errors.push(result.reason);
+      }
+    }
+  }
+  if (errors.length === 1) {
+    throw errors[0];
+  }
+  if (errors.length > 1) {
+    throw new AggregateError(errors, `${errors.length} waitUntil promises rejected`);
+  }
+}
+
+export async function runHandler(module) {
+  // thrown outside the try block so the host sees why instead of a bare 500
+  const fetchHandler = resolveFetchHandler(module);
+
   try {
     console.log("[ski/run.js] Getting request parts...");
     const {
@@ -9,17 +75,18 @@ export async function runHandler() {
       1: method,
       2: headers,
       3: rid,
+      4: envEntries,
     } = core.ops.op_get_request_parts();
 
     const body = rid !== null ? readableStreamForRid(rid) : null;
 
     const request = new Request(url, { method, headers, body });
 
-    if (typeof handler !== "function") {
-      throw new Error("User code must define a global 'handler' function.");
-    }
+    const env = Object.freeze(Object.fromEntries(envEntries));
+    const ctx = createContext();
+
     console.log("[ski/run.js] Calling user handler...");
-    const response = await handler(request);
+    const response = await fetchHandler(request, env, ctx);
     console.log("[ski/run.js] Handler returned, status:", response.status);
 
     const responseBody = response.body;
@@ -0,0 +1,53 @@
+use deno_core::SourceCodeCacheInfo;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// SHA-256 of a user module's original source, which the caches and the pool key
+/// it by, so two sources never share an entry.
+pub(crate) type SourceDigest = [u8; 32];
+
+pub(crate) fn source_digest(code: &str) -> SourceDigest {
+    Sha256::digest(code.as_bytes()).into()
+}
+
+/// V8 code cache of user modules keyed by their original source digest, so a fresh
+/// isolate skips parsing and compiling code another isolate already compiled.
+///
+/// V8 only checks the length of the source against the cached data, so the entry
+/// must not be shared between sources.
+#[derive(Clone, Default)]
+pub struct CodeCache {
+    entries: Arc<Mutex<HashMap<SourceDigest, Arc<[u8]>>>>,
+}
+
+impl CodeCache {
+    /// deno_core compiles with `data` when present and reports a new cache through
+    /// `ModuleLoader::code_cache_ready` when absent or rejected.
+    pub(crate) fn source_info(&self, digest: &SourceDigest) -> SourceCodeCacheInfo {
+        let data = self.entries.lock().unwrap().get(digest).cloned();
+        SourceCodeCacheInfo {
+            // deno_core only hands it back to `code_cache_ready`
+            hash: u64::from_le_bytes(digest[..8].try_into().unwrap()),
+            data: data.map(|data| Cow::Owned(data.to_vec())),
+        }
+    }
+
+    pub(crate) fn insert(&self, digest: SourceDigest, data: Arc<[u8]>) {
+        self.entries.lock().unwrap().insert(digest, data);
+    }
+
+    pub fn remove(&self, code: &str) {
+        self.remove_digest(&source_digest(code));
+    }
+
+    pub(crate) fn remove_digest(&self, digest: &SourceDigest) {
+        self.entries.lock().unwrap().remove(digest);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn contains(&self, digest: &SourceDigest) -> bool {
+        self.entries.lock().unwrap().contains_key(digest)
+    }
+}
@@ -1,23 +1,56 @@
+mod code_cache;
 mod http_body_resource;
+mod limits;
+mod module_loader;
+mod net_policy;
+mod pool;
 mod runtime_options;
+mod storage;
+mod transpile;
 
 use bytes::Bytes;
+pub use code_cache::CodeCache;
 use deno_core::anyhow::{Result, anyhow};
 use deno_core::*;
+use futures::FutureExt;
 use http::*;
 use http_body_resource::*;
 use http_body_util::combinators::UnsyncBoxBody;
 use http_body_util::{BodyExt, Empty};
+pub use limits::{Limits, Termination};
+use limits::{TerminationReason, Watchdog, install_near_heap_limit_callback};
+use measure_cpu_time::{ThreadCpuClock, TimeTracker, measure_cpu_time, with_cpu_budget};
+use module_loader::{USER_MODULE_SPECIFIER, UserModuleLoader};
+pub use pool::{IsolatePool, PoolConfig, WaitUntilReporter};
 use runtime_options::*;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+pub use storage::{KvBackend, StorageConfig};
+pub use transpile::TranspileCache;
+use transpile::transpile;
 
 type Body = UnsyncBoxBody<Bytes, anyhow::Error>;
 type Request = hyper::Request<Body>;
 type Response = hyper::Response<Body>;
 
+/// Environment bindings of the user code, like API keys and config.
+pub type Env = Arc<BTreeMap<String, String>>;
+
 static RUNTIME_SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/RUNJS_SNAPSHOT.bin"));
 
-pub async fn run(code: &str, request: Request) -> Result<Response> {
+/// Runs `code`, which may be JavaScript, TypeScript or TSX, as an ES module.
+/// Fails with a [`Termination`] when the handler crosses one of `limits`.
+///
+/// `env` becomes the handler's second argument.
+///
+/// Resolves as soon as the response is ready; work passed to `ctx.waitUntil` keeps
+/// running on the isolate thread afterwards, and its failures are only logged. See
+/// [`IsolatePool::with_wait_until_reporter`] to observe them.
+pub async fn run(code: &str, request: Request, env: Env, limits: Limits) -> Result<Response> {
     let code = code.to_string();
+    let (res_tx, res_rx) = tokio::sync::oneshot::channel();
 
     tokio::task::spawn_blocking(move || {
         let rt = tokio::runtime::Builder::new_current_thread()
@@ -25,75 +58,198 @@ pub async fn run(code: &str, request: Request) -> Result<Response> {
             .build()
             .unwrap();
         rt.block_on(async move {
-            let mut runtime_options = runtime_options();
-            runtime_options.startup_snapshot = Some(RUNTIME_SNAPSHOT);
-
-            let mut runtime = JsRuntime::new(runtime_options);
-            runtime.execute_script("[user code]", code.to_string())?;
-
-            register_hyper_request(&mut runtime, request);
-
-            eprintln!("[ski/lib.rs] Executing __ski_runHandler()...");
-            let script_result = runtime.execute_script(
-                "[run]",
-                ascii_str!("globalThis.__ski_runHandler();"),
-            )?;
-            eprintln!("[ski/lib.rs] Script executed, resolving future...");
-            let run_future = runtime.resolve(script_result);
-            eprintln!("[ski/lib.rs] Awaiting run_future with event loop...");
-            runtime.with_event_loop_future(run_future, Default::default()).await?;
-            eprintln!("[ski/lib.rs] Handler completed");
-
-            eprintln!("[ski/lib.rs] Getting op_state...");
-            let op_state = runtime.op_state();
-
-            eprintln!("[ski/lib.rs] Extracting ResponseParts...");
-            let response_parts = op_state
-                .borrow_mut()
-                .try_take::<ResponseParts>()
-                .ok_or_else(|| anyhow!("Did not get a response from JavaScript"))?;
-
-            eprintln!("[ski/lib.rs] Got ResponseParts: status={}, rid={:?}", response_parts.status, response_parts.rid);
-
-            let mut builder =
-                hyper::Response::builder().status(StatusCode::from_u16(response_parts.status)?);
-
-            for (key, value) in response_parts.headers {
-                if let Ok(name) = HeaderName::from_bytes(key.as_bytes()) {
-                    builder = builder.header(name, value);
+            let transpiled = match transpile(&code) {
+                Ok(transpiled) => Arc::new(transpiled),
+                Err(error) => {
+                    let _ = res_tx.send(Err(error));
+                    return;
                 }
-            }
+            };
+            let reason = TerminationReason::default();
+            let module_loader = UserModuleLoader::new(transpiled, None);
+            let mut runtime = new_runtime(limits, reason.clone(), module_loader);
+            let watchdog = Watchdog::spawn(&mut runtime, limits, reason.clone());
 
-            let Some(rid) = response_parts.rid else {
-                eprintln!("[ski/lib.rs] No RID, returning empty body");
-                let body =
-                    BodyExt::boxed_unsync(Empty::<Bytes>::new().map_err(|never| match never {}));
-                return Ok(builder.body(body)?);
+            let result = match load_user_module(&mut runtime).await {
+                Ok(module) => {
+                    run_handler(&mut runtime, module, request, &env, limits.cpu_time).await
+                }
+                Err(error) => Err(error),
             };
+            drop(watchdog);
 
-            eprintln!("[ski/lib.rs] Getting resource from table, RID: {}", rid);
+            // a terminated isolate surfaces as a generic JS error, so the reason takes precedence
+            let result = match reason.get() {
+                Some(termination) => Err(termination.into()),
+                None => result,
+            };
+            let responded = result.is_ok();
+            let _ = res_tx.send(result);
 
-            // Get the resource that was created by resourceForReadableStream() or is Deno-backed
-            let resource = op_state
-                .borrow_mut()
-                .resource_table
-                .get_any(rid)
-                .map_err(|_| anyhow!("Resource not found"))?;
+            if responded && let Err(error) = run_wait_until(&mut runtime, limits, reason).await {
+                eprintln!("[ski/lib.rs] waitUntil failed: {error:#}");
+            }
+        })
+    });
 
-            eprintln!("[ski/lib.rs] Creating body from resource using ResourceToBodyAdapter...");
+    res_rx
+        .await
+        .map_err(|_| anyhow!("ski runtime thread exited before responding"))?
+}
 
-            // Use Deno's ResourceToBodyAdapter to convert Resource to Hyper Body
-            let body_adapter = deno_fetch::ResourceToBodyAdapter::new(resource);
-            let body = BodyExt::boxed_unsync(body_adapter.map_err(|e| anyhow::anyhow!(e)));
+fn new_runtime(
+    limits: Limits,
+    reason: TerminationReason,
+    module_loader: UserModuleLoader,
+) -> JsRuntime {
+    let mut runtime_options = runtime_options(limits.allow_private_network);
+    runtime_options.startup_snapshot = Some(RUNTIME_SNAPSHOT);
+    runtime_options.create_params =
+        Some(v8::CreateParams::default().heap_limits(0, limits.max_heap_size));
+    runtime_options.module_loader = Some(Rc::new(module_loader));
 
-            eprintln!("[ski/lib.rs] Body created, building response...");
-            Ok(builder.body(body)?)
-        })
-    })
-    .await?
+    let mut runtime = JsRuntime::new(runtime_options);
+    install_near_heap_limit_callback(&mut runtime, reason);
+    runtime
+}
+
+/// Loads and evaluates the user code as an ES module, returning its namespace.
+async fn load_user_module(runtime: &mut JsRuntime) -> Result<v8::Global<v8::Object>> {
+    let specifier = ModuleSpecifier::parse(USER_MODULE_SPECIFIER)?;
+    let module_id = runtime.load_main_es_module(&specifier).await?;
+    let evaluation = runtime.mod_evaluate(module_id).boxed_local();
+    runtime
+        .with_event_loop_future(evaluation, Default::default())
+        .await?;
+    Ok(runtime.get_module_namespace(module_id)?)
 }
 
-fn register_hyper_request(runtime: &mut JsRuntime, req: Request) {
+/// Runs the handler exported by `module`, the namespace of the loaded user code.
+async fn run_handler(
+    runtime: &mut JsRuntime,
+    module: v8::Global<v8::Object>,
+    request: Request,
+    env: &Env,
+    cpu_time: Duration,
+) -> Result<Response> {
+    register_hyper_request(runtime, request, env);
+
+    eprintln!("[ski/lib.rs] Executing __ski_runHandler()...");
+    let run_handler = runtime.execute_script("[run]", ascii_str!("globalThis.__ski_runHandler"))?;
+    let (run_handler, module) = {
+        deno_core::scope!(scope, runtime);
+        let run_handler = v8::Local::new(scope, run_handler);
+        let run_handler = v8::Local::<v8::Function>::try_from(run_handler)
+            .map_err(|_| anyhow!("__ski_runHandler is not a function"))?;
+        let module: v8::Local<v8::Value> = v8::Local::new(scope, module).into();
+        (
+            v8::Global::new(scope, run_handler),
+            v8::Global::new(scope, module),
+        )
+    };
+    let run_future = runtime.call_with_args(&run_handler, &[module]);
+    eprintln!("[ski/lib.rs] Awaiting run_future with event loop...");
+    // the runtime lives on this thread alone, so its CPU clock is the handler's
+    let time_tracker = TimeTracker::new(ThreadCpuClock);
+    with_cpu_budget(
+        time_tracker.clone(),
+        cpu_time,
+        measure_cpu_time(
+            time_tracker,
+            runtime.with_event_loop_promise(run_future, Default::default()),
+        ),
+    )
+    .await
+    .map_err(|_exceeded| Termination::CpuTimeExceeded)??;
+    eprintln!("[ski/lib.rs] Handler completed");
+
+    eprintln!("[ski/lib.rs] Getting op_state...");
+    let op_state = runtime.op_state();
+
+    eprintln!("[ski/lib.rs] Extracting ResponseParts...");
+    let response_parts = op_state
+        .borrow_mut()
+        .try_take::<ResponseParts>()
+        .ok_or_else(|| anyhow!("Did not get a response from JavaScript"))?;
+
+    eprintln!(
+        "[ski/lib.rs] Got ResponseParts: status={}, rid={:?}",
+        response_parts.status, response_parts.rid
+    );
+
+    let mut builder =
+        hyper::Response::builder().status(StatusCode::from_u16(response_parts.status)?);
+
+    for (key, value) in response_parts.headers {
+        if let Ok(name) = HeaderName::from_bytes(key.as_bytes()) {
+            builder = builder.header(name, value);
+        }
+    }
+
+    let Some(rid) = response_parts.rid else {
+        eprintln!("[ski/lib.rs] No RID, returning empty body");
+        let body = BodyExt::boxed_unsync(Empty::<Bytes>::new().map_err(|never| match never {}));
+        return Ok(builder.body(body)?);
+    };
+
+    eprintln!("[ski/lib.rs] Getting resource from table, RID: {}", rid);
+
+    // Get the resource that was created by resourceForReadableStream() or is Deno-backed
+    let resource = op_state
+        .borrow_mut()
+        .resource_table
+        .get_any(rid)
+        .map_err(|_| anyhow!("Resource not found"))?;
+
+    eprintln!("[ski/lib.rs] Creating body from resource using ResourceToBodyAdapter...");
+
+    // Use Deno's ResourceToBodyAdapter to convert Resource to Hyper Body
+    let body_adapter = deno_fetch::ResourceToBodyAdapter::new(resource);
+    let body = BodyExt::boxed_unsync(body_adapter.map_err(|e| anyhow::anyhow!(e)));
+
+    eprintln!("[ski/lib.rs] Body created, building response...");
+    Ok(builder.body(body)?)
+}
+
+/// Settles the promises passed to `ctx.waitUntil` under the `wait_until_*` limits.
+/// Fails with a [`Termination`] when the work crosses one of them.
+async fn run_wait_until(
+    runtime: &mut JsRuntime,
+    limits: Limits,
+    reason: TerminationReason,
+) -> Result<()> {
+    let limits = limits.wait_until();
+    let watchdog = Watchdog::spawn(runtime, limits, reason.clone());
+    let result = settle_wait_until(runtime, limits.cpu_time).await;
+    drop(watchdog);
+
+    match reason.get() {
+        Some(termination) => Err(termination.into()),
+        None => result,
+    }
+}
+
+async fn settle_wait_until(runtime: &mut JsRuntime, cpu_time: Duration) -> Result<()> {
+    let settled = runtime.execute_script(
+        "[wait_until]",
+        ascii_str!("globalThis.__ski_runWaitUntil();"),
+    )?;
+    let settled = runtime.resolve(settled);
+    let time_tracker = TimeTracker::new(ThreadCpuClock);
+    with_cpu_budget(
+        time_tracker.clone(),
+        cpu_time,
+        measure_cpu_time(
+            time_tracker,
+            runtime.with_event_loop_future(settled, Default::default()),
+        ),
+    )
+    .await
+    .map_err(|_exceeded| Termination::CpuTimeExceeded)??;
+    Ok(())
+}
+
+fn register_hyper_request(runtime: &mut JsRuntime, req: Request, env: &Env) {
     let op_state = runtime.op_state();
     let mut state = op_state.borrow_mut();
 
@@ -123,17 +279,277 @@ fn register_hyper_request(runtime: &mut JsRuntime, req: Request) {
         method,
         headers,
         rid,
+        env: env
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
     });
 }
 
 #[tokio::test]
 async fn test() {
     run(
-        "new MessageChannel();",
+        "new MessageChannel(); export default () => new Response();",
+        Request::new(UnsyncBoxBody::new(
+            http_body_util::Empty::new().map_err(|never| match never {}),
+        )),
+        Default::default(),
+        Limits::default(),
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_infinite_loop_is_terminated() {
+    let result = run(
+        "while (true) {}",
+        Request::new(UnsyncBoxBody::new(
+            http_body_util::Empty::new().map_err(|never| match never {}),
+        )),
+        Default::default(),
+        Limits {
+            cpu_time: Duration::from_millis(100),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let termination = result.unwrap_err().downcast::<Termination>().unwrap();
+    assert_eq!(termination, Termination::CpuTimeExceeded);
+}
+
+#[tokio::test]
+async fn test_heap_limit_is_terminated() {
+    let result = run(
+        "const chunks = []; while (true) { chunks.push(new Array(1024 * 1024).fill(0)); }",
+        Request::new(UnsyncBoxBody::new(
+            http_body_util::Empty::new().map_err(|never| match never {}),
+        )),
+        Default::default(),
+        Limits {
+            max_heap_size: 32 * 1024 * 1024,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let termination = result.unwrap_err().downcast::<Termination>().unwrap();
+    assert_eq!(termination, Termination::HeapLimitExceeded);
+}
+
+#[cfg(test)]
+async fn run_to_text(code: &str) -> Result<String> {
+    run_to_text_with_limits(code, Limits::default()).await
+}
+
+#[cfg(test)]
+async fn run_to_text_with_limits(code: &str, limits: Limits) -> Result<String> {
+    let response = run(
+        code,
+        Request::new(UnsyncBoxBody::new(
+            http_body_util::Empty::new().map_err(|never| match never {}),
+        )),
+        Default::default(),
+        limits,
+    )
+    .await?;
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok(String::from_utf8(body.to_vec())?)
+}
+
+#[tokio::test]
+async fn test_default_export_function() {
+    let text = run_to_text("export default async (request) => new Response(request.method);")
+        .await
+        .unwrap();
+    assert_eq!(text, "GET");
+}
+
+#[tokio::test]
+async fn test_default_export_fetch_object() {
+    let text = run_to_text(
+        r#"
+        export default {
+            greeting: "hello",
+            async fetch(request, env, ctx) {
+                return new Response(`${this.greeting} ${typeof env} ${typeof ctx}`);
+            },
+        };
+        "#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(text, "hello object object");
+}
+
+#[tokio::test]
+async fn test_missing_default_export() {
+    let error = run_to_text("export const handler = () => new Response();")
+        .await
+        .unwrap_err();
+    assert!(
+        format!("{error:#}").contains("no default export"),
+        "got {error:#}"
+    );
+}
+
+#[tokio::test]
+async fn test_default_export_without_fetch() {
+    let error = run_to_text("export default { get: () => new Response() };")
+        .await
+        .unwrap_err();
+    assert!(
+        format!("{error:#}").contains("no `fetch` method"),
+        "got {error:#}"
+    );
+}
+
+#[tokio::test]
+async fn test_import_is_rejected() {
+    let error = run_to_text("import \"npm:hono\"; export default () => new Response();")
+        .await
+        .unwrap_err();
+    assert!(
+        format!("{error:#}").contains("bundle its dependencies"),
+        "got {error:#}"
+    );
+}
+
+#[tokio::test]
+async fn test_typescript_module() {
+    let text = run_to_text(
+        r#"
+        interface Greeting {
+            text: string;
+        }
+        const greeting: Greeting = { text: "typed" };
+        export default (request: Request): Response => new Response(greeting.text as string);
+        "#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(text, "typed");
+}
+
+#[tokio::test]
+async fn test_stack_trace_points_at_typescript_line() {
+    let error = run_to_text(
+        "interface Greeting {\n  text: string;\n}\n\ntype Unused = number;\n\nthrow new Error(\"boom\");\n",
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        format!("{error:#}").contains("user-code.tsx:7:"),
+        "got {error:#}"
+    );
+}
+
+#[tokio::test]
+async fn test_wait_until_runs_after_response() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let code = format!(
+        r#"
+        export default {{
+            fetch(request, env, ctx) {{
+                ctx.waitUntil(
+                    new Promise((resolve) => setTimeout(resolve, 100))
+                        .then(() => fetch("http://{address}/log")),
+                );
+                return new Response("responded");
+            }},
+        }};
+        "#
+    );
+
+    let limits = Limits {
+        allow_private_network: true,
+        ..Default::default()
+    };
+    let text = run_to_text_with_limits(&code, limits).await.unwrap();
+    assert_eq!(text, "responded");
+
+    let (mut stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .expect("waitUntil work did not run")
+        .unwrap();
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await.unwrap();
+    assert!(String::from_utf8_lossy(&buffer[..read]).starts_with("GET /log"));
+    stream
+        .write_all(b"HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_host_network_is_denied() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let code = format!(
+        r#"
+        export default async () => {{
+            const results = [];
+            for (const url of ["http://127.0.0.1:{port}/", "http://localhost:{port}/", "http://169.254.169.254/"]) {{
+                try {{
+                    await fetch(url);
+                    results.push("reached");
+                }} catch (error) {{
+                    results.push(error.name);
+                }}
+            }}
+            return new Response(results.join(","));
+        }};
+        "#
+    );
+
+    let text = run_to_text(&code).await.unwrap();
+    assert!(!text.contains("reached"), "{text}");
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), listener.accept())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_pending_wait_until_does_not_delay_response() {
+    let text = tokio::time::timeout(
+        Duration::from_secs(5),
+        run_to_text(
+            r#"
+            export default (request, env, ctx) => {
+                ctx.waitUntil(new Promise(() => {}));
+                return new Response("responded");
+            };
+            "#,
+        ),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(text, "responded");
+}
+
+#[tokio::test]
+async fn test_env_is_second_argument() {
+    let env = Arc::new(BTreeMap::from([(
+        "GREETING".to_string(),
+        "hello from env".to_string(),
+    )]));
+    let response = run(
+        "export default (request, env) => new Response(env.GREETING);",
         Request::new(UnsyncBoxBody::new(
             http_body_util::Empty::new().map_err(|never| match never {}),
         )),
+        env,
+        Limits::default(),
     )
     .await
     .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "hello from env");
 }
@@ -0,0 +1,198 @@
+use deno_core::JsRuntime;
+use deno_core::v8::IsolateHandle;
+use measure_cpu_time::ThreadCpuHandle;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, LazyLock, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Per-invocation limits of a JS handler.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// CPU time of the thread running the isolate, including synchronous script execution.
+    pub cpu_time: Duration,
+    /// Wall time of the whole invocation, including time spent waiting on I/O.
+    pub wall_time: Duration,
+    /// Upper bound of the V8 heap in bytes.
+    pub max_heap_size: usize,
+    /// CPU time of the work passed to `ctx.waitUntil`, counted after the response is out.
+    pub wait_until_cpu_time: Duration,
+    /// Wall time of the work passed to `ctx.waitUntil`, counted after the response is out.
+    pub wait_until_wall_time: Duration,
+    /// Lets `fetch` and `WebSocket` reach loopback, private and link-local addresses,
+    /// which are denied to keep user code off the host's network. For development
+    /// and tests.
+    pub allow_private_network: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            cpu_time: Duration::from_millis(1000),
+            wall_time: Duration::from_secs(30),
+            max_heap_size: 128 * 1024 * 1024,
+            wait_until_cpu_time: Duration::from_millis(500),
+            wait_until_wall_time: Duration::from_secs(10),
+            allow_private_network: false,
+        }
+    }
+}
+
+impl Limits {
+    /// The limits a [`Watchdog`] enforces while `ctx.waitUntil` work settles.
+    pub(crate) fn wait_until(self) -> Self {
+        Self {
+            cpu_time: self.wait_until_cpu_time,
+            wall_time: self.wait_until_wall_time,
+            ..self
+        }
+    }
+}
+
+/// Why a handler was terminated before it finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum Termination {
+    #[error("cpu time limit exceeded")]
+    CpuTimeExceeded,
+    #[error("wall time limit exceeded")]
+    WallTimeExceeded,
+    #[error("heap limit exceeded")]
+    HeapLimitExceeded,
+}
+
+/// The first reason wins; later ones are consequences of the same termination.
+#[derive(Clone, Default)]
+pub(crate) struct TerminationReason(Arc<OnceLock<Termination>>);
+
+impl TerminationReason {
+    pub(crate) fn get(&self) -> Option<Termination> {
+        self.0.get().copied()
+    }
+
+    fn terminate(&self, isolate: &IsolateHandle, termination: Termination) {
+        let _ = self.0.set(termination);
+        isolate.terminate_execution();
+    }
+}
+
+/// Terminates the isolate when the heap is about to run out. V8 aborts the process
+/// if the limit is actually hit, so the limit is raised to let termination unwind.
+pub(crate) fn install_near_heap_limit_callback(runtime: &mut JsRuntime, reason: TerminationReason) {
+    let isolate = runtime.v8_isolate().thread_safe_handle();
+    runtime.add_near_heap_limit_callback(move |current_limit, _initial_limit| {
+        reason.terminate(&isolate, Termination::HeapLimitExceeded);
+        current_limit * 2
+    });
+}
+
+/// Watches the thread that created it and terminates the isolate once it crosses
+/// the CPU or wall time limit. Stops when dropped.
+///
+/// Every watchdog is checked by one shared thread, so isolates don't each cost an
+/// OS thread.
+pub(crate) struct Watchdog {
+    id: u64,
+}
+
+struct Watch {
+    isolate: IsolateHandle,
+    cpu: ThreadCpuHandle,
+    cpu_start: Duration,
+    wall_start: Instant,
+    limits: Limits,
+    reason: TerminationReason,
+}
+
+impl Watch {
+    fn exceeded(&self) -> Option<Termination> {
+        if self.cpu.elapsed().saturating_sub(self.cpu_start) > self.limits.cpu_time {
+            return Some(Termination::CpuTimeExceeded);
+        }
+        if self.wall_start.elapsed() > self.limits.wall_time {
+            return Some(Termination::WallTimeExceeded);
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+struct Watches {
+    next_id: u64,
+    active: HashMap<u64, Watch>,
+}
+
+struct WatchdogThread {
+    watches: Mutex<Watches>,
+    wake: Condvar,
+}
+
+/// Started on first use.
+static WATCHDOG_THREAD: LazyLock<WatchdogThread> = LazyLock::new(|| {
+    std::thread::Builder::new()
+        .name("ski-watchdog".to_string())
+        .spawn(|| WATCHDOG_THREAD.run())
+        .expect("failed to spawn the watchdog thread");
+    WatchdogThread {
+        watches: Default::default(),
+        wake: Condvar::new(),
+    }
+});
+
+impl WatchdogThread {
+    fn run(&self) {
+        loop {
+            {
+                let mut watches = self.watches.lock().unwrap();
+                while watches.active.is_empty() {
+                    watches = self.wake.wait(watches).unwrap();
+                }
+                // checked under the lock, so a dropped watchdog's thread is never read
+                watches.active.retain(|_, watch| match watch.exceeded() {
+                    Some(termination) => {
+                        watch.reason.terminate(&watch.isolate, termination);
+                        false
+                    }
+                    None => true,
+                });
+            }
+            std::thread::sleep(WATCHDOG_INTERVAL);
+        }
+    }
+}
+
+impl Watchdog {
+    pub(crate) fn spawn(
+        runtime: &mut JsRuntime,
+        limits: Limits,
+        reason: TerminationReason,
+    ) -> Self {
+        let cpu = ThreadCpuHandle::current();
+        let watch = Watch {
+            isolate: runtime.v8_isolate().thread_safe_handle(),
+            cpu_start: cpu.elapsed(),
+            cpu,
+            wall_start: Instant::now(),
+            limits,
+            reason,
+        };
+
+        let mut watches = WATCHDOG_THREAD.watches.lock().unwrap();
+        let id = watches.next_id;
+        watches.next_id += 1;
+        watches.active.insert(id, watch);
+        WATCHDOG_THREAD.wake.notify_one();
+        Self { id }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        WATCHDOG_THREAD
+            .watches
+            .lock()
+            .unwrap()
+            .active
+            .remove(&self.id);
+    }
+}
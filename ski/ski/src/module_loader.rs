@@ -0,0 +1,86 @@
+use crate::code_cache::CodeCache;
+use crate::transpile::Transpiled;
+use deno_core::error::ModuleLoaderError;
+use deno_core::futures::FutureExt;
+use deno_core::futures::future::LocalBoxFuture;
+use deno_core::url::Url;
+use deno_core::{
+    ModuleLoadOptions, ModuleLoadReferrer, ModuleLoadResponse, ModuleLoader, ModuleSource,
+    ModuleSourceCode, ModuleSpecifier, ModuleType, ResolutionKind,
+};
+use deno_error::JsErrorBox;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+pub(crate) const USER_MODULE_SPECIFIER: &str = "file:///user-code.tsx";
+
+/// Serves the user code as the only module of the isolate.
+///
+/// Dependencies have to be bundled into it, so any other import fails to resolve.
+pub(crate) struct UserModuleLoader {
+    transpiled: Arc<Transpiled>,
+    code_cache: Option<CodeCache>,
+}
+
+impl UserModuleLoader {
+    pub(crate) fn new(transpiled: Arc<Transpiled>, code_cache: Option<CodeCache>) -> Self {
+        Self {
+            transpiled,
+            code_cache,
+        }
+    }
+}
+
+impl ModuleLoader for UserModuleLoader {
+    fn resolve(
+        &self,
+        specifier: &str,
+        referrer: &str,
+        _kind: ResolutionKind,
+    ) -> Result<ModuleSpecifier, ModuleLoaderError> {
+        if specifier == USER_MODULE_SPECIFIER {
+            return ModuleSpecifier::parse(specifier).map_err(JsErrorBox::from_err);
+        }
+        Err(JsErrorBox::type_error(format!(
+            "Cannot import \"{specifier}\" from \"{referrer}\": \
+            user code runs as a single module, so bundle its dependencies into it"
+        )))
+    }
+
+    fn load(
+        &self,
+        module_specifier: &ModuleSpecifier,
+        _maybe_referrer: Option<&ModuleLoadReferrer>,
+        _options: ModuleLoadOptions,
+    ) -> ModuleLoadResponse {
+        ModuleLoadResponse::Sync(Ok(ModuleSource::new(
+            ModuleType::JavaScript,
+            ModuleSourceCode::String(self.transpiled.code.to_string().into()),
+            module_specifier,
+            self.code_cache
+                .as_ref()
+                .map(|code_cache| code_cache.source_info(&self.transpiled.source_digest)),
+        )))
+    }
+
+    fn code_cache_ready(
+        &self,
+        _module_specifier: Url,
+        _hash: u64,
+        code_cache: &[u8],
+    ) -> LocalBoxFuture<'static, ()> {
+        if let Some(cache) = &self.code_cache {
+            cache.insert(self.transpiled.source_digest, code_cache.into());
+        }
+        std::future::ready(()).boxed_local()
+    }
+
+    // deno_core maps the stack traces of `JsError`s through this
+    fn get_source_map(&self, file_name: &str) -> Option<Cow<'_, [u8]>> {
+        if file_name != USER_MODULE_SPECIFIER {
+            return None;
+        }
+        let source_map = self.transpiled.source_map.as_ref()?;
+        Some(Cow::Borrowed(source_map))
+    }
+}
@@ -0,0 +1,126 @@
+use deno_fetch::dns::{Resolve, Resolving};
+use deno_permissions::NetDescriptor;
+use hyper_util::client::legacy::connect::dns::Name;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Ranges of the host's own network, which `fetch` and `WebSocket` may not reach:
+/// loopback, private, shared and link-local addresses, like the cloud metadata
+/// service at 169.254.169.254.
+///
+/// The net permission denies them as literal hosts, and [`PublicResolver`] drops
+/// them from resolved names, so a name pointing at one doesn't get around it.
+const DENIED_NETS: &[&str] = &[
+    "localhost",
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "::/128",
+    "::1/128",
+    "::ffff:0:0/96",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+pub(crate) fn denied_nets() -> Vec<NetDescriptor> {
+    DENIED_NETS
+        .iter()
+        .map(|net| NetDescriptor::parse_for_list(net).expect("denied nets are valid"))
+        .collect()
+}
+
+/// Whether `ip` is in one of [`DENIED_NETS`].
+fn is_denied(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_denied_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_denied_v4(ip),
+            None => is_denied_v6(ip),
+        },
+    }
+}
+
+fn is_denied_v4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    first == 0
+        || ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || (first == 100 && second & 0xc0 == 64)
+}
+
+fn is_denied_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_unspecified() || ip.is_loopback() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+}
+
+/// Resolves names for `fetch` and `WebSocket` like the system resolver, but
+/// without the addresses of [`DENIED_NETS`].
+#[derive(Debug)]
+pub(crate) struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| !is_denied(addr.ip()))
+                .collect::<Vec<_>>();
+            if addrs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} resolves to no public address", name.as_str()),
+                ));
+            }
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_denied_nets_match_is_denied() {
+        assert_eq!(denied_nets().len(), DENIED_NETS.len());
+        for net in &DENIED_NETS[1..] {
+            let (address, _) = net.split_once('/').unwrap();
+            assert!(is_denied(address.parse().unwrap()), "{net}");
+        }
+    }
+
+    #[test]
+    fn test_is_denied() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.31.255.255",
+            "192.168.0.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "::ffff:127.0.0.1",
+            "fd00::1",
+            "fe80::1",
+        ] {
+            assert!(is_denied(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["1.1.1.1", "172.32.0.1", "100.128.0.1", "2606:4700::1111"] {
+            assert!(!is_denied(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolver_drops_denied_addresses() {
+        let name = "localhost".parse::<Name>().unwrap();
+        let error = PublicResolver.resolve(name).await.unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -0,0 +1,669 @@
+use crate::code_cache::{CodeCache, SourceDigest, source_digest};
+use crate::limits::{Limits, TerminationReason, Watchdog};
+use crate::module_loader::UserModuleLoader;
+use crate::storage::{CodeStorage, StorageConfig};
+use crate::transpile::TranspileCache;
+use crate::{Env, Request, Response, load_user_module, new_runtime, run_handler, run_wait_until};
+use deno_cache::{CacheBudget, MemoryCache};
+use deno_core::anyhow::{Error, Result, anyhow};
+use deno_core::{JsRuntime, v8};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc, oneshot};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolConfig {
+    /// Warm isolates waiting for a request per code_id, each on its own thread.
+    pub isolates_per_code: usize,
+    /// Isolates running beyond `isolates_per_code` of their code_id, across all
+    /// code_ids. Requests of a code_id with none warm wait for one beyond this.
+    pub max_extra_isolates: usize,
+    /// Workers of the least recently used code_id stop beyond this.
+    pub max_codes: usize,
+    pub limits: Limits,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            isolates_per_code: 2,
+            max_extra_isolates: 512,
+            max_codes: 256,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// Runs JS handlers on pre-warmed isolates instead of building one per request.
+///
+/// Each worker loads the user code into a fresh isolate from the snapshot while it is
+/// idle, so a request only pays for the handler itself. An isolate serves a single
+/// request and is then thrown away, which resets all JS state between requests.
+/// A worker hands its place to a fresh one as soon as it takes a request, so a slow
+/// handler or its `ctx.waitUntil` work doesn't hold up the requests behind it.
+/// Transpiled and compiled user code is shared between isolates through a
+/// [`TranspileCache`] and a [`CodeCache`].
+///
+/// `caches` and `Deno.openKv` persist across requests, per code_id, as set by
+/// [`IsolatePool::with_storage`].
+#[derive(Clone)]
+pub struct IsolatePool {
+    config: PoolConfig,
+    wait_until_reporter: WaitUntilReporter,
+    storage: StorageConfig,
+    /// Shared by the `caches` of all code_ids.
+    cache_budget: CacheBudget,
+    /// Permits for isolates beyond `isolates_per_code`, shared by all code_ids.
+    extra_isolates: Arc<Semaphore>,
+    codes: Arc<Mutex<HashMap<String, CodeWorkers>>>,
+    caches: Caches,
+}
+
+/// Called with the code_id and the error when the `ctx.waitUntil` work of a request
+/// fails, after its response is out. The error is a [`Termination`] when the work
+/// crossed one of the `wait_until_*` limits.
+///
+/// [`Termination`]: crate::Termination
+pub type WaitUntilReporter = Arc<dyn Fn(&str, &Error) + Send + Sync>;
+
+#[derive(Clone, Default)]
+struct Caches {
+    transpile: TranspileCache,
+    code: CodeCache,
+}
+
+struct CodeWorkers {
+    source_digest: SourceDigest,
+    job_tx: mpsc::Sender<Job>,
+    last_used: Instant,
+    /// Outlives redeploys of the code_id, but not its eviction.
+    cache: MemoryCache,
+}
+
+/// Shared by the workers of one deploy of a code_id. The last one to stop drops the
+/// transpiled and compiled source from the shared caches, unless another code_id still runs it, so
+/// the caches don't keep every version ever deployed.
+///
+/// Dropped only after the workers stopped, as a worker still warming an isolate
+/// would put the source back.
+struct SourceGuard {
+    source_digest: SourceDigest,
+    codes: Arc<Mutex<HashMap<String, CodeWorkers>>>,
+    caches: Caches,
+}
+
+impl Drop for SourceGuard {
+    fn drop(&mut self) {
+        let in_use = self
+            .codes
+            .lock()
+            .unwrap()
+            .values()
+            .any(|workers| workers.source_digest == self.source_digest);
+        if !in_use {
+            self.caches.transpile.remove_digest(&self.source_digest);
+            self.caches.code.remove_digest(&self.source_digest);
+        }
+    }
+}
+
+struct Job {
+    request: Request,
+    env: Env,
+    res_tx: oneshot::Sender<Result<Response>>,
+}
+
+impl IsolatePool {
+    pub fn new(config: PoolConfig) -> Self {
+        let storage = StorageConfig::default();
+        Self {
+            config,
+            wait_until_reporter: Arc::new(|code_id, error| {
+                eprintln!("[ski/pool.rs] waitUntil of {code_id} failed: {error:#}");
+            }),
+            cache_budget: CacheBudget::new(storage.pool_cache_max_bytes),
+            extra_isolates: Arc::new(Semaphore::new(config.max_extra_isolates)),
+            storage,
+            codes: Default::default(),
+            caches: Default::default(),
+        }
+    }
+
+    pub fn with_storage(mut self, storage: StorageConfig) -> Self {
+        self.cache_budget = CacheBudget::new(storage.pool_cache_max_bytes);
+        self.storage = storage;
+        self
+    }
+
+    /// Where failures of `ctx.waitUntil` work go. By default they are logged to stderr.
+    pub fn with_wait_until_reporter(
+        mut self,
+        reporter: impl Fn(&str, &Error) + Send + Sync + 'static,
+    ) -> Self {
+        self.wait_until_reporter = Arc::new(reporter);
+        self
+    }
+
+    pub async fn run(
+        &self,
+        code_id: &str,
+        code: &str,
+        request: Request,
+        env: Env,
+    ) -> Result<Response> {
+        let job_tx = self.job_tx(code_id, code);
+        let (res_tx, res_rx) = oneshot::channel();
+
+        job_tx
+            .send(Job {
+                request,
+                env,
+                res_tx,
+            })
+            .await
+            .map_err(|_| anyhow!("isolate workers of {code_id} stopped"))?;
+        res_rx
+            .await
+            .map_err(|_| anyhow!("isolate worker of {code_id} dropped the request"))?
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.config.limits
+    }
+
+    /// Stops the workers of `code_id`. In-flight requests still complete.
+    pub fn remove(&self, code_id: &str) {
+        self.codes.lock().unwrap().remove(code_id);
+    }
+
+    fn job_tx(&self, code_id: &str, code: &str) -> mpsc::Sender<Job> {
+        let source_digest = source_digest(code);
+        let mut codes = self.codes.lock().unwrap();
+
+        if let Some(workers) = codes.get_mut(code_id)
+            && workers.source_digest == source_digest
+        {
+            workers.last_used = Instant::now();
+            return workers.job_tx.clone();
+        }
+
+        if !codes.contains_key(code_id)
+            && codes.len() >= self.config.max_codes
+            && let Some(least_recently_used) = codes
+                .iter()
+                .min_by_key(|(_, workers)| workers.last_used)
+                .map(|(code_id, _)| code_id.clone())
+        {
+            codes.remove(&least_recently_used);
+        }
+
+        let cache = codes
+            .get(code_id)
+            .map(|workers| workers.cache.clone())
+            .unwrap_or_else(|| {
+                MemoryCache::with_budget(self.storage.cache_max_bytes, self.cache_budget.clone())
+            });
+        let storage = CodeStorage::new(code_id, cache.clone(), self.storage.kv.clone());
+
+        // dropping the old sender of a redeployed code_id stops its workers
+        let job_tx = self.spawn_workers(code_id, code, source_digest, storage);
+        codes.insert(
+            code_id.to_string(),
+            CodeWorkers {
+                source_digest,
+                job_tx: job_tx.clone(),
+                last_used: Instant::now(),
+                cache,
+            },
+        );
+        job_tx
+    }
+
+    fn spawn_workers(
+        &self,
+        code_id: &str,
+        code: &str,
+        source_digest: SourceDigest,
+        storage: CodeStorage,
+    ) -> mpsc::Sender<Job> {
+        let (job_tx, job_rx) = mpsc::channel(self.config.isolates_per_code.max(1) * 16);
+        let workers = Arc::new(Workers {
+            code_id: code_id.to_string(),
+            code: code.to_string(),
+            caches: self.caches.clone(),
+            storage,
+            limits: self.config.limits,
+            isolates_per_code: self.config.isolates_per_code.max(1),
+            extra_isolates: self.extra_isolates.clone(),
+            reporter: self.wait_until_reporter.clone(),
+            job_rx: tokio::sync::Mutex::new(job_rx),
+            job_tx: job_tx.downgrade(),
+            counts: Default::default(),
+            _source_guard: SourceGuard {
+                source_digest,
+                codes: self.codes.clone(),
+                caches: self.caches.clone(),
+            },
+        });
+        workers.refill();
+
+        job_tx
+    }
+}
+
+/// The workers of one deploy of a code_id, each a thread running a single isolate.
+///
+/// A worker warms its isolate, takes one job and spawns its replacement right away,
+/// then responds and settles the job's `ctx.waitUntil` work before it stops.
+struct Workers {
+    code_id: String,
+    code: String,
+    caches: Caches,
+    storage: CodeStorage,
+    limits: Limits,
+    isolates_per_code: usize,
+    extra_isolates: Arc<Semaphore>,
+    reporter: WaitUntilReporter,
+    job_rx: tokio::sync::Mutex<mpsc::Receiver<Job>>,
+    /// Gone once the code_id is redeployed, removed or evicted, which stops the workers.
+    job_tx: mpsc::WeakSender<Job>,
+    counts: Mutex<WorkerCounts>,
+    _source_guard: SourceGuard,
+}
+
+#[derive(Default)]
+struct WorkerCounts {
+    /// Warming up or waiting for a job.
+    warm: usize,
+    /// Running, warm or not.
+    live: usize,
+    spawned: usize,
+}
+
+impl Workers {
+    /// Spawns workers until `isolates_per_code` of them are warm. Beyond
+    /// `isolates_per_code` running ones, each takes a permit of `extra_isolates`.
+    fn refill(self: &Arc<Self>) {
+        loop {
+            if self.job_tx.upgrade().is_none() {
+                return;
+            }
+
+            let mut counts = self.counts.lock().unwrap();
+            if counts.warm >= self.isolates_per_code {
+                return;
+            }
+            let permit = if counts.live < self.isolates_per_code {
+                None
+            } else {
+                match self.extra_isolates.clone().try_acquire_owned() {
+                    Ok(permit) => Some(permit),
+                    // an exiting worker refills again
+                    Err(_) => return,
+                }
+            };
+            counts.warm += 1;
+            counts.live += 1;
+            counts.spawned += 1;
+            let index = counts.spawned;
+            drop(counts);
+
+            self.spawn(index, permit);
+        }
+    }
+
+    fn spawn(self: &Arc<Self>, index: usize, permit: Option<OwnedSemaphorePermit>) {
+        let workers = self.clone();
+        std::thread::Builder::new()
+            .name(format!("ski-{}-{index}", self.code_id))
+            .spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                rt.block_on(workers.work());
+
+                drop(permit);
+                workers.counts.lock().unwrap().live -= 1;
+                workers.refill();
+            })
+            .expect("failed to spawn isolate worker thread");
+    }
+
+    async fn work(self: &Arc<Self>) {
+        let warm = WarmIsolate::new(&self.code, &self.caches, &self.storage, self.limits).await;
+        let job = self.job_rx.lock().await.recv().await;
+        self.counts.lock().unwrap().warm -= 1;
+        let Some(job) = job else {
+            return;
+        };
+        self.refill();
+
+        match warm {
+            Ok(warm) => {
+                let report = |error: &Error| (self.reporter)(&self.code_id, error);
+                warm.run(job, self.limits, &report).await;
+            }
+            Err(error) => {
+                let _ = job.res_tx.send(Err(error));
+            }
+        }
+    }
+}
+
+/// An isolate with the user code loaded, waiting for its single request.
+struct WarmIsolate {
+    runtime: JsRuntime,
+    module: v8::Global<v8::Object>,
+    reason: TerminationReason,
+}
+
+impl WarmIsolate {
+    async fn new(
+        code: &str,
+        caches: &Caches,
+        storage: &CodeStorage,
+        limits: Limits,
+    ) -> Result<Self> {
+        let transpiled = caches.transpile.get_or_transpile(code)?;
+        let reason = TerminationReason::default();
+        let module_loader = UserModuleLoader::new(transpiled, Some(caches.code.clone()));
+        let mut runtime = new_runtime(limits, reason.clone(), module_loader);
+        storage.install(&mut runtime.op_state().borrow_mut());
+
+        let watchdog = Watchdog::spawn(&mut runtime, limits, reason.clone());
+        let result = load_user_module(&mut runtime).await;
+        drop(watchdog);
+
+        if let Some(termination) = reason.get() {
+            return Err(termination.into());
+        }
+        let module = result?;
+
+        Ok(Self {
+            runtime,
+            module,
+            reason,
+        })
+    }
+
+    /// Responds to `job`, then settles its `ctx.waitUntil` work.
+    async fn run(mut self, job: Job, limits: Limits, report_wait_until: &dyn Fn(&Error)) {
+        let watchdog = Watchdog::spawn(&mut self.runtime, limits, self.reason.clone());
+        let result = run_handler(
+            &mut self.runtime,
+            self.module,
+            job.request,
+            &job.env,
+            limits.cpu_time,
+        )
+        .await;
+        drop(watchdog);
+
+        let result = match self.reason.get() {
+            Some(termination) => Err(termination.into()),
+            None => result,
+        };
+        let responded = result.is_ok();
+        let _ = job.res_tx.send(result);
+
+        if responded
+            && let Err(error) = run_wait_until(&mut self.runtime, limits, self.reason).await
+        {
+            report_wait_until(&error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use http_body_util::combinators::UnsyncBoxBody;
+
+    fn empty_request() -> Request {
+        Request::new(UnsyncBoxBody::new(
+            http_body_util::Empty::new().map_err(|never| match never {}),
+        ))
+    }
+
+    const COUNTER: &str = r#"
+        globalThis.count = (globalThis.count ?? 0) + 1;
+        globalThis.handler = () => new Response(String(globalThis.count));
+    "#;
+
+    #[tokio::test]
+    async fn test_state_is_reset_between_requests() {
+        let pool = IsolatePool::new(PoolConfig {
+            isolates_per_code: 1,
+            ..Default::default()
+        });
+
+        for _ in 0..3 {
+            let response = pool
+                .run("counter", COUNTER, empty_request(), Default::default())
+                .await
+                .unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, "1");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redeploy_replaces_workers() {
+        let pool = IsolatePool::new(Default::default());
+
+        let response = pool
+            .run(
+                "code",
+                "globalThis.handler = () => new Response('v1');",
+                empty_request(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "v1");
+
+        let response = pool
+            .run(
+                "code",
+                "globalThis.handler = () => new Response('v2');",
+                empty_request(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "v2");
+    }
+
+    #[tokio::test]
+    async fn test_redeploy_drops_old_source_from_caches() {
+        let pool = IsolatePool::new(PoolConfig {
+            isolates_per_code: 1,
+            ..Default::default()
+        });
+        let v1 = "export default () => new Response('v1');";
+        let v2 = "export default () => new Response('v2');";
+
+        assert_eq!(run_to_text(&pool, "code", v1).await, "v1");
+        assert_eq!(run_to_text(&pool, "other", v1).await, "v1");
+        assert!(pool.caches.code.contains(&source_digest(v1)));
+
+        assert_eq!(run_to_text(&pool, "code", v2).await, "v2");
+        // still run by "other"
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(pool.caches.code.contains(&source_digest(v1)));
+        assert!(pool.caches.transpile.contains(&source_digest(v1)));
+
+        pool.remove("other");
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        while pool.caches.code.contains(&source_digest(v1)) {
+            assert!(Instant::now() < deadline, "old code was never dropped");
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!pool.caches.transpile.contains(&source_digest(v1)));
+        assert!(pool.caches.code.contains(&source_digest(v2)));
+        assert!(pool.caches.transpile.contains(&source_digest(v2)));
+    }
+
+    #[tokio::test]
+    async fn test_top_level_infinite_loop_is_terminated() {
+        let pool = IsolatePool::new(PoolConfig {
+            limits: Limits {
+                cpu_time: std::time::Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let result = pool
+            .run(
+                "loop",
+                "while (true) {}",
+                empty_request(),
+                Default::default(),
+            )
+            .await;
+        let termination = result
+            .unwrap_err()
+            .downcast::<crate::Termination>()
+            .unwrap();
+        assert_eq!(termination, crate::Termination::CpuTimeExceeded);
+    }
+
+    async fn run_to_text(pool: &IsolatePool, code_id: &str, code: &str) -> String {
+        let response = pool
+            .run(code_id, code, empty_request(), Default::default())
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_shared_per_code_id() {
+        let pool = IsolatePool::new(PoolConfig {
+            isolates_per_code: 1,
+            ..Default::default()
+        });
+        let code = r#"
+            export default async () => {
+                const cached = await caches.default.match("https://cache.test/");
+                if (cached) {
+                    return cached;
+                }
+                await caches.default.put("https://cache.test/", new Response("cached"));
+                return new Response("miss");
+            };
+        "#;
+
+        assert_eq!(run_to_text(&pool, "a", code).await, "miss");
+        assert_eq!(run_to_text(&pool, "a", code).await, "cached");
+        assert_eq!(run_to_text(&pool, "b", code).await, "miss");
+    }
+
+    #[tokio::test]
+    async fn test_kv_is_kept_per_code_id() {
+        let dir = std::env::temp_dir().join(format!("ski-kv-test-{}", std::process::id()));
+        let pool = IsolatePool::new(Default::default()).with_storage(StorageConfig {
+            kv: Some(crate::KvBackend::Sqlite { dir: dir.clone() }),
+            ..Default::default()
+        });
+        let code = r#"
+            export default async () => {
+                const kv = await Deno.openKv();
+                const { value } = await kv.get(["count"]);
+                const count = (value ?? 0) + 1;
+                await kv.set(["count"], count);
+                kv.close();
+                return new Response(String(count));
+            };
+        "#;
+
+        assert_eq!(run_to_text(&pool, "a", code).await, "1");
+        assert_eq!(run_to_text(&pool, "a", code).await, "2");
+        assert_eq!(run_to_text(&pool, "b", code).await, "1");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_failures_are_reported() {
+        let (reported_tx, mut reported_rx) = tokio::sync::mpsc::unbounded_channel();
+        let pool = IsolatePool::new(PoolConfig {
+            isolates_per_code: 1,
+            ..Default::default()
+        })
+        .with_wait_until_reporter(move |code_id, error| {
+            let _ = reported_tx.send((code_id.to_string(), format!("{error:#}")));
+        });
+        let code = r#"
+            export default (request, env, ctx) => {
+                ctx.waitUntil(Promise.reject(new Error("background failed")));
+                return new Response("responded");
+            };
+        "#;
+
+        assert_eq!(run_to_text(&pool, "code", code).await, "responded");
+        let (code_id, error) =
+            tokio::time::timeout(std::time::Duration::from_secs(5), reported_rx.recv())
+                .await
+                .expect("waitUntil failure was not reported")
+                .unwrap();
+        assert_eq!(code_id, "code");
+        assert!(error.contains("background failed"), "got {error}");
+    }
+
+    #[tokio::test]
+    async fn test_slow_requests_overlap_beyond_isolates_per_code() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        const REQUESTS: usize = 4;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        // answers only once every request is waiting on it, so they must all be in flight
+        tokio::spawn(async move {
+            let mut streams = vec![];
+            while streams.len() < REQUESTS {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0; 1024];
+                let _ = stream.read(&mut buffer).await.unwrap();
+                streams.push(stream);
+            }
+            for mut stream in streams {
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\nslow")
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let pool = IsolatePool::new(PoolConfig {
+            isolates_per_code: 1,
+            limits: Limits {
+                allow_private_network: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let code = format!(
+            r#"
+            export default async () => {{
+                const response = await fetch("http://{address}/");
+                return new Response(await response.text());
+            }};
+            "#
+        );
+
+        let texts = tokio::time::timeout(
+            std::time::Duration::from_secs(30),
+            futures::future::join_all((0..REQUESTS).map(|_| run_to_text(&pool, "slow", &code))),
+        )
+        .await
+        .expect("requests did not run at once");
+        assert_eq!(texts, vec!["slow"; REQUESTS]);
+    }
+}
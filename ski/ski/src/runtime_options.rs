@@ -1,36 +1,129 @@
-use deno_core::{OpState, ResourceId, op2};
+use crate::net_policy::{PublicResolver, denied_nets};
+use crate::storage::KvHandler;
+use deno_ast::{
+    EmitOptions, MediaType, ParseParams, SourceMapOption, TranspileModuleOptions, TranspileOptions,
+};
+use deno_core::url::Url;
+use deno_core::{ModuleCodeString, ModuleName, OpState, ResourceId, SourceMapData, op2};
 use deno_core::{RuntimeOptions, extension, v8::CreateParams};
 use deno_error::JsErrorBox;
+use deno_kv::KvConfig;
+use deno_permissions::{
+    EnvDescriptor, Permissions, PermissionsContainer, RuntimePermissionDescriptorParser,
+};
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::Arc;
+
+/// `allow_private_network` as in [`Limits`](crate::Limits).
+pub fn runtime_options(allow_private_network: bool) -> RuntimeOptions {
+    let resolver = if allow_private_network {
+        deno_fetch::dns::Resolver::default()
+    } else {
+        deno_fetch::dns::Resolver::Custom(Arc::new(PublicResolver))
+    };
 
-pub fn runtime_options() -> RuntimeOptions {
     RuntimeOptions {
         extensions: vec![
             deno_webidl::deno_webidl::init(),
             deno_web::deno_web::init(Default::default()),
-            deno_fetch::deno_fetch::init(Default::default()),
-            bootstrap::init(),
+            deno_fetch::deno_fetch::init(deno_fetch::Options {
+                resolver,
+                ..Default::default()
+            }),
+            deno_crypto::deno_crypto::init(None),
+            deno_websocket::deno_websocket::init(),
+            deno_cache::deno_cache::init(None),
+            deno_kv::deno_kv::init(KvHandler, KvConfig::builder().build()),
+            bootstrap::init(allow_private_network),
             request_response_extension::init(),
         ],
         create_params: Some(CreateParams::default()),
+        extension_transpiler: Some(Rc::new(transpile_extension)),
         ..Default::default()
     }
 }
 
+/// deno_kv ships its JS as TypeScript, which is stripped before going into the snapshot.
+fn transpile_extension(
+    specifier: ModuleName,
+    source: ModuleCodeString,
+) -> Result<(ModuleCodeString, Option<SourceMapData>), JsErrorBox> {
+    if !specifier.ends_with(".ts") {
+        return Ok((source, None));
+    }
+
+    let parsed = deno_ast::parse_module(ParseParams {
+        specifier: Url::parse(&specifier)
+            .map_err(|error| JsErrorBox::generic(error.to_string()))?,
+        text: source.into(),
+        media_type: MediaType::TypeScript,
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+    })
+    .map_err(|error| JsErrorBox::generic(error.to_string()))?;
+    let transpiled = parsed
+        .transpile(
+            &TranspileOptions {
+                imports_not_used_as_values: deno_ast::ImportsNotUsedAsValues::Remove,
+                ..Default::default()
+            },
+            &TranspileModuleOptions::default(),
+            &EmitOptions {
+                source_map: SourceMapOption::None,
+                ..Default::default()
+            },
+        )
+        .map_err(|error| JsErrorBox::generic(error.to_string()))?
+        .into_source();
+    Ok((transpiled.text.into(), None))
+}
+
 extension!(
     bootstrap,
     esm_entry_point = "ext:bootstrap/bootstrap.js",
     esm = ["bootstrap.js", "run.js"],
+    options = {
+        allow_private_network: bool,
+    },
+    state = |s, options| {
+        s.put(permissions(options.allow_private_network));
+    },
 );
 
+/// User code may reach the network through `fetch` and `WebSocket`, but nothing else,
+/// and not the host's own network. The remote KV backend also reads its access token
+/// from the host environment.
+fn permissions(allow_private_network: bool) -> PermissionsContainer {
+    let mut permissions = Permissions::none_without_prompt();
+    let denied_nets = (!allow_private_network).then(denied_nets);
+    permissions.net = Permissions::new_unary(Some(vec![]), denied_nets, false);
+    permissions.env = Permissions::new_unary(
+        Some(vec![EnvDescriptor::new(Cow::Borrowed(
+            "DENO_KV_ACCESS_TOKEN",
+        ))]),
+        None,
+        false,
+    );
+    PermissionsContainer::new(
+        Arc::new(RuntimePermissionDescriptorParser::new(
+            sys_traits::impls::RealSys,
+        )),
+        permissions,
+    )
+}
+
 #[derive(Default)]
 pub struct RequestParts {
     pub url: String,
     pub method: String,
     pub headers: Vec<(String, String)>,
     pub rid: Option<ResourceId>,
+    /// Passed to the handler as `env`.
+    pub env: Vec<(String, String)>,
 }
 
 pub struct ResponseParts {
@@ -39,7 +132,13 @@ pub struct ResponseParts {
     pub rid: Option<ResourceId>,
 }
 
-type OpGetRequestParts = (String, String, Vec<(String, String)>, Option<ResourceId>);
+type OpGetRequestParts = (
+    String,
+    String,
+    Vec<(String, String)>,
+    Option<ResourceId>,
+    Vec<(String, String)>,
+);
 
 #[op2]
 #[serde]
@@ -47,7 +146,7 @@ fn op_get_request_parts(state: &mut OpState) -> Result<OpGetRequestParts, JsErro
     let parts = state
         .try_take::<RequestParts>()
         .ok_or_else(|| JsErrorBox::generic("Request parts not found"))?;
-    Ok((parts.url, parts.method, parts.headers, parts.rid))
+    Ok((parts.url, parts.method, parts.headers, parts.rid, parts.env))
 }
 
 #[op2(async)]
@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use deno_cache::{CacheImpl, MemoryCache};
+use deno_core::OpState;
+use deno_core::url::Url;
+use deno_error::JsErrorBox;
+use deno_kv::DatabaseHandler;
+use deno_kv::dynamic::{DynamicDbHandler, RcDynamicDb};
+use deno_kv::remote::{HttpOptions, RemoteDbHandler};
+use deno_kv::sqlite::SqliteDbHandler;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Host-side storage behind `caches` and `Deno.openKv`, kept apart per code_id.
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    /// Upper bound of all `caches` of a code_id together. The least recently used
+    /// responses are evicted beyond it.
+    pub cache_max_bytes: usize,
+    /// Upper bound of the `caches` of all code_ids of a pool together. The least
+    /// recently used responses of any code_id are evicted beyond it.
+    pub pool_cache_max_bytes: usize,
+    /// `Deno.openKv` rejects when `None`.
+    pub kv: Option<KvBackend>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            cache_max_bytes: 32 * 1024 * 1024,
+            pool_cache_max_bytes: 512 * 1024 * 1024,
+            kv: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum KvBackend {
+    /// A SQLite database per code_id, at `dir/<code_id>/kv.sqlite3`.
+    Sqlite { dir: PathBuf },
+    /// A KV Connect server, where each code_id opens `url` joined with the code_id,
+    /// so `url` should end with a `/`. Authenticates with the `DENO_KV_ACCESS_TOKEN`
+    /// of the host process.
+    Remote { url: Url },
+}
+
+/// Storage of the code_id an isolate serves, put into its `OpState`.
+#[derive(Clone)]
+pub(crate) struct CodeStorage {
+    code_id: String,
+    cache: MemoryCache,
+    kv: Option<KvBackend>,
+}
+
+impl CodeStorage {
+    pub(crate) fn new(code_id: &str, cache: MemoryCache, kv: Option<KvBackend>) -> Self {
+        Self {
+            code_id: code_id.to_string(),
+            cache,
+            kv,
+        }
+    }
+
+    pub(crate) fn install(&self, state: &mut OpState) {
+        state.put(CacheImpl::Memory(self.cache.clone()));
+        state.put(self.clone());
+    }
+}
+
+/// Opens the KV database of the code_id in `OpState`. Without one, as in `ski::run`,
+/// `Deno.openKv` rejects.
+pub(crate) struct KvHandler;
+
+#[async_trait(?Send)]
+impl DatabaseHandler for KvHandler {
+    type DB = RcDynamicDb;
+
+    async fn open(
+        &self,
+        state: Rc<RefCell<OpState>>,
+        path: Option<String>,
+    ) -> Result<Self::DB, JsErrorBox> {
+        if path.is_some() {
+            return Err(JsErrorBox::type_error(
+                "Deno.openKv takes no path, each deployment has a single database",
+            ));
+        }
+        let storage = state.borrow().try_borrow::<CodeStorage>().cloned();
+        let Some(CodeStorage {
+            code_id,
+            kv: Some(kv),
+            ..
+        }) = storage
+        else {
+            return Err(JsErrorBox::type_error(
+                "Deno.openKv is not available in this context",
+            ));
+        };
+        // the code_id becomes a path segment
+        if code_id.is_empty()
+            || !code_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(JsErrorBox::type_error(format!(
+                "Deno.openKv is not available for code_id {code_id:?}"
+            )));
+        }
+
+        match kv {
+            KvBackend::Sqlite { dir } => {
+                SqliteDbHandler::new(Some(dir.join(&code_id)), None)
+                    .dyn_open(state, None)
+                    .await
+            }
+            KvBackend::Remote { url } => {
+                let url = url
+                    .join(&code_id)
+                    .map_err(|error| JsErrorBox::type_error(error.to_string()))?;
+                RemoteDbHandler::new(HttpOptions {
+                    user_agent: "ski".to_string(),
+                    root_cert_store_provider: None,
+                    proxy: None,
+                    unsafely_ignore_certificate_errors: None,
+                    client_cert_chain_and_key: Default::default(),
+                })
+                .dyn_open(state, Some(url.to_string()))
+                .await
+            }
+        }
+    }
+}
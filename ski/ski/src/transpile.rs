@@ -0,0 +1,139 @@
+use crate::code_cache::{SourceDigest, source_digest};
+use crate::module_loader::USER_MODULE_SPECIFIER;
+use deno_ast::{
+    EmitOptions, JsxClassicOptions, JsxRuntime, MediaType, ParseParams, SourceMapOption,
+    TranspileModuleOptions, TranspileOptions,
+};
+use deno_core::ModuleSpecifier;
+use deno_core::anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// User code after type stripping and JSX transform, with the source map back to it.
+pub(crate) struct Transpiled {
+    pub(crate) source_digest: SourceDigest,
+    pub(crate) code: Arc<str>,
+    pub(crate) source_map: Option<Arc<[u8]>>,
+}
+
+/// Transpiles user code as TSX, which also covers plain JavaScript, TypeScript and JSX.
+///
+/// JSX uses the classic runtime, so `React` has to be in scope unless the module
+/// picks another factory with a `/** @jsx h */` pragma.
+pub(crate) fn transpile(code: &str) -> Result<Transpiled> {
+    let parsed = deno_ast::parse_module(ParseParams {
+        specifier: ModuleSpecifier::parse(USER_MODULE_SPECIFIER)?,
+        text: code.into(),
+        media_type: MediaType::Tsx,
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+    })
+    .map_err(|error| anyhow!("failed to parse user code: {error}"))?;
+
+    let emitted = parsed
+        .transpile(
+            &TranspileOptions {
+                imports_not_used_as_values: deno_ast::ImportsNotUsedAsValues::Remove,
+                jsx: Some(JsxRuntime::Classic(JsxClassicOptions {
+                    factory: "React.createElement".to_string(),
+                    fragment_factory: "React.Fragment".to_string(),
+                })),
+                ..Default::default()
+            },
+            &TranspileModuleOptions::default(),
+            &EmitOptions {
+                source_map: SourceMapOption::Separate,
+                ..Default::default()
+            },
+        )
+        .map_err(|error| anyhow!("failed to transpile user code: {error}"))?
+        .into_source();
+
+    Ok(Transpiled {
+        source_digest: source_digest(code),
+        code: emitted.text.into(),
+        source_map: emitted
+            .source_map
+            .map(|source_map| source_map.into_bytes().into()),
+    })
+}
+
+/// Transpiled user code keyed by the digest of its original source.
+#[derive(Clone, Default)]
+pub struct TranspileCache {
+    entries: Arc<Mutex<HashMap<SourceDigest, Arc<Transpiled>>>>,
+}
+
+impl TranspileCache {
+    pub(crate) fn get_or_transpile(&self, code: &str) -> Result<Arc<Transpiled>> {
+        let key = source_digest(code);
+        if let Some(transpiled) = self.entries.lock().unwrap().get(&key) {
+            return Ok(transpiled.clone());
+        }
+
+        // transpiled outside the lock; a concurrent miss only duplicates the work
+        let transpiled = Arc::new(transpile(code)?);
+        self.entries.lock().unwrap().insert(key, transpiled.clone());
+        Ok(transpiled)
+    }
+
+    pub fn remove(&self, code: &str) {
+        self.remove_digest(&source_digest(code));
+    }
+
+    pub(crate) fn remove_digest(&self, digest: &SourceDigest) {
+        self.entries.lock().unwrap().remove(digest);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn contains(&self, digest: &SourceDigest) -> bool {
+        self.entries.lock().unwrap().contains_key(digest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_types_are_stripped() {
+        let transpiled = transpile(
+            "interface Greeting { text: string }\n\
+            export default (request: Request): Response => new Response(\"hi\" as string);",
+        )
+        .unwrap();
+
+        assert!(!transpiled.code.contains("interface"));
+        assert!(!transpiled.code.contains(": Response"));
+        assert!(transpiled.source_map.is_some());
+    }
+
+    #[test]
+    fn test_jsx_uses_pragma() {
+        let transpiled = transpile("/** @jsx h */\nconst element = <div id=\"a\" />;").unwrap();
+
+        assert!(transpiled.code.contains("h(\"div\""), "{}", transpiled.code);
+    }
+
+    #[test]
+    fn test_cache_is_keyed_by_source() {
+        let cache = TranspileCache::default();
+        let first = cache.get_or_transpile("const a: number = 1;").unwrap();
+        let second = cache.get_or_transpile("const a: number = 1;").unwrap();
+        let other = cache.get_or_transpile("const a: number = 2;").unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &other));
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let error = transpile("export default (").err().unwrap();
+
+        assert!(
+            format!("{error:#}").contains("failed to parse user code"),
+            "{error:#}"
+        );
+    }
+}
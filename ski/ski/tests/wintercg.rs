@@ -0,0 +1,57 @@
+//! A subset of the WinterCG minimum common API tests, run through `ski::run`.
+//!
+//! Each file in `tests/wintercg` is appended to `harness.js`, which answers with the
+//! failed tests.
+
+use http_body_util::BodyExt;
+use http_body_util::combinators::UnsyncBoxBody;
+use ski::Limits;
+
+#[derive(serde::Deserialize)]
+struct Report {
+    total: usize,
+    failures: Vec<String>,
+}
+
+async fn run_wintercg_test(test: &str) {
+    let code = format!("{}\n{test}", include_str!("wintercg/harness.js"));
+    let response = ski::run(
+        &code,
+        hyper::Request::new(UnsyncBoxBody::new(
+            http_body_util::Empty::new().map_err(|never| match never {}),
+        )),
+        Default::default(),
+        // websocket.js connects to loopback
+        Limits {
+            allow_private_network: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let report: Report = serde_json::from_slice(&body).unwrap();
+
+    assert!(report.total > 0);
+    assert!(
+        report.failures.is_empty(),
+        "{} of {} failed:\n{}",
+        report.failures.len(),
+        report.total,
+        report.failures.join("\n")
+    );
+}
+
+macro_rules! wintercg_tests {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[tokio::test]
+            async fn $name() {
+                let test = include_str!(concat!("wintercg/", stringify!($name), ".js"));
+                run_wintercg_test(test).await;
+            }
+        )*
+    };
+}
+
+wintercg_tests!(globals, crypto, navigator, websocket);
@@ -0,0 +1,124 @@
+// Web Crypto API, after WPT WebCryptoAPI/.
+
+function hex(buffer) {
+  return Array.from(new Uint8Array(buffer))
+    .map((byte) => byte.toString(16).padStart(2, "0"))
+    .join("");
+}
+
+test(() => {
+  const array = new Uint8Array(16);
+  assert_equals(crypto.getRandomValues(array), array);
+  assert_true(array.some((byte) => byte !== 0), "filled with random bytes");
+}, "getRandomValues fills and returns the array");
+
+test(() => {
+  crypto.getRandomValues(new Uint8Array(65536));
+  assert_throws_dom(
+    "QuotaExceededError",
+    () => crypto.getRandomValues(new Uint8Array(65537)),
+  );
+}, "getRandomValues is limited to 65536 bytes");
+
+test(() => {
+  assert_throws_dom(
+    "TypeMismatchError",
+    () => crypto.getRandomValues(new Float32Array(4)),
+  );
+}, "getRandomValues rejects float arrays");
+
+test(() => {
+  const uuid = crypto.randomUUID();
+  assert_true(
+    /^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$/
+      .test(uuid),
+    uuid,
+  );
+  assert_not_equals(crypto.randomUUID(), uuid);
+}, "randomUUID is a version 4 UUID");
+
+test(() => {
+  assert_throws_js(TypeError, () => new SubtleCrypto());
+  assert_throws_js(TypeError, () => new CryptoKey());
+}, "SubtleCrypto and CryptoKey are not constructible");
+
+promise_test(async () => {
+  const digest = await crypto.subtle.digest(
+    "SHA-256",
+    new TextEncoder().encode("abc"),
+  );
+  assert_equals(
+    hex(digest),
+    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+  );
+}, "digest SHA-256");
+
+promise_test(async () => {
+  const key = await crypto.subtle.importKey(
+    "raw",
+    new TextEncoder().encode("key"),
+    { name: "HMAC", hash: "SHA-256" },
+    false,
+    ["sign", "verify"],
+  );
+  const data = new TextEncoder().encode(
+    "The quick brown fox jumps over the lazy dog",
+  );
+  const signature = await crypto.subtle.sign("HMAC", key, data);
+  assert_equals(
+    hex(signature),
+    "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8",
+  );
+  assert_true(await crypto.subtle.verify("HMAC", key, signature, data));
+}, "HMAC SHA-256 sign and verify");
+
+promise_test(async () => {
+  const key = await crypto.subtle.generateKey(
+    { name: "AES-GCM", length: 256 },
+    true,
+    ["encrypt", "decrypt"],
+  );
+  assert_true(key instanceof CryptoKey);
+  assert_equals(key.algorithm.name, "AES-GCM");
+
+  const iv = crypto.getRandomValues(new Uint8Array(12));
+  const plaintext = new TextEncoder().encode("hello");
+  const ciphertext = await crypto.subtle.encrypt(
+    { name: "AES-GCM", iv },
+    key,
+    plaintext,
+  );
+  const decrypted = await crypto.subtle.decrypt(
+    { name: "AES-GCM", iv },
+    key,
+    ciphertext,
+  );
+  assert_equals(new TextDecoder().decode(decrypted), "hello");
+}, "AES-GCM encrypt and decrypt");
+
+promise_test(async () => {
+  const { privateKey, publicKey } = await crypto.subtle.generateKey(
+    { name: "ECDSA", namedCurve: "P-256" },
+    true,
+    ["sign", "verify"],
+  );
+  const data = new TextEncoder().encode("hello");
+  const algorithm = { name: "ECDSA", hash: "SHA-256" };
+  const signature = await crypto.subtle.sign(algorithm, privateKey, data);
+  assert_true(await crypto.subtle.verify(algorithm, publicKey, signature, data));
+
+  const jwk = await crypto.subtle.exportKey("jwk", publicKey);
+  assert_equals(jwk.kty, "EC");
+  assert_equals(jwk.crv, "P-256");
+}, "ECDSA P-256 sign, verify and export");
+
+promise_test(async () => {
+  let error;
+  try {
+    await crypto.subtle.digest("SHA-0", new Uint8Array());
+  } catch (e) {
+    error = e;
+  }
+  assert_true(error instanceof DOMException, "rejects with a DOMException");
+  assert_equals(error.name, "NotSupportedError");
+}, "digest rejects unknown algorithms");
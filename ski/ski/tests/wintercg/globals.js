@@ -0,0 +1,95 @@
+// Interfaces and global methods of the WinterCG minimum common API.
+
+const interfaces = [
+  "AbortController",
+  "AbortSignal",
+  "Blob",
+  "ByteLengthQueuingStrategy",
+  "CompressionStream",
+  "CountQueuingStrategy",
+  "Crypto",
+  "CryptoKey",
+  "CustomEvent",
+  "DecompressionStream",
+  "DOMException",
+  "Event",
+  "EventTarget",
+  "File",
+  "FormData",
+  "Headers",
+  "Navigator",
+  "ReadableByteStreamController",
+  "ReadableStream",
+  "ReadableStreamBYOBReader",
+  "ReadableStreamBYOBRequest",
+  "ReadableStreamDefaultController",
+  "ReadableStreamDefaultReader",
+  "Request",
+  "Response",
+  "SubtleCrypto",
+  "TextDecoder",
+  "TextDecoderStream",
+  "TextEncoder",
+  "TextEncoderStream",
+  "TransformStream",
+  "TransformStreamDefaultController",
+  "URL",
+  "URLSearchParams",
+  "WebAssembly",
+  "WebSocket",
+  "WritableStream",
+  "WritableStreamDefaultController",
+  "WritableStreamDefaultWriter",
+];
+
+for (const name of interfaces) {
+  test(() => {
+    assert_true(name in globalThis, `${name} is defined`);
+    assert_false(
+      Object.getOwnPropertyDescriptor(globalThis, name).enumerable,
+      `${name} is not enumerable`,
+    );
+  }, `interface ${name}`);
+}
+
+const methods = [
+  "atob",
+  "btoa",
+  "clearInterval",
+  "clearTimeout",
+  "fetch",
+  "queueMicrotask",
+  "setInterval",
+  "setTimeout",
+  "structuredClone",
+];
+
+for (const name of methods) {
+  test(() => {
+    assert_equals(typeof globalThis[name], "function", name);
+  }, `method ${name}`);
+}
+
+test(() => {
+  assert_equals(typeof globalThis.console.log, "function");
+  assert_true(globalThis.crypto instanceof Crypto);
+  assert_true(globalThis.crypto.subtle instanceof SubtleCrypto);
+  assert_true(globalThis.navigator instanceof Navigator);
+  assert_equals(globalThis.globalThis, globalThis);
+}, "global objects");
+
+promise_test(async () => {
+  const order = [];
+  queueMicrotask(() => order.push("microtask"));
+  await new Promise((resolve) => setTimeout(resolve, 0));
+  order.push("timeout");
+  assert_array_equals(order, ["microtask", "timeout"]);
+}, "queueMicrotask runs before timers");
+
+test(() => {
+  const source = { date: new Date(0), bytes: new Uint8Array([1, 2]) };
+  const clone = structuredClone(source);
+  assert_not_equals(clone, source);
+  assert_equals(clone.date.getTime(), 0);
+  assert_array_equals(clone.bytes, [1, 2]);
+}, "structuredClone");
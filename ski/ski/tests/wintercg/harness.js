@@ -0,0 +1,88 @@
+// A small testharness.js lookalike, so the tests read like the WPT tests the
+// WinterCG minimum common API points to. Each test file is appended to this
+// module, and the handler answers with the failures as JSON.
+
+const tests = [];
+
+function test(fn, name) {
+  tests.push({ name, fn });
+}
+
+function promise_test(fn, name) {
+  tests.push({ name, fn });
+}
+
+function format(value) {
+  return typeof value === "string" ? JSON.stringify(value) : String(value);
+}
+
+function assert_true(actual, description = "") {
+  if (actual !== true) {
+    throw new Error(`${description}: expected true, got ${format(actual)}`);
+  }
+}
+
+function assert_false(actual, description = "") {
+  if (actual !== false) {
+    throw new Error(`${description}: expected false, got ${format(actual)}`);
+  }
+}
+
+function assert_equals(actual, expected, description = "") {
+  if (!Object.is(actual, expected)) {
+    throw new Error(
+      `${description}: expected ${format(expected)}, got ${format(actual)}`,
+    );
+  }
+}
+
+function assert_not_equals(actual, expected, description = "") {
+  if (Object.is(actual, expected)) {
+    throw new Error(`${description}: got unexpected ${format(actual)}`);
+  }
+}
+
+function assert_array_equals(actual, expected, description = "") {
+  assert_equals(actual.length, expected.length, `${description} (length)`);
+  for (let i = 0; i < expected.length; i++) {
+    assert_equals(actual[i], expected[i], `${description} [${i}]`);
+  }
+}
+
+function assert_throws_js(constructor, fn, description = "") {
+  try {
+    fn();
+  } catch (error) {
+    if (!(error instanceof constructor)) {
+      throw new Error(
+        `${description}: expected ${constructor.name}, got ${error}`,
+      );
+    }
+    return;
+  }
+  throw new Error(`${description}: expected ${constructor.name} to be thrown`);
+}
+
+function assert_throws_dom(name, fn, description = "") {
+  try {
+    fn();
+  } catch (error) {
+    if (!(error instanceof DOMException) || error.name !== name) {
+      throw new Error(`${description}: expected DOMException ${name}, got ${error}`);
+    }
+    return;
+  }
+  throw new Error(`${description}: expected DOMException ${name} to be thrown`);
+}
+
+export default async () => {
+  const failures = [];
+  for (const { name, fn } of tests) {
+    try {
+      await fn();
+    } catch (error) {
+      failures.push(`${name}: ${error?.message ?? error}`);
+    }
+  }
+  return Response.json({ total: tests.length, failures });
+};
@@ -0,0 +1,18 @@
+// navigator.userAgent, the only navigator member in the minimum common API.
+
+test(() => {
+  assert_equals(typeof navigator.userAgent, "string");
+  assert_not_equals(navigator.userAgent, "");
+}, "navigator.userAgent");
+
+test(() => {
+  assert_throws_js(TypeError, () => new Navigator());
+}, "Navigator is not constructible");
+
+test(() => {
+  const getter = Object.getOwnPropertyDescriptor(
+    Navigator.prototype,
+    "userAgent",
+  ).get;
+  assert_throws_js(TypeError, () => getter.call({}));
+}, "userAgent checks its receiver");
@@ -0,0 +1,51 @@
+// WebSocket, after WPT websockets/Create-*.any.js.
+
+test(() => {
+  assert_throws_dom("SyntaxError", () => new WebSocket("not a url"));
+}, "invalid URL");
+
+test(() => {
+  assert_throws_dom(
+    "SyntaxError",
+    () => new WebSocket("ftp://example.com"),
+  );
+}, "non-WebSocket scheme");
+
+test(() => {
+  assert_throws_dom(
+    "SyntaxError",
+    () => new WebSocket("wss://example.com/#fragment"),
+  );
+}, "URL with a fragment");
+
+test(() => {
+  assert_throws_dom(
+    "SyntaxError",
+    () => new WebSocket("wss://example.com", ["chat", "chat"]),
+  );
+}, "repeated protocols");
+
+test(() => {
+  assert_equals(WebSocket.CONNECTING, 0);
+  assert_equals(WebSocket.OPEN, 1);
+  assert_equals(WebSocket.CLOSING, 2);
+  assert_equals(WebSocket.CLOSED, 3);
+}, "readyState constants");
+
+promise_test(async () => {
+  // nothing listens on port 1, so the connection is refused
+  const socket = new WebSocket("ws://127.0.0.1:1");
+  assert_equals(socket.readyState, WebSocket.CONNECTING);
+
+  const events = [];
+  const close = await new Promise((resolve) => {
+    socket.onerror = () => events.push("error");
+    socket.onclose = (event) => {
+      events.push("close");
+      resolve(event);
+    };
+  });
+  assert_array_equals(events, ["error", "close"]);
+  assert_false(close.wasClean);
+  assert_equals(socket.readyState, WebSocket.CLOSED);
+}, "failed connection fires error then close");